@@ -0,0 +1,158 @@
+//! Live engine analysis of the position on the board.
+
+use crate::engine::{Engine, Score};
+use crate::record::GameRecord;
+use chess::game::Team;
+use std::io;
+use std::path::Path;
+
+/// Number of principal variations shown.
+pub const ANALYSIS_LINES: usize = 3;
+
+/// A principal variation ready to be shown.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnalysisLine {
+    /// Evaluation from white's point of view.
+    pub score: Score,
+    pub san: Vec<String>,
+    /// First move of the line in UCI notation.
+    pub best_move: String,
+}
+
+pub struct Analyzer {
+    engine: Engine,
+    /// UCI `position` currently searched.
+    position: Option<String>,
+    /// Ply and side to move of the searched position.
+    ply: usize,
+    player: Team,
+    /// Variations of the searched position in UCI notation with their SAN,
+    /// so that the game is replayed only for lines the engine has not sent before.
+    san_lines: Vec<(Vec<String>, Vec<String>)>,
+    pub lines: Vec<AnalysisLine>,
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+impl Analyzer {
+    pub fn start(path: &Path) -> io::Result<Analyzer> {
+        let mut engine = Engine::start(path)?;
+        engine.set_option("MultiPV", &ANALYSIS_LINES.to_string())?;
+        Ok(Analyzer {
+            engine,
+            position: None,
+            ply: 0,
+            player: Team::White,
+            san_lines: Vec::new(),
+            lines: Vec::new(),
+            depth: 0,
+            nodes: 0,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.engine.name
+    }
+
+    /// Makes sure the position after `ply` moves of `record` is being searched,
+    /// restarting the search if another position was searched before.
+    pub fn analyze(&mut self, record: &GameRecord, ply: usize, player: Team) -> io::Result<()> {
+        let position = record.uci_position_at(ply);
+        if self.position.as_ref() == Some(&position) {
+            return Ok(());
+        }
+        if self.position.is_some() {
+            self.engine.stop()?;
+        }
+        self.engine.set_variant(record.variant)?;
+        self.engine.go_infinite(&position)?;
+        self.position = Some(position);
+        self.ply = ply;
+        self.player = player;
+        self.san_lines.clear();
+        self.lines.clear();
+        self.depth = 0;
+        self.nodes = 0;
+        Ok(())
+    }
+
+    /// Reads the engine's progress, translating the variations that changed to SAN.
+    pub fn poll(&mut self, record: &GameRecord) {
+        self.engine.poll();
+        let info = &self.engine.info;
+        self.depth = info.depth;
+        self.nodes = info.nodes;
+        let mut san_lines = Vec::new();
+        self.lines.clear();
+        for variation in info.variations.iter().filter(|variation| !variation.moves.is_empty()) {
+            let known = self.san_lines.iter().find(|(moves, _)| *moves == variation.moves);
+            let san = match known {
+                Some((_, san)) => san.clone(),
+                None => record.san_line(self.ply, &variation.moves),
+            };
+            self.lines.push(AnalysisLine {
+                score: white_score(variation.score, self.player),
+                san: san.clone(),
+                best_move: variation.moves[0].clone(),
+            });
+            san_lines.push((variation.moves.clone(), san));
+        }
+        self.san_lines = san_lines;
+    }
+
+    /// First move of the best line, if the position after `ply` moves is the one analysed.
+    pub fn best_move(&self, ply: usize) -> Option<&str> {
+        if self.position.is_some() && self.ply == ply {
+            self.lines.first().map(|line| line.best_move.as_str())
+        } else {
+            None
+        }
+    }
+
+    /// Evaluation of the best line from white's point of view.
+    pub fn score(&self) -> Option<Score> {
+        self.lines.first().map(|line| line.score)
+    }
+}
+
+/// Turns a score for the side to move into one from white's point of view.
+pub fn white_score(score: Score, player: Team) -> Score {
+    match (player, score) {
+        (Team::White, score) => score,
+        (Team::Black, Score::Centipawns(centipawns)) => Score::Centipawns(-centipawns),
+        // Black having been mated is white having mated, and the other way round.
+        (Team::Black, Score::Mated) => Score::Mate(0),
+        (Team::Black, Score::Mate(0)) => Score::Mated,
+        (Team::Black, Score::Mate(moves)) => Score::Mate(-moves),
+    }
+}
+
+/// Share of the evaluation bar filled with white, between 0 and 1.
+pub fn white_share(score: Score) -> f32 {
+    match score {
+        Score::Centipawns(centipawns) => 1.0 / (1.0 + 10f32.powf(-centipawns as f32 / 400.0)),
+        Score::Mate(moves) if moves >= 0 => 1.0,
+        Score::Mate(_) | Score::Mated => 0.0,
+    }
+}
+
+/// Formats a score from white's point of view, e.g. "+0.35" or "-M3".
+pub fn format_score(score: Score) -> String {
+    match score {
+        Score::Centipawns(centipawns) => format!("{:+.2}", centipawns as f32 / 100.0),
+        Score::Mate(moves) if moves >= 0 => format!("+M{}", moves),
+        Score::Mate(moves) => format!("-M{}", -moves),
+        Score::Mated => "-M0".to_string(),
+    }
+}
+
+/// Formats a node count briefly, e.g. "850", "35k" or "1.2M".
+pub fn format_nodes(nodes: u64) -> String {
+    if nodes >= 1_000_000 {
+        format!("{:.1}M", nodes as f64 / 1_000_000.0)
+    } else if nodes >= 1000 {
+        format!("{}k", nodes / 1000)
+    } else {
+        nodes.to_string()
+    }
+}
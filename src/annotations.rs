@@ -0,0 +1,203 @@
+//! Arrows and square highlights drawn on the board with the right mouse button.
+//!
+//! They are stored in PGN comments with the same `[%cal ...]` and `[%csl ...]`
+//! commands used by other chess software, e.g. `{[%csl Gd4][%cal Rg1f3]}`.
+
+use crate::position::{coordinate_to_string, string_to_coordinate, Coordinate};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AnnotationColor {
+    Green,
+    Red,
+    Yellow,
+    Blue,
+}
+
+impl AnnotationColor {
+    fn letter(self) -> char {
+        match self {
+            AnnotationColor::Green => 'G',
+            AnnotationColor::Red => 'R',
+            AnnotationColor::Yellow => 'Y',
+            AnnotationColor::Blue => 'B',
+        }
+    }
+
+    fn from_letter(letter: char) -> Option<AnnotationColor> {
+        match letter {
+            'G' => Some(AnnotationColor::Green),
+            'R' => Some(AnnotationColor::Red),
+            'Y' => Some(AnnotationColor::Yellow),
+            'B' => Some(AnnotationColor::Blue),
+            _ => None,
+        }
+    }
+
+    /// RGB components used when rendering, alpha is chosen by the renderer.
+    pub fn rgb(self) -> (f32, f32, f32) {
+        match self {
+            AnnotationColor::Green => (21.0 / 255.0, 120.0 / 255.0, 27.0 / 255.0),
+            AnnotationColor::Red => (136.0 / 255.0, 32.0 / 255.0, 32.0 / 255.0),
+            AnnotationColor::Yellow => (230.0 / 255.0, 143.0 / 255.0, 0.0),
+            AnnotationColor::Blue => (0.0, 48.0 / 255.0, 136.0 / 255.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Arrow {
+    pub from: Coordinate,
+    pub to: Coordinate,
+    pub color: AnnotationColor,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SquareMark {
+    pub square: Coordinate,
+    pub color: AnnotationColor,
+}
+
+/// Arrows and circled squares attached to one position.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Annotations {
+    pub arrows: Vec<Arrow>,
+    pub squares: Vec<SquareMark>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.arrows.is_empty() && self.squares.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.arrows.clear();
+        self.squares.clear();
+    }
+
+    /// Adds an arrow, or removes it if the same arrow is already drawn.
+    /// Drawing over an arrow in another color recolors it.
+    pub fn toggle_arrow(&mut self, arrow: Arrow) {
+        if let Some(index) = self
+            .arrows
+            .iter()
+            .position(|a| a.from == arrow.from && a.to == arrow.to)
+        {
+            let removed = self.arrows.remove(index);
+            if removed.color == arrow.color {
+                return;
+            }
+        }
+        self.arrows.push(arrow);
+    }
+
+    /// Adds a square circle, with the same toggling rules as `toggle_arrow`.
+    pub fn toggle_square(&mut self, mark: SquareMark) {
+        if let Some(index) = self.squares.iter().position(|s| s.square == mark.square) {
+            let removed = self.squares.remove(index);
+            if removed.color == mark.color {
+                return;
+            }
+        }
+        self.squares.push(mark);
+    }
+
+    /// Formats the annotations as PGN comment commands, empty if there are none.
+    pub fn to_commands(&self) -> String {
+        let mut commands = String::new();
+        if !self.squares.is_empty() {
+            let squares: Vec<String> = self
+                .squares
+                .iter()
+                .map(|s| format!("{}{}", s.color.letter(), coordinate_to_string(s.square)))
+                .collect();
+            commands += &format!("[%csl {}]", squares.join(","));
+        }
+        if !self.arrows.is_empty() {
+            let arrows: Vec<String> = self
+                .arrows
+                .iter()
+                .map(|a| {
+                    format!(
+                        "{}{}{}",
+                        a.color.letter(),
+                        coordinate_to_string(a.from),
+                        coordinate_to_string(a.to)
+                    )
+                })
+                .collect();
+            commands += &format!("[%cal {}]", arrows.join(","));
+        }
+        commands
+    }
+
+    /// Collects every `[%cal]` and `[%csl]` command found in a comment.
+    /// Malformed entries are skipped.
+    pub fn from_comment(comment: &str) -> Annotations {
+        let mut annotations = Annotations::default();
+        for (name, arguments) in comment_commands(comment) {
+            for entry in arguments.split(',').map(str::trim) {
+                let color = match entry.chars().next().and_then(AnnotationColor::from_letter) {
+                    Some(color) => color,
+                    None => continue,
+                };
+                let squares = &entry[1..];
+                match name {
+                    "csl" => {
+                        if let Some(square) = string_to_coordinate(squares) {
+                            annotations.squares.push(SquareMark { square, color });
+                        }
+                    }
+                    "cal" if squares.len() == 4 && squares.is_ascii() => {
+                        if let (Some(from), Some(to)) = (
+                            squares.get(..2).and_then(string_to_coordinate),
+                            squares.get(2..).and_then(string_to_coordinate),
+                        ) {
+                            annotations.arrows.push(Arrow { from, to, color });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        annotations
+    }
+}
+
+/// Splits the `[%name arguments]` commands out of a PGN comment.
+pub fn comment_commands(comment: &str) -> Vec<(&str, &str)> {
+    let mut commands = Vec::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        let after = &rest[start + 2..];
+        let end = match after.find(']') {
+            Some(end) => end,
+            None => break,
+        };
+        let command = after[..end].trim();
+        let (name, arguments) = match command.find(char::is_whitespace) {
+            Some(split) => (&command[..split], command[split..].trim()),
+            None => (command, ""),
+        };
+        commands.push((name, arguments));
+        rest = &after[end + 1..];
+    }
+    commands
+}
+
+/// Removes all `[%...]` commands from a comment, leaving the free text.
+pub fn strip_commands(comment: &str) -> String {
+    let mut text = String::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        text += &rest[..start];
+        match rest[start..].find(']') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                rest = "";
+                break;
+            }
+        }
+    }
+    text += rest;
+    text.trim().to_string()
+}
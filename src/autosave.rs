@@ -0,0 +1,126 @@
+//! Recovery file holding the unfinished game, written after every move.
+
+use crate::clock::Clock;
+use crate::opponent::Opponent;
+use crate::outcome::team_name;
+use crate::record::GameRecord;
+use chess::game::Team;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Who the saved game was played against. Network games cannot be resumed
+/// without the other player and are not saved.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SavedOpponent {
+    HotSeat,
+    Engine {
+        path: PathBuf,
+        /// Color played by the engine, "white" or "black".
+        color: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SavedClock {
+    /// Time control as given on the command line, e.g. "5+3".
+    pub control: String,
+    pub white_ms: u64,
+    pub black_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Autosave {
+    /// When the file was written, e.g. "2020-10-05 18:31".
+    pub saved_at: String,
+    /// The game so far, including the starting position and comments.
+    pub pgn: String,
+    pub clock: Option<SavedClock>,
+    pub opponent: SavedOpponent,
+}
+
+/// Location of the recovery file, `$XDG_DATA_HOME/oskhen-chess-gui/autosave.json`.
+pub fn autosave_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("oskhen-chess-gui").join("autosave.json"))
+}
+
+impl Autosave {
+    /// Captures the game, or `None` for games that cannot be resumed.
+    pub fn new(record: &GameRecord, clock: Option<&Clock>, opponent: &Opponent) -> Option<Autosave> {
+        let opponent = match opponent {
+            Opponent::HotSeat => SavedOpponent::HotSeat,
+            Opponent::Engine { engine, color, .. } => SavedOpponent::Engine {
+                path: engine.path.clone(),
+                color: team_name(*color).to_string(),
+            },
+            Opponent::Waiting { .. }
+            | Opponent::Network { .. }
+            | Opponent::Spectating { .. }
+            | Opponent::Review
+            | Opponent::Trainer { .. } => return None,
+        };
+        Some(Autosave {
+            saved_at: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            pgn: record.to_pgn(),
+            clock: clock.map(|clock| SavedClock {
+                control: clock.control.to_string(),
+                white_ms: clock.remaining(Team::White).as_millis() as u64,
+                black_ms: clock.remaining(Team::Black).as_millis() as u64,
+            }),
+            opponent,
+        })
+    }
+
+    /// Reads the recovery file if there is one.
+    pub fn load() -> Option<Autosave> {
+        Autosave::load_from(&autosave_path()?)
+    }
+
+    /// Reads the recovery file at `path`. A file that cannot be read is
+    /// ignored, so that a damaged one does not stop the program from starting.
+    pub fn load_from(path: &Path) -> Option<Autosave> {
+        let text = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    /// Writes the recovery file.
+    pub fn save(&self) -> io::Result<()> {
+        let path = autosave_path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no data directory on this system")
+        })?;
+        self.save_to(&path)
+    }
+
+    /// Writes the recovery file to `path`. The file is replaced in one step,
+    /// so a crash while saving leaves the previous version intact.
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temporary, path)
+    }
+
+    /// Restores a clock to the times it showed when the game was saved.
+    pub fn restore_clock(&self) -> Result<Option<Clock>, String> {
+        let saved = match &self.clock {
+            Some(saved) => saved,
+            None => return Ok(None),
+        };
+        let mut clock = Clock::new(saved.control.parse()?);
+        clock.set_remaining(Team::White, Duration::from_millis(saved.white_ms));
+        clock.set_remaining(Team::Black, Duration::from_millis(saved.black_ms));
+        Ok(Some(clock))
+    }
+}
+
+/// Removes the recovery file, e.g. once the game has ended.
+pub fn clear() {
+    if let Some(path) = autosave_path() {
+        let _ = fs::remove_file(path);
+    }
+}
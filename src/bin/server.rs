@@ -0,0 +1,36 @@
+//! Hosts games over WebSocket without a window, see `server`.
+
+use oskhen_chess_gui::server::Server;
+use std::process;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "server",
+    about = "Hosts chess games over WebSocket without a window"
+)]
+struct Options {
+    /// Port to listen on, the one `join` connects to by default
+    #[structopt(long, default_value = "7878")]
+    port: u16,
+
+    /// Address to listen on, e.g. 127.0.0.1 to take connections from this computer only
+    #[structopt(long, default_value = "0.0.0.0")]
+    address: String,
+}
+
+fn main() {
+    let options = Options::from_args();
+    let server = match Server::bind((options.address.as_str(), options.port)) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!(
+                "Could not listen on {}:{}: {}",
+                options.address, options.port, error
+            );
+            process::exit(1);
+        }
+    };
+    println!("Serving games on {}:{}", options.address, options.port);
+    server.run();
+}
@@ -0,0 +1,200 @@
+//! Opening books in the Polyglot `.bin` format.
+//!
+//! A book is a list of 16 byte entries sorted by the Zobrist key of the
+//! position they belong to: key, move, weight and a learning value, all big
+//! endian. Keys are built from the 781 random numbers of the Polyglot
+//! format, listed in `polyglot.txt` in their published order.
+//!
+//! Chess960 books use the same keys, with the castling rights of the rooks
+//! that may still castle, and keep castling as the king taking its own rook.
+
+use crate::chess960;
+use crate::fen::Fen;
+use crate::position::{coordinate_to_string, Coordinate};
+use crate::util;
+use chess::game::{Rank, Team};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const POLYGLOT_TABLE: &str = include_str!("polyglot.txt");
+const CASTLING_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+const ENTRY_SIZE: u64 = 16;
+
+/// The random numbers Polyglot keys are made of.
+pub struct PolyglotKeys {
+    keys: Vec<u64>,
+}
+
+impl PolyglotKeys {
+    /// The numbers compiled into the program, written as hexadecimal literals
+    /// separated by commas.
+    pub fn bundled() -> PolyglotKeys {
+        let keys = POLYGLOT_TABLE
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(|word| u64::from_str_radix(word.trim_start_matches("0x"), 16).ok())
+            .collect();
+        PolyglotKeys { keys }
+    }
+
+    /// Zobrist key of the position described by `fen`, as used in Polyglot books.
+    pub fn key(&self, fen: &Fen) -> u64 {
+        let mut key = 0;
+        for ((file, rank), team, piece) in fen.placement.iter() {
+            let kind = piece_index(*piece) * 2 + if *team == Team::White { 1 } else { 0 };
+            key ^= self.keys[64 * kind + 8 * *rank as usize + *file as usize];
+        }
+        // White short, white long, black short, black long, whichever way
+        // the castling field names the rooks.
+        for rook in chess960::castling_rooks(fen) {
+            let team = if rook.1 == 0 { Team::White } else { Team::Black };
+            let kingside = fen.placement.iter().any(|(square, owner, piece)| {
+                *owner == team && *piece == Rank::King && chess960::is_kingside(*square, rook)
+            });
+            let index = match team {
+                Team::White => 0,
+                Team::Black => 2,
+            } + if kingside { 0 } else { 1 };
+            key ^= self.keys[CASTLING_OFFSET + index];
+        }
+        // The en passant square only counts when a pawn can actually capture on it.
+        if let Some((file, _)) = fen.en_passant {
+            let rank = if fen.player == Team::White { 4 } else { 3 };
+            let capturer = [file - 1, file + 1]
+                .iter()
+                .any(|side| fen.piece_at((*side, rank)) == Some((fen.player, Rank::Pawn)));
+            if capturer {
+                key ^= self.keys[EN_PASSANT_OFFSET + file as usize];
+            }
+        }
+        if fen.player == Team::White {
+            key ^= self.keys[TURN_OFFSET];
+        }
+        key
+    }
+}
+
+/// Index of a piece type in the key table, pawns first and kings last.
+fn piece_index(rank: Rank) -> usize {
+    match rank {
+        Rank::Pawn => 0,
+        Rank::Knight => 1,
+        Rank::Bishop => 2,
+        Rank::Rook => 3,
+        Rank::Queen => 4,
+        Rank::King => 5,
+    }
+}
+
+/// A move suggested by the book.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BookMove {
+    /// The move in UCI notation, castling written as the king's move, or in
+    /// Chess960 as the king taking its own rook.
+    pub uci: String,
+    /// How often the move should be chosen relative to the other book moves.
+    pub weight: u16,
+}
+
+pub struct Book {
+    file: File,
+    entries: u64,
+    keys: PolyglotKeys,
+}
+
+impl Book {
+    pub fn open(path: &Path) -> io::Result<Book> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        if length % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a Polyglot book, the size is not a multiple of 16 bytes",
+            ));
+        }
+        Ok(Book {
+            file,
+            entries: length / ENTRY_SIZE,
+            keys: PolyglotKeys::bundled(),
+        })
+    }
+
+    fn read_entry(&mut self, index: u64) -> io::Result<(u64, u16, u16)> {
+        let mut bytes = [0; ENTRY_SIZE as usize];
+        self.file.seek(SeekFrom::Start(index * ENTRY_SIZE))?;
+        self.file.read_exact(&mut bytes)?;
+        let number = |range: std::ops::Range<usize>| {
+            bytes[range].iter().fold(0u64, |value, byte| value << 8 | *byte as u64)
+        };
+        Ok((number(0..8), number(8..10) as u16, number(10..12) as u16))
+    }
+
+    /// Book moves for the position described by `fen`, most played first.
+    /// With `chess960`, the book is taken to be one for Chess960.
+    pub fn moves(&mut self, fen: &Fen, chess960: bool) -> io::Result<Vec<BookMove>> {
+        let key = self.keys.key(fen);
+        // The entries are sorted by key, find the first one for this position.
+        let (mut low, mut high) = (0, self.entries);
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.read_entry(middle)?.0 < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let mut moves = Vec::new();
+        for index in low..self.entries {
+            let (entry_key, raw, weight) = self.read_entry(index)?;
+            if entry_key != key {
+                break;
+            }
+            if let Some(uci) = decode_move(raw, fen, chess960) {
+                moves.push(BookMove { uci, weight });
+            }
+        }
+        moves.sort_by(|a, b| b.weight.cmp(&a.weight));
+        Ok(moves)
+    }
+}
+
+/// Turns a Polyglot move into UCI notation. Polyglot writes castling as the
+/// king capturing its own rook, e.g. "e1h1" for "e1g1", which is also how
+/// Chess960 castling is written in UCI, so only standard castling is changed.
+pub fn decode_move(raw: u16, fen: &Fen, chess960: bool) -> Option<String> {
+    let square = |bits: u16| -> Coordinate { ((bits & 7) as isize, (bits >> 3 & 7) as isize) };
+    let from = square(raw >> 6);
+    let mut to = square(raw);
+    let promotion = match raw >> 12 & 7 {
+        0 => "",
+        1 => "n",
+        2 => "b",
+        3 => "r",
+        4 => "q",
+        _ => return None,
+    };
+    let king = fen.piece_at(from).map(|(_, rank)| rank) == Some(Rank::King);
+    if !chess960 && king && (to.0 - from.0).abs() > 1 {
+        to.0 = if to.0 > from.0 { 6 } else { 2 };
+    }
+    Some(coordinate_to_string(from) + &coordinate_to_string(to) + promotion)
+}
+
+/// Picks one of `moves` at random, in proportion to their weights.
+pub fn choose(moves: &[BookMove]) -> Option<&BookMove> {
+    let total: u64 = moves.iter().map(|book_move| book_move.weight as u64).sum();
+    if total == 0 {
+        return moves.first();
+    }
+    let mut left = util::random() % total;
+    for book_move in moves {
+        if left < book_move.weight as u64 {
+            return Some(book_move);
+        }
+        left -= book_move.weight as u64;
+    }
+    None
+}
@@ -0,0 +1,114 @@
+//! Chat of network games.
+//!
+//! Lines are kept with the game: as comments on the position they were
+//! written at, so that they are saved with its PGN, or in a log file of their
+//! own when one is given.
+
+use crate::outcome::capitalized;
+use crate::record::GameRecord;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name the players know spectators by.
+pub const SPECTATOR: &str = "spectator";
+/// Longest line sent, in characters.
+const MAX_LENGTH: usize = 200;
+
+pub struct ChatLine {
+    /// "white", "black" or `SPECTATOR`.
+    pub from: String,
+    pub text: String,
+    /// Whether the line was written in this window.
+    pub own: bool,
+}
+
+pub struct Chat {
+    pub lines: Vec<ChatLine>,
+    /// Lines of the other players, or the spectators, are not shown. They are still kept.
+    pub mute_players: bool,
+    pub mute_spectators: bool,
+    log: Option<PathBuf>,
+}
+
+impl Chat {
+    /// A chat kept in the comments of the game, or in `log` when given.
+    pub fn new(log: Option<PathBuf>) -> Chat {
+        Chat {
+            lines: Vec::new(),
+            mute_players: false,
+            mute_spectators: false,
+            log,
+        }
+    }
+
+    /// Adds a line written by `from` at the position `record` ends in.
+    pub fn add(
+        &mut self,
+        from: &str,
+        text: &str,
+        own: bool,
+        record: &mut GameRecord,
+    ) -> Result<(), String> {
+        let text = clean(text);
+        if text.is_empty() {
+            return Ok(());
+        }
+        let kept = match &self.log {
+            Some(path) => append_log(path, from, &text),
+            None => {
+                let notes = record.current_notes();
+                if !notes.comment.is_empty() {
+                    notes.comment.push(' ');
+                }
+                notes.comment += &format!("{}: {}", capitalized(from), text);
+                Ok(())
+            }
+        };
+        self.lines.push(ChatLine {
+            from: from.to_string(),
+            text,
+            own,
+        });
+        kept
+    }
+
+    pub fn is_muted(&self, line: &ChatLine) -> bool {
+        let muted = if line.from == SPECTATOR {
+            self.mute_spectators
+        } else {
+            self.mute_players
+        };
+        muted && !line.own
+    }
+
+    /// The lines to show, oldest first.
+    pub fn visible(&self) -> impl Iterator<Item = &ChatLine> {
+        self.lines.iter().filter(move |line| !self.is_muted(line))
+    }
+}
+
+/// `text` as a single line that fits in a PGN comment.
+pub fn clean(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match c {
+            '{' => '(',
+            '}' => ')',
+            c => c,
+        })
+        .take(MAX_LENGTH)
+        .collect();
+    text.trim().to_string()
+}
+
+fn append_log(path: &Path, from: &str, text: &str) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|error| format!("{}: {}", path.display(), error))?;
+    let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+    writeln!(file, "[{}] {}: {}", time, capitalized(from), text).map_err(|error| error.to_string())
+}
@@ -0,0 +1,259 @@
+//! Chess960 starting positions and castling.
+//!
+//! Positions are numbered 0–959 as in the Scharnagl scheme, 518 being the
+//! standard one. The board of the `chess` crate only knows how to castle from
+//! e1 and e8, so castling is done here: the king is moved onto one of its own
+//! rooks, as in the UCI `UCI_Chess960` convention, and both end up on the
+//! squares they take after castling in standard chess.
+//!
+//! Castling rights are kept as the squares of the rooks that may still
+//! castle. In a FEN they are written as in X-FEN, "KQkq" unless another rook
+//! stands further out on the same side and the file has to be named, or as in
+//! Shredder-FEN, always by the file of the rook, e.g. "HAha". Both are read.
+
+use crate::fen::Fen;
+use crate::position::{in_check, is_attacked, king_position, opponent, piece_at, Coordinate};
+use crate::util;
+use chess::game::{Game, Rank, Team};
+
+pub const POSITION_COUNT: u16 = 960;
+/// Number of the standard starting position.
+pub const STANDARD_POSITION: u16 = 518;
+
+/// Places of the knights among the five squares left once bishops and queen are placed.
+const KNIGHT_PLACES: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// Pieces of the first rank of position `number`, from the a-file to the h-file.
+pub fn back_rank(number: u16) -> [Rank; 8] {
+    let mut rank: [Option<Rank>; 8] = [None; 8];
+    let mut n = number as usize % POSITION_COUNT as usize;
+    rank[n % 4 * 2 + 1] = Some(Rank::Bishop);
+    n /= 4;
+    rank[n % 4 * 2] = Some(Rank::Bishop);
+    n /= 4;
+    let empty = |rank: &[Option<Rank>; 8]| -> Vec<usize> {
+        (0..8).filter(|file| rank[*file].is_none()).collect()
+    };
+    let queen = empty(&rank)[n % 6];
+    rank[queen] = Some(Rank::Queen);
+    n /= 6;
+    let (first, second) = KNIGHT_PLACES[n];
+    let free = empty(&rank);
+    rank[free[first]] = Some(Rank::Knight);
+    rank[free[second]] = Some(Rank::Knight);
+    for (file, piece) in empty(&rank)
+        .into_iter()
+        .zip([Rank::Rook, Rank::King, Rank::Rook].iter())
+    {
+        rank[file] = Some(*piece);
+    }
+
+    let mut pieces = [Rank::Pawn; 8];
+    for (file, piece) in rank.iter().enumerate() {
+        pieces[file] = piece.unwrap();
+    }
+    pieces
+}
+
+/// The starting position with number `number`, white to move with all castling rights.
+pub fn start_position(number: u16) -> Fen {
+    let mut fen: Fen = "8/8/8/8/8/8/8/8 w KQkq - 0 1".parse().unwrap();
+    for (file, piece) in back_rank(number).iter().enumerate() {
+        let file = file as isize;
+        fen.placement.push(((file, 0), Team::White, *piece));
+        fen.placement.push(((file, 1), Team::White, Rank::Pawn));
+        fen.placement.push(((file, 6), Team::Black, Rank::Pawn));
+        fen.placement.push(((file, 7), Team::Black, *piece));
+    }
+    fen
+}
+
+/// One of the 960 positions, picked at random.
+pub fn random_position() -> u16 {
+    (util::random() % POSITION_COUNT as u64) as u16
+}
+
+fn home_rank(team: Team) -> isize {
+    match team {
+        Team::White => 0,
+        Team::Black => 7,
+    }
+}
+
+fn team_of_rank(rank: isize) -> Team {
+    if rank == 0 {
+        Team::White
+    } else {
+        Team::Black
+    }
+}
+
+/// King of `team` if it stands on its first rank, where it may castle from.
+fn home_king(placement: &[(Coordinate, Team, Rank)], team: Team) -> Option<Coordinate> {
+    placement
+        .iter()
+        .find(|(square, owner, rank)| {
+            *owner == team && *rank == Rank::King && square.1 == home_rank(team)
+        })
+        .map(|(square, _, _)| *square)
+}
+
+fn is_rook(placement: &[(Coordinate, Team, Rank)], square: Coordinate, team: Team) -> bool {
+    placement.contains(&(square, team, Rank::Rook))
+}
+
+/// Squares of the rooks that may castle according to the castling field of `fen`.
+pub fn castling_rooks(fen: &Fen) -> Vec<Coordinate> {
+    let mut rooks = Vec::new();
+    for c in fen.castling.chars() {
+        let team = if c.is_ascii_uppercase() {
+            Team::White
+        } else {
+            Team::Black
+        };
+        let rank = home_rank(team);
+        let king = match home_king(&fen.placement, team) {
+            Some(king) => king,
+            None => continue,
+        };
+        let own_rook = |file: &isize| is_rook(&fen.placement, (*file, rank), team);
+        let file = match c.to_ascii_uppercase() {
+            'K' => (king.0 + 1..8).rev().find(own_rook),
+            'Q' => (0..king.0).find(own_rook),
+            letter @ 'A'..='H' => Some(letter as isize - 'A' as isize).filter(own_rook),
+            _ => None,
+        };
+        if let Some(file) = file {
+            if !rooks.contains(&(file, rank)) {
+                rooks.push((file, rank));
+            }
+        }
+    }
+    rooks
+}
+
+/// Castling field for `rooks` in the position `placement`, in X-FEN or, with
+/// `shredder`, Shredder-FEN.
+pub fn castling_field(
+    placement: &[(Coordinate, Team, Rank)],
+    rooks: &[Coordinate],
+    shredder: bool,
+) -> String {
+    let mut field = String::new();
+    for team in [Team::White, Team::Black].iter() {
+        let king = match home_king(placement, *team) {
+            Some(king) => king,
+            None => continue,
+        };
+        let mut own: Vec<Coordinate> = rooks
+            .iter()
+            .copied()
+            .filter(|rook| team_of_rank(rook.1) == *team)
+            .collect();
+        // Kingside first, as in "KQ".
+        own.sort_by_key(|rook| -rook.0);
+        for rook in own {
+            let kingside = is_kingside(king, rook);
+            let outermost = if kingside {
+                (rook.0 + 1..8).all(|file| !is_rook(placement, (file, rook.1), *team))
+            } else {
+                (0..rook.0).all(|file| !is_rook(placement, (file, rook.1), *team))
+            };
+            let letter = match (shredder || !outermost, kingside) {
+                (true, _) => (b'A' + rook.0 as u8) as char,
+                (false, true) => 'K',
+                (false, false) => 'Q',
+            };
+            field.push(match team {
+                Team::White => letter,
+                Team::Black => letter.to_ascii_lowercase(),
+            });
+        }
+    }
+    if field.is_empty() {
+        field.push('-');
+    }
+    field
+}
+
+/// Squares the king and the rook end up on when the king on `king` castles with the rook on `rook`.
+fn castled_squares(king: Coordinate, rook: Coordinate) -> (Coordinate, Coordinate) {
+    if is_kingside(king, rook) {
+        ((6, king.1), (5, king.1))
+    } else {
+        ((2, king.1), (3, king.1))
+    }
+}
+
+/// Castling moves of the player in turn as pairs of king and rook square,
+/// given the rooks that still have the right to castle.
+pub fn castling_moves(game: &Game, rooks: &[Coordinate]) -> Vec<(Coordinate, Coordinate)> {
+    let player = game.player;
+    let king = match king_position(game, player) {
+        Some(king) if king.1 == home_rank(player) => king,
+        _ => return Vec::new(),
+    };
+    if in_check(game, player) {
+        return Vec::new();
+    }
+    rooks
+        .iter()
+        .filter(|rook| rook.1 == king.1 && piece_at(game, **rook) == Some((player, Rank::Rook)))
+        .filter(|rook| {
+            let (king_to, rook_to) = castled_squares(king, **rook);
+            let low = king.0.min(rook.0).min(king_to.0).min(rook_to.0);
+            let high = king.0.max(rook.0).max(king_to.0).max(rook_to.0);
+            let path_clear = (low..=high).map(|file| (file, king.1)).all(|square| {
+                square == king || square == **rook || piece_at(game, square).is_none()
+            });
+            let (from, to) = (king.0.min(king_to.0), king.0.max(king_to.0));
+            path_clear
+                && (from..=to).all(|file| !is_attacked(game, (file, king.1), opponent(player)))
+        })
+        .map(|rook| (king, *rook))
+        .collect()
+}
+
+/// Whether castling with the rook on `rook` is castling short, written "O-O".
+pub fn is_kingside(king: Coordinate, rook: Coordinate) -> bool {
+    rook.0 > king.0
+}
+
+/// Castles the king on `king` with the rook on `rook` and passes the turn.
+/// The move must be one of `castling_moves`.
+pub fn castle(game: &mut Game, king: Coordinate, rook: Coordinate) {
+    let (king_to, rook_to) = castled_squares(king, rook);
+    let mut king_piece = None;
+    let mut rook_piece = None;
+    for square in game.matrix.iter_mut().flat_map(|column| column.iter_mut()) {
+        if square.coordinate == king {
+            king_piece = square.piece.take();
+        } else if square.coordinate == rook {
+            rook_piece = square.piece.take();
+        }
+    }
+    for square in game.matrix.iter_mut().flat_map(|column| column.iter_mut()) {
+        if square.coordinate == king_to {
+            square.piece = king_piece;
+        } else if square.coordinate == rook_to {
+            square.piece = rook_piece;
+        }
+    }
+    game.player = opponent(game.player);
+}
+
+/// Number of the starting position `fen` if it is one of the 960.
+pub fn position_number(fen: &Fen) -> Option<u16> {
+    (0..POSITION_COUNT).find(|number| start_position(*number).board_field() == fen.board_field())
+}
@@ -0,0 +1,342 @@
+//! Command line options.
+
+use crate::chess960::{self, POSITION_COUNT};
+use crate::clock::TimeControl;
+use crate::network::DEFAULT_PORT;
+use crate::theme::Theme;
+use crate::settings::Settings;
+use crate::variant::Variant;
+use chess::game::Team;
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(name = "oskhen-chess-gui", about = "Chess GUI on ahanifi's engine")]
+pub struct Options {
+    #[structopt(subcommand)]
+    pub mode: Option<Mode>,
+
+    /// Start from this position instead of the standard one
+    #[structopt(long, global = true)]
+    pub fen: Option<String>,
+
+    /// Play Chess960 from position N (0-959), or with --fen from that position.
+    /// Without either, the position is picked on a selection screen, or at
+    /// random for a hosted game
+    #[structopt(long, global = true, value_name = "N")]
+    pub chess960: Option<Option<u16>>,
+
+    /// Rules to play by: standard, chess960, kingofthehill, threecheck, horde
+    /// or atomic
+    #[structopt(long, global = true)]
+    pub variant: Option<Variant>,
+
+    /// Board colors: gray, brown, green or blue [default: from the settings]
+    #[structopt(long, global = true)]
+    pub theme: Option<Theme>,
+
+    /// Time control as minutes+increment in seconds, e.g. 5+3
+    #[structopt(long, global = true)]
+    pub time: Option<TimeControl>,
+
+    /// Window size in pixels, e.g. 800x600
+    #[structopt(long, global = true)]
+    pub window_size: Option<WindowSize>,
+
+    /// Append the chat of network games to this file instead of keeping it
+    /// in the comments of the game
+    #[structopt(long, global = true, parse(from_os_str), value_name = "file")]
+    pub chat_log: Option<PathBuf>,
+
+    /// PGN file the game is saved to with Ctrl+S and loaded from with Ctrl+O
+    #[structopt(long, global = true, parse(from_os_str), default_value = "./game.pgn")]
+    pub game_file: PathBuf,
+
+    /// Directory holding the piece images and icon
+    #[structopt(long, global = true, parse(from_os_str), default_value = "./custom_resources")]
+    pub resources: PathBuf,
+}
+
+#[derive(StructOpt)]
+pub enum Mode {
+    /// Two players sharing this window (the default)
+    Play,
+    /// Play against a UCI engine
+    VsEngine {
+        /// Path to the engine executable [default: the first engine in the settings]
+        #[structopt(long, parse(from_os_str))]
+        engine: Option<PathBuf>,
+        /// Your color, white or black
+        #[structopt(long, default_value = "white")]
+        color: Side,
+    },
+    /// Wait for an opponent to join over the network
+    Host {
+        #[structopt(long, default_value = "7878")]
+        port: u16,
+        /// Your color, white or black
+        #[structopt(long, default_value = "white")]
+        color: Side,
+        /// Most spectators allowed to watch at once
+        #[structopt(long, default_value = "0")]
+        spectators: usize,
+        /// Seconds to wait for a dropped opponent to reconnect before the game is adjudicated
+        #[structopt(long, default_value = "60")]
+        reconnect_timeout: u64,
+        /// Keep the clocks running while the connection is down instead of pausing them
+        #[structopt(long)]
+        run_clock: bool,
+    },
+    /// Join a game hosted on another computer
+    Join {
+        /// Address of the host, e.g. 192.168.0.2 or 192.168.0.2:7878
+        address: String,
+    },
+    /// Watch a game hosted on another computer without playing
+    Watch {
+        /// Address of the host, e.g. 192.168.0.2 or 192.168.0.2:7878
+        address: String,
+    },
+    /// Step through a game stored as PGN
+    Review {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Solve tactics puzzles from a CSV (Lichess format) or EPD file
+    Puzzles {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Only puzzles with one of these themes, e.g. fork,pin
+        #[structopt(long, use_delimiter = true)]
+        themes: Vec<String>,
+    },
+    /// Drill an opening repertoire stored as PGN, variations included
+    Repertoire {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// The side whose moves you learn, white or black
+        #[structopt(long, default_value = "white")]
+        color: Side,
+    },
+    /// Win or hold classic endgame positions against an engine or a built-in opponent
+    Endgames {
+        /// Drill to start with: kqk, krk, kbnk, lucena or philidor
+        #[structopt(long, default_value = "kqk")]
+        drill: String,
+        /// Engine playing the other side [default: the first engine in the settings]
+        #[structopt(long, parse(from_os_str))]
+        engine: Option<PathBuf>,
+        /// Play against the built-in opponent even when there is an engine
+        #[structopt(long)]
+        builtin: bool,
+    },
+    /// Play a match between two UCI engines, in the window or in the terminal
+    Match {
+        /// Engine executables, the first one playing white in the first game
+        #[structopt(parse(from_os_str))]
+        first: PathBuf,
+        #[structopt(parse(from_os_str))]
+        second: PathBuf,
+        /// Number of games to play
+        #[structopt(long, default_value = "10")]
+        games: usize,
+        /// Positions (EPD) or games (PGN) to start from, each played with both colors
+        #[structopt(long, parse(from_os_str))]
+        openings: Option<PathBuf>,
+        /// File the games are appended to
+        #[structopt(long, parse(from_os_str), default_value = "match.pgn")]
+        pgn: PathBuf,
+        /// Play in the terminal without opening a window
+        #[structopt(long)]
+        headless: bool,
+        #[structopt(flatten)]
+        adjudication: AdjudicationOptions,
+    },
+    /// Run a round-robin or Swiss tournament between people and engines
+    Tournament {
+        /// Tournament file, created from the players given when it does not exist
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Name of a human player, repeated for each one
+        #[structopt(long = "player")]
+        players: Vec<String>,
+        /// Engine player as path or name=path, repeated for each one
+        #[structopt(long = "engine")]
+        engines: Vec<String>,
+        /// Pair a Swiss tournament of this many rounds instead of a round-robin
+        #[structopt(long, value_name = "ROUNDS")]
+        swiss: Option<usize>,
+        /// Print the standings and exit
+        #[structopt(long)]
+        standings: bool,
+        /// Write the standings and results to this file and exit
+        #[structopt(long, parse(from_os_str))]
+        export: Option<PathBuf>,
+        #[structopt(flatten)]
+        adjudication: AdjudicationOptions,
+    },
+}
+
+/// When engine games are decided without being played out. A tablebase result
+/// from the tables in the settings always decides the game.
+#[derive(StructOpt, Clone, Copy, PartialEq, Debug)]
+pub struct AdjudicationOptions {
+    /// Score in centipawns at which the side behind is adjudicated lost
+    #[structopt(long, default_value = "1000")]
+    pub resign_score: i32,
+    /// Moves in a row both engines have to see the resign score for
+    #[structopt(long, default_value = "3")]
+    pub resign_moves: u32,
+    /// Score in centipawns within which the game is adjudicated drawn
+    #[structopt(long, default_value = "10")]
+    pub draw_score: i32,
+    /// Moves in a row both engines have to see the draw score for
+    #[structopt(long, default_value = "8")]
+    pub draw_moves: u32,
+    /// Move after which games may be adjudicated drawn
+    #[structopt(long, default_value = "40")]
+    pub draw_after: usize,
+    /// Only end games by the rules and the tablebases
+    #[structopt(long)]
+    pub no_adjudication: bool,
+}
+
+/// Color chosen on the command line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Side {
+    White,
+    Black,
+}
+
+impl Side {
+    pub fn team(self) -> Team {
+        match self {
+            Side::White => Team::White,
+            Side::Black => Team::Black,
+        }
+    }
+}
+
+impl FromStr for Side {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Side, String> {
+        match text.to_lowercase().as_str() {
+            "white" | "w" => Ok(Side::White),
+            "black" | "b" => Ok(Side::Black),
+            _ => Err(format!("Unknown color '{}', expected white or black", text)),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(into = "String")]
+pub struct WindowSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl FromStr for WindowSize {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<WindowSize, String> {
+        let invalid = || format!("Invalid window size '{}', expected e.g. 800x600", text);
+        let mut parts = text.splitn(2, 'x');
+        let width: f32 = parts.next().unwrap().parse().map_err(|_| invalid())?;
+        let height: f32 = parts.next().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+        if width < 1.0 || height < 1.0 {
+            return Err(invalid());
+        }
+        Ok(WindowSize { width, height })
+    }
+}
+
+impl From<WindowSize> for String {
+    fn from(size: WindowSize) -> String {
+        size.to_string()
+    }
+}
+
+impl fmt::Display for WindowSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl Options {
+    /// Fills in what was not given on the command line from the user's settings.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.theme = self.theme.or(Some(settings.theme));
+        self.time = self.time.or(settings.time_control);
+        self.window_size = self.window_size.or(settings.window_size);
+        match &mut self.mode {
+            Some(Mode::VsEngine { engine, .. })
+            | Some(Mode::Endgames {
+                engine,
+                builtin: false,
+                ..
+            }) if engine.is_none() => *engine = settings.engines.first().cloned(),
+            _ => {}
+        }
+    }
+
+    /// Rules chosen on the command line.
+    pub fn variant(&self) -> Variant {
+        match (self.chess960, self.variant) {
+            (Some(_), _) => Variant::Chess960,
+            (None, Some(variant)) => variant,
+            (None, None) => Variant::Standard,
+        }
+    }
+
+    /// Sets `fen` to the starting position of the variant asked for. A
+    /// Chess960 game without a position number gets a random one, and `true`
+    /// is returned when the player is to pick one on the selection screen instead.
+    pub fn choose_start_position(&mut self) -> Result<bool, String> {
+        if self.variant == Some(Variant::Chess960) && self.chess960.is_none() {
+            self.chess960 = Some(None);
+        }
+        match &self.mode {
+            Some(Mode::Join { .. })
+            | Some(Mode::Watch { .. })
+            | Some(Mode::Review { .. })
+            | Some(Mode::Puzzles { .. })
+            | Some(Mode::Repertoire { .. })
+            | Some(Mode::Endgames { .. })
+            | Some(Mode::Match { .. })
+            | Some(Mode::Tournament { .. }) => return Ok(false),
+            _ if self.fen.is_some() => return Ok(false),
+            _ => {}
+        }
+        let (number, select) = match (self.chess960, &self.mode) {
+            (None, _) => {
+                self.fen = self.variant().start_position().map(|fen| fen.to_string());
+                return Ok(false);
+            }
+            (Some(Some(number)), _) => (number, false),
+            (Some(None), Some(Mode::Host { .. })) => (chess960::random_position(), false),
+            (Some(None), _) => (chess960::random_position(), true),
+        };
+        if number >= POSITION_COUNT {
+            return Err(format!(
+                "There is no Chess960 position {}, they are numbered 0 to {}",
+                number,
+                POSITION_COUNT - 1
+            ));
+        }
+        self.fen = Some(chess960::start_position(number).to_string());
+        Ok(select)
+    }
+}
+
+/// Adds the default port to an address given without one.
+pub fn with_default_port(address: &str) -> String {
+    if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    }
+}
@@ -0,0 +1,163 @@
+//! Chess clocks with Fischer increment.
+
+use crate::position::opponent;
+use chess::game::Team;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Base time and increment, written as "minutes+seconds", e.g. "5+3".
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(into = "String")]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl TimeControl {
+    /// Value of the PGN `TimeControl` tag, e.g. "300+3".
+    pub fn pgn_tag(&self) -> String {
+        format!("{}+{}", self.base.as_secs(), self.increment.as_secs())
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<TimeControl, String> {
+        let mut parts = text.splitn(2, '+');
+        let minutes: f64 = parts
+            .next()
+            .unwrap()
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid base time in '{}', expected e.g. 5+3", text))?;
+        let seconds: f64 = match parts.next() {
+            Some(increment) => increment
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid increment in '{}', expected e.g. 5+3", text))?,
+            None => 0.0,
+        };
+        if minutes <= 0.0 || seconds < 0.0 {
+            return Err(format!("Time control '{}' must have a positive base time", text));
+        }
+        Ok(TimeControl {
+            base: Duration::from_secs_f64(minutes * 60.0),
+            increment: Duration::from_secs_f64(seconds),
+        })
+    }
+}
+
+impl From<TimeControl> for String {
+    fn from(time: TimeControl) -> String {
+        time.to_string()
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let minutes = self.base.as_secs_f64() / 60.0;
+        write!(f, "{}+{}", minutes, self.increment.as_secs())
+    }
+}
+
+pub struct Clock {
+    pub control: TimeControl,
+    // Time left for white and black, not counting the running period.
+    remaining: [Duration; 2],
+    running: Option<Team>,
+    since: Instant,
+}
+
+fn index(team: Team) -> usize {
+    match team {
+        Team::White => 0,
+        Team::Black => 1,
+    }
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control,
+            remaining: [control.base, control.base],
+            running: None,
+            since: Instant::now(),
+        }
+    }
+
+    pub fn remaining(&self, team: Team) -> Duration {
+        let left = self.remaining[index(team)];
+        if self.running == Some(team) {
+            left.checked_sub(self.since.elapsed()).unwrap_or_default()
+        } else {
+            left
+        }
+    }
+
+    pub fn set_remaining(&mut self, team: Team, remaining: Duration) {
+        self.remaining[index(team)] = remaining;
+        if self.running == Some(team) {
+            self.since = Instant::now();
+        }
+    }
+
+    pub fn running(&self) -> Option<Team> {
+        self.running
+    }
+
+    /// Starts counting down the time of `team`.
+    pub fn start(&mut self, team: Team) {
+        self.stop();
+        self.running = Some(team);
+        self.since = Instant::now();
+    }
+
+    /// Stops the clock, keeping the time used so far.
+    pub fn stop(&mut self) {
+        if let Some(team) = self.running {
+            self.remaining[index(team)] = self.remaining(team);
+            self.running = None;
+        }
+    }
+
+    /// `team` has finished its move: adds the increment and starts the opponent's time.
+    pub fn press(&mut self, team: Team) {
+        if self.running == Some(team) {
+            self.stop();
+        }
+        self.remaining[index(team)] += self.control.increment;
+        self.start(opponent(team));
+    }
+
+    /// Milliseconds left for white and black, as sent over the network.
+    pub fn remaining_millis(&self) -> [u64; 2] {
+        let millis = |team| self.remaining(team).as_millis() as u64;
+        [millis(Team::White), millis(Team::Black)]
+    }
+
+    pub fn set_remaining_millis(&mut self, remaining: [u64; 2]) {
+        self.set_remaining(Team::White, Duration::from_millis(remaining[0]));
+        self.set_remaining(Team::Black, Duration::from_millis(remaining[1]));
+    }
+
+    /// The team whose time has run out, if any.
+    pub fn flagged(&self) -> Option<Team> {
+        match self.running {
+            Some(team) if self.remaining(team) == Duration::from_secs(0) => Some(team),
+            _ => None,
+        }
+    }
+}
+
+/// Formats a clock reading as "m:ss", with tenths of a second below ten seconds.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 10 {
+        format!("0:0{}.{}", seconds, duration.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,364 @@
+//! Local store of finished games.
+//!
+//! Games are appended to a PGN archive that is never rewritten. Next to it an
+//! index with one JSON line per game keeps the archive offset and the fields
+//! searched on, so the archive only has to be read for the selected game. A
+//! missing or damaged index is rebuilt from the archive.
+
+use crate::fen::Fen;
+use crate::record::GameRecord;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Number of opening moves, in half moves, shown for games without an opening name.
+const OPENING_PLIES: usize = 6;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct GameEntry {
+    /// Byte range of the game in the archive.
+    pub offset: u64,
+    pub length: u64,
+    pub date: String,
+    pub white: String,
+    pub black: String,
+    pub result: String,
+    pub opening: String,
+    /// Piece placement of every position reached, starting position included.
+    pub positions: Vec<String>,
+    /// Whether the game could not be read. Such games are kept in the index
+    /// so that it still covers the whole archive, but are never found.
+    #[serde(default)]
+    pub skipped: bool,
+}
+
+impl GameEntry {
+    /// Describes `record`, which is stored at `offset` with the given length.
+    fn new(record: &GameRecord, offset: u64, length: u64) -> Result<GameEntry, String> {
+        let header = |key: &str| record.header(key).unwrap_or("?").to_string();
+        let opening = match (record.header("ECO"), record.header("Opening")) {
+            (Some(eco), Some(name)) => format!("{} {}", eco, name),
+            (None, Some(name)) => name.to_string(),
+            (Some(eco), None) => eco.to_string(),
+            (None, None) => record
+                .san
+                .iter()
+                .take(OPENING_PLIES)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        Ok(GameEntry {
+            offset,
+            length,
+            date: header("Date"),
+            white: header("White"),
+            black: header("Black"),
+            result: record.result.clone(),
+            opening,
+            positions: positions(record)?,
+            skipped: false,
+        })
+    }
+
+    /// Stands in for the game stored at `offset` that could not be read.
+    fn skipped(offset: u64, length: u64) -> GameEntry {
+        GameEntry {
+            offset,
+            length,
+            date: String::new(),
+            white: String::new(),
+            black: String::new(),
+            result: String::new(),
+            opening: String::new(),
+            positions: Vec::new(),
+            skipped: true,
+        }
+    }
+
+    /// One line for the browser, e.g. "2020.10.05  Alice - Bob  1-0  Italian Game".
+    pub fn summary(&self) -> String {
+        format!(
+            "{}  {} - {}  {}  {}",
+            self.date, self.white, self.black, self.result, self.opening
+        )
+    }
+}
+
+/// Piece placement after every move of `record`.
+fn positions(record: &GameRecord) -> Result<Vec<String>, String> {
+    let mut game = record.start_game();
+    let mut scratch = record.restarted();
+    let mut positions = vec![Fen::from_game(&game).board_field()];
+    for recorded in record.moves.iter() {
+        scratch.play_move(&mut game, *recorded)?;
+        positions.push(Fen::from_game(&game).board_field());
+    }
+    Ok(positions)
+}
+
+/// What to search for. Empty fields match every game; text is matched
+/// case-insensitively as a substring.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Query {
+    pub date: String,
+    /// Name of either player.
+    pub player: String,
+    pub result: String,
+    pub opening: String,
+    /// Part of a FEN piece placement, e.g. "4P3/8/PPPP1PPP".
+    pub position: String,
+}
+
+impl Query {
+    pub fn matches(&self, entry: &GameEntry) -> bool {
+        let contains = |text: &str, part: &str| text.to_lowercase().contains(&part.trim().to_lowercase());
+        !entry.skipped
+            && contains(&entry.date, &self.date)
+            && (contains(&entry.white, &self.player) || contains(&entry.black, &self.player))
+            && contains(&entry.result, &self.result)
+            && contains(&entry.opening, &self.opening)
+            && (self.position.trim().is_empty()
+                || entry
+                    .positions
+                    .iter()
+                    .any(|position| position.contains(self.position.trim())))
+    }
+}
+
+pub struct Database {
+    archive: PathBuf,
+    index: PathBuf,
+    pub entries: Vec<GameEntry>,
+}
+
+impl Database {
+    /// Opens the database in `$XDG_DATA_HOME/oskhen-chess-gui`.
+    pub fn open_default() -> io::Result<Database> {
+        let dir = dirs::data_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory on this system"))?
+            .join("oskhen-chess-gui");
+        Database::open(dir)
+    }
+
+    pub fn open(dir: PathBuf) -> io::Result<Database> {
+        fs::create_dir_all(&dir)?;
+        let mut database = Database {
+            archive: dir.join("games.pgn"),
+            index: dir.join("games.index"),
+            entries: Vec::new(),
+        };
+        let archive_length = fs::metadata(&database.archive).map(|data| data.len()).unwrap_or(0);
+        match database.read_index() {
+            Some(entries)
+                if entries.last().map_or(0, |entry| entry.offset + entry.length) == archive_length =>
+            {
+                database.entries = entries;
+            }
+            _ => database.rebuild_index()?,
+        }
+        Ok(database)
+    }
+
+    fn read_index(&self) -> Option<Vec<GameEntry>> {
+        let text = fs::read_to_string(&self.index).ok()?;
+        text.lines().map(|line| serde_json::from_str(line).ok()).collect()
+    }
+
+    /// Reads every game of the archive and writes a fresh index.
+    fn rebuild_index(&mut self) -> io::Result<()> {
+        let text = match fs::read_to_string(&self.archive) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+        self.entries = split_games(&text)
+            .into_iter()
+            .map(|(offset, pgn)| {
+                let (offset, length) = (offset as u64, pgn.len() as u64);
+                GameRecord::from_pgn(pgn)
+                    .and_then(|(record, _)| GameEntry::new(&record, offset, length))
+                    .unwrap_or_else(|_| GameEntry::skipped(offset, length))
+            })
+            .collect();
+
+        let mut index = String::new();
+        for entry in self.entries.iter() {
+            index += &serde_json::to_string(entry)?;
+            index.push('\n');
+        }
+        fs::write(&self.index, index)
+    }
+
+    /// Appends a finished game to the archive and the index.
+    pub fn add(&mut self, record: &GameRecord) -> io::Result<()> {
+        let pgn = format!("{}\n", record.to_pgn());
+        let mut archive = OpenOptions::new().create(true).append(true).open(&self.archive)?;
+        let offset = archive.seek(SeekFrom::End(0))?;
+        let entry = GameEntry::new(record, offset, pgn.len() as u64)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        archive.write_all(pgn.as_bytes())?;
+
+        let mut index = OpenOptions::new().create(true).append(true).open(&self.index)?;
+        writeln!(index, "{}", serde_json::to_string(&entry)?)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Indices of the matching entries, most recent game first.
+    pub fn search(&self, query: &Query) -> Vec<usize> {
+        (0..self.entries.len())
+            .rev()
+            .filter(|index| query.matches(&self.entries[*index]))
+            .collect()
+    }
+
+    /// Reads the PGN of a stored game.
+    pub fn pgn(&self, entry: &GameEntry) -> io::Result<String> {
+        let mut archive = File::open(&self.archive)?;
+        archive.seek(SeekFrom::Start(entry.offset))?;
+        let mut pgn = String::new();
+        archive.take(entry.length).read_to_string(&mut pgn)?;
+        Ok(pgn)
+    }
+}
+
+/// Splits an archive into its games, each with its byte offset. A game starts
+/// at a tag line that follows the movetext of the previous one.
+pub fn split_games(text: &str) -> Vec<(usize, &str)> {
+    let mut starts = Vec::new();
+    let mut in_tags = false;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let is_tag = line.trim_start().starts_with('[');
+        if is_tag && !in_tags {
+            starts.push(offset);
+        }
+        if !line.trim().is_empty() {
+            in_tags = is_tag;
+        }
+        offset += line.len();
+    }
+    let mut games = Vec::new();
+    for (index, start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).copied().unwrap_or_else(|| text.len());
+        games.push((*start, &text[*start..end]));
+    }
+    games
+}
+
+/// Rows of the browser screen: the search fields followed by the results.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchField {
+    Date,
+    Player,
+    Result,
+    Opening,
+    Position,
+}
+
+impl SearchField {
+    pub const ALL: [SearchField; 5] = [
+        SearchField::Date,
+        SearchField::Player,
+        SearchField::Result,
+        SearchField::Opening,
+        SearchField::Position,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SearchField::Date => "Date",
+            SearchField::Player => "Player",
+            SearchField::Result => "Result",
+            SearchField::Opening => "Opening",
+            SearchField::Position => "Position (FEN)",
+        }
+    }
+
+    pub fn text(self, query: &Query) -> &str {
+        match self {
+            SearchField::Date => &query.date,
+            SearchField::Player => &query.player,
+            SearchField::Result => &query.result,
+            SearchField::Opening => &query.opening,
+            SearchField::Position => &query.position,
+        }
+    }
+
+    fn text_mut(self, query: &mut Query) -> &mut String {
+        match self {
+            SearchField::Date => &mut query.date,
+            SearchField::Player => &mut query.player,
+            SearchField::Result => &mut query.result,
+            SearchField::Opening => &mut query.opening,
+            SearchField::Position => &mut query.position,
+        }
+    }
+}
+
+/// State of the game browser: the query, its results and the selected row.
+#[derive(Default)]
+pub struct Browser {
+    pub query: Query,
+    pub results: Vec<usize>,
+    /// Search fields come first, then the results.
+    pub selected: usize,
+    /// First result shown, for scrolling through long lists.
+    pub scroll: usize,
+}
+
+impl Browser {
+    pub fn refresh(&mut self, database: &Database) {
+        self.results = database.search(&self.query);
+        let rows = SearchField::ALL.len() + self.results.len();
+        self.selected = self.selected.min(rows - 1);
+        self.scroll = self.scroll.min(self.results.len().saturating_sub(1));
+    }
+
+    pub fn selected_field(&self) -> Option<SearchField> {
+        SearchField::ALL.get(self.selected).copied()
+    }
+
+    /// Index into the database entries of the selected result.
+    pub fn selected_entry(&self) -> Option<usize> {
+        let row = self.selected.checked_sub(SearchField::ALL.len())?;
+        self.results.get(row).copied()
+    }
+
+    /// Moves the selection, keeping the selected result among the `visible` shown.
+    pub fn move_selection(&mut self, down: bool, visible: usize) {
+        let rows = SearchField::ALL.len() + self.results.len();
+        self.selected = if down {
+            (self.selected + 1).min(rows - 1)
+        } else {
+            self.selected.saturating_sub(1)
+        };
+        if let Some(row) = self.selected.checked_sub(SearchField::ALL.len()) {
+            if row < self.scroll {
+                self.scroll = row;
+            } else if row >= self.scroll + visible {
+                self.scroll = row + 1 - visible;
+            }
+        }
+    }
+
+    /// Types into the selected search field, returning whether the query changed.
+    pub fn type_char(&mut self, c: char) -> bool {
+        match self.selected_field() {
+            Some(field) if !c.is_control() => {
+                field.text_mut(&mut self.query).push(c);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn backspace(&mut self) -> bool {
+        match self.selected_field() {
+            Some(field) => field.text_mut(&mut self.query).pop().is_some(),
+            None => false,
+        }
+    }
+}
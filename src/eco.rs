@@ -0,0 +1,91 @@
+//! Opening names from the Encyclopaedia of Chess Openings classification.
+//!
+//! The table in `eco.tsv` lists a code, a name and the moves of each
+//! opening. Openings are recognised by the position those moves lead to, so
+//! a game reaching the position in another move order still gets the name.
+
+use crate::fen::Fen;
+use crate::record::GameRecord;
+use crate::variant::Variant;
+use chess::game::Team;
+use std::collections::HashMap;
+use std::fmt;
+
+const ECO_TABLE: &str = include_str!("eco.tsv");
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Opening {
+    /// ECO code, e.g. "C50".
+    pub code: String,
+    /// Name of the opening, with the variation after a colon, e.g. "Italian Game: Giuoco Piano".
+    pub name: String,
+}
+
+impl fmt::Display for Opening {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.name)
+    }
+}
+
+pub struct EcoTable {
+    openings: HashMap<String, Opening>,
+}
+
+impl EcoTable {
+    /// The table compiled into the program. Lines that cannot be read are skipped.
+    pub fn bundled() -> EcoTable {
+        let mut openings = HashMap::new();
+        for line in ECO_TABLE.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if let [code, name, moves] = fields[..] {
+                if let Ok((record, game)) = GameRecord::from_pgn(moves) {
+                    let opening = Opening {
+                        code: code.to_string(),
+                        name: name.to_string(),
+                    };
+                    openings.insert(position_key(&record.fen(&game)), opening);
+                }
+            }
+        }
+        EcoTable { openings }
+    }
+
+    /// The opening of the first `ply` moves of `record`: the one named for
+    /// the latest position reached that is in the table.
+    pub fn classify(&self, record: &GameRecord, ply: usize) -> Option<&Opening> {
+        let mut game = record.start_game();
+        let mut scratch = record.restarted();
+        let mut found = self.openings.get(&position_key(&scratch.fen(&game)));
+        for recorded in record.moves.iter().take(ply) {
+            if scratch.play_move(&mut game, *recorded).is_err() {
+                break;
+            }
+            if let Some(opening) = self.openings.get(&position_key(&scratch.fen(&game))) {
+                found = Some(opening);
+            }
+        }
+        found
+    }
+
+    /// Sets the `ECO` and `Opening` tags of `record` to the opening of its
+    /// moves so far. Only standard chess has ECO codes.
+    pub fn tag(&self, record: &mut GameRecord) {
+        if record.variant != Variant::Standard {
+            return;
+        }
+        if let Some(opening) = self.classify(record, record.ply()).cloned() {
+            record.set_header("ECO", &opening.code);
+            record.set_header("Opening", &opening.name);
+        }
+    }
+}
+
+/// Pieces, side to move and castling rights; move counters and en passant
+/// squares do not tell openings apart.
+fn position_key(fen: &Fen) -> String {
+    let player = match fen.player {
+        Team::White => "w",
+        Team::Black => "b",
+    };
+    format!("{} {} {}", fen.board_field(), player, fen.castling)
+}
@@ -0,0 +1,137 @@
+//! Setting up a position by hand, to play or analyse it from there.
+
+use crate::fen::{Fen, START_FEN};
+use crate::outcome::{capitalized, team_name};
+use crate::position::{coordinate_to_string, in_check, opponent, Coordinate};
+use chess::game::{Rank, Team};
+
+/// Castling rights in FEN order, with the king and rook squares each one needs.
+const CASTLING_RIGHTS: [(char, Coordinate, Coordinate); 4] = [
+    ('K', (4, 0), (7, 0)),
+    ('Q', (4, 0), (0, 0)),
+    ('k', (4, 7), (7, 7)),
+    ('q', (4, 7), (0, 7)),
+];
+
+pub struct Editor {
+    /// The position being set up. Only the pieces, player in turn and
+    /// castling rights are edited.
+    pub fen: Fen,
+    /// Piece put down by clicking a square.
+    pub selected: (Team, Rank),
+    /// Square a piece is being dragged from.
+    pub drag_from: Option<Coordinate>,
+}
+
+impl Editor {
+    /// Starts editing from `fen`, usually the position on screen.
+    pub fn new(mut fen: Fen) -> Editor {
+        fen.en_passant = None;
+        fen.halfmove_clock = 0;
+        fen.fullmove_number = 1;
+        Editor {
+            fen,
+            selected: (Team::White, Rank::Pawn),
+            drag_from: None,
+        }
+    }
+
+    /// Puts the selected piece on `square`, or takes it away if it is already there.
+    pub fn click(&mut self, square: Coordinate) {
+        let existing = self.fen.piece_at(square);
+        self.remove(square);
+        if existing != Some(self.selected) {
+            self.fen.placement.push((square, self.selected.0, self.selected.1));
+        }
+    }
+
+    pub fn remove(&mut self, square: Coordinate) {
+        self.fen.placement.retain(|(at, _, _)| *at != square);
+    }
+
+    /// Moves a piece, replacing whatever stood on `to`.
+    pub fn drag(&mut self, from: Coordinate, to: Coordinate) {
+        if let Some((team, rank)) = self.fen.piece_at(from) {
+            self.remove(from);
+            self.remove(to);
+            self.fen.placement.push((to, team, rank));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.fen.placement.clear();
+        self.fen.castling = "-".to_string();
+    }
+
+    pub fn reset(&mut self) {
+        *self = Editor::new(START_FEN.parse().unwrap());
+    }
+
+    pub fn toggle_player(&mut self) {
+        self.fen.player = opponent(self.fen.player);
+    }
+
+    pub fn has_castling(&self, right: char) -> bool {
+        self.fen.castling.contains(right)
+    }
+
+    /// Grants or takes away one of the castling rights "KQkq".
+    pub fn toggle_castling(&mut self, right: char) {
+        let castling: String = CASTLING_RIGHTS
+            .iter()
+            .map(|(c, _, _)| *c)
+            .filter(|c| (*c == right) != self.has_castling(*c))
+            .collect();
+        self.fen.castling = if castling.is_empty() {
+            "-".to_string()
+        } else {
+            castling
+        };
+    }
+
+    /// The position as a FEN a game can start from, or what is wrong with it.
+    pub fn validate(&self) -> Result<Fen, String> {
+        let placement = &self.fen.placement;
+        for team in [Team::White, Team::Black].iter() {
+            let kings = placement
+                .iter()
+                .filter(|(_, owner, rank)| owner == team && *rank == Rank::King)
+                .count();
+            if kings != 1 {
+                return Err(format!("{} needs exactly one king", capitalized(team_name(*team))));
+            }
+            if placement.iter().filter(|(_, owner, _)| owner == team).count() > 16 {
+                return Err(format!("{} has more than 16 pieces", capitalized(team_name(*team))));
+            }
+        }
+        let back_rank_pawn = placement
+            .iter()
+            .find(|((_, rank), _, piece)| *piece == Rank::Pawn && (*rank == 0 || *rank == 7));
+        if let Some((square, _, _)) = back_rank_pawn {
+            return Err(format!("Pawns cannot stand on {}", coordinate_to_string(*square)));
+        }
+        let granted = CASTLING_RIGHTS.iter().filter(|(right, _, _)| self.has_castling(*right));
+        for (right, king, rook) in granted {
+            let team = if right.is_ascii_uppercase() { Team::White } else { Team::Black };
+            if self.fen.piece_at(*king) != Some((team, Rank::King))
+                || self.fen.piece_at(*rook) != Some((team, Rank::Rook))
+            {
+                return Err(format!(
+                    "Castling {} needs the king on {} and a rook on {}",
+                    right,
+                    coordinate_to_string(*king),
+                    coordinate_to_string(*rook)
+                ));
+            }
+        }
+        let waiting = opponent(self.fen.player);
+        if in_check(&self.fen.setup(), waiting) {
+            return Err(format!(
+                "{} is in check with {} to move",
+                capitalized(team_name(waiting)),
+                team_name(self.fen.player)
+            ));
+        }
+        Ok(self.fen.clone())
+    }
+}
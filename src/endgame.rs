@@ -0,0 +1,430 @@
+//! Endgame drills: classic positions to win or hold against an engine or the
+//! simple mover in this module.
+//!
+//! The player always has the first move of a drill. A drill is over when the
+//! game ends, its goal is reached or the player has used up the moves it allows.
+
+use crate::fen::Fen;
+use crate::outcome::Outcome;
+use crate::position::{
+    in_check, insufficient_material, is_attacked, king_position, legal_moves, opponent, pieces,
+    Coordinate,
+};
+use crate::record::{GameRecord, RecordedMove};
+use crate::util;
+use chess::game::{Game, GameState, Rank, Team};
+use chess::moves::ActionType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// What the player has to do to pass a drill.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Goal {
+    /// Checkmate within the move limit.
+    Mate,
+    /// Queen a pawn within the move limit and still have the queen after the
+    /// opponent's reply.
+    Promote,
+    /// Neither lose nor let a pawn queen before the move limit.
+    Hold,
+}
+
+pub struct Drill {
+    /// Short name used on the command line and in the stats file.
+    pub key: &'static str,
+    pub name: &'static str,
+    pub fen: &'static str,
+    pub goal: Goal,
+    /// Moves of the player allowed to reach the goal, or to hold out for.
+    pub move_limit: usize,
+}
+
+pub const DRILLS: [Drill; 5] = [
+    Drill {
+        key: "kqk",
+        name: "King and queen against king",
+        fen: "8/8/8/3k4/8/8/8/4K1Q1 w - - 0 1",
+        goal: Goal::Mate,
+        move_limit: 20,
+    },
+    Drill {
+        key: "krk",
+        name: "King and rook against king",
+        fen: "8/8/8/3k4/8/8/8/4K2R w - - 0 1",
+        goal: Goal::Mate,
+        move_limit: 30,
+    },
+    Drill {
+        key: "kbnk",
+        name: "King, bishop and knight against king",
+        fen: "8/8/8/3k4/8/8/8/2B1KN2 w - - 0 1",
+        goal: Goal::Mate,
+        move_limit: 50,
+    },
+    Drill {
+        key: "lucena",
+        name: "Lucena position",
+        fen: "1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1",
+        goal: Goal::Promote,
+        move_limit: 15,
+    },
+    Drill {
+        key: "philidor",
+        name: "Philidor position",
+        fen: "4k3/R7/1r6/4K3/4P3/8/8/8 b - - 0 1",
+        goal: Goal::Hold,
+        move_limit: 30,
+    },
+];
+
+impl Drill {
+    /// Side played by the player, the one to move in the drill's position.
+    pub fn color(&self) -> Team {
+        self.start().player
+    }
+
+    pub fn start(&self) -> Fen {
+        self.fen.parse().expect("drill positions are valid FEN")
+    }
+
+    /// The task, as shown when the drill starts.
+    pub fn task(&self) -> String {
+        match self.goal {
+            Goal::Mate => format!("Checkmate within {} moves", self.move_limit),
+            Goal::Promote => format!("Queen the pawn within {} moves", self.move_limit),
+            Goal::Hold => format!("Hold the draw for {} moves", self.move_limit),
+        }
+    }
+}
+
+/// Index of the drill called `key`, e.g. "krk" or "lucena".
+pub fn find(key: &str) -> Result<usize, String> {
+    DRILLS
+        .iter()
+        .position(|drill| drill.key.eq_ignore_ascii_case(key))
+        .ok_or_else(|| {
+            let keys: Vec<&str> = DRILLS.iter().map(|drill| drill.key).collect();
+            format!(
+                "Unknown drill '{}', expected one of {}",
+                key,
+                keys.join(", ")
+            )
+        })
+}
+
+/// How the player has done in one drill over all sessions.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub struct DrillStats {
+    pub attempts: u32,
+    pub successes: u32,
+    /// Fewest moves the goal was reached in, for the drills that have one to reach.
+    pub best: Option<usize>,
+}
+
+/// Location of the stats, `$XDG_DATA_HOME/oskhen-chess-gui/endgames.json`.
+pub fn stats_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("oskhen-chess-gui").join("endgames.json"))
+}
+
+fn load_stats() -> HashMap<String, DrillStats> {
+    stats_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// The state of the drill after a move.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Verdict {
+    Playing,
+    Passed(String),
+    Failed(String),
+}
+
+/// A session going through the drills.
+pub struct DrillTrainer {
+    current: usize,
+    pub stats: HashMap<String, DrillStats>,
+    finished: bool,
+}
+
+impl DrillTrainer {
+    pub fn new(first: usize) -> DrillTrainer {
+        DrillTrainer {
+            current: first,
+            stats: load_stats(),
+            finished: false,
+        }
+    }
+
+    pub fn drill(&self) -> &'static Drill {
+        &DRILLS[self.current]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn drill_stats(&self) -> DrillStats {
+        self.stats
+            .get(self.drill().key)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Starts the current drill over.
+    pub fn restart(&mut self) -> Fen {
+        self.finished = false;
+        self.drill().start()
+    }
+
+    /// Moves on to the next drill, after the last one back to the first.
+    pub fn next_drill(&mut self) -> Fen {
+        self.current = (self.current + 1) % DRILLS.len();
+        self.restart()
+    }
+
+    /// Counts a drill left before it was over as failed, if the player has moved.
+    pub fn give_up(&mut self, record: &GameRecord) {
+        if !self.finished && record.ply() > 0 {
+            self.finish(None);
+        }
+    }
+
+    fn finish(&mut self, passed_in: Option<usize>) {
+        self.finished = true;
+        let stats = self.stats.entry(self.drill().key.to_string()).or_default();
+        stats.attempts += 1;
+        if let Some(moves) = passed_in {
+            stats.successes += 1;
+            if DRILLS[self.current].goal != Goal::Hold {
+                stats.best = Some(stats.best.map_or(moves, |best| best.min(moves)));
+            }
+        }
+    }
+
+    /// Judges the drill after a move, `outcome` being how the game ended if it did.
+    pub fn judge(
+        &mut self,
+        record: &GameRecord,
+        game: &Game,
+        outcome: Option<&Outcome>,
+    ) -> Verdict {
+        if self.finished {
+            return Verdict::Playing;
+        }
+        let drill = self.drill();
+        let color = drill.color();
+        // The player moves first, so every odd ply is theirs.
+        let moves = (record.ply() + 1) / 2;
+        let players_turn = game.player == color;
+        let queens = |team: Team| {
+            pieces(game)
+                .into_iter()
+                .filter(|(_, owner, rank)| *owner == team && *rank == Rank::Queen)
+                .count()
+        };
+        let start_queens = |team: Team| {
+            drill
+                .start()
+                .placement
+                .into_iter()
+                .filter(|(_, owner, rank)| *owner == team && *rank == Rank::Queen)
+                .count()
+        };
+        let verdict = match outcome {
+            Some(outcome) => match outcome.winner() {
+                Some(winner) if winner == color => {
+                    Verdict::Passed(format!("{} in {} moves", outcome.description(), moves))
+                }
+                Some(_) => Verdict::Failed(outcome.description()),
+                None if drill.goal == Goal::Hold => Verdict::Passed(outcome.description()),
+                None => Verdict::Failed(outcome.description()),
+            },
+            None => match drill.goal {
+                Goal::Promote if players_turn && queens(color) > start_queens(color) => {
+                    Verdict::Passed(format!("Pawn queened in {} moves", moves))
+                }
+                Goal::Hold if queens(opponent(color)) > start_queens(opponent(color)) => {
+                    Verdict::Failed("The pawn queened".to_string())
+                }
+                Goal::Hold if moves >= drill.move_limit && !players_turn => {
+                    Verdict::Passed(format!("Held for {} moves", moves))
+                }
+                // The queen has to survive the reply of a move made on the last move.
+                Goal::Promote if moves >= drill.move_limit && players_turn => {
+                    Verdict::Failed(format!("Out of moves after {}", drill.move_limit))
+                }
+                Goal::Mate if moves >= drill.move_limit && !players_turn => {
+                    Verdict::Failed(format!("Out of moves after {}", drill.move_limit))
+                }
+                _ => Verdict::Playing,
+            },
+        };
+        match verdict {
+            Verdict::Passed(_) => self.finish(Some(moves)),
+            Verdict::Failed(_) => self.finish(None),
+            Verdict::Playing => {}
+        }
+        verdict
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = stats_path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no data directory on this system")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&self.stats)?)
+    }
+}
+
+/// Score of giving mate, in hundredths of a pawn like the rest of the evaluation.
+const MATE_SCORE: i32 = 100_000;
+
+fn value(rank: Rank) -> i32 {
+    match rank {
+        Rank::Queen => 900,
+        Rank::Rook => 500,
+        Rank::Bishop | Rank::Knight => 300,
+        Rank::Pawn => 100,
+        Rank::King => 0,
+    }
+}
+
+/// Distance of a square from the four center squares, 0 to 6.
+fn center_distance(square: Coordinate) -> i32 {
+    let file = (2 * square.0 as i32 - 7).abs() / 2;
+    let rank = (2 * square.1 as i32 - 7).abs() / 2;
+    file + rank
+}
+
+fn distance(a: Coordinate, b: Coordinate) -> i32 {
+    (a.0 as i32 - b.0 as i32).abs() + (a.1 as i32 - b.1 as i32).abs()
+}
+
+/// A copy of `game`, set up anew from its pieces.
+fn copy(game: &Game) -> Game {
+    let mut copy = Fen::from_game(game).setup();
+    copy.set_promotion_piece(Rank::Queen);
+    copy
+}
+
+fn is_mate(game: &Game) -> bool {
+    king_position(game, game.player).is_some() && game.get_game_state() == GameState::Checkmate
+}
+
+/// Scores the position for `mover`, who has just moved, with the opponent to move.
+fn evaluate(game: &mut Game, mover: Team) -> i32 {
+    if is_mate(game) {
+        return MATE_SCORE;
+    }
+    let replies = legal_moves(game);
+    if (replies.is_empty() && !in_check(game, game.player)) || insufficient_material(game) {
+        return 0;
+    }
+    let pieces = pieces(game);
+    let material: i32 = pieces
+        .iter()
+        .map(|(_, team, rank)| {
+            if *team == mover {
+                value(*rank)
+            } else {
+                -value(*rank)
+            }
+        })
+        .sum();
+    // The opponent takes the most valuable piece left unguarded.
+    let hanging = pieces
+        .iter()
+        .filter(|(square, team, _)| {
+            *team == mover
+                && is_attacked(game, *square, opponent(mover))
+                && !is_attacked(game, *square, mover)
+        })
+        .map(|(_, _, rank)| value(*rank))
+        .max()
+        .unwrap_or(0);
+    // Pawns are worth more the closer they are to queening.
+    let advance: i32 = pieces
+        .iter()
+        .filter(|(_, _, rank)| *rank == Rank::Pawn)
+        .map(|(square, team, _)| {
+            let steps = match team {
+                Team::White => square.1 as i32 - 1,
+                Team::Black => 6 - square.1 as i32,
+            };
+            if *team == mover {
+                10 * steps
+            } else {
+                -10 * steps
+            }
+        })
+        .sum();
+    // The side ahead drives the other king to the edge and follows it with
+    // its own, the side behind keeps its king in the center.
+    let kings = (
+        king_position(game, mover),
+        king_position(game, opponent(mover)),
+    );
+    let kings = match kings {
+        (Some(own), Some(other)) => {
+            let chase = 10 * center_distance(other) + 4 * (14 - distance(own, other));
+            let shelter = 10 * center_distance(own);
+            if material > 0 {
+                chase
+            } else {
+                -shelter
+            }
+        }
+        _ => 0,
+    };
+    // A move that allows mate in one is as good as lost.
+    for (_, action) in replies {
+        let mut after = copy(game);
+        after.perform_action(action);
+        if is_mate(&after) {
+            return -MATE_SCORE;
+        }
+    }
+    material - hanging + advance + kings
+}
+
+/// A move for the side to move found by looking one move ahead, a weak but
+/// quick opponent for drills without an engine. Picks at random between
+/// equally good moves so that the drills do not repeat.
+pub fn builtin_move(game: &mut Game) -> Option<RecordedMove> {
+    let mover = game.player;
+    let mut best: Vec<RecordedMove> = Vec::new();
+    let mut best_score = i32::MIN;
+    for (from, action) in legal_moves(game) {
+        let promotion = if action.action_type == ActionType::Promotion {
+            Some(Rank::Queen)
+        } else {
+            None
+        };
+        let recorded = RecordedMove {
+            from,
+            to: action.to.coordinate,
+            promotion,
+        };
+        let mut after = copy(game);
+        after.perform_action(action);
+        let score = evaluate(&mut after, mover);
+        if score > best_score {
+            best_score = score;
+            best.clear();
+        }
+        if score == best_score {
+            best.push(recorded);
+        }
+    }
+    if best.is_empty() {
+        return None;
+    }
+    Some(best[util::random() as usize % best.len()])
+}
@@ -0,0 +1,312 @@
+//! Communication with external engines over the Universal Chess Interface.
+
+use crate::clock::Clock;
+use crate::variant::Variant;
+use chess::game::Team;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an engine may take to answer `uci` and `isready`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Thinking time per move when the game is played without a clock.
+const MOVE_TIME_MS: u64 = 1000;
+/// Engines accept a draw unless they think they are better by more than this.
+const DRAW_ACCEPT_CENTIPAWNS: i32 = 30;
+
+/// Evaluation from the point of view of the side to move in the searched position.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Score {
+    Centipawns(i32),
+    /// Mate in this many moves, negative if the side to move gets mated.
+    /// `Mate(0)` is the other side having been mated.
+    Mate(i32),
+    /// The side whose point of view the score is from has been mated, what
+    /// engines report as `score mate 0`.
+    Mated,
+}
+
+/// One principal variation reported by the engine.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Variation {
+    pub score: Score,
+    /// Moves in UCI notation, best first.
+    pub moves: Vec<String>,
+}
+
+/// Progress of the running search, taken from the engine's `info` lines.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub nodes: u64,
+    /// Best lines, the first being the main one. More than one with `MultiPV`.
+    pub variations: Vec<Variation>,
+}
+
+impl SearchInfo {
+    fn update(&mut self, line: &str) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let value = |key: &str| {
+            let index = words.iter().position(|word| *word == key)?;
+            words.get(index + 1).copied()
+        };
+        if let Some(depth) = value("depth").and_then(|depth| depth.parse().ok()) {
+            self.depth = depth;
+        }
+        if let Some(nodes) = value("nodes").and_then(|nodes| nodes.parse().ok()) {
+            self.nodes = nodes;
+        }
+        let pv = match words.iter().position(|word| *word == "pv") {
+            Some(index) => &words[index + 1..],
+            None => return,
+        };
+        let score = match parse_score(line) {
+            Some(score) => score,
+            None => return,
+        };
+        let multipv: usize = value("multipv").and_then(|number| number.parse().ok()).unwrap_or(1);
+        let index = multipv.max(1) - 1;
+        if index > self.variations.len() {
+            return;
+        }
+        let variation = Variation {
+            score,
+            moves: pv.iter().map(|word| word.to_string()).collect(),
+        };
+        if index == self.variations.len() {
+            self.variations.push(variation);
+        } else {
+            self.variations[index] = variation;
+        }
+    }
+}
+
+pub struct Engine {
+    pub name: String,
+    pub path: PathBuf,
+    /// Score of the most recent search.
+    pub score: Option<Score>,
+    pub info: SearchInfo,
+    /// Rules the engine has been told to play by.
+    variant: Variant,
+    /// Whether a search has been started and its best move not read yet.
+    searching: bool,
+    /// Number of stopped searches whose output is still to be skipped.
+    stopped: usize,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    /// Launches the engine at `path` and waits for it to finish the UCI handshake.
+    pub fn start(path: &Path) -> io::Result<Engine> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut engine = Engine {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "Engine".to_string()),
+            path: path.to_path_buf(),
+            score: None,
+            info: SearchInfo::default(),
+            variant: Variant::Standard,
+            searching: false,
+            stopped: 0,
+            child,
+            stdin,
+            lines,
+        };
+        engine.send("uci")?;
+        for line in engine.wait_for("uciok")? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+        }
+        engine.send("ucinewgame")?;
+        engine.send("isready")?;
+        engine.wait_for("readyok")?;
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// Blocks until the engine prints a line starting with `expected`, returning the lines before it.
+    fn wait_for(&mut self, expected: &str) -> io::Result<Vec<String>> {
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let mut received = Vec::new();
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(left) {
+                Ok(line) if line.split_whitespace().next() == Some(expected) => return Ok(received),
+                Ok(line) => received.push(line),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("engine did not answer with {}", expected),
+                    ))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "engine closed its output",
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    /// Switches the engine to the rules of `variant`, telling it only when
+    /// they change. Variants other than Chess960 are set with `UCI_Variant`,
+    /// which engines playing only standard chess ignore.
+    pub fn set_variant(&mut self, variant: Variant) -> io::Result<()> {
+        if variant == self.variant {
+            return Ok(());
+        }
+        let chess960 = variant == Variant::Chess960;
+        if chess960 != (self.variant == Variant::Chess960) {
+            self.set_option("UCI_Chess960", &chess960.to_string())?;
+        }
+        if variant.uci_name() != self.variant.uci_name() {
+            self.set_option("UCI_Variant", variant.uci_name())?;
+        }
+        self.variant = variant;
+        Ok(())
+    }
+
+    /// Starts searching `position`, given in the form of the UCI `position` command.
+    pub fn go(&mut self, position: &str, clock: Option<&Clock>) -> io::Result<()> {
+        self.start_search(position)?;
+        match clock {
+            Some(clock) => {
+                let increment = clock.control.increment.as_millis();
+                self.send(&format!(
+                    "go wtime {} btime {} winc {} binc {}",
+                    clock.remaining(Team::White).as_millis(),
+                    clock.remaining(Team::Black).as_millis(),
+                    increment,
+                    increment
+                ))
+            }
+            None => self.send(&format!("go movetime {}", MOVE_TIME_MS)),
+        }
+    }
+
+    /// Searches `position` for a fixed time.
+    pub fn go_movetime(&mut self, position: &str, time: Duration) -> io::Result<()> {
+        self.start_search(position)?;
+        self.send(&format!("go movetime {}", time.as_millis()))
+    }
+
+    /// Searches `position` until stopped, reporting progress in `info`.
+    pub fn go_infinite(&mut self, position: &str) -> io::Result<()> {
+        self.start_search(position)?;
+        self.send("go infinite")
+    }
+
+    /// Sets up `position` for the `go` command that follows.
+    fn start_search(&mut self, position: &str) -> io::Result<()> {
+        self.info = SearchInfo::default();
+        self.send(&format!("position {}", position))?;
+        self.searching = true;
+        Ok(())
+    }
+
+    /// Aborts the running search without waiting for it. Its best move, and
+    /// whatever else it still prints, is skipped by `poll`.
+    pub fn stop(&mut self) -> io::Result<()> {
+        if !self.searching {
+            return Ok(());
+        }
+        self.searching = false;
+        self.stopped += 1;
+        self.send("stop")
+    }
+
+    /// Reads the engine output received so far, returning the best move in UCI
+    /// notation once the search has finished.
+    pub fn poll(&mut self) -> Option<String> {
+        while let Ok(line) = self.lines.try_recv() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                // Output of a stopped search, which ends with its best move.
+                Some("bestmove") if self.stopped > 0 => self.stopped -= 1,
+                _ if self.stopped > 0 => {}
+                Some("info") => {
+                    if let Some(score) = parse_score(&line) {
+                        self.score = Some(score);
+                    }
+                    self.info.update(&line);
+                }
+                Some("bestmove") => {
+                    self.searching = false;
+                    return words.next().map(str::to_string);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Whether the engine agrees to a draw, judged by its last evaluation.
+    pub fn accepts_draw(&self) -> bool {
+        match self.score {
+            Some(Score::Centipawns(centipawns)) => centipawns <= DRAW_ACCEPT_CENTIPAWNS,
+            Some(Score::Mate(moves)) => moves < 0,
+            Some(Score::Mated) => true,
+            None => false,
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Reads the `score cp <x>` or `score mate <y>` part of an info line.
+pub fn parse_score(line: &str) -> Option<Score> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let index = words.iter().position(|word| *word == "score")?;
+    let value: i32 = words.get(index + 2)?.parse().ok()?;
+    match *words.get(index + 1)? {
+        "cp" => Some(Score::Centipawns(value)),
+        "mate" if value == 0 => Some(Score::Mated),
+        "mate" => Some(Score::Mate(value)),
+        _ => None,
+    }
+}
@@ -0,0 +1,542 @@
+//! Matches between two UCI engines, played in the window or headless.
+//!
+//! Every opening is played twice with the colors swapped. Games end by the
+//! rules, on time, by an illegal move or by adjudication: a tablebase result,
+//! both engines agreeing that one side is winning by a wide margin, or both
+//! engines scoring the position as dead even for long enough.
+
+use crate::analysis::white_score;
+use crate::cli::AdjudicationOptions;
+use crate::clock::{Clock, TimeControl};
+use crate::database::split_games;
+use crate::engine::{Engine, Score};
+use crate::fen::Fen;
+use crate::outcome::{team_name, Outcome};
+use crate::position::{opponent, pieces};
+use crate::record::{GameRecord, RecordedMove};
+use crate::syzygy::{Tablebase, Wdl};
+use chess::game::{Game, Rank, Team};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Centipawns standing in for a mate score when comparing against thresholds.
+const MATE_CENTIPAWNS: i32 = 100_000;
+/// Plies without captures or pawn moves after which a game is drawn.
+const FIFTY_MOVES: u32 = 100;
+/// Pause between polls of a headless match, so as not to spin while engines think.
+const HEADLESS_POLL: Duration = Duration::from_millis(2);
+/// Factor of the standard error giving a 95% confidence interval.
+const CONFIDENCE_95: f64 = 1.959_964;
+
+/// A position to start games from, with the moves played to reach it.
+pub struct Opening {
+    pub start: Option<Fen>,
+    pub moves: Vec<RecordedMove>,
+}
+
+/// Reads openings from EPD, one position per line, or from PGN, one game each.
+pub fn load_openings(path: &Path) -> Result<Vec<Opening>, String> {
+    let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let is_epd = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("epd"));
+    let mut openings = Vec::new();
+    if is_epd {
+        for (index, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+            if fields.is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let fen = fields
+                .join(" ")
+                .parse()
+                .map_err(|error| format!("Line {}: {}", index + 1, error))?;
+            openings.push(Opening {
+                start: Some(fen),
+                moves: Vec::new(),
+            });
+        }
+    } else {
+        for (number, (_, game)) in split_games(&text).into_iter().enumerate() {
+            let (record, _) = GameRecord::from_pgn(game)
+                .map_err(|error| format!("Game {}: {}", number + 1, error))?;
+            openings.push(Opening {
+                start: record.start,
+                moves: record.moves,
+            });
+        }
+    }
+    if openings.is_empty() {
+        return Err("No openings found".to_string());
+    }
+    Ok(openings)
+}
+
+/// When games are decided without being played out.
+pub struct Adjudication {
+    pub options: AdjudicationOptions,
+    pub tablebase: Option<Tablebase>,
+}
+
+impl Adjudication {
+    /// The result the tablebase gives the position, as the winner if any.
+    fn probe(&mut self, record: &GameRecord, game: &Game) -> Option<Option<Team>> {
+        let tablebase = self.tablebase.as_mut()?;
+        if pieces(game).len() > tablebase.max_pieces {
+            return None;
+        }
+        let result = tablebase.probe(&record.fen(game)).ok()??;
+        Some(match result.wdl {
+            Wdl::Win => Some(game.player),
+            Wdl::Loss => Some(opponent(game.player)),
+            Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => None,
+        })
+    }
+}
+
+/// How a game ended, `winner` being `None` for a draw.
+#[derive(Clone, PartialEq)]
+pub struct GameEnd {
+    pub winner: Option<Team>,
+    pub reason: String,
+}
+
+impl GameEnd {
+    pub fn result(&self) -> &'static str {
+        match self.winner {
+            Some(Team::White) => "1-0",
+            Some(Team::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+/// Progress of a game after a poll.
+#[derive(Clone, PartialEq)]
+pub enum Step {
+    Waiting,
+    Moved,
+    Finished(GameEnd),
+}
+
+/// One game between two of a list of engines.
+pub struct EngineGame {
+    pub record: GameRecord,
+    pub board: Game,
+    pub clock: Option<Clock>,
+    /// Indices of the engines playing white and black.
+    pub white: usize,
+    pub black: usize,
+    thinking: bool,
+    /// Positions reached so far, without the move counters, to spot repetitions.
+    positions: Vec<String>,
+    /// Consecutive plies scored as winning for the same side, and as even.
+    winning_plies: u32,
+    even_plies: u32,
+    /// Score of the last ply from white's point of view.
+    last_score: i32,
+}
+
+impl EngineGame {
+    /// Sets up the game after the moves of `opening`, with `headers` added to the record.
+    pub fn new(
+        opening: Option<&Opening>,
+        white: usize,
+        black: usize,
+        time: Option<TimeControl>,
+        headers: &[(&str, &str)],
+    ) -> Result<EngineGame, String> {
+        let mut record =
+            GameRecord::starting_from(opening.and_then(|opening| opening.start.clone()));
+        let mut board = record.start_game();
+        for recorded in opening.map_or(&[][..], |opening| &opening.moves[..]) {
+            record.play_move(&mut board, *recorded)?;
+        }
+        for (key, value) in headers {
+            record.set_header(key, value);
+        }
+        let mut clock = time.map(Clock::new);
+        if let Some(clock) = clock.as_mut() {
+            record.set_header("TimeControl", &clock.control.pgn_tag());
+            clock.start(board.player);
+        }
+        let positions = vec![position_key(&record, &board)];
+        Ok(EngineGame {
+            record,
+            board,
+            clock,
+            white,
+            black,
+            thinking: false,
+            positions,
+            winning_plies: 0,
+            even_plies: 0,
+            last_score: 0,
+        })
+    }
+
+    fn engine_of(&self, team: Team) -> usize {
+        match team {
+            Team::White => self.white,
+            Team::Black => self.black,
+        }
+    }
+
+    /// Starts the engine to move searching, or plays its move once found.
+    pub fn poll(
+        &mut self,
+        engines: &mut [Engine],
+        adjudication: &mut Adjudication,
+    ) -> Result<Step, String> {
+        let mover = self.board.player;
+        let engine = &mut engines[self.engine_of(mover)];
+        if !self.thinking {
+            engine
+                .go(&self.record.uci_position(), self.clock.as_ref())
+                .map_err(|error| format!("{}: {}", engine.name, error))?;
+            self.thinking = true;
+            return Ok(Step::Waiting);
+        }
+        if self.clock.as_ref().and_then(Clock::flagged) == Some(mover) {
+            let _ = engine.stop();
+            self.thinking = false;
+            let reason = format!("{} lost on time", engine.name);
+            return Ok(self.finish(Some(opponent(mover)), reason, "time forfeit"));
+        }
+        let best_move = match engine.poll() {
+            Some(best_move) => best_move,
+            None => return Ok(Step::Waiting),
+        };
+        self.thinking = false;
+        let score = engine
+            .info
+            .variations
+            .first()
+            .map(|variation| variation.score);
+        let played = RecordedMove::from_uci(&best_move)
+            .ok_or_else(|| "not a move".to_string())
+            .and_then(|recorded| self.record.play_move(&mut self.board, recorded));
+        if played.is_err() {
+            let reason = format!("{} played the illegal move {}", engine.name, best_move);
+            return Ok(self.finish(Some(opponent(mover)), reason, "rules infraction"));
+        }
+        if let Some(clock) = self.clock.as_mut() {
+            clock.press(mover);
+        }
+        self.positions.push(position_key(&self.record, &self.board));
+
+        if let Some(outcome) = Outcome::detect(&mut self.board, &self.record) {
+            return Ok(self.finish(outcome.winner(), outcome.description(), "normal"));
+        }
+        let key = self.positions.last().unwrap();
+        if self
+            .positions
+            .iter()
+            .filter(|position| *position == key)
+            .count()
+            >= 3
+        {
+            return Ok(self.finish(None, "Draw by threefold repetition".to_string(), "normal"));
+        }
+        if *self.record.halfmove_clocks.last().unwrap() >= FIFTY_MOVES {
+            return Ok(self.finish(None, "Draw by the fifty-move rule".to_string(), "normal"));
+        }
+        if let Some(winner) = adjudication.probe(&self.record, &self.board) {
+            let reason = match winner {
+                Some(winner) => format!("Tablebase win for {}", team_name(winner)),
+                None => "Tablebase draw".to_string(),
+            };
+            return Ok(self.finish(winner, reason, "adjudication"));
+        }
+        match score {
+            Some(score) => {
+                Ok(self.adjudicate_score(white_score(score, mover), &adjudication.options))
+            }
+            None => Ok(Step::Moved),
+        }
+    }
+
+    /// Ends the game once the engines have agreed on the score for long enough.
+    fn adjudicate_score(&mut self, score: Score, options: &AdjudicationOptions) -> Step {
+        let score = match score {
+            Score::Centipawns(centipawns) => centipawns,
+            Score::Mate(moves) if moves >= 0 => MATE_CENTIPAWNS,
+            Score::Mate(_) | Score::Mated => -MATE_CENTIPAWNS,
+        };
+        let same_side = score.signum() == self.last_score.signum();
+        self.winning_plies = if score.abs() < options.resign_score {
+            0
+        } else if same_side {
+            self.winning_plies + 1
+        } else {
+            1
+        };
+        let move_number = (self.record.first_ply() + self.record.ply()) / 2 + 1;
+        self.even_plies = if score.abs() <= options.draw_score && move_number > options.draw_after {
+            self.even_plies + 1
+        } else {
+            0
+        };
+        self.last_score = score;
+        if options.no_adjudication {
+            return Step::Moved;
+        }
+        // Each engine has to see it for the number of moves asked for.
+        if self.winning_plies >= 2 * options.resign_moves {
+            let winner = if score > 0 { Team::White } else { Team::Black };
+            let reason = format!("{} wins by adjudication", team_name(winner));
+            return self.finish(Some(winner), reason, "adjudication");
+        }
+        if self.even_plies >= 2 * options.draw_moves {
+            return self.finish(None, "Draw by adjudication".to_string(), "adjudication");
+        }
+        Step::Moved
+    }
+
+    fn finish(&mut self, winner: Option<Team>, reason: String, termination: &str) -> Step {
+        let end = GameEnd { winner, reason };
+        let notes = self.record.current_notes();
+        if !notes.comment.is_empty() {
+            notes.comment.push(' ');
+        }
+        notes.comment += &format!("{}.", end.reason);
+        self.record.set_result(end.result());
+        self.record.set_header("Termination", termination);
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop();
+        }
+        Step::Finished(end)
+    }
+}
+
+/// The position with the side to move, castling rights and en passant square,
+/// which have to match for a position to count as repeated. The en passant
+/// square only counts when a pawn of the side to move stands ready to take on it.
+fn position_key(record: &GameRecord, game: &Game) -> String {
+    let mut fen = record.fen(game);
+    if let Some((file, rank)) = fen.en_passant {
+        // The pawns that could take stand beside the one that made the double step.
+        let row = if fen.player == Team::White { rank - 1 } else { rank + 1 };
+        let pawn = Some((fen.player, Rank::Pawn));
+        if fen.piece_at((file - 1, row)) != pawn && fen.piece_at((file + 1, row)) != pawn {
+            fen.en_passant = None;
+        }
+    }
+    let fen = fen.to_string();
+    fen.split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+}
+
+/// Elo difference of a player scoring `wins`, `losses` and `draws`, with the
+/// margin of its 95% confidence interval. `None` while the score is 0% or 100%,
+/// which no finite difference explains.
+pub fn elo_difference(wins: u32, losses: u32, draws: u32) -> Option<(f64, f64)> {
+    let games = f64::from(wins + losses + draws);
+    let score = (f64::from(wins) + f64::from(draws) / 2.0) / games;
+    if games == 0.0 || score <= 0.0 || score >= 1.0 {
+        return None;
+    }
+    let share = |count: u32| f64::from(count) / games;
+    let variance = share(wins) * (1.0 - score).powi(2)
+        + share(losses) * score.powi(2)
+        + share(draws) * (0.5 - score).powi(2);
+    let margin = CONFIDENCE_95 * (variance / games).sqrt();
+    let elo = |score: f64| {
+        let score = score.max(f64::EPSILON).min(1.0 - f64::EPSILON);
+        -400.0 * (1.0 / score - 1.0).log10()
+    };
+    Some((
+        elo(score),
+        (elo(score + margin) - elo(score - margin)) / 2.0,
+    ))
+}
+
+/// Appends a finished game to a PGN file.
+pub fn append_pgn(path: &Path, record: &GameRecord) -> Result<(), String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", record.to_pgn()))
+        .map_err(|error| format!("Could not save the game to {}: {}", path.display(), error))
+}
+
+/// What happened in a match since the last poll.
+#[derive(Clone, PartialEq, Debug)]
+pub enum MatchEvent {
+    Waiting,
+    Started,
+    Moved,
+    /// A game has ended, described in a line for the log.
+    GameOver(String),
+    Finished,
+}
+
+pub struct EngineMatch {
+    pub engines: Vec<Engine>,
+    openings: Vec<Opening>,
+    /// Number of games to play.
+    pub games: usize,
+    time: Option<TimeControl>,
+    adjudication: Adjudication,
+    pgn: PathBuf,
+    pub game: Option<EngineGame>,
+    /// Number of the game being played or last played, from 1.
+    pub round: usize,
+    /// Wins, losses and draws of the first engine.
+    pub results: [u32; 3],
+    /// Headers set on every game, in place of the match's own.
+    pub headers: Vec<(String, String)>,
+}
+
+impl EngineMatch {
+    /// Starts both engines. Without openings all games start from the standard position.
+    pub fn start(
+        paths: [&Path; 2],
+        games: usize,
+        openings: Vec<Opening>,
+        time: Option<TimeControl>,
+        adjudication: Adjudication,
+        pgn: PathBuf,
+    ) -> Result<EngineMatch, String> {
+        let mut engines = Vec::new();
+        for path in paths.iter() {
+            let engine = Engine::start(path)
+                .map_err(|error| format!("Could not start {}: {}", path.display(), error))?;
+            engines.push(engine);
+        }
+        Ok(EngineMatch {
+            engines,
+            openings,
+            games,
+            time,
+            adjudication,
+            pgn,
+            game: None,
+            round: 0,
+            results: [0; 3],
+            headers: Vec::new(),
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.game.is_none() && self.round >= self.games
+    }
+
+    /// Starts the next game or moves the current one along.
+    pub fn poll(&mut self) -> Result<MatchEvent, String> {
+        if self.game.is_none() {
+            if self.is_finished() {
+                return Ok(MatchEvent::Finished);
+            }
+            return self.start_game().map(|()| MatchEvent::Started);
+        }
+        let game = self.game.as_mut().unwrap();
+        let end = match game.poll(&mut self.engines, &mut self.adjudication)? {
+            Step::Waiting => return Ok(MatchEvent::Waiting),
+            Step::Moved => return Ok(MatchEvent::Moved),
+            Step::Finished(end) => end,
+        };
+        let game = self.game.take().unwrap();
+        let first = if game.white == 0 {
+            Team::White
+        } else {
+            Team::Black
+        };
+        match end.winner {
+            Some(winner) if winner == first => self.results[0] += 1,
+            Some(_) => self.results[1] += 1,
+            None => self.results[2] += 1,
+        }
+        let line = format!(
+            "Game {} of {}: {} - {} {} ({})",
+            self.round,
+            self.games,
+            self.engines[game.white].name,
+            self.engines[game.black].name,
+            end.result(),
+            end.reason
+        );
+        append_pgn(&self.pgn, &game.record)?;
+        Ok(MatchEvent::GameOver(line))
+    }
+
+    /// Sets up the next game: each opening is played twice, the first engine
+    /// taking white in odd games and black in even ones.
+    fn start_game(&mut self) -> Result<(), String> {
+        let index = self.round;
+        self.round += 1;
+        let opening = match self.openings.len() {
+            0 => None,
+            count => Some(&self.openings[index / 2 % count]),
+        };
+        let (white, black) = if index % 2 == 0 { (0, 1) } else { (1, 0) };
+        let round = self.round.to_string();
+        let mut headers = vec![
+            ("Event", "Engine match"),
+            ("Round", round.as_str()),
+            ("White", self.engines[white].name.as_str()),
+            ("Black", self.engines[black].name.as_str()),
+        ];
+        headers.extend(
+            self.headers
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        );
+        let game = EngineGame::new(opening, white, black, self.time, &headers)?;
+        for engine in self.engines.iter_mut() {
+            engine
+                .send("ucinewgame")
+                .map_err(|error| format!("{}: {}", engine.name, error))?;
+        }
+        self.game = Some(game);
+        Ok(())
+    }
+
+    /// The results as a table, with the Elo difference of the first engine.
+    pub fn summary(&self) -> Vec<String> {
+        let [wins, losses, draws] = self.results;
+        let played = wins + losses + draws;
+        let row = |name: &str, wins: u32, losses: u32| {
+            let score = f64::from(wins) + f64::from(draws) / 2.0;
+            format!(
+                "{:<24} {:>5}/{:<4} {:>5} {:>6} {:>5}",
+                name, score, played, wins, losses, draws
+            )
+        };
+        let mut lines = vec![
+            format!(
+                "{:<24} {:>10} {:>5} {:>6} {:>5}",
+                "Engine", "Score", "Wins", "Losses", "Draws"
+            ),
+            row(&self.engines[0].name, wins, losses),
+            row(&self.engines[1].name, losses, wins),
+        ];
+        lines.push(match elo_difference(wins, losses, draws) {
+            Some((elo, margin)) => format!(
+                "Elo difference: {:+.0} ± {:.0} for {}",
+                elo, margin, self.engines[0].name
+            ),
+            None => "Elo difference: not measurable from this score".to_string(),
+        });
+        lines
+    }
+}
+
+/// Plays the whole match in the terminal, printing each result and the table at the end.
+pub fn run_headless(mut engine_match: EngineMatch) -> Result<(), String> {
+    loop {
+        match engine_match.poll()? {
+            MatchEvent::Waiting => thread::sleep(HEADLESS_POLL),
+            MatchEvent::Started | MatchEvent::Moved => {}
+            MatchEvent::GameOver(line) => println!("{}", line),
+            MatchEvent::Finished => break,
+        }
+    }
+    for line in engine_match.summary() {
+        println!("{}", line);
+    }
+    Ok(())
+}
@@ -0,0 +1,198 @@
+//! Forsyth–Edwards Notation, used to start games from arbitrary positions.
+
+use crate::position::{
+    coordinate_to_string, letter_rank, pieces, rank_letter, string_to_coordinate, Coordinate,
+};
+use chess::game::{Game, Rank, Team};
+use std::fmt;
+use std::str::FromStr;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone)]
+pub struct Fen {
+    pub placement: Vec<(Coordinate, Team, Rank)>,
+    pub player: Team,
+    /// Castling availability as written in the FEN, e.g. "KQkq" or "-".
+    pub castling: String,
+    pub en_passant: Option<Coordinate>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+impl Fen {
+    /// Describes the pieces and player in turn of `game`. Castling and en passant
+    /// are not known to the board itself and have to be filled in by the caller.
+    pub fn from_game(game: &Game) -> Fen {
+        Fen {
+            placement: pieces(game),
+            player: game.player,
+            castling: "-".to_string(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    pub fn piece_at(&self, coordinate: Coordinate) -> Option<(Team, Rank)> {
+        self.placement
+            .iter()
+            .find(|(square, _, _)| *square == coordinate)
+            .map(|(_, team, rank)| (*team, *rank))
+    }
+
+    /// Only the piece placement field, e.g. "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".
+    pub fn board_field(&self) -> String {
+        let mut field = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_at((file, rank)) {
+                    Some((team, piece)) => {
+                        if empty > 0 {
+                            field += &empty.to_string();
+                            empty = 0;
+                        }
+                        field.push(piece_char(team, piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                field += &empty.to_string();
+            }
+            if rank > 0 {
+                field.push('/');
+            }
+        }
+        field
+    }
+
+    /// Builds a game with this position on the board.
+    ///
+    /// The pieces are copied from a fresh game, so castling rights and en passant
+    /// follow whatever the game infers from the piece placement.
+    pub fn setup(&self) -> Game {
+        let mut game = Game::new();
+        let prototypes: Vec<_> = game
+            .matrix
+            .iter()
+            .flat_map(|column| column.iter())
+            .filter_map(|square| square.piece)
+            .collect();
+
+        for column in game.matrix.iter_mut() {
+            for square in column.iter_mut() {
+                square.piece = self.piece_at(square.coordinate).and_then(|(team, rank)| {
+                    prototypes
+                        .iter()
+                        .find(|piece| piece.team == team && piece.rank == rank)
+                        .copied()
+                });
+            }
+        }
+        game.player = self.player;
+        game
+    }
+}
+
+fn piece_char(team: Team, rank: Rank) -> char {
+    let letter = match rank {
+        Rank::Pawn => 'P',
+        _ => rank_letter(rank).chars().next().unwrap(),
+    };
+    match team {
+        Team::White => letter,
+        Team::Black => letter.to_ascii_lowercase(),
+    }
+}
+
+impl FromStr for Fen {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Fen, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.is_empty() {
+            return Err("Empty FEN".to_string());
+        }
+
+        let rows: Vec<&str> = fields[0].split('/').collect();
+        if rows.len() != 8 {
+            return Err(format!("FEN board must have 8 ranks, found {}", rows.len()));
+        }
+        let mut placement = Vec::new();
+        for (index, row) in rows.iter().enumerate() {
+            let rank = 7 - index as isize;
+            let mut file = 0;
+            for c in row.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    file += skip as isize;
+                    continue;
+                }
+                let piece = letter_rank(c).ok_or_else(|| format!("Unknown piece '{}' in FEN", c))?;
+                let team = if c.is_ascii_uppercase() {
+                    Team::White
+                } else {
+                    Team::Black
+                };
+                if file > 7 {
+                    return Err(format!("Rank {} of the FEN is too long", rank + 1));
+                }
+                placement.push(((file, rank), team, piece));
+                file += 1;
+            }
+            if file != 8 {
+                return Err(format!("Rank {} of the FEN does not have 8 files", rank + 1));
+            }
+        }
+
+        let player = match fields.get(1) {
+            None | Some(&"w") => Team::White,
+            Some(&"b") => Team::Black,
+            Some(other) => return Err(format!("Unknown side to move '{}' in FEN", other)),
+        };
+        let castling = fields.get(2).unwrap_or(&"-").to_string();
+        let en_passant = match fields.get(3) {
+            None | Some(&"-") => None,
+            Some(square) => Some(
+                string_to_coordinate(square)
+                    .ok_or_else(|| format!("Invalid en passant square '{}' in FEN", square))?,
+            ),
+        };
+        let number = |index: usize, default: u32| match fields.get(index) {
+            Some(field) => field
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid move counter '{}' in FEN", field)),
+            None => Ok(default),
+        };
+
+        Ok(Fen {
+            placement,
+            player,
+            castling,
+            en_passant,
+            halfmove_clock: number(4, 0)?,
+            fullmove_number: number(5, 1)?,
+        })
+    }
+}
+
+impl fmt::Display for Fen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            self.board_field(),
+            match self.player {
+                Team::White => "w",
+                Team::Black => "b",
+            },
+            self.castling,
+            self.en_passant
+                .map(coordinate_to_string)
+                .unwrap_or_else(|| "-".to_string()),
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+}
@@ -0,0 +1,230 @@
+//! Engine pass over a finished game that points out inaccuracies, mistakes and blunders.
+
+use crate::analysis::{format_score, white_score};
+use crate::engine::{Engine, Score};
+use crate::outcome::Outcome;
+use crate::position::opponent;
+use crate::record::GameRecord;
+use chess::game::Team;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Thinking time spent on each position of the game.
+const POSITION_TIME: Duration = Duration::from_millis(300);
+/// Mates count as this many centipawns, less the number of moves to mate.
+const MATE_CENTIPAWNS: i32 = 10_000;
+/// Evaluations are capped at this, so that a lost position cannot get much worse.
+pub const EVAL_CAP: i32 = 1000;
+
+/// How good a move was, judged by how much evaluation it gave away.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveClass {
+    /// The engine's choice.
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl MoveClass {
+    /// Classifies a move losing `loss` centipawns for the player who made it.
+    fn from_loss(loss: i32, best: bool) -> MoveClass {
+        match loss {
+            _ if best => MoveClass::Best,
+            loss if loss >= 300 => MoveClass::Blunder,
+            loss if loss >= 100 => MoveClass::Mistake,
+            loss if loss >= 50 => MoveClass::Inaccuracy,
+            _ => MoveClass::Good,
+        }
+    }
+
+    /// Numeric annotation glyph written to the PGN.
+    pub fn nag(self) -> Option<u8> {
+        match self {
+            MoveClass::Best | MoveClass::Good => None,
+            MoveClass::Inaccuracy => Some(6),
+            MoveClass::Mistake => Some(2),
+            MoveClass::Blunder => Some(4),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MoveClass::Best => "Best move",
+            MoveClass::Good => "Good move",
+            MoveClass::Inaccuracy => "Inaccuracy",
+            MoveClass::Mistake => "Mistake",
+            MoveClass::Blunder => "Blunder",
+        }
+    }
+}
+
+pub struct GameReview {
+    /// Dropped once every position has been evaluated.
+    engine: Option<Engine>,
+    /// UCI `position` of every position of the game, starting position included.
+    positions: Vec<String>,
+    /// Moves played, in UCI notation.
+    played: Vec<String>,
+    first_player: Team,
+    /// Score of the last position when the game ended on the board, for
+    /// which engines report no line.
+    final_score: Score,
+    searching: bool,
+    /// Evaluation of each position reviewed so far, from white's point of view.
+    pub scores: Vec<Score>,
+    /// The engine's choice in each position reviewed so far, in UCI notation.
+    best_moves: Vec<Option<String>>,
+}
+
+impl GameReview {
+    /// Starts reviewing the game in `record` with the engine at `path`.
+    pub fn start(path: &Path, record: &GameRecord) -> io::Result<GameReview> {
+        let mut engine = Engine::start(path)?;
+        engine.set_variant(record.variant)?;
+        Ok(GameReview {
+            engine: Some(engine),
+            positions: (0..=record.ply()).map(|ply| record.uci_position_at(ply)).collect(),
+            played: record.moves.iter().map(|recorded| recorded.to_uci()).collect(),
+            first_player: record.player_at(0),
+            final_score: final_score(record),
+            searching: false,
+            scores: Vec::new(),
+            best_moves: Vec::new(),
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.scores.len() == self.positions.len()
+    }
+
+    /// Positions evaluated and positions in total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.scores.len(), self.positions.len())
+    }
+
+    fn player_at(&self, ply: usize) -> Team {
+        if ply % 2 == 0 {
+            self.first_player
+        } else {
+            opponent(self.first_player)
+        }
+    }
+
+    /// Moves the review along, evaluating one position after the other.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let ply = self.scores.len();
+        let player = self.player_at(ply);
+        let engine = match &mut self.engine {
+            Some(engine) if ply < self.positions.len() => engine,
+            _ => return Ok(()),
+        };
+        if !self.searching {
+            engine.go_movetime(&self.positions[ply], POSITION_TIME)?;
+            self.searching = true;
+            return Ok(());
+        }
+        let best_move = match engine.poll() {
+            Some(best_move) => best_move,
+            None => return Ok(()),
+        };
+        // Engines report no line for checkmate or stalemate, only "bestmove (none)".
+        let score = match engine.info.variations.first() {
+            Some(variation) => variation.score,
+            None if ply == self.played.len() => self.final_score,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "engine gave no evaluation")),
+        };
+        self.scores.push(white_score(score, player));
+        self.best_moves.push(Some(best_move).filter(|uci| uci != "(none)"));
+        self.searching = false;
+        if self.is_done() {
+            self.engine = None;
+        }
+        Ok(())
+    }
+
+    /// Evaluation of the position after `ply` moves in centipawns from white's
+    /// point of view, capped at `EVAL_CAP`.
+    pub fn capped_eval(&self, ply: usize) -> Option<i32> {
+        let centipawns = match *self.scores.get(ply)? {
+            Score::Centipawns(centipawns) => centipawns,
+            Score::Mate(moves) if moves >= 0 => MATE_CENTIPAWNS - moves,
+            Score::Mate(moves) => -MATE_CENTIPAWNS - moves,
+            Score::Mated => -MATE_CENTIPAWNS,
+        };
+        Some(centipawns.max(-EVAL_CAP).min(EVAL_CAP))
+    }
+
+    /// Classification of every move reviewed so far.
+    pub fn classes(&self) -> Vec<MoveClass> {
+        (0..self.scores.len().saturating_sub(1))
+            .map(|ply| {
+                let sign = if self.player_at(ply) == Team::White { 1 } else { -1 };
+                let before = self.capped_eval(ply).unwrap() * sign;
+                let after = self.capped_eval(ply + 1).unwrap() * sign;
+                let best = self.best_moves[ply].as_ref() == Some(&self.played[ply]);
+                MoveClass::from_loss(before - after, best)
+            })
+            .collect()
+    }
+
+    /// Adds the evaluations, glyphs and better moves to the notes of `record`,
+    /// which must hold the reviewed game.
+    pub fn annotate(&self, record: &mut GameRecord) {
+        for (ply, class) in self.classes().into_iter().enumerate() {
+            let mut comment = format!("[%eval {}]", pgn_eval(self.scores[ply + 1]));
+            if let Some(nag) = class.nag() {
+                comment += &format!(" {}.", class.name());
+                let best = self.best_moves[ply].iter().cloned().collect::<Vec<_>>();
+                if let Some(san) = record.san_line(ply, &best).first() {
+                    comment += &format!(" {} was best.", san);
+                }
+                record.notes[ply + 1].nags.push(nag);
+            }
+            let notes = &mut record.notes[ply + 1];
+            notes.comment = if notes.comment.is_empty() {
+                comment
+            } else {
+                format!("{} {}", comment, notes.comment)
+            };
+        }
+    }
+
+    /// One line summing up the review, e.g. "2 inaccuracies, 0 mistakes, 3 blunders".
+    pub fn summary(&self) -> String {
+        let classes = self.classes();
+        let count = |class: MoveClass| classes.iter().filter(|c| **c == class).count();
+        format!(
+            "{} inaccuracies, {} mistakes, {} blunders",
+            count(MoveClass::Inaccuracy),
+            count(MoveClass::Mistake),
+            count(MoveClass::Blunder)
+        )
+    }
+}
+
+/// Score for the side to move in the last position of `record`: lost or won
+/// when the game ended there on the board, even otherwise.
+fn final_score(record: &GameRecord) -> Score {
+    let player = record.player_at(record.ply());
+    let outcome = record
+        .replay(record.ply())
+        .ok()
+        .and_then(|mut game| Outcome::detect(&mut game, record));
+    match outcome.and_then(|outcome| outcome.winner()) {
+        Some(winner) if winner == player => Score::Mate(0),
+        Some(_) => Score::Mated,
+        None => Score::Centipawns(0),
+    }
+}
+
+/// Evaluation as written in `[%eval]` commands, e.g. "0.35" or "#-3".
+fn pgn_eval(score: Score) -> String {
+    match score {
+        Score::Centipawns(_) => format_score(score).trim_start_matches('+').to_string(),
+        Score::Mate(moves) => format!("#{}", moves),
+        Score::Mated => "#-0".to_string(),
+    }
+}
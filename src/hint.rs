@@ -0,0 +1,150 @@
+//! Hints for the player in turn: the piece the best move is made with, then
+//! the whole move. The hints used by each side are counted in the PGN headers.
+
+use crate::annotations::{AnnotationColor, Annotations, Arrow, SquareMark};
+use crate::engine::Engine;
+use crate::position::coordinate_to_string;
+use crate::record::{GameRecord, RecordedMove};
+use chess::game::Team;
+use std::path::Path;
+
+/// Best move suggested to the player in turn.
+pub struct Hint {
+    /// Number of moves played when the hint was asked for.
+    pub ply: usize,
+    /// `None` while the engine is still searching.
+    pub best_move: Option<RecordedMove>,
+    /// Whether the whole move is shown rather than just the piece to move.
+    pub revealed: bool,
+}
+
+#[derive(Default)]
+pub struct Hints {
+    pub current: Option<Hint>,
+    /// Engine asked for hints, started on the first one.
+    engine: Option<Engine>,
+    searching: bool,
+}
+
+impl Hints {
+    /// Gives a hint for the position `record` ends in: `known`, the best move
+    /// found by the analysis, or else the one the engine at `path` finds. Asked
+    /// a second time in the position, the whole move is shown. Every position
+    /// a hint is asked for in counts towards the hints recorded in `record`.
+    pub fn ask(
+        &mut self,
+        record: &mut GameRecord,
+        known: Option<RecordedMove>,
+        path: Option<&Path>,
+    ) -> Result<(), String> {
+        let ply = record.ply();
+        if let Some(hint) = self.current.as_mut() {
+            if hint.ply == ply {
+                if hint.best_move.is_some() {
+                    hint.revealed = true;
+                }
+                return Ok(());
+            }
+        }
+        if known.is_none() {
+            self.search(record, path)?;
+        }
+        self.current = Some(Hint {
+            ply,
+            best_move: known,
+            revealed: false,
+        });
+        let tag = match record.player_at(ply) {
+            Team::White => "WhiteHints",
+            Team::Black => "BlackHints",
+        };
+        record.set_header(tag, &(used(record, tag) + 1).to_string());
+        Ok(())
+    }
+
+    /// Starts the hint engine on the position `record` ends in.
+    fn search(&mut self, record: &GameRecord, path: Option<&Path>) -> Result<(), String> {
+        if self.engine.is_none() {
+            let path = path.ok_or_else(|| String::from("add an engine in the settings (F2)"))?;
+            let engine = Engine::start(path)
+                .map_err(|error| format!("could not start {}: {}", path.display(), error))?;
+            self.engine = Some(engine);
+        }
+        let engine = self.engine.as_mut().unwrap();
+        if self.searching {
+            engine.stop().map_err(|error| error.to_string())?;
+        }
+        let position = record.uci_position();
+        engine
+            .set_variant(record.variant)
+            .and_then(|()| engine.go(&position, None))
+            .map_err(|error| error.to_string())?;
+        self.searching = true;
+        Ok(())
+    }
+
+    /// Picks up the move found by the hint engine. Returns whether it is the
+    /// hint for the position after `ply` moves.
+    pub fn poll(&mut self, ply: usize) -> bool {
+        if !self.searching {
+            return false;
+        }
+        let best_move = match self.engine.as_mut().and_then(Engine::poll) {
+            Some(best_move) => best_move,
+            None => return false,
+        };
+        self.searching = false;
+        match self.current.as_mut() {
+            Some(hint) if hint.ply == ply && hint.best_move.is_none() => {
+                hint.best_move = RecordedMove::from_uci(&best_move);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// What the current hint tells the player.
+    pub fn text(&self) -> Option<String> {
+        let hint = self.current.as_ref()?;
+        Some(match (hint.best_move, hint.revealed) {
+            (None, _) => String::from("Looking for a hint..."),
+            (Some(best_move), false) => format!(
+                "Hint: move the piece on {}. H again for the move",
+                coordinate_to_string(best_move.from)
+            ),
+            (Some(best_move), true) => format!(
+                "Hint: {}-{}",
+                coordinate_to_string(best_move.from),
+                coordinate_to_string(best_move.to)
+            ),
+        })
+    }
+
+    /// The hint as a mark on the board, if it is for the position after `ply` moves.
+    pub fn annotations(&self, ply: usize) -> Option<Annotations> {
+        let hint = self.current.as_ref()?;
+        let best_move = hint.best_move?;
+        if hint.ply != ply {
+            return None;
+        }
+        let mut annotations = Annotations::default();
+        if hint.revealed {
+            annotations.toggle_arrow(Arrow {
+                from: best_move.from,
+                to: best_move.to,
+                color: AnnotationColor::Blue,
+            });
+        } else {
+            annotations.toggle_square(SquareMark {
+                square: best_move.from,
+                color: AnnotationColor::Blue,
+            });
+        }
+        Some(annotations)
+    }
+}
+
+/// Number of hints counted in the header `tag` of `record`.
+fn used(record: &GameRecord, tag: &str) -> u32 {
+    record.header(tag).and_then(|used| used.parse().ok()).unwrap_or(0)
+}
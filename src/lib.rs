@@ -0,0 +1,36 @@
+//! The parts of the chess GUI that do not need a window, shared by the GUI in
+//! `main.rs` and the headless game server in `bin/server.rs`.
+
+pub mod analysis;
+pub mod annotations;
+pub mod autosave;
+pub mod book;
+pub mod chat;
+pub mod chess960;
+pub mod cli;
+pub mod clock;
+pub mod database;
+pub mod eco;
+pub mod editor;
+pub mod endgame;
+pub mod engine;
+pub mod engine_match;
+pub mod fen;
+pub mod game_review;
+pub mod hint;
+pub mod network;
+pub mod opponent;
+pub mod outcome;
+pub mod position;
+pub mod puzzle;
+pub mod record;
+pub mod repertoire;
+pub mod server;
+pub mod settings;
+pub mod sound;
+pub mod syzygy;
+pub mod theme;
+pub mod tournament;
+pub mod util;
+pub mod variant;
+pub mod websocket;
@@ -1,6 +1,28 @@
-use chess::game::{Game, GameState, Rank, Team};
+use oskhen_chess_gui::{
+    analysis, annotations, autosave, book, chat, chess960, cli, clock, database, eco, editor,
+    endgame, engine, engine_match, fen, game_review, hint, network, opponent, outcome, position,
+    puzzle, record, repertoire, settings, sound, syzygy, theme, tournament, variant,
+};
+
+use analysis::{format_nodes, format_score, white_share, Analyzer};
+use annotations::{AnnotationColor, Annotations, Arrow, SquareMark};
+use autosave::{Autosave, SavedOpponent};
+use book::Book;
+use chat::{Chat, SPECTATOR};
+use chess::game::{Game, Rank, Team};
 use chess::moves::{Action, ActionType};
 use chess::pgn;
+use cli::{Mode, Options};
+use clock::{format_duration, Clock, TimeControl};
+use database::{Browser, Database, SearchField};
+use eco::{EcoTable, Opening};
+use editor::Editor;
+use endgame::DrillTrainer;
+use engine::Engine;
+use engine_match::{Adjudication, EngineMatch, MatchEvent};
+use fen::Fen;
+use game_review::{GameReview, EVAL_CAP};
+use hint::Hints;
 #[allow(unused_imports)]
 
 /**
@@ -8,14 +30,31 @@ use chess::pgn;
  * Author: Eskil Queseth <eskilq@kth.se>, Viola Söderlund <violaso@kth.se>
  * Last updated: 2020-09-20
  */
+use ggez::audio::{self, SoundSource};
 use ggez::event;
 use ggez::event::MouseButton;
 use ggez::event::{EventHandler, KeyCode, KeyMods};
 use ggez::graphics::{self, Color, DrawMode, DrawParam};
 use ggez::input::keyboard;
 use ggez::{Context, GameResult};
+use network::{Message, NetworkEvent, Order};
+use opponent::Opponent;
+use outcome::{capitalized, status, team_from_name, team_name, Outcome};
+use position::{coordinate_to_string, opponent, piece_at, Coordinate};
+use puzzle::{PuzzleTrainer, Verdict};
+use record::{GameRecord, RecordedMove};
+use repertoire::RepertoireTrainer;
+use settings::{Field, Orientation, Settings, SettingsEditor};
+use variant::{Variant, VariantPicker};
+use std::fs;
 use std::io;
-use std::path;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use syzygy::{Tablebase, Wdl};
+use theme::Theme;
+use tournament::{Format, Player, Tournament, TournamentSession};
 
 const MULTIPLE_SCREEN: f32 = 1.5;
 
@@ -27,18 +66,59 @@ const GRID_CELL_SIZE: (i16, i16) = (
     (45.0 * MULTIPLE_SCREEN) as i16,
 );
 
-/// Size of the application window.
-const SCREEN_SIZE: (f32, f32) = (
+/// Size of the board.
+const BOARD_SIZE: (f32, f32) = (
     GRID_SIZE.0 as f32 * GRID_CELL_SIZE.0 as f32,
     GRID_SIZE.1 as f32 * GRID_CELL_SIZE.1 as f32,
 );
+/// Width of the information panel right of the board.
+const PANEL_WIDTH: f32 = 220.0;
+/// Width of the evaluation bar on the left edge of the panel.
+const EVAL_BAR_WIDTH: f32 = 16.0;
+/// Left edge of the text in the panel.
+const PANEL_TEXT_X: f32 = BOARD_SIZE.0 + EVAL_BAR_WIDTH + 10.0;
+
+/// Size of the application window.
+const SCREEN_SIZE: (f32, f32) = (BOARD_SIZE.0 + PANEL_WIDTH, BOARD_SIZE.1);
 
 // GUI Color representations
-const BLACK: Color = Color::new(60.0 / 255.0, 60.0 / 255.0, 60.0 / 255.0, 1.0);
-const WHITE: Color = Color::new(120.0 / 255.0, 120.0 / 255.0, 120.0 / 255.0, 1.0);
-const AVAILABLE_TILE: Color = Color::new(190.0 / 255.0, 120.0 / 255.0, 100.0 / 255.0, 0.5);
+const PANEL_COLOR: Color = Color::new(40.0 / 255.0, 40.0 / 255.0, 40.0 / 255.0, 1.0);
+const PANEL_TEXT_COLOR: Color = Color::new(230.0 / 255.0, 230.0 / 255.0, 230.0 / 255.0, 1.0);
+const RUNNING_CLOCK_COLOR: Color = Color::new(1.0, 1.0, 1.0, 1.0);
+const STOPPED_CLOCK_COLOR: Color = Color::new(130.0 / 255.0, 130.0 / 255.0, 130.0 / 255.0, 1.0);
 
 const REPLAY_BUTTON_SIZE: (f32, f32) = (120f32, 120f32);
+/// Area of the panel showing the evaluation over the game after a review.
+const EVAL_GRAPH_RECT: (f32, f32, f32, f32) =
+    (PANEL_TEXT_X, 354f32, SCREEN_SIZE.0 - PANEL_TEXT_X - 10f32, 100f32);
+/// Number of games listed at once in the game browser.
+const BROWSER_RESULT_ROWS: usize = 8;
+/// Number of chat rows shown below the chat header, see `draw_chat`.
+const CHAT_ROWS: usize = 6;
+/// Size of the buttons listed on the right side of the pause menu.
+const MENU_BUTTON_SIZE: (f32, f32) = (150f32, 40f32);
+const MENU_BUTTON_COLOR: Color = Color::new(200.0 / 255.0, 200.0 / 255.0, 150.0 / 255.0, 1.0);
+
+/// Number of book moves listed in the panel.
+const BOOK_MOVES_SHOWN: usize = 4;
+/// Number of tablebase moves listed in the panel.
+const TABLEBASE_MOVES_SHOWN: usize = 3;
+/// Characters of a line of text beside the board in the board editor.
+const EDITOR_TEXT_WIDTH: usize = 24;
+/// Left edge of the Chess960 position on the new game screen, and the size
+/// of its squares.
+const NEW_GAME_POSITION_X: f32 = 320.0;
+const NEW_GAME_CELL: f32 = 50.0;
+
+/// Color of the center squares in King of the Hill.
+const HILL_COLOR: Color = Color::new(230.0 / 255.0, 190.0 / 255.0, 60.0 / 255.0, 0.45);
+/// Color of an explosion in Atomic chess at its start, and how long it lasts.
+const EXPLOSION_COLOR: Color = Color::new(1.0, 140.0 / 255.0, 0.0, 0.8);
+const EXPLOSION_DURATION: Duration = Duration::from_millis(600);
+
+/// Time a trainer waits before playing the opponent's move, so that the
+/// player sees their own move first.
+const TRAINER_REPLY_DELAY: Duration = Duration::from_millis(500);
 
 /// GUI logic and event implementation structure.
 struct AppState {
@@ -51,6 +131,81 @@ struct AppState {
     state: State,
     is_replay: bool,
     text:String,
+    record: GameRecord,
+    // Arrows and circles drawn on the current position with the right mouse button.
+    annotations: Annotations,
+    right_drag_start: Option<Coordinate>,
+    outcome: Option<Outcome>,
+    // Team that has offered a draw which the opponent has not answered yet.
+    draw_offer: Option<Team>,
+    opponent: Opponent,
+    clock: Option<Clock>,
+    theme: Theme,
+    // Earlier position shown while stepping through the game with the arrow keys.
+    history: Option<(usize, Game)>,
+    // Window pixels per screen coordinate, see `main`.
+    scale: f32,
+    settings: Settings,
+    settings_editor: SettingsEditor,
+    // Last move as it slides across the board: from, to and when it was played.
+    animation: Option<(Coordinate, Coordinate, Instant)>,
+    move_sound: Option<audio::Source>,
+    // Unfinished game from an earlier session, offered for resuming on startup.
+    resume: Option<Autosave>,
+    // Finished games are stored here, `None` if the database could not be opened.
+    database: Option<Database>,
+    browser: Browser,
+    // Whether the game is stored in the database once it ends, which is not
+    // the case for games that were loaded rather than played.
+    archive_on_end: bool,
+    // Engine analysing the position on screen, see `toggle_analysis`.
+    analyzer: Option<Analyzer>,
+    hints: Hints,
+    // Engine pass over the finished game, see `start_review`.
+    review: Option<GameReview>,
+    // Opening book the engine opponent plays from while it has moves.
+    book: Option<Book>,
+    // Book moves of the position on screen with their share, e.g. "e4 45%",
+    // and the displayed and played ply they were looked up for.
+    book_moves: Vec<String>,
+    book_position: Option<(usize, usize)>,
+    eco: EcoTable,
+    // Opening of the position on screen, looked up like the book moves.
+    opening: Option<Opening>,
+    opening_position: Option<(usize, usize)>,
+    // Syzygy tables, `None` when no directory is set or none could be read.
+    tablebase: Option<Tablebase>,
+    // Result and best moves of the position on screen, looked up like the book
+    // moves, and the best move in UCI notation.
+    tablebase_lines: Vec<String>,
+    tablebase_move: Option<String>,
+    tablebase_position: Option<(usize, usize)>,
+    // Position being set up, see `open_editor`.
+    editor: Option<Editor>,
+    // Squares the selected piece reaches by moves the board does not know,
+    // like castling onto a rook in Chess960 or a horde pawn's double step.
+    variant_targets: Vec<Coordinate>,
+    // Square and time of the last explosion in an Atomic game.
+    explosion: Option<(Coordinate, Instant)>,
+    // Puzzles being solved, see `next_puzzle`.
+    puzzles: Option<PuzzleTrainer>,
+    // Repertoire being drilled, see `next_repertoire_line`.
+    repertoire: Option<RepertoireTrainer>,
+    // Endgame drills being played, see `start_drill`.
+    drills: Option<DrillTrainer>,
+    // Engine match being watched, see `poll_match`.
+    engine_match: Option<EngineMatch>,
+    // Tournament being played, see `next_tournament_game`.
+    tournament: Option<TournamentSession>,
+    // When the trainer was asked to play the opponent's next move.
+    trainer_reply: Option<Instant>,
+    // Choices on the new game screen, see `open_new_game`.
+    new_game: Option<VariantPicker>,
+    // Chat of a network game, and the line being typed while the chat is open.
+    chat: Chat,
+    chat_input: Option<String>,
+    // File written by Ctrl+S and read by Ctrl+O.
+    game_file: PathBuf,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -58,8 +213,101 @@ enum State {
     Active,
     Gameover,
     Pause,
+    Settings,
+    Database,
+    Editor,
+    NewGame,
+}
+
+/// Buttons shown on the right side of the pause menu.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MenuAction {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+}
+
+impl MenuAction {
+    fn label(self) -> &'static str {
+        match self {
+            MenuAction::Resign => "Resign",
+            MenuAction::OfferDraw => "Offer draw",
+            MenuAction::AcceptDraw => "Accept draw",
+            MenuAction::DeclineDraw => "Decline draw",
+        }
+    }
+}
+
+/// Buttons of the board editor, next to the board.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum EditorAction {
+    SideToMove,
+    /// Toggles one of the castling rights "KQkq".
+    Castling(char),
+    Clear,
+    StartPosition,
+    Play,
+    Analyze,
+}
+
+impl EditorAction {
+    fn label(self, editor: &Editor) -> String {
+        match self {
+            EditorAction::SideToMove => format!("{} to move", capitalized(team_name(editor.fen.player))),
+            EditorAction::Castling(right) => {
+                let side = if right.is_ascii_uppercase() { "W" } else { "B" };
+                let castle = if right.to_ascii_uppercase() == 'K' { "O-O" } else { "O-O-O" };
+                format!("{} {}", side, castle)
+            }
+            EditorAction::Clear => String::from("Clear board"),
+            EditorAction::StartPosition => String::from("Start position"),
+            EditorAction::Play => String::from("Play from here"),
+            EditorAction::Analyze => String::from("Analyze"),
+        }
+    }
+}
+
+/// Buttons of the new game screen.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum NewGameAction {
+    Variant(Variant),
+    PreviousPosition,
+    NextPosition,
+    RandomPosition,
+    StandardPosition,
+    Start,
+}
+
+impl NewGameAction {
+    fn label(self) -> &'static str {
+        match self {
+            NewGameAction::Variant(variant) => variant.name(),
+            NewGameAction::PreviousPosition => "Previous",
+            NewGameAction::NextPosition => "Next",
+            NewGameAction::RandomPosition => "Random",
+            NewGameAction::StandardPosition => "Standard (518)",
+            NewGameAction::Start => "Start",
+        }
+    }
 }
 
+/// Pieces to pick from in the board editor, white on the first row.
+const EDITOR_PALETTE: [(Team, Rank); 12] = [
+    (Team::White, Rank::King),
+    (Team::White, Rank::Queen),
+    (Team::White, Rank::Rook),
+    (Team::White, Rank::Bishop),
+    (Team::White, Rank::Knight),
+    (Team::White, Rank::Pawn),
+    (Team::Black, Rank::King),
+    (Team::Black, Rank::Queen),
+    (Team::Black, Rank::Rook),
+    (Team::Black, Rank::Bishop),
+    (Team::Black, Rank::Knight),
+    (Team::Black, Rank::Pawn),
+];
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Tile {
     pos: BoardPosition,
@@ -89,86 +337,2866 @@ impl BoardPosition {
             8 => "h",
             _ => panic!("there shouldnt be a out of bounds letter here"),
         };
-        String::from(column_letter) + &row_letter
+        String::from(column_letter) + &row_letter
+    }
+}
+
+impl From<BoardPosition> for graphics::Rect {
+    fn from(pos: BoardPosition) -> Self {
+        graphics::Rect::new_i32(
+            pos.x as i32 * GRID_CELL_SIZE.0 as i32,
+            pos.y as i32 * GRID_CELL_SIZE.1 as i32,
+            GRID_CELL_SIZE.0 as i32,
+            GRID_CELL_SIZE.1 as i32,
+        )
+    }
+}
+
+impl From<BoardPosition> for ggez::mint::Point2<f32> {
+    fn from(pos: BoardPosition) -> Self {
+        ggez::mint::Point2 {
+            x: pos.x as f32 * GRID_CELL_SIZE.0 as f32,
+            y: (7 - pos.y) as f32 * GRID_CELL_SIZE.1 as f32,
+        }
+    }
+}
+
+impl Tile {
+    fn draw(&mut self, ctx: &mut Context, color: Color) -> GameResult {
+        let rectangle = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            self.pos.into(),
+            color,
+        )?;
+        graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))
+    }
+}
+
+impl AppState {
+    /// Initialise new application, i.e. set up the game chosen on the command line and load resources.
+    fn new(
+        ctx: &mut Context,
+        options: &Options,
+        opponent: Opponent,
+        record: GameRecord,
+        board: Game,
+        scale: f32,
+        settings: Settings,
+    ) -> GameResult<AppState> {
+        let sprites = AppState::load_sprites();
+        let move_sound =
+            audio::Source::from_data(ctx, audio::SoundData::from_bytes(&sound::move_click())).ok();
+        let database = Database::open_default()
+            .map_err(|error| eprintln!("Could not open the game database: {}", error))
+            .ok();
+        let archive_on_end = !matches!(opponent, Opponent::Review | Opponent::Spectating { .. });
+        let book = settings.opening_book.as_ref().and_then(|path| {
+            Book::open(path)
+                .map_err(|error| {
+                    eprintln!("Could not open the opening book {}: {}", path.display(), error)
+                })
+                .ok()
+        });
+        let tablebase = if settings.syzygy.is_empty() {
+            None
+        } else {
+            Tablebase::open(&settings.syzygy)
+                .map_err(|error| eprintln!("Could not open the Syzygy tablebases: {}", error))
+                .ok()
+        };
+
+        let mut state = AppState {
+            sprites: sprites
+                .iter()
+                .map(|_sprite| {
+                    (
+                        _sprite.0,
+                        graphics::Image::new(ctx, _sprite.1.clone()).unwrap(),
+                    )
+                })
+                .collect::<Vec<((Team, Rank), graphics::Image)>>(),
+            board,
+            available_tiles: vec![],
+            available_actions: vec![],
+            selected_piece: None,
+            state: State::Active,
+            is_replay: false,
+            text:String::new(),
+            record,
+            annotations: Annotations::default(),
+            right_drag_start: None,
+            outcome: None,
+            draw_offer: None,
+            opponent,
+            clock: options.time.map(Clock::new),
+            theme: options.theme.unwrap_or(settings.theme),
+            history: None,
+            scale,
+            settings,
+            settings_editor: SettingsEditor::default(),
+            animation: None,
+            move_sound,
+            resume: None,
+            archive_on_end,
+            database,
+            browser: Browser::default(),
+            analyzer: None,
+            hints: Hints::default(),
+            review: None,
+            book,
+            book_moves: Vec::new(),
+            book_position: None,
+            eco: EcoTable::bundled(),
+            opening: None,
+            opening_position: None,
+            tablebase,
+            tablebase_lines: Vec::new(),
+            tablebase_move: None,
+            tablebase_position: None,
+            editor: None,
+            variant_targets: Vec::new(),
+            explosion: None,
+            puzzles: None,
+            repertoire: None,
+            drills: None,
+            engine_match: None,
+            tournament: None,
+            trainer_reply: None,
+            new_game: None,
+            chat: Chat::new(options.chat_log.clone()),
+            chat_input: None,
+            game_file: options.game_file.clone(),
+        };
+
+        if let Some(time) = options.time {
+            state.record.set_header("TimeControl", &time.pgn_tag());
+        }
+        if let Opponent::Engine { engine, color, .. } = &state.opponent {
+            let (engine_tag, player_tag) = match color {
+                Team::White => ("White", "Black"),
+                Team::Black => ("Black", "White"),
+            };
+            state.record.set_header(engine_tag, &engine.name);
+            state.record.set_header(player_tag, "Player");
+        }
+
+        if let Opponent::Review = state.opponent {
+            state.annotations = state.record.notes.last().unwrap().annotations.clone();
+            state.show_ply(0);
+            state.text = String::from("Step through the game with the arrow keys");
+            if state.record.result != "*" {
+                state.outcome = Outcome::detect(&mut state.board, &state.record);
+            }
+        } else if state.opponent.is_waiting() {
+            // The clock starts once the opponent has joined, see `poll_host`.
+            state.text = state.opponent.description();
+        } else {
+            state.text = status(&state.board);
+            if let Some(clock) = state.clock.as_mut() {
+                clock.start(state.board.player);
+            }
+            if let Some(outcome) = Outcome::detect(&mut state.board, &state.record) {
+                state.end_game(outcome);
+            }
+        }
+
+        let resumable = match state.opponent {
+            Opponent::HotSeat | Opponent::Engine { .. } => options.fen.is_none(),
+            _ => false,
+        };
+        if resumable && state.outcome.is_none() {
+            if let Some(saved) = Autosave::load() {
+                state.text = format!("Resume the unfinished game from {}? Y / N", saved.saved_at);
+                state.resume = Some(saved);
+                if let Some(clock) = state.clock.as_mut() {
+                    clock.stop();
+                }
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Loads chess piese images into vector.
+    fn load_sprites() -> Vec<((Team, Rank), String)> {
+        let mut sprites = Vec::new();
+        sprites.push(((Team::Black, Rank::King), "/black_king.png".to_string()));
+        sprites.push(((Team::Black, Rank::Queen), "/black_queen.png".to_string()));
+        sprites.push(((Team::Black, Rank::Rook), "/black_rook.png".to_string()));
+        sprites.push(((Team::Black, Rank::Pawn), "/black_pawn.png".to_string()));
+        sprites.push(((Team::Black, Rank::Bishop), "/black_bishop.png".to_string()));
+        sprites.push(((Team::Black, Rank::Knight), "/black_knight.png".to_string()));
+        sprites.push(((Team::White, Rank::King), "/white_king.png".to_string()));
+        sprites.push(((Team::White, Rank::Queen), "/white_queen.png".to_string()));
+        sprites.push(((Team::White, Rank::Rook), "/white_rook.png".to_string()));
+        sprites.push(((Team::White, Rank::Pawn), "/white_pawn.png".to_string()));
+        sprites.push(((Team::White, Rank::Bishop), "/white_bishop.png".to_string()));
+        sprites.push(((Team::White, Rank::Knight), "/white_knight.png".to_string()));
+        sprites
+    }
+
+    /// Attaches the drawn annotations to the current position of the record and
+    /// removes them from the board.
+    fn clear_annotations(&mut self) {
+        if !self.annotations.is_empty() {
+            self.record.current_notes().annotations = self.annotations.clone();
+            self.annotations.clear();
+        }
+    }
+
+    fn save_pgn(&mut self) {
+        if !self.annotations.is_empty() {
+            self.record.current_notes().annotations = self.annotations.clone();
+        }
+        self.text = match fs::write(&self.game_file, self.record.to_pgn()) {
+            Ok(()) => format!("Saved game to {}", self.game_file.display()),
+            Err(error) => format!("Could not save game: {}", error),
+        };
+    }
+
+    fn load_pgn(&mut self) {
+        if self.opponent.is_network() {
+            self.text = String::from("Games cannot be loaded during a network game");
+            return;
+        }
+        let loaded = fs::read_to_string(&self.game_file)
+            .map_err(|error| error.to_string())
+            .and_then(|text| GameRecord::from_pgn(&text));
+        match loaded {
+            Ok((record, board)) => {
+                self.leave_training();
+                self.archive_on_end = false;
+                self.reset_game(record, board);
+                self.annotations = self.record.notes.last().unwrap().annotations.clone();
+                self.text = format!("Loaded game from {}", self.game_file.display());
+                if self.outcome.is_none() && self.record.result != "*" {
+                    self.state = State::Gameover;
+                }
+            }
+            Err(error) => self.text = format!("Could not load game: {}", error),
+        }
+    }
+
+    /// Replaces the game on the board, e.g. when restarting or loading a game.
+    fn reset_game(&mut self, record: GameRecord, mut board: Game) {
+        board.promotion_piece = self.board.promotion_piece;
+        self.board = board;
+        self.record = record;
+        self.available_tiles.clear();
+        self.available_actions.clear();
+        self.selected_piece = None;
+        self.annotations.clear();
+        self.outcome = None;
+        self.draw_offer = None;
+        self.history = None;
+        self.hints.current = None;
+        self.review = None;
+        self.book_position = None;
+        self.opening_position = None;
+        self.tablebase_position = None;
+        self.explosion = None;
+        self.state = State::Active;
+        self.text = status(&self.board);
+        if let Some(clock) = self.clock.as_mut() {
+            *clock = Clock::new(clock.control);
+            clock.start(self.board.player);
+        }
+        if let Opponent::Engine { engine, thinking, .. } = &mut self.opponent {
+            if *thinking {
+                let _ = engine.stop();
+                *thinking = false;
+            }
+            let _ = engine.send("ucinewgame");
+        }
+        if let Some(outcome) = Outcome::detect(&mut self.board, &self.record) {
+            self.end_game(outcome);
+        }
+    }
+
+    /// Continues the game from the recovery file.
+    fn resume_game(&mut self) {
+        let saved = match self.resume.take() {
+            Some(saved) => saved,
+            None => return,
+        };
+        let restored = GameRecord::from_pgn(&saved.pgn)
+            .and_then(|(record, board)| Ok((record, board, saved.restore_clock()?)));
+        let (record, board, clock) = match restored {
+            Ok(restored) => restored,
+            Err(error) => return self.decline_resume(format!("Could not resume the game: {}", error)),
+        };
+
+        self.opponent = match &saved.opponent {
+            SavedOpponent::HotSeat => Opponent::HotSeat,
+            SavedOpponent::Engine { path, color } => {
+                let color = match team_from_name(color) {
+                    Some(color) => color,
+                    None => return self.decline_resume(format!("Unknown engine color '{}'", color)),
+                };
+                match Engine::start(path) {
+                    Ok(engine) => Opponent::Engine {
+                        engine,
+                        color,
+                        thinking: false,
+                    },
+                    Err(error) => {
+                        return self.decline_resume(format!(
+                            "Could not start {}: {}",
+                            path.display(),
+                            error
+                        ))
+                    }
+                }
+            }
+        };
+        self.archive_on_end = true;
+        self.reset_game(record, board);
+        self.clock = clock;
+        if let Some(clock) = self.clock.as_mut() {
+            clock.start(self.board.player);
+        }
+        if self.outcome.is_none() {
+            self.text = format!("Resumed the game from {}", saved.saved_at);
+        }
+    }
+
+    /// Starts the game given on the command line instead of the saved one.
+    fn decline_resume(&mut self, text: String) {
+        self.resume = None;
+        self.text = text;
+        if let Some(clock) = self.clock.as_mut() {
+            clock.start(self.board.player);
+        }
+    }
+
+    /// Writes the game to the recovery file.
+    fn autosave(&mut self) {
+        if let Some(saved) = Autosave::new(&self.record, self.clock.as_ref(), &self.opponent) {
+            if let Err(error) = saved.save() {
+                self.text = format!("Could not autosave the game: {}", error);
+            }
+        }
+    }
+
+    /// The board shown on screen, which is an earlier position while stepping through the game.
+    fn displayed_board(&self) -> &Game {
+        match &self.history {
+            Some((_, game)) => game,
+            None => &self.board,
+        }
+    }
+
+    /// Shows the position after `ply` moves, or the current position once `ply` reaches it.
+    fn show_ply(&mut self, ply: usize) {
+        if ply >= self.record.ply() {
+            self.history = None;
+            return;
+        }
+        match self.record.replay(ply) {
+            Ok(game) => {
+                self.history = Some((ply, game));
+                self.available_tiles.clear();
+                self.available_actions.clear();
+                self.selected_piece = None;
+            }
+            Err(error) => self.text = error,
+        }
+    }
+
+    fn displayed_ply(&self) -> usize {
+        match &self.history {
+            Some((ply, _)) => *ply,
+            None => self.record.ply(),
+        }
+    }
+
+    /// Whether black is shown at the bottom of the board.
+    fn flipped(&self) -> bool {
+        match self.settings.orientation {
+            Orientation::White => false,
+            Orientation::Black => true,
+            Orientation::Player => self.local_team() == Some(Team::Black),
+        }
+    }
+
+    /// Top left corner of the square at `coordinate`.
+    fn square_position(&self, coordinate: Coordinate) -> ggez::mint::Point2<f32> {
+        let (column, row) = if self.flipped() {
+            (7 - coordinate.0, coordinate.1)
+        } else {
+            (coordinate.0, 7 - coordinate.1)
+        };
+        ggez::mint::Point2 {
+            x: column as f32 * GRID_CELL_SIZE.0 as f32,
+            y: row as f32 * GRID_CELL_SIZE.1 as f32,
+        }
+    }
+
+    /// Where the piece standing on `coordinate` is drawn, on its way there if it just moved.
+    fn piece_position(&self, coordinate: Coordinate) -> ggez::mint::Point2<f32> {
+        let end = self.square_position(coordinate);
+        let (from, since) = match self.animation {
+            Some((from, to, since)) if to == coordinate && self.history.is_none() => (from, since),
+            _ => return end,
+        };
+        let duration = self.settings.animation_speed.duration();
+        if since.elapsed() >= duration {
+            return end;
+        }
+        let progress = since.elapsed().as_secs_f32() / duration.as_secs_f32();
+        let start = self.square_position(from);
+        ggez::mint::Point2 {
+            x: start.x + (end.x - start.x) * progress,
+            y: start.y + (end.y - start.y) * progress,
+        }
+    }
+
+    /// Translates a screen position into the board coordinate under it.
+    fn screen_to_coordinate(&self, x: f32, y: f32) -> Option<Coordinate> {
+        let (column, row) = screen_to_square(x, y)?;
+        if self.flipped() {
+            Some((7 - column, row))
+        } else {
+            Some((column, 7 - row))
+        }
+    }
+
+    /// Leaves the settings screen and stores the settings.
+    fn close_settings(&mut self) {
+        self.settings_editor.select(self.settings_editor.selected);
+        self.return_to_board();
+        self.text = match self.settings.save() {
+            Ok(path) => format!("Settings saved to {}", path.display()),
+            Err(error) => format!("Could not save settings: {}", error),
+        };
+    }
+
+    /// Leaves a screen shown instead of the board.
+    fn return_to_board(&mut self) {
+        self.state = if self.outcome.is_some() && self.history.is_none() {
+            State::Gameover
+        } else {
+            State::Active
+        };
+    }
+
+    fn open_browser(&mut self) {
+        match &self.database {
+            Some(database) => {
+                self.browser.refresh(database);
+                self.state = State::Database;
+            }
+            None => self.text = String::from("The game database could not be opened"),
+        }
+    }
+
+    fn browser_key(&mut self, key: KeyCode) {
+        let changed = match key {
+            KeyCode::Up => {
+                self.browser.move_selection(false, BROWSER_RESULT_ROWS);
+                false
+            }
+            KeyCode::Down => {
+                self.browser.move_selection(true, BROWSER_RESULT_ROWS);
+                false
+            }
+            KeyCode::Back => self.browser.backspace(),
+            KeyCode::Return | KeyCode::NumpadEnter => {
+                if let Some(index) = self.browser.selected_entry() {
+                    self.load_from_database(index);
+                }
+                false
+            }
+            KeyCode::Escape | KeyCode::F3 => {
+                self.return_to_board();
+                false
+            }
+            _ => false,
+        };
+        if changed {
+            self.refresh_browser();
+        }
+    }
+
+    fn refresh_browser(&mut self) {
+        if let Some(database) = &self.database {
+            self.browser.refresh(database);
+        }
+    }
+
+    /// Puts a stored game on the board for stepping through it.
+    fn load_from_database(&mut self, index: usize) {
+        if self.opponent.is_network() {
+            self.text = String::from("Games cannot be loaded during a network game");
+            return self.return_to_board();
+        }
+        let database = match &self.database {
+            Some(database) => database,
+            None => return,
+        };
+        let loaded = database
+            .pgn(&database.entries[index])
+            .map_err(|error| error.to_string())
+            .and_then(|pgn| GameRecord::from_pgn(&pgn));
+        match loaded {
+            Ok((record, board)) => {
+                self.leave_training();
+                self.opponent = Opponent::Review;
+                self.clock = None;
+                self.archive_on_end = false;
+                self.reset_game(record, board);
+                self.annotations = self.record.notes.last().unwrap().annotations.clone();
+                self.show_ply(0);
+                self.state = State::Active;
+                self.text = String::from("Step through the game with the arrow keys");
+            }
+            Err(error) => {
+                self.text = format!("Could not load the game: {}", error);
+                self.return_to_board();
+            }
+        }
+    }
+
+    /// Renders the game browser instead of the board.
+    fn draw_browser(&self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, PANEL_COLOR);
+        draw_text(ctx, "Games", (20f32, 20f32), 40f32, PANEL_TEXT_COLOR)?;
+
+        let browser = &self.browser;
+        let selected_color: Color = [0.0, 0.0, 0.0, 1.0].into();
+        for (index, field) in SearchField::ALL.iter().enumerate() {
+            let rect = browser_row_rect(index);
+            let selected = index == browser.selected;
+            if selected {
+                let highlight =
+                    graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, MENU_BUTTON_COLOR)?;
+                graphics::draw(ctx, &highlight, DrawParam::default())?;
+            }
+            let color = if selected { selected_color } else { PANEL_TEXT_COLOR };
+            let text = field.text(&browser.query);
+            let text = if selected { format!("{}_", text) } else { text.to_string() };
+            draw_text(ctx, field.label(), (rect.x + 10f32, rect.y + 5f32), 18f32, color)?;
+            draw_text(ctx, &text, (rect.x + 200f32, rect.y + 5f32), 18f32, color)?;
+        }
+
+        let database = match &self.database {
+            Some(database) => database,
+            None => return Ok(()),
+        };
+        let shown = browser.results.iter().skip(browser.scroll).take(BROWSER_RESULT_ROWS);
+        for (row, entry) in shown.enumerate() {
+            let index = SearchField::ALL.len() + browser.scroll + row;
+            let rect = browser_row_rect(SearchField::ALL.len() + row);
+            let selected = index == browser.selected;
+            if selected {
+                let highlight =
+                    graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, MENU_BUTTON_COLOR)?;
+                graphics::draw(ctx, &highlight, DrawParam::default())?;
+            }
+            let color = if selected { selected_color } else { PANEL_TEXT_COLOR };
+            let summary = database.entries[*entry].summary();
+            draw_text(ctx, &summary, (rect.x + 10f32, rect.y + 5f32), 18f32, color)?;
+        }
+
+        let help = format!(
+            "{} of {} games. Type to search, Enter to load, Esc to close",
+            browser.results.len(),
+            database.entries.len()
+        );
+        draw_text(ctx, &help, (20f32, SCREEN_SIZE.1 - 40f32), 20f32, PANEL_TEXT_COLOR)
+    }
+
+    /// Opens the board editor on the position on screen.
+    fn open_editor(&mut self) {
+        if self.opponent.is_network() {
+            self.text = String::from("The board cannot be edited during a network game");
+            return;
+        }
+        let fen = match self.record.fen_at(self.displayed_ply()) {
+            Ok(fen) => fen,
+            Err(error) => {
+                self.text = error;
+                return;
+            }
+        };
+        self.editor = Some(Editor::new(fen));
+        self.state = State::Editor;
+        self.text = String::from("Click to place the selected piece, drag to move, right-click to remove");
+    }
+
+    fn close_editor(&mut self) {
+        self.editor = None;
+        self.return_to_board();
+        self.text = status(&self.board);
+    }
+
+    fn editor_key(&mut self, key: KeyCode) {
+        if let KeyCode::Escape | KeyCode::F4 = key {
+            self.close_editor();
+        }
+    }
+
+    /// Handles a click in the board editor: on the board, the palette or a button.
+    fn editor_click(&mut self, x: f32, y: f32, button: MouseButton) {
+        let square = self.screen_to_coordinate(x, y);
+        let point = ggez::mint::Point2 { x, y };
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let drag_from = editor.drag_from.take();
+        match (square, drag_from) {
+            (Some(square), _) if button == MouseButton::Right => return editor.remove(square),
+            (Some(square), Some(from)) if from != square => return editor.drag(from, square),
+            (Some(square), _) => return editor.click(square),
+            // Pieces dragged off the board are taken away.
+            (None, Some(from)) => return editor.remove(from),
+            (None, None) => {}
+        }
+        let picked = (0..EDITOR_PALETTE.len()).find(|index| editor_palette_rect(*index).contains(point));
+        if let Some(index) = picked {
+            editor.selected = EDITOR_PALETTE[index];
+            return;
+        }
+
+        let action = editor_buttons()
+            .into_iter()
+            .find(|(_, rect)| rect.contains(point))
+            .map(|(action, _)| action);
+        match action {
+            Some(EditorAction::SideToMove) => editor.toggle_player(),
+            Some(EditorAction::Castling(right)) => editor.toggle_castling(right),
+            Some(EditorAction::Clear) => editor.clear(),
+            Some(EditorAction::StartPosition) => editor.reset(),
+            Some(EditorAction::Play) => self.play_from_editor(false),
+            Some(EditorAction::Analyze) => self.play_from_editor(true),
+            None => {}
+        }
+    }
+
+    /// Starts a game from the position in the editor if it is a legal one.
+    /// Analysing sets up a game without clock for both sides, with the engine
+    /// looking at the position.
+    fn play_from_editor(&mut self, analyze: bool) {
+        let fen = match self.editor.as_ref().map(Editor::validate) {
+            Some(Ok(fen)) => fen,
+            Some(Err(error)) => {
+                self.text = error;
+                return;
+            }
+            None => return,
+        };
+        self.editor = None;
+        self.leave_training();
+        if analyze || matches!(self.opponent, Opponent::Review) {
+            self.opponent = Opponent::HotSeat;
+        }
+        if analyze {
+            self.clock = None;
+        }
+        let record = GameRecord::starting_from(Some(fen));
+        let board = record.start_game();
+        self.archive_on_end = !analyze;
+        self.reset_game(record, board);
+        if analyze && self.analyzer.is_none() {
+            self.toggle_analysis();
+        }
+    }
+
+    /// Renders the board editor: the position being set up with the palette
+    /// and buttons in place of the panel.
+    fn draw_editor(&self, ctx: &mut Context, editor: &Editor) -> GameResult {
+        graphics::clear(ctx, PANEL_COLOR);
+        let light: Color = self.theme.light_square().into();
+        let dark: Color = self.theme.dark_square().into();
+        let cell = |corner: ggez::mint::Point2<f32>| {
+            graphics::Rect::new(corner.x, corner.y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32)
+        };
+        for file in 0..8 {
+            for rank in 0..8 {
+                let color = match (file + rank) % 2 {
+                    0 => dark,
+                    _ => light,
+                };
+                let rect = cell(self.square_position((file, rank)));
+                let square = graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
+                graphics::draw(ctx, &square, DrawParam::default())?;
+            }
+        }
+        if let Some(from) = editor.drag_from {
+            let rect = cell(self.square_position(from));
+            let color = self.theme.available_tile().into();
+            let square = graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
+            graphics::draw(ctx, &square, DrawParam::default())?;
+        }
+        for (coordinate, team, rank) in editor.fen.placement.iter() {
+            self.draw_sprite(ctx, (*team, *rank), cell(self.square_position(*coordinate)))?;
+        }
+
+        for (index, piece) in EDITOR_PALETTE.iter().enumerate() {
+            let rect = editor_palette_rect(index);
+            if *piece == editor.selected {
+                let highlight =
+                    graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, MENU_BUTTON_COLOR)?;
+                graphics::draw(ctx, &highlight, DrawParam::default())?;
+            }
+            self.draw_sprite(ctx, *piece, rect)?;
+        }
+        for (action, rect) in editor_buttons() {
+            draw_button(ctx, rect, &action.label(editor))?;
+            // Castling rights not granted are shown greyed out.
+            if let EditorAction::Castling(right) = action {
+                if !editor.has_castling(right) {
+                    let shade = graphics::Mesh::new_rectangle(
+                        ctx,
+                        DrawMode::fill(),
+                        rect,
+                        Color::new(0.0, 0.0, 0.0, 0.5),
+                    )?;
+                    graphics::draw(ctx, &shade, DrawParam::default())?;
+                }
+            }
+        }
+        for (index, line) in wrap_text(&self.text, EDITOR_TEXT_WIDTH).iter().enumerate() {
+            let position = (BOARD_SIZE.0 + 8f32, 350f32 + 18f32 * index as f32);
+            draw_text(ctx, line, position, 16f32, PANEL_TEXT_COLOR)?;
+        }
+        draw_text(ctx, "Esc to leave", (BOARD_SIZE.0 + 8f32, BOARD_SIZE.1 - 26f32), 16f32, PANEL_TEXT_COLOR)
+    }
+
+    /// Draws the image of `piece` scaled to fill `rect`.
+    fn draw_sprite(&self, ctx: &mut Context, piece: (Team, Rank), rect: graphics::Rect) -> GameResult {
+        let sprite = match self.sprites.iter().find(|(sprite_piece, _)| *sprite_piece == piece) {
+            Some((_, sprite)) => sprite,
+            None => return Ok(()),
+        };
+        graphics::draw(
+            ctx,
+            sprite,
+            DrawParam::default()
+                .scale(ggez::mint::Point2 {
+                    x: rect.w / sprite.width() as f32,
+                    y: rect.h / sprite.height() as f32,
+                })
+                .dest(ggez::mint::Point2 { x: rect.x, y: rect.y }),
+        )
+    }
+
+    /// Opens the screen picking the rules and starting position of a new game.
+    fn open_new_game(&mut self) {
+        if self.opponent.is_network() {
+            self.text = String::from("A new game cannot be started during a network game");
+            return;
+        }
+        let position = self
+            .record
+            .start
+            .as_ref()
+            .and_then(chess960::position_number)
+            .unwrap_or_else(chess960::random_position);
+        self.new_game = Some(VariantPicker::new(self.record.variant, position));
+        self.state = State::NewGame;
+    }
+
+    fn close_new_game(&mut self) {
+        self.new_game = None;
+        self.return_to_board();
+    }
+
+    fn new_game_key(&mut self, key: KeyCode) {
+        let picker = match self.new_game.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        let chess960 = picker.variant == Variant::Chess960;
+        match key {
+            KeyCode::Up => picker.step_variant(false),
+            KeyCode::Down => picker.step_variant(true),
+            KeyCode::Left if chess960 => picker.step_position(false),
+            KeyCode::Right if chess960 => picker.step_position(true),
+            KeyCode::R if chess960 => picker.random_position(),
+            KeyCode::Back => picker.backspace(),
+            KeyCode::Return | KeyCode::NumpadEnter => self.start_new_game(),
+            KeyCode::Escape | KeyCode::F5 => self.close_new_game(),
+            _ => {}
+        }
+    }
+
+    fn new_game_click(&mut self, x: f32, y: f32) {
+        let picker = match self.new_game.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        let action = new_game_buttons(picker.variant)
+            .into_iter()
+            .find(|(_, rect)| rect.contains(ggez::mint::Point2 { x, y }))
+            .map(|(action, _)| action);
+        match action {
+            Some(NewGameAction::Variant(variant)) => picker.variant = variant,
+            Some(NewGameAction::PreviousPosition) => picker.step_position(false),
+            Some(NewGameAction::NextPosition) => picker.step_position(true),
+            Some(NewGameAction::RandomPosition) => picker.random_position(),
+            Some(NewGameAction::StandardPosition) => picker.standard_position(),
+            Some(NewGameAction::Start) => self.start_new_game(),
+            None => {}
+        }
+    }
+
+    /// Starts a game by the rules picked on the new game screen, against the
+    /// same opponent.
+    fn start_new_game(&mut self) {
+        let picker = match self.new_game.take() {
+            Some(picker) => picker,
+            None => return,
+        };
+        self.leave_training();
+        if let Opponent::Review = self.opponent {
+            self.opponent = Opponent::HotSeat;
+        }
+        let mut record = GameRecord::starting_from(picker.start_position());
+        record.set_variant(picker.variant);
+        let board = record.start_game();
+        self.archive_on_end = true;
+        self.reset_game(record, board);
+        autosave::clear();
+    }
+
+    /// Renders the new game screen over the board, with the first ranks of
+    /// the Chess960 position picked.
+    fn draw_new_game(&self, ctx: &mut Context, picker: &VariantPicker) -> GameResult {
+        graphics::clear(ctx, PANEL_COLOR);
+        draw_text(ctx, "New game", (20f32, 20f32), 40f32, PANEL_TEXT_COLOR)?;
+
+        for (action, rect) in new_game_buttons(picker.variant) {
+            let selected = match action {
+                NewGameAction::Variant(variant) => variant == picker.variant,
+                _ => {
+                    draw_button(ctx, rect, action.label())?;
+                    continue;
+                }
+            };
+            let color: Color = if selected {
+                let highlight =
+                    graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, MENU_BUTTON_COLOR)?;
+                graphics::draw(ctx, &highlight, DrawParam::default())?;
+                [0.0, 0.0, 0.0, 1.0].into()
+            } else {
+                PANEL_TEXT_COLOR
+            };
+            draw_text(ctx, action.label(), (rect.x + 10f32, rect.y + 6f32), 24f32, color)?;
+        }
+
+        if picker.variant == Variant::Chess960 {
+            let number = if picker.typed.is_empty() {
+                picker.position.to_string()
+            } else {
+                format!("{}_", picker.typed)
+            };
+            let title = format!("Position {}", number);
+            draw_text(ctx, &title, (NEW_GAME_POSITION_X, 90f32), 28f32, PANEL_TEXT_COLOR)?;
+
+            let light: Color = self.theme.light_square().into();
+            let dark: Color = self.theme.dark_square().into();
+            let pieces = chess960::back_rank(picker.position);
+            // Black's first rank above white's, as on the board.
+            for (row, team) in [Team::Black, Team::White].iter().enumerate() {
+                for (file, rank) in pieces.iter().enumerate() {
+                    let rect = graphics::Rect::new(
+                        NEW_GAME_POSITION_X + NEW_GAME_CELL * file as f32,
+                        290f32 + NEW_GAME_CELL * row as f32,
+                        NEW_GAME_CELL,
+                        NEW_GAME_CELL,
+                    );
+                    let color = if (file + row) % 2 == 0 { light } else { dark };
+                    let square = graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
+                    graphics::draw(ctx, &square, DrawParam::default())?;
+                    self.draw_sprite(ctx, (*team, *rank), rect)?;
+                }
+            }
+        } else if picker.variant != Variant::Standard {
+            let rules = wrap_text(picker.variant.rules(), EDITOR_TEXT_WIDTH + 8);
+            for (index, line) in rules.iter().enumerate() {
+                let position = (NEW_GAME_POSITION_X, 90f32 + 26f32 * index as f32);
+                draw_text(ctx, line, position, 22f32, PANEL_TEXT_COLOR)?;
+            }
+        }
+
+        let help = match picker.variant {
+            Variant::Chess960 => {
+                "Up/Down: rules, Left/Right or digits: position, R: random, Enter: start"
+            }
+            _ => "Up/Down to pick the rules, Enter to start, Esc to cancel",
+        };
+        draw_text(ctx, help, (20f32, SCREEN_SIZE.1 - 40f32), 20f32, PANEL_TEXT_COLOR)
+    }
+
+    /// Applies a change made on the settings screen to the running game.
+    fn settings_changed(&mut self) {
+        self.theme = self.settings.theme;
+    }
+
+    fn settings_key(&mut self, key: KeyCode) {
+        let editor = &mut self.settings_editor;
+        if editor.editing.is_some() {
+            match key {
+                KeyCode::Return | KeyCode::NumpadEnter => editor.commit(&mut self.settings),
+                KeyCode::Back => editor.backspace(),
+                KeyCode::Escape => editor.select(editor.selected),
+                _ => {}
+            }
+            return;
+        }
+        match key {
+            KeyCode::Up => editor.up(),
+            KeyCode::Down => editor.down(),
+            KeyCode::Left => editor.activate(&mut self.settings, false),
+            KeyCode::Right | KeyCode::Return | KeyCode::NumpadEnter | KeyCode::Space => {
+                editor.activate(&mut self.settings, true)
+            }
+            KeyCode::Escape | KeyCode::F2 => return self.close_settings(),
+            _ => {}
+        }
+        self.settings_changed();
+    }
+
+    /// Renders the settings screen over the board.
+    fn draw_settings(&self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, PANEL_COLOR);
+        draw_text(ctx, "Settings", (20f32, 20f32), 40f32, PANEL_TEXT_COLOR)?;
+
+        let editor = &self.settings_editor;
+        for (index, field) in Field::ALL.iter().enumerate() {
+            let rect = settings_row_rect(index);
+            if index == editor.selected {
+                let highlight =
+                    graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, MENU_BUTTON_COLOR)?;
+                graphics::draw(ctx, &highlight, DrawParam::default())?;
+            }
+            let color: Color = if index == editor.selected {
+                [0.0, 0.0, 0.0, 1.0].into()
+            } else {
+                PANEL_TEXT_COLOR
+            };
+            let value = match &editor.editing {
+                Some(text) if index == editor.selected => format!("{}_", text),
+                _ => field.value(&self.settings),
+            };
+            let value = if field.needs_restart() {
+                format!("{} (next start)", value)
+            } else {
+                value
+            };
+            draw_text(ctx, field.label(), (rect.x + 10f32, rect.y + 6f32), 24f32, color)?;
+            draw_text(ctx, &value, (rect.x + 200f32, rect.y + 6f32), 24f32, color)?;
+        }
+
+        let help = match (&editor.error, &editor.editing) {
+            (Some(error), _) => error.clone(),
+            (None, Some(_)) => String::from("Type the value, Enter to keep it, Esc to cancel"),
+            (None, None) if editor.field() == Field::Engines => {
+                String::from("Enter to edit, separate engine paths with ';'")
+            }
+            (None, None) => String::from("Up/Down to select, Left/Right or Enter to change, Esc to close"),
+        };
+        draw_text(ctx, &help, (20f32, SCREEN_SIZE.1 - 40f32), 20f32, PANEL_TEXT_COLOR)
+    }
+
+    /// The team played by the person in front of this window, `None` when reviewing a game.
+    fn local_team(&self) -> Option<Team> {
+        match &self.opponent {
+            Opponent::HotSeat => Some(self.board.player),
+            Opponent::Review => None,
+            other => other.color().map(opponent),
+        }
+    }
+
+    /// Whether the pieces on the board may be moved with the mouse right now.
+    fn can_move(&self) -> bool {
+        self.outcome.is_none()
+            && self.history.is_none()
+            && self.resume.is_none()
+            && self.opponent.is_local(self.board.player)
+            && !self.training_finished()
+            && !self.connection_down()
+    }
+
+    /// Whether the puzzle, repertoire line or drill on the board is over, so
+    /// that neither side moves any more.
+    fn training_finished(&self) -> bool {
+        self.puzzles.as_ref().map_or(false, PuzzleTrainer::is_finished)
+            || self.repertoire.as_ref().map_or(false, RepertoireTrainer::is_finished)
+            || self.drills.as_ref().map_or(false, DrillTrainer::is_finished)
+    }
+
+    /// Sends a message to the remote player, or to the host when watching.
+    fn send(&mut self, message: Message) {
+        let connection = match &mut self.opponent {
+            Opponent::Network { connection, .. } | Opponent::Spectating { connection } => {
+                connection
+            }
+            _ => return,
+        };
+        if let Err(error) = connection.send(&message) {
+            self.text = format!("Lost connection: {}", error);
+        }
+    }
+
+    /// Sends a message to the spectators of a hosted game.
+    fn broadcast(&mut self, message: Message) {
+        if let Opponent::Network {
+            spectators: Some(spectators),
+            ..
+        } = &mut self.opponent
+        {
+            spectators.broadcast(&message);
+        }
+    }
+
+    /// Bookkeeping after `mover` has made a move on the board.
+    fn after_move(&mut self, mover: Team) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.press(mover);
+        }
+        let last = *self.record.moves.last().unwrap();
+        self.animation = Some((last.from, last.to, Instant::now()));
+        // Only an explosion takes away the piece that just moved.
+        if self.record.variant == Variant::Atomic && piece_at(&self.board, last.to).is_none() {
+            self.explosion = Some((last.to, Instant::now()));
+        }
+        if self.settings.sound {
+            if let Some(sound) = self.move_sound.as_mut() {
+                let _ = sound.play();
+            }
+        }
+        // Moving instead of answering declines the opponent's draw offer.
+        if self.draw_offer == Some(opponent(mover)) {
+            self.draw_offer = None;
+        }
+        if self.opponent.color().map_or(false, |remote| remote != mover) {
+            self.send(Message::Move {
+                uci: last.to_uci(),
+                seq: Some(self.record.ply()),
+            });
+        }
+        self.broadcast(Message::Move {
+            uci: last.to_uci(),
+            seq: Some(self.record.ply()),
+        });
+        if let Some(remaining) = self.clock.as_ref().map(Clock::remaining_millis) {
+            self.broadcast(Message::Clock { remaining });
+        }
+
+        self.eco.tag(&mut self.record);
+
+        self.available_tiles.clear();
+        self.available_actions.clear();
+        self.variant_targets.clear();
+        self.selected_piece = None;
+        // Puzzles end with the solution, not with the game.
+        if self.puzzles.is_some() {
+            return self.puzzle_moved(mover);
+        }
+        if self.repertoire.is_some() {
+            return self.repertoire_moved(mover);
+        }
+        self.text = status(&self.board);
+        if let Some(outcome) = Outcome::detect(&mut self.board, &self.record) {
+            self.end_game(outcome);
+        } else if let Some(team) = self.draw_offer {
+            self.text = format!(
+                "{} offers a draw. Y to accept, N to decline.",
+                capitalized(team_name(team))
+            );
+        }
+        // Drills are judged once the game has been checked for its end, and not saved.
+        if self.drills.is_some() {
+            return self.drill_moved(mover);
+        }
+        if self.outcome.is_none() {
+            self.autosave();
+        }
+    }
+
+    /// Puts the next puzzle on the board. Leaving a puzzle unsolved counts as failing it.
+    fn next_puzzle(&mut self) {
+        let trainer = match self.puzzles.as_mut() {
+            Some(trainer) => trainer,
+            None => return,
+        };
+        if !trainer.is_finished() && trainer.puzzle().is_some() {
+            trainer.give_up();
+            self.save_puzzle_stats();
+        }
+        let trainer = self.puzzles.as_mut().unwrap();
+        let fen = match trainer.next_puzzle() {
+            Some(Ok(fen)) => fen,
+            Some(Err(error)) => {
+                self.text = format!("Could not set up the puzzle: {}", error);
+                return;
+            }
+            None => {
+                self.text = String::from("No puzzle has that theme, F to pick another");
+                return;
+            }
+        };
+        // The trainer plays the side to move when the solution starts with the opponent's move.
+        let opponent_first = trainer.puzzle().map_or(false, |puzzle| puzzle.opponent_first);
+        let color = if opponent_first { fen.player } else { opponent(fen.player) };
+        self.opponent = Opponent::Trainer { color };
+        self.clock = None;
+        let record = GameRecord::starting_from(Some(fen));
+        let board = record.start_game();
+        self.archive_on_end = false;
+        self.reset_game(record, board);
+        if opponent_first {
+            self.trainer_reply = Some(Instant::now());
+        } else {
+            self.text = format!("Find the best move for {}", team_name(self.board.player));
+        }
+    }
+
+    /// Filters the puzzles by the next theme, see `PuzzleTrainer::next_theme`.
+    fn next_puzzle_theme(&mut self) {
+        if let Some(trainer) = self.puzzles.as_mut() {
+            trainer.next_theme();
+            self.text = match trainer.themes.first() {
+                Some(theme) => format!("Puzzles with theme {}, N for the next one", theme),
+                None => String::from("Puzzles of every theme, N for the next one"),
+            };
+        }
+    }
+
+    /// Judges a move made in a puzzle, or prompts for the player's move after the trainer's.
+    fn puzzle_moved(&mut self, mover: Team) {
+        let trainer = match self.puzzles.as_mut() {
+            Some(trainer) => trainer,
+            None => return,
+        };
+        if self.opponent.color() == Some(mover) {
+            self.text = format!("Find the best move for {}", team_name(self.board.player));
+            return;
+        }
+        let (record, ply) = (&self.record, self.record.ply());
+        let solution = trainer
+            .puzzle()
+            .and_then(|puzzle| puzzle.solution.get(ply - 1).cloned())
+            .and_then(|uci| record.san_line(ply - 1, &[uci]).pop())
+            .unwrap_or_default();
+        match trainer.judge(&self.record, &self.board) {
+            Verdict::Correct => {
+                self.text = String::from("Correct, keep going");
+                self.trainer_reply = Some(Instant::now());
+            }
+            Verdict::Solved => {
+                self.text = format!(
+                    "Solved! Rating {:.0} ({:+.0}), N for the next puzzle",
+                    trainer.stats.rating.rating, trainer.last_change
+                );
+                self.save_puzzle_stats();
+            }
+            Verdict::Wrong => {
+                self.text = format!(
+                    "Wrong, the move was {}. Rating {:.0} ({:+.0}), N for the next puzzle",
+                    solution, trainer.stats.rating.rating, trainer.last_change
+                );
+                self.save_puzzle_stats();
+            }
+        }
+    }
+
+    fn save_puzzle_stats(&mut self) {
+        if let Some(trainer) = &self.puzzles {
+            if let Err(error) = trainer.stats.save() {
+                self.text = format!("Could not save the puzzle rating: {}", error);
+            }
+        }
+    }
+
+    /// Puts the next line of the repertoire on the board, see `RepertoireTrainer::next_line`.
+    fn next_repertoire_line(&mut self) {
+        let trainer = match self.repertoire.as_mut() {
+            Some(trainer) => trainer,
+            None => return,
+        };
+        let is_due = trainer.next_line(repertoire::now());
+        let color = trainer.color;
+        let start = trainer.line().and_then(|line| line.start.clone());
+        self.opponent = Opponent::Trainer { color: opponent(color) };
+        self.clock = None;
+        let record = GameRecord::starting_from(start);
+        let board = record.start_game();
+        self.archive_on_end = false;
+        self.reset_game(record, board);
+        if self.board.player != color {
+            self.trainer_reply = Some(Instant::now());
+        }
+        self.text = if is_due {
+            format!("Play the repertoire moves for {}", team_name(color))
+        } else {
+            String::from("No line is due, this one is drilled ahead of schedule")
+        };
+    }
+
+    /// Judges a move made in a repertoire line, taking it back when it is not
+    /// the repertoire move.
+    fn repertoire_moved(&mut self, mover: Team) {
+        let trainer = match self.repertoire.as_mut() {
+            Some(trainer) => trainer,
+            None => return,
+        };
+        let ply = self.record.ply();
+        let time = repertoire::now();
+        if self.opponent.color() == Some(mover) {
+            self.text = if trainer.complete_at(ply, time) {
+                let days = trainer.days_until_due(time).unwrap_or_default();
+                format!("Line learned, due again in {} days, N for the next line", days)
+            } else {
+                format!("Play the repertoire moves for {}", team_name(self.board.player))
+            };
+            return self.save_repertoire();
+        }
+        let played = self.record.moves[ply - 1].to_uci();
+        let expected = trainer.expected(ply - 1).map(String::from);
+        match trainer.judge(ply - 1, &played, time) {
+            repertoire::Verdict::Correct => {
+                self.text = String::from("Correct");
+                self.trainer_reply = Some(Instant::now());
+            }
+            repertoire::Verdict::Completed => {
+                let days = trainer.days_until_due(time).unwrap_or_default();
+                self.text = format!("Line learned, due again in {} days, N for the next line", days);
+                self.save_repertoire();
+            }
+            repertoire::Verdict::Wrong => {
+                let expected = expected
+                    .and_then(|uci| self.record.san_line(ply - 1, &[uci]).pop())
+                    .unwrap_or_default();
+                // Take the move back by replaying the line up to it.
+                let mut record = self.record.restarted();
+                let mut board = record.start_game();
+                for recorded in self.record.moves[..ply - 1].to_vec() {
+                    if let Err(error) = record.play_move(&mut board, recorded) {
+                        self.text = format!("The repertoire line is broken: {}", error);
+                        return;
+                    }
+                }
+                self.reset_game(record, board);
+                self.text = format!("The repertoire plays {} here, try again", expected);
+            }
+        }
+    }
+
+    fn save_repertoire(&mut self) {
+        if let Some(trainer) = &self.repertoire {
+            if let Err(error) = trainer.save() {
+                self.text = format!("Could not save the repertoire progress: {}", error);
+            }
+        }
+    }
+
+    /// Puts a drill on the board, `fen` being its position.
+    fn start_drill(&mut self, fen: Fen) {
+        let color = opponent(fen.player);
+        match &mut self.opponent {
+            Opponent::Engine { color: engine_color, .. } => *engine_color = color,
+            _ => self.opponent = Opponent::Trainer { color },
+        }
+        self.clock = None;
+        let record = GameRecord::starting_from(Some(fen));
+        let board = record.start_game();
+        self.archive_on_end = false;
+        self.reset_game(record, board);
+        if let Some(trainer) = &self.drills {
+            let drill = trainer.drill();
+            self.text = format!("{}: {}", drill.name, drill.task());
+        }
+    }
+
+    /// Moves on to the next drill with N, or plays the same one again with R.
+    /// Leaving a drill before it is over counts as failing it.
+    fn change_drill(&mut self, next: bool) {
+        let trainer = match self.drills.as_mut() {
+            Some(trainer) => trainer,
+            None => return,
+        };
+        trainer.give_up(&self.record);
+        let fen = if next { trainer.next_drill() } else { trainer.restart() };
+        self.save_drill_stats();
+        self.start_drill(fen);
+    }
+
+    /// Judges the drill after a move, and has the built-in opponent answer the player's.
+    fn drill_moved(&mut self, mover: Team) {
+        let trainer = match self.drills.as_mut() {
+            Some(trainer) => trainer,
+            None => return,
+        };
+        match trainer.judge(&self.record, &self.board, self.outcome.as_ref()) {
+            endgame::Verdict::Playing => {
+                if let Opponent::Trainer { color } = self.opponent {
+                    if color != mover {
+                        self.trainer_reply = Some(Instant::now());
+                    }
+                }
+            }
+            endgame::Verdict::Passed(reason) => {
+                self.text = format!("Passed, {}. N for the next drill, R to retry", reason);
+                self.save_drill_stats();
+            }
+            endgame::Verdict::Failed(reason) => {
+                self.text = format!("Failed, {}. R to retry, N for the next drill", reason);
+                self.save_drill_stats();
+            }
+        }
+    }
+
+    fn save_drill_stats(&mut self) {
+        if let Some(trainer) = &self.drills {
+            if let Err(error) = trainer.save() {
+                self.text = format!("Could not save the drill stats: {}", error);
+            }
+        }
+    }
+
+    /// Plays the trainer's move once the player has had time to see their own.
+    fn poll_trainer(&mut self) {
+        match self.trainer_reply {
+            Some(since) if since.elapsed() >= TRAINER_REPLY_DELAY => self.trainer_reply = None,
+            _ => return,
+        }
+        let ply = self.record.ply();
+        let player = self.board.player;
+        let reply = match (&self.puzzles, &self.repertoire) {
+            (Some(trainer), _) => trainer.reply(ply),
+            (None, Some(trainer)) => trainer.reply(ply, player),
+            (None, None) if self.drills.is_some() && self.outcome.is_none() => {
+                endgame::builtin_move(&mut self.board)
+            }
+            (None, None) => None,
+        };
+        if let Some(recorded) = reply {
+            let mover = self.board.player;
+            match self.record.play_move(&mut self.board, recorded) {
+                Ok(()) => self.after_move(mover),
+                Err(error) => self.text = format!("The trainer's move is illegal: {}", error),
+            }
+        }
+    }
+
+    /// Ends training, or stops watching an engine match or tournament game, when
+    /// another game is put on the board.
+    fn leave_training(&mut self) {
+        self.engine_match = None;
+        // An unfinished tournament game is played again with N.
+        if let Some(session) = self.tournament.as_mut() {
+            session.current = None;
+        }
+        if let Opponent::Trainer { .. } = self.opponent {
+            self.opponent = Opponent::HotSeat;
+        }
+        self.puzzles = None;
+        self.repertoire = None;
+        if let Some(trainer) = self.drills.as_mut() {
+            trainer.give_up(&self.record);
+        }
+        self.save_drill_stats();
+        self.drills = None;
+        self.trainer_reply = None;
+    }
+
+    /// Moves the engine match along, showing its games as they are played.
+    fn poll_match(&mut self) {
+        loop {
+            let mut tournament_result = None;
+            let engine_match = match self.engine_match.as_mut() {
+                Some(engine_match) if !engine_match.is_finished() => engine_match,
+                _ => return,
+            };
+            match engine_match.poll() {
+                Ok(MatchEvent::Waiting) | Ok(MatchEvent::Finished) => return,
+                Ok(MatchEvent::Started) | Ok(MatchEvent::Moved) => {}
+                Ok(MatchEvent::GameOver(line)) => {
+                    self.text = line;
+                    if engine_match.is_finished() && self.tournament.is_some() {
+                        // Tournament games are matches of a single game.
+                        tournament_result = Some(match engine_match.results {
+                            [1, _, _] => "1-0",
+                            [_, 1, _] => "0-1",
+                            _ => "1/2-1/2",
+                        });
+                    }
+                }
+                Err(error) => {
+                    self.engine_match = None;
+                    return self.abandon_tournament_game(format!("The match stopped: {}", error));
+                }
+            }
+            // A finished game stays on the board until the next one starts.
+            let record = engine_match.game.as_ref().map(|game| game.record.clone());
+            if let Some(record) = record {
+                match record.replay(record.ply()) {
+                    Ok(board) => self.show_match_game(record, board),
+                    Err(error) => self.text = error,
+                }
+            }
+            if let Some(result) = tournament_result {
+                self.finish_tournament_game(result);
+                self.engine_match = None;
+                return;
+            }
+        }
+    }
+
+    fn show_match_game(&mut self, record: GameRecord, board: Game) {
+        if let Some(last) = record.moves.last() {
+            if record.ply() > self.record.ply() {
+                self.animation = Some((last.from, last.to, Instant::now()));
+            }
+        }
+        self.board = board;
+        self.record = record;
+        self.available_tiles.clear();
+        self.available_actions.clear();
+        self.selected_piece = None;
+        self.history = None;
+    }
+
+    /// Puts the next game of the tournament on the board: a match of one game
+    /// between two engines, or a game against an engine or between two people
+    /// played in the window.
+    fn next_tournament_game(&mut self) {
+        if self.tournament.as_ref().map_or(true, |session| session.current.is_some()) {
+            return;
+        }
+        self.leave_training();
+        let session = self.tournament.as_mut().unwrap();
+        let (round, board) = match session.tournament.next_game() {
+            Some(game) => game,
+            None => {
+                self.text = "The tournament is over".to_string();
+                return;
+            }
+        };
+        session.current = Some((round, board));
+        let tournament = &session.tournament;
+        let pairing = &tournament.rounds[round][board];
+        let white = tournament.players[pairing.white].clone();
+        // Byes are never played.
+        let black = tournament.players[pairing.black.unwrap()].clone();
+        let headers = vec![
+            ("Event".to_string(), tournament.name.clone()),
+            ("Round".to_string(), format!("{}.{}", round + 1, board + 1)),
+            ("White".to_string(), white.name.clone()),
+            ("Black".to_string(), black.name.clone()),
+        ];
+        let (time, adjudication) = (tournament.time_control(), session.adjudication);
+        let pgn = session.pgn_path();
+        // The pairing of a new Swiss round is kept even if the game is not played.
+        let saved = session.save();
+
+        if let (Some(first), Some(second)) = (&white.engine, &black.engine) {
+            let tablebase = if self.settings.syzygy.is_empty() {
+                None
+            } else {
+                Tablebase::open(&self.settings.syzygy).ok()
+            };
+            let adjudication = Adjudication {
+                options: adjudication,
+                tablebase,
+            };
+            let engines = [first.as_path(), second.as_path()];
+            match EngineMatch::start(engines, 1, Vec::new(), time, adjudication, pgn) {
+                Ok(mut engine_match) => {
+                    engine_match.headers = headers;
+                    self.engine_match = Some(engine_match);
+                    self.opponent = Opponent::Review;
+                    self.clock = None;
+                }
+                Err(error) => return self.abandon_tournament_game(error),
+            }
+        } else {
+            self.opponent = match (&white.engine, &black.engine) {
+                (Some(path), None) | (None, Some(path)) => {
+                    let color = if white.engine.is_some() { Team::White } else { Team::Black };
+                    match Engine::start(path) {
+                        Ok(engine) => Opponent::Engine {
+                            engine,
+                            color,
+                            thinking: false,
+                        },
+                        Err(error) => {
+                            let error = format!("Could not start {}: {}", path.display(), error);
+                            return self.abandon_tournament_game(error);
+                        }
+                    }
+                }
+                _ => Opponent::HotSeat,
+            };
+            let mut record = GameRecord::new();
+            for (key, value) in headers.iter() {
+                record.set_header(key, value);
+            }
+            if let Some(time) = time {
+                record.set_header("TimeControl", &time.pgn_tag());
+            }
+            self.clock = time.map(Clock::new);
+            self.archive_on_end = true;
+            self.reset_game(record, Game::new());
+        }
+        self.text = match saved {
+            Ok(()) => format!("Round {}: {} - {}", round + 1, white.name, black.name),
+            Err(error) => error,
+        };
+    }
+
+    /// Records the result of the tournament game that has just ended and saves
+    /// the tournament.
+    fn finish_tournament_game(&mut self, result: &str) {
+        let session = match self.tournament.as_mut() {
+            Some(session) => session,
+            None => return,
+        };
+        let (round, board) = match session.current.take() {
+            Some(game) => game,
+            None => return,
+        };
+        session.tournament.set_result(round, board, result);
+        // Games between engines are written by their match.
+        let mut saved = session.save();
+        if saved.is_ok() && self.engine_match.is_none() {
+            saved = engine_match::append_pgn(&session.pgn_path(), &self.record);
+        }
+        let leader = session.tournament.standings()[0].player;
+        let leader = session.tournament.players[leader].name.clone();
+        let finished = session.tournament.is_finished();
+        self.text = match saved {
+            Err(error) => error,
+            Ok(()) if finished => format!("The tournament is over, {} wins", leader),
+            Ok(()) => format!("{} {}, N for the next game", self.text, result),
+        };
+    }
+
+    /// Gives up starting or playing a tournament game, which can be tried again with N.
+    fn abandon_tournament_game(&mut self, text: String) {
+        if let Some(session) = self.tournament.as_mut() {
+            session.current = None;
+        }
+        self.text = text;
+    }
+
+    /// Starts the engine when it is its turn and plays its move once found.
+    fn poll_engine(&mut self) {
+        // Nothing more is played once a drill is over.
+        if self.training_finished() {
+            return;
+        }
+        let best_move = match self.engine_book_move() {
+            Some(book_move) => book_move,
+            None => match &mut self.opponent {
+                Opponent::Engine {
+                    engine,
+                    color,
+                    thinking,
+                } => {
+                    if !*thinking {
+                        let engine_to_move = self.board.player == *color;
+                        if self.outcome.is_none() && self.resume.is_none() && engine_to_move {
+                            let (position, clock) = (self.record.uci_position(), self.clock.as_ref());
+                            let started = engine
+                                .set_variant(self.record.variant)
+                                .and_then(|()| engine.go(&position, clock));
+                            match started {
+                                Ok(()) => *thinking = true,
+                                Err(error) => self.text = format!("Engine error: {}", error),
+                            }
+                        }
+                        return;
+                    }
+                    match engine.poll() {
+                        Some(best_move) => {
+                            *thinking = false;
+                            best_move
+                        }
+                        None => return,
+                    }
+                }
+                _ => return,
+            },
+        };
+
+        let engine_team = self.board.player;
+        if self.outcome.is_some() || self.opponent.color() != Some(engine_team) {
+            return;
+        }
+        let played = RecordedMove::from_uci(&best_move)
+            .ok_or_else(|| format!("Engine sent invalid move {}", best_move))
+            .and_then(|recorded| self.record.play_move(&mut self.board, recorded));
+        match played {
+            Ok(()) => self.after_move(engine_team),
+            Err(error) => self.text = error,
+        }
+    }
+
+    /// A move from the opening book for the engine to play instead of searching,
+    /// picked at random in proportion to the book weights.
+    fn engine_book_move(&mut self) -> Option<String> {
+        match &self.opponent {
+            Opponent::Engine {
+                color,
+                thinking: false,
+                ..
+            } if *color == self.board.player => {}
+            _ => return None,
+        }
+        // Books hold standard chess or Chess960.
+        let variant = self.record.variant;
+        let booked = matches!(variant, Variant::Standard | Variant::Chess960);
+        if self.outcome.is_some() || self.resume.is_some() || !booked {
+            return None;
+        }
+        let fen = self.record.fen(&self.board);
+        let moves = self.book.as_mut()?.moves(&fen, variant == Variant::Chess960).ok()?;
+        book::choose(&moves).map(|book_move| book_move.uci.clone())
+    }
+
+    /// Looks up the book moves of the position on screen whenever it changes.
+    fn refresh_book_moves(&mut self) {
+        let shown = (self.displayed_ply(), self.record.ply());
+        if self.book.is_none() || self.book_position == Some(shown) {
+            return;
+        }
+        self.book_position = Some(shown);
+        self.book_moves.clear();
+        let variant = self.record.variant;
+        if !matches!(variant, Variant::Standard | Variant::Chess960) {
+            return;
+        }
+        let ply = shown.0;
+        let fen = match self.record.fen_at(ply) {
+            Ok(fen) => fen,
+            Err(_) => return,
+        };
+        let moves = match self.book.as_mut().unwrap().moves(&fen, variant == Variant::Chess960) {
+            Ok(moves) => moves,
+            Err(error) => {
+                self.text = format!("Could not read the opening book: {}", error);
+                return;
+            }
+        };
+        let total: u32 = moves.iter().map(|book_move| book_move.weight as u32).sum();
+        for book_move in moves.iter().take(BOOK_MOVES_SHOWN) {
+            if let Some(san) = self.record.san_line(ply, &[book_move.uci.clone()]).first() {
+                let share = book_move.weight as u32 * 100 / total.max(1);
+                self.book_moves.push(format!("{} {}%", san, share));
+            }
+        }
+    }
+
+    /// Names the opening of the position on screen whenever it changes.
+    fn refresh_opening(&mut self) {
+        let shown = (self.displayed_ply(), self.record.ply());
+        if self.opening_position != Some(shown) {
+            self.opening_position = Some(shown);
+            self.opening = match self.record.variant {
+                Variant::Standard => self.eco.classify(&self.record, shown.0).cloned(),
+                _ => None,
+            };
+        }
+    }
+
+    /// Probes the tablebases for the position on screen whenever it changes.
+    fn refresh_tablebase(&mut self) {
+        let shown = (self.displayed_ply(), self.record.ply());
+        let standard_rules = self.record.variant.has_standard_rules();
+        if self.tablebase.is_none() || !standard_rules || self.tablebase_position == Some(shown) {
+            return;
+        }
+        self.tablebase_position = Some(shown);
+        self.tablebase_lines.clear();
+        self.tablebase_move = None;
+        let ply = shown.0;
+        let fen = match self.record.fen_at(ply) {
+            Ok(fen) => fen,
+            Err(_) => return,
+        };
+        let result = match self.tablebase.as_mut().unwrap().probe(&fen) {
+            Ok(Some(result)) => result,
+            Ok(None) => return,
+            Err(error) => {
+                self.text = format!("Could not probe the tablebases: {}", error);
+                return;
+            }
+        };
+
+        let player = self.record.player_at(ply);
+        let winner = match result.wdl {
+            Wdl::Win | Wdl::CursedWin => Some(player),
+            Wdl::Loss | Wdl::BlessedLoss => Some(opponent(player)),
+            Wdl::Draw => None,
+        };
+        // Cursed wins are won positions that the fifty-move rule makes a draw.
+        self.tablebase_lines.push(match (winner, result.wdl) {
+            (None, _) => String::from("TB: draw"),
+            (Some(team), Wdl::CursedWin) | (Some(team), Wdl::BlessedLoss) => {
+                format!("TB: cursed win for {}", team_name(team))
+            }
+            (Some(team), _) => format!("TB: {} wins, DTZ {}", team_name(team), result.dtz.abs()),
+        });
+        for tablebase_move in result.moves.iter().take(TABLEBASE_MOVES_SHOWN) {
+            if let Some(san) = self.record.san_line(ply, &[tablebase_move.uci.clone()]).first() {
+                let line = match tablebase_move.wdl {
+                    Wdl::Draw => format!("{} draw", san),
+                    wdl => format!("{} {}, DTZ {}", san, wdl.name(), tablebase_move.dtz.abs()),
+                };
+                self.tablebase_lines.push(line);
+            }
+        }
+        self.tablebase_move = result.moves.first().map(|best| best.uci.clone());
+    }
+
+    /// Plays the move the tablebases rank best for the player in turn.
+    fn play_tablebase_move(&mut self) {
+        if !self.can_move() {
+            self.text = String::from("Tablebase moves are only played for your own move");
+            return;
+        }
+        if self.tablebase.is_none() {
+            self.text = String::from("No tablebases, add a Syzygy directory in the settings (F2)");
+            return;
+        }
+        let recorded = match self.tablebase_move.as_deref().and_then(RecordedMove::from_uci) {
+            Some(recorded) => recorded,
+            None => {
+                self.text = String::from("The position is not in the tablebases");
+                return;
+            }
+        };
+        let mover = self.board.player;
+        match self.record.play_move(&mut self.board, recorded) {
+            Ok(()) => self.after_move(mover),
+            Err(error) => self.text = error,
+        }
+    }
+
+    /// Starts a hosted game once the opponent has joined.
+    fn poll_host(&mut self) {
+        match self.opponent.poll_host() {
+            Ok(false) => {}
+            Ok(true) => {
+                self.text = status(&self.board);
+                if let Some(clock) = self.clock.as_mut() {
+                    clock.start(self.board.player);
+                }
+            }
+            Err(error) => self.text = error,
+        }
+    }
+
+    /// Stops waiting for an opponent to join the hosted game, leaving both
+    /// sides to be played in this window.
+    fn stop_hosting(&mut self) {
+        self.opponent = Opponent::HotSeat;
+        self.text = String::from("Stopped hosting, both sides are played here");
+        if let Some(clock) = self.clock.as_mut() {
+            clock.start(self.board.player);
+        }
+    }
+
+    /// Handles everything received from the remote player.
+    fn poll_network(&mut self) {
+        self.adjudicate_disconnection();
+        loop {
+            let (event, remote) = match &mut self.opponent {
+                Opponent::Network { connection, color, .. } => match connection.poll() {
+                    Some(event) => (event, *color),
+                    None => return,
+                },
+                _ => return,
+            };
+            let message = match event {
+                NetworkEvent::Message(message) => message,
+                NetworkEvent::Disconnected => {
+                    self.connection_dropped();
+                    continue;
+                }
+                NetworkEvent::Reconnected => {
+                    let session = match &self.opponent {
+                        Opponent::Network { connection, .. } => connection.reconnection.clone(),
+                        _ => None,
+                    };
+                    if let Some(terms) = session {
+                        let ply = self.record.ply();
+                        self.send(Message::Rejoin {
+                            session: terms.session,
+                            ply,
+                        });
+                    }
+                    continue;
+                }
+            };
+
+            match message {
+                Message::Move { uci, seq } => {
+                    match Order::of(seq, self.record.ply()) {
+                        Order::Next => {}
+                        Order::Played => continue,
+                        Order::Ahead => {
+                            let ply = self.record.ply();
+                            self.send(Message::Sync { ply });
+                            continue;
+                        }
+                    }
+                    if self.outcome.is_some() || self.board.player != remote {
+                        continue;
+                    }
+                    let played = RecordedMove::from_uci(&uci)
+                        .ok_or_else(|| format!("Opponent sent invalid move {}", uci))
+                        .and_then(|recorded| self.record.play_move(&mut self.board, recorded));
+                    match played {
+                        Ok(()) => self.after_move(remote),
+                        Err(error) => self.text = error,
+                    }
+                }
+                Message::Resign => {
+                    if self.outcome.is_none() {
+                        self.end_game(Outcome::Resignation {
+                            winner: opponent(remote),
+                        });
+                    }
+                }
+                Message::DrawOffer => {
+                    if self.outcome.is_none() {
+                        self.draw_offer = Some(remote);
+                        self.text = format!(
+                            "{} offers a draw. Y to accept, N to decline.",
+                            capitalized(team_name(remote))
+                        );
+                    }
+                }
+                Message::DrawAccept => {
+                    if self.outcome.is_none() && self.draw_offer == Some(opponent(remote)) {
+                        self.end_game(Outcome::DrawAgreement);
+                    }
+                }
+                Message::DrawDecline => {
+                    if self.draw_offer == Some(opponent(remote)) {
+                        self.draw_offer = None;
+                        self.text = format!("{} declines the draw", capitalized(team_name(remote)));
+                    }
+                }
+                // The player is back, or asks for moves it missed.
+                Message::Rejoin { ply, .. } | Message::Sync { ply } => {
+                    let resync = self.resync_message(ply);
+                    self.send(resync);
+                    self.resume_clock();
+                    self.text = status(&self.board);
+                }
+                Message::Resync { ply, moves, clock } => self.resync(ply, &moves, clock),
+                Message::Chat { from, text } => {
+                    // The host knows who wrote a line, the joining player is
+                    // told when the host passes on a spectator's.
+                    let from = if !self.hosting() && from == SPECTATOR {
+                        from
+                    } else {
+                        team_name(remote).to_string()
+                    };
+                    let text = chat::clean(&text);
+                    self.broadcast(Message::Chat {
+                        from: from.clone(),
+                        text: text.clone(),
+                    });
+                    self.add_chat(&from, &text, false);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether this window hosts the network game.
+    fn hosting(&self) -> bool {
+        match &self.opponent {
+            Opponent::Network { connection, .. } => connection.is_host(),
+            _ => false,
+        }
+    }
+
+    /// Who the chat lines written here are from.
+    fn chat_name(&self) -> &'static str {
+        match &self.opponent {
+            Opponent::Network { color, .. } => team_name(opponent(*color)),
+            _ => SPECTATOR,
+        }
+    }
+
+    /// Sends the line typed in the chat to the other player and the spectators.
+    fn send_chat(&mut self, text: &str) {
+        let text = chat::clean(text);
+        if text.is_empty() {
+            return;
+        }
+        let from = self.chat_name();
+        let message = Message::Chat {
+            from: from.to_string(),
+            text: text.clone(),
+        };
+        self.send(message.clone());
+        self.broadcast(message);
+        self.add_chat(from, &text, true);
+    }
+
+    fn add_chat(&mut self, from: &str, text: &str, own: bool) {
+        if let Err(error) = self.chat.add(from, text, own, &mut self.record) {
+            self.text = format!("Could not save the chat: {}", error);
+        }
+    }
+
+    /// Typing in the chat, opened with Enter: Enter sends the line and Escape
+    /// closes the chat without sending it.
+    fn chat_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Return | KeyCode::NumpadEnter => {
+                if let Some(text) = self.chat_input.take() {
+                    self.send_chat(&text);
+                }
+            }
+            KeyCode::Escape => self.chat_input = None,
+            KeyCode::Back => {
+                if let Some(input) = self.chat_input.as_mut() {
+                    input.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Hides or shows again the chat lines of the other player, or of the spectators.
+    fn toggle_mute(&mut self, spectators: bool) {
+        let (muted, who) = if spectators {
+            (&mut self.chat.mute_spectators, "spectators")
+        } else {
+            (&mut self.chat.mute_players, "other player")
+        };
+        *muted = !*muted;
+        let verb = if *muted { "Muted" } else { "Unmuted" };
+        self.text = format!("{} the {} in the chat", verb, who);
+    }
+
+    /// Whether the connection to the remote player is down.
+    fn connection_down(&self) -> bool {
+        match &self.opponent {
+            Opponent::Network { connection, .. } => connection.lost_since().is_some(),
+            _ => false,
+        }
+    }
+
+    /// Waits for the connection to come back if the host allows it, with the
+    /// clocks stopped if it pauses them.
+    fn connection_dropped(&mut self) {
+        let terms = match &self.opponent {
+            Opponent::Network { connection, .. } => connection.reconnection.clone(),
+            _ => None,
+        };
+        match terms {
+            Some(terms) if self.outcome.is_none() => {
+                if terms.pause_clock {
+                    if let Some(clock) = self.clock.as_mut() {
+                        clock.stop();
+                    }
+                }
+                self.text = format!(
+                    "Connection lost, waiting {} seconds for it to come back",
+                    terms.timeout
+                );
+            }
+            _ => self.text = String::from("Opponent disconnected"),
+        }
+    }
+
+    /// Ends the game in favor of the local player once the connection has
+    /// been down for longer than the host waits.
+    fn adjudicate_disconnection(&mut self) {
+        let (lost_since, timeout, remote) = match &self.opponent {
+            Opponent::Network {
+                connection, color, ..
+            } => match (connection.lost_since(), &connection.reconnection) {
+                (Some(lost_since), Some(terms)) => (lost_since, terms.timeout, *color),
+                _ => return,
+            },
+            _ => return,
+        };
+        if self.outcome.is_none() && lost_since.elapsed() >= Duration::from_secs(timeout) {
+            self.end_game(Outcome::Disconnection {
+                winner: opponent(remote),
+            });
+        }
+    }
+
+    /// The moves after the first `ply` for the other player, with the clock
+    /// when hosting as the host's clock is the one both follow.
+    fn resync_message(&self, ply: usize) -> Message {
+        let hosting = self.hosting();
+        Message::Resync {
+            ply: self.record.ply(),
+            moves: self.record.moves.iter().skip(ply).map(RecordedMove::to_uci).collect(),
+            clock: self.clock.as_ref().filter(|_| hosting).map(Clock::remaining_millis),
+        }
+    }
+
+    /// Plays the moves missed while the connection was down, and sends the
+    /// other player those it missed, having only `their_ply` moves.
+    fn resync(&mut self, their_ply: usize, moves: &[String], remaining: Option<[u64; 2]>) {
+        let played: Vec<String> = self.record.moves.iter().map(RecordedMove::to_uci).collect();
+        for message in network::missed_moves(&played, their_ply) {
+            self.send(message);
+        }
+        for uci in moves {
+            if self.outcome.is_some() {
+                break;
+            }
+            let mover = self.board.player;
+            let played = RecordedMove::from_uci(uci)
+                .ok_or_else(|| format!("Opponent sent invalid move {}", uci))
+                .and_then(|recorded| self.record.play_move(&mut self.board, recorded));
+            match played {
+                Ok(()) => self.after_move(mover),
+                Err(error) => {
+                    self.text = error;
+                    return;
+                }
+            }
+        }
+        if let (Some(clock), Some(remaining)) = (self.clock.as_mut(), remaining) {
+            clock.set_remaining_millis(remaining);
+        }
+        self.resume_clock();
+        if self.outcome.is_none() {
+            self.text = status(&self.board);
+        }
+    }
+
+    /// Starts the clock of the side to move again after the connection has come back.
+    fn resume_clock(&mut self) {
+        if self.outcome.is_some() {
+            return;
+        }
+        if let Some(clock) = self.clock.as_mut() {
+            if clock.running().is_none() {
+                clock.start(self.board.player);
+            }
+        }
+    }
+
+    /// Lets in the spectators who have connected to the hosted game, sending
+    /// them the game so far.
+    fn poll_spectators(&mut self) {
+        let (arriving, chat) = match &mut self.opponent {
+            Opponent::Network {
+                spectators: Some(spectators),
+                ..
+            } => (spectators.poll(), spectators.chat()),
+            _ => return,
+        };
+        // What spectators write goes to the other player and the other spectators.
+        for (id, text) in chat {
+            let text = chat::clean(&text);
+            if text.is_empty() {
+                continue;
+            }
+            let message = Message::Chat {
+                from: SPECTATOR.to_string(),
+                text: text.clone(),
+            };
+            self.send(message.clone());
+            if let Opponent::Network {
+                spectators: Some(spectators),
+                ..
+            } = &mut self.opponent
+            {
+                spectators.relay(id, &message);
+            }
+            self.add_chat(SPECTATOR, &text, false);
+        }
+        if !arriving {
+            return;
+        }
+        let mut welcome = vec![self.history_message()];
+        welcome.extend(self.outcome.as_ref().map(game_over_message));
+        if let Opponent::Network {
+            spectators: Some(spectators),
+            ..
+        } = &mut self.opponent
+        {
+            spectators.admit(&welcome);
+        }
+    }
+
+    /// The game so far, for a spectator who has just been let in.
+    fn history_message(&self) -> Message {
+        Message::History {
+            fen: self.record.start.as_ref().map(|fen| fen.to_string()),
+            time: self.clock.as_ref().map(|clock| clock.control.to_string()),
+            variant: Some(self.record.variant.name().to_string()),
+            moves: self.record.moves.iter().map(RecordedMove::to_uci).collect(),
+            clock: self.clock.as_ref().map(Clock::remaining_millis),
+        }
+    }
+
+    /// Follows the game watched as a spectator.
+    fn poll_watched_game(&mut self) {
+        loop {
+            let event = match &mut self.opponent {
+                Opponent::Spectating { connection } => match connection.poll() {
+                    Some(event) => event,
+                    None => return,
+                },
+                _ => return,
+            };
+            let message = match event {
+                NetworkEvent::Message(message) => message,
+                NetworkEvent::Disconnected => {
+                    if self.outcome.is_none() {
+                        self.text = String::from("The host closed the game");
+                        if let Some(clock) = self.clock.as_mut() {
+                            clock.stop();
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            match message {
+                Message::History {
+                    fen,
+                    time,
+                    variant,
+                    moves,
+                    clock,
+                } => {
+                    if let Err(error) = self.watch_from(fen, time, variant, &moves, clock) {
+                        self.text = format!("Could not follow the game: {}", error);
+                    }
+                }
+                Message::Move { uci, .. } => {
+                    if self.outcome.is_some() {
+                        continue;
+                    }
+                    let mover = self.board.player;
+                    let played = RecordedMove::from_uci(&uci)
+                        .ok_or_else(|| format!("The host sent invalid move {}", uci))
+                        .and_then(|recorded| self.record.play_move(&mut self.board, recorded));
+                    match played {
+                        Ok(()) => self.after_move(mover),
+                        Err(error) => self.text = error,
+                    }
+                }
+                Message::Clock { remaining } => {
+                    if let Some(clock) = self.clock.as_mut() {
+                        clock.set_remaining_millis(remaining);
+                    }
+                }
+                Message::GameOver { winner, reason } => {
+                    let winner = winner.as_deref().and_then(team_from_name);
+                    let outcome = match (reason.as_str(), winner) {
+                        ("resignation", Some(winner)) => Outcome::Resignation { winner },
+                        ("timeout", Some(winner)) => Outcome::Timeout { winner },
+                        ("agreement", None) => Outcome::DrawAgreement,
+                        ("disconnection", Some(winner)) => Outcome::Disconnection { winner },
+                        // Other endings are seen on the board.
+                        _ => continue,
+                    };
+                    if self.outcome.is_none() {
+                        self.end_game(outcome);
+                    }
+                }
+                Message::Full => {
+                    self.text = String::from("The game has as many spectators as the host allows");
+                }
+                Message::Chat { from, text } => self.add_chat(&from, &text, false),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sets up the watched game from the history sent by the host.
+    fn watch_from(
+        &mut self,
+        fen: Option<String>,
+        time: Option<String>,
+        variant: Option<String>,
+        moves: &[String],
+        remaining: Option<[u64; 2]>,
+    ) -> Result<(), String> {
+        let start = fen.map(|fen| fen.parse::<Fen>()).transpose()?;
+        let variant = variant.map(|name| name.parse::<Variant>()).transpose()?;
+        let mut record = GameRecord::starting_from(start);
+        record.set_variant(variant.unwrap_or(Variant::Standard));
+        let mut board = record.start_game();
+        for uci in moves {
+            let recorded =
+                RecordedMove::from_uci(uci).ok_or_else(|| format!("Invalid move {}", uci))?;
+            record.play_move(&mut board, recorded)?;
+        }
+        let time = time.map(|time| time.parse::<TimeControl>()).transpose()?;
+        self.clock = time.map(Clock::new);
+        self.reset_game(record, board);
+        if let (Some(clock), Some(remaining)) = (self.clock.as_mut(), remaining) {
+            clock.set_remaining_millis(remaining);
+        }
+        Ok(())
+    }
+
+    /// Ends the game, writing the result into the record.
+    fn end_game(&mut self, outcome: Outcome) {
+        let result = outcome.result();
+        let description = format!("{}.", outcome.description());
+        let notes = self.record.current_notes();
+        if !notes.comment.contains(&description) {
+            if !notes.comment.is_empty() {
+                notes.comment.push(' ');
+            }
+            notes.comment += &description;
+        }
+        self.record.set_result(outcome.result());
+        self.text = format!("{} ({})", outcome.description(), outcome.result());
+        self.outcome = Some(outcome);
+        self.draw_offer = None;
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop();
+        }
+        self.broadcast(game_over_message(&outcome));
+        // A finished game needs no recovering, but is kept in the database.
+        autosave::clear();
+        if self.archive_on_end && self.record.ply() > 0 {
+            self.archive_on_end = false;
+            if let Some(database) = self.database.as_mut() {
+                if let Err(error) = database.add(&self.record) {
+                    self.text = format!("Could not store the game: {}", error);
+                }
+            }
+            // Played games are checked for blunders straight away when there is an engine.
+            if self.engine_path().is_some() {
+                self.start_review();
+            }
+        }
+        self.available_tiles.clear();
+        self.available_actions.clear();
+        self.selected_piece = None;
+        self.state = State::Gameover;
+        self.finish_tournament_game(result);
+    }
+
+    /// The local player resigns, in hot-seat games the player in turn.
+    fn resign(&mut self) {
+        let team = match self.local_team() {
+            Some(team) if self.outcome.is_none() && !self.opponent.is_waiting() => team,
+            _ => return,
+        };
+        self.send(Message::Resign);
+        self.end_game(Outcome::Resignation {
+            winner: opponent(team),
+        });
+    }
+
+    /// The local player offers a draw, which stands until the opponent
+    /// answers it or makes a move. Engines answer right away.
+    fn offer_draw(&mut self) {
+        let team = match self.local_team() {
+            Some(team)
+                if self.outcome.is_none()
+                    && self.draw_offer.is_none()
+                    && !self.opponent.is_waiting() =>
+            {
+                team
+            }
+            _ => return,
+        };
+        if let Opponent::Engine { engine, .. } = &self.opponent {
+            if engine.accepts_draw() {
+                self.end_game(Outcome::DrawAgreement);
+            } else {
+                self.text = format!("{} declines the draw", engine.name);
+            }
+            return;
+        }
+
+        self.draw_offer = Some(team);
+        self.send(Message::DrawOffer);
+        self.text = match self.opponent {
+            Opponent::Network { .. } => String::from("Draw offered"),
+            _ => format!(
+                "{} offers a draw. Y to accept, N to decline.",
+                capitalized(team_name(team))
+            ),
+        };
+    }
+
+    /// Whether the local player may answer the pending draw offer.
+    fn can_answer_draw(&self) -> bool {
+        match (self.draw_offer, &self.opponent) {
+            (Some(_), Opponent::HotSeat) => true,
+            (Some(team), _) => self.opponent.color() == Some(team),
+            (None, _) => false,
+        }
+    }
+
+    fn accept_draw(&mut self) {
+        if self.outcome.is_none() && self.can_answer_draw() {
+            self.send(Message::DrawAccept);
+            self.end_game(Outcome::DrawAgreement);
+        }
+    }
+
+    fn decline_draw(&mut self) {
+        if self.can_answer_draw() {
+            let team = self.draw_offer.take().unwrap();
+            self.send(Message::DrawDecline);
+            self.text = format!("{} declines the draw", capitalized(team_name(opponent(team))));
+        }
+    }
+
+    /// Buttons currently available in the pause menu.
+    fn menu_actions(&self) -> Vec<MenuAction> {
+        if self.state != State::Pause
+            || self.outcome.is_some()
+            || self.local_team().is_none()
+            || self.opponent.is_waiting()
+        {
+            return vec![];
+        }
+        if self.can_answer_draw() {
+            vec![MenuAction::Resign, MenuAction::AcceptDraw, MenuAction::DeclineDraw]
+        } else if self.draw_offer.is_some() {
+            vec![MenuAction::Resign]
+        } else {
+            vec![MenuAction::Resign, MenuAction::OfferDraw]
+        }
+    }
+
+    fn perform_menu_action(&mut self, action: MenuAction) {
+        match action {
+            MenuAction::Resign => self.resign(),
+            MenuAction::OfferDraw => self.offer_draw(),
+            MenuAction::AcceptDraw => self.accept_draw(),
+            MenuAction::DeclineDraw => self.decline_draw(),
+        }
+        if self.state == State::Pause {
+            self.state = State::Active;
+        }
+    }
+
+    /// Renders the arrows and circled squares drawn on the board.
+    fn draw_annotations(&self, ctx: &mut Context, annotations: &Annotations) -> GameResult {
+        if annotations.is_empty() {
+            return Ok(());
+        }
+        let center = |coordinate: Coordinate| {
+            let corner = self.square_position(coordinate);
+            ggez::mint::Point2 {
+                x: corner.x + GRID_CELL_SIZE.0 as f32 / 2.0,
+                y: corner.y + GRID_CELL_SIZE.1 as f32 / 2.0,
+            }
+        };
+        let color = |annotation: AnnotationColor| {
+            let (r, g, b) = annotation.rgb();
+            Color::new(r, g, b, 0.8)
+        };
+
+        let mut builder = graphics::MeshBuilder::new();
+        for mark in annotations.squares.iter() {
+            builder.circle(
+                DrawMode::stroke(4.0),
+                center(mark.square),
+                GRID_CELL_SIZE.0 as f32 / 2.0 - 3.0,
+                0.5,
+                color(mark.color),
+            );
+        }
+        for arrow in annotations.arrows.iter() {
+            let from = center(arrow.from);
+            let to = center(arrow.to);
+            let (dx, dy) = (to.x - from.x, to.y - from.y);
+            let length = (dx * dx + dy * dy).sqrt();
+            let (ux, uy) = (dx / length, dy / length);
+            let head = GRID_CELL_SIZE.0 as f32 * 0.4;
+            let base = ggez::mint::Point2 {
+                x: to.x - ux * head,
+                y: to.y - uy * head,
+            };
+            builder.line(&[from, base], GRID_CELL_SIZE.0 as f32 * 0.15, color(arrow.color))?;
+            builder.polygon(
+                DrawMode::fill(),
+                &[
+                    to,
+                    ggez::mint::Point2 {
+                        x: base.x - uy * head / 2.0,
+                        y: base.y + ux * head / 2.0,
+                    },
+                    ggez::mint::Point2 {
+                        x: base.x + uy * head / 2.0,
+                        y: base.y - ux * head / 2.0,
+                    },
+                ],
+                color(arrow.color),
+            )?;
+        }
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
+    }
+
+    /// Marks the squares that matter in the variant played, like the hill.
+    fn draw_variant_squares(&self, ctx: &mut Context) -> GameResult {
+        for square in self.record.variant.highlighted_squares() {
+            let corner = self.square_position(*square);
+            let (width, height) = (GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32);
+            let rect = graphics::Rect::new(corner.x, corner.y, width, height);
+            let rectangle = graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, HILL_COLOR)?;
+            graphics::draw(ctx, &rectangle, DrawParam::default())?;
+        }
+        Ok(())
+    }
+
+    /// Draws the last explosion of an Atomic game as a fading ring of fire
+    /// spreading over the squares it cleared.
+    fn draw_explosion(&self, ctx: &mut Context) -> GameResult {
+        let (square, since) = match self.explosion {
+            Some(explosion) if self.history.is_none() => explosion,
+            _ => return Ok(()),
+        };
+        if since.elapsed() >= EXPLOSION_DURATION {
+            return Ok(());
+        }
+        let progress = since.elapsed().as_secs_f32() / EXPLOSION_DURATION.as_secs_f32();
+        let corner = self.square_position(square);
+        let center = ggez::mint::Point2 {
+            x: corner.x + GRID_CELL_SIZE.0 as f32 / 2.0,
+            y: corner.y + GRID_CELL_SIZE.1 as f32 / 2.0,
+        };
+        let mut color = EXPLOSION_COLOR;
+        color.a *= 1.0 - progress;
+        // Grows from the capture square to the squares around it.
+        let radius = GRID_CELL_SIZE.0 as f32 * (0.3 + 1.2 * progress);
+        let circle = graphics::Mesh::new_circle(ctx, DrawMode::fill(), center, radius, 0.5, color)?;
+        graphics::draw(ctx, &circle, DrawParam::default())
+    }
+
+    /// Engine used for analysis, hints and reviews: the opponent if it is an
+    /// engine, otherwise the first one in the settings.
+    fn engine_path(&self) -> Option<PathBuf> {
+        match &self.opponent {
+            Opponent::Engine { engine, .. } => Some(engine.path.clone()),
+            _ => self.settings.engines.first().cloned(),
+        }
+    }
+
+    /// Starts or stops analysing the position on screen with an engine.
+    fn toggle_analysis(&mut self) {
+        if self.analyzer.take().is_some() {
+            self.text = String::from("Analysis stopped");
+            return;
+        }
+        let path = match self.engine_path() {
+            Some(path) => path,
+            None => {
+                self.text = String::from("No engine to analyse with, add one in the settings (F2)");
+                return;
+            }
+        };
+        match Analyzer::start(&path) {
+            Ok(analyzer) => {
+                self.text = format!("Analysing with {}", analyzer.name());
+                self.analyzer = Some(analyzer);
+            }
+            Err(error) => self.text = format!("Could not start {}: {}", path.display(), error),
+        }
+    }
+
+    /// Shows the piece the best move is made with, or the whole move when
+    /// asked a second time, see `Hints::ask`.
+    fn ask_hint(&mut self) {
+        if !self.can_move() {
+            self.text = String::from("Hints are only given for your own move");
+            return;
+        }
+        let known = self
+            .analyzer
+            .as_ref()
+            .and_then(|analyzer| analyzer.best_move(self.record.ply()))
+            .and_then(RecordedMove::from_uci);
+        let path = self.engine_path();
+        match self.hints.ask(&mut self.record, known, path.as_deref()) {
+            Ok(()) => self.show_hint_text(),
+            Err(error) => self.text = format!("No hint available: {}", error),
+        }
+    }
+
+    /// Picks up the move found by the hint engine.
+    fn poll_hint(&mut self) {
+        if self.hints.poll(self.record.ply()) {
+            self.show_hint_text();
+        }
+    }
+
+    fn show_hint_text(&mut self) {
+        if let Some(text) = self.hints.text() {
+            self.text = text;
+        }
+    }
+
+    /// The hint as a mark on the board, if it is for the position shown.
+    fn hint_annotations(&self) -> Option<Annotations> {
+        if self.history.is_some() {
+            return None;
+        }
+        self.hints.annotations(self.record.ply())
+    }
+
+    /// Has an engine look at every move of the game for inaccuracies, mistakes
+    /// and blunders. The findings end up as comments in the record.
+    fn start_review(&mut self) {
+        if self.record.ply() == 0 {
+            self.text = String::from("There are no moves to review");
+            return;
+        }
+        let path = match self.engine_path() {
+            Some(path) => path,
+            None => {
+                self.text = String::from("No engine to review with, add one in the settings (F2)");
+                return;
+            }
+        };
+        match GameReview::start(&path, &self.record) {
+            Ok(review) => {
+                self.text = String::from("Reviewing the game...");
+                self.review = Some(review);
+            }
+            Err(error) => self.text = format!("Could not start {}: {}", path.display(), error),
+        }
     }
-}
 
-impl From<BoardPosition> for graphics::Rect {
-    fn from(pos: BoardPosition) -> Self {
-        graphics::Rect::new_i32(
-            pos.x as i32 * GRID_CELL_SIZE.0 as i32,
-            pos.y as i32 * GRID_CELL_SIZE.1 as i32,
-            GRID_CELL_SIZE.0 as i32,
-            GRID_CELL_SIZE.1 as i32,
-        )
+    /// Moves the review along and annotates the record once it is done.
+    fn poll_review(&mut self) {
+        let review = match self.review.as_mut() {
+            Some(review) if !review.is_done() => review,
+            _ => return,
+        };
+        if let Err(error) = review.poll() {
+            self.text = format!("Review stopped: {}", error);
+            self.review = None;
+            return;
+        }
+        if review.is_done() {
+            review.annotate(&mut self.record);
+            self.text = format!("{}. Ctrl+S saves the annotated game", review.summary());
+        }
     }
-}
 
-impl From<BoardPosition> for ggez::mint::Point2<f32> {
-    fn from(pos: BoardPosition) -> Self {
-        ggez::mint::Point2 {
-            x: pos.x as f32 * GRID_CELL_SIZE.0 as f32,
-            y: (7 - pos.y) as f32 * GRID_CELL_SIZE.1 as f32,
+    /// Renders the evaluation after every move of the reviewed game, with the
+    /// position on screen marked.
+    fn draw_review(&self, ctx: &mut Context, review: &GameReview) -> GameResult {
+        let (left, top, width, height) = EVAL_GRAPH_RECT;
+        let (done, total) = review.progress();
+        if !review.is_done() {
+            let progress = format!("Reviewing {}/{}", done, total);
+            draw_text(ctx, &progress, (left, top - 24f32), 16f32, PANEL_TEXT_COLOR)?;
         }
+        let moves = (total - 1).max(1) as f32;
+        let middle = top + height / 2f32;
+        let point = |ply: usize, eval: i32| ggez::mint::Point2 {
+            x: left + width * ply as f32 / moves,
+            y: middle - height / 2f32 * eval as f32 / EVAL_CAP as f32,
+        };
+
+        let mut builder = graphics::MeshBuilder::new();
+        builder.rectangle(
+            DrawMode::fill(),
+            graphics::Rect::new(left, top, width, height),
+            Color::new(0.1, 0.1, 0.1, 1.0),
+        );
+        builder.line(
+            &[
+                ggez::mint::Point2 { x: left, y: middle },
+                ggez::mint::Point2 { x: left + width, y: middle },
+            ],
+            1f32,
+            STOPPED_CLOCK_COLOR,
+        )?;
+        let points: Vec<_> = (0..done)
+            .filter_map(|ply| Some(point(ply, review.capped_eval(ply)?)))
+            .collect();
+        if points.len() >= 2 {
+            builder.line(&points, 2f32, Color::new(0.95, 0.95, 0.95, 1.0))?;
+        }
+        let shown = point(self.displayed_ply(), 0).x;
+        builder.line(
+            &[
+                ggez::mint::Point2 { x: shown, y: top },
+                ggez::mint::Point2 { x: shown, y: top + height },
+            ],
+            2f32,
+            Color::new(1.0, 0.8, 0.2, 1.0),
+        )?;
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
     }
-}
 
-impl Tile {
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        let rectangle = graphics::Mesh::new_rectangle(
+    /// Shows the position clicked on in the evaluation graph, returning whether
+    /// the click hit the graph.
+    fn click_review(&mut self, x: f32, y: f32) -> bool {
+        let (left, top, width, height) = EVAL_GRAPH_RECT;
+        let total = match &self.review {
+            Some(review) => review.progress().1,
+            None => return false,
+        };
+        if !graphics::Rect::new(left, top, width, height).contains(ggez::mint::Point2 { x, y }) {
+            return false;
+        }
+        let moves = (total - 1).max(1) as f32;
+        let ply = ((x - left) / width * moves).round() as usize;
+        self.show_ply(ply.min(self.record.ply()));
+        self.state = State::Active;
+        true
+    }
+
+    /// Keeps the analysis on the position on screen and reads its progress.
+    fn poll_analysis(&mut self) {
+        let ply = self.displayed_ply();
+        let player = self.displayed_board().player;
+        if let Some(analyzer) = self.analyzer.as_mut() {
+            match analyzer.analyze(&self.record, ply, player) {
+                Ok(()) => analyzer.poll(&self.record),
+                Err(error) => {
+                    self.text = format!("Analysis stopped: {}", error);
+                    self.analyzer = None;
+                }
+            }
+        }
+    }
+
+    /// Renders the evaluation bar and the best lines found by the analysis.
+    fn draw_analysis(&self, ctx: &mut Context, analyzer: &Analyzer) -> GameResult {
+        let share = analyzer.score().map_or(0.5, white_share);
+        let white_height = BOARD_SIZE.1 * share;
+        // White's part of the bar grows from white's side of the board.
+        let white_top = if self.flipped() { 0.0 } else { BOARD_SIZE.1 - white_height };
+        let dark = graphics::Mesh::new_rectangle(
             ctx,
-            graphics::DrawMode::fill(),
-            self.pos.into(),
-            AVAILABLE_TILE,
+            DrawMode::fill(),
+            graphics::Rect::new(BOARD_SIZE.0, 0.0, EVAL_BAR_WIDTH, BOARD_SIZE.1),
+            Color::new(0.1, 0.1, 0.1, 1.0),
         )?;
-        graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))
+        graphics::draw(ctx, &dark, DrawParam::default())?;
+        if white_height > 0.0 {
+            let light = graphics::Mesh::new_rectangle(
+                ctx,
+                DrawMode::fill(),
+                graphics::Rect::new(BOARD_SIZE.0, white_top, EVAL_BAR_WIDTH, white_height),
+                Color::new(0.95, 0.95, 0.95, 1.0),
+            )?;
+            graphics::draw(ctx, &light, DrawParam::default())?;
+        }
+
+        let mut lines = vec![format!(
+            "Depth {}, {} nodes",
+            analyzer.depth,
+            format_nodes(analyzer.nodes)
+        )];
+        for line in analyzer.lines.iter() {
+            let moves: Vec<&str> = line.san.iter().take(5).map(String::as_str).collect();
+            lines.push(format!("{} {}", format_score(line.score), moves.join(" ")));
+        }
+        for (index, line) in lines.iter().enumerate() {
+            draw_text(ctx, line, (PANEL_TEXT_X, 230f32 + 22f32 * index as f32), 16f32, PANEL_TEXT_COLOR)?;
+        }
+        Ok(())
     }
-}
 
-impl AppState {
-    /// Initialise new application, i.e. initialise new game and load resources.
-    fn new(ctx: &mut Context) -> GameResult<AppState> {
-        let sprites = AppState::load_sprites();
-        let board = Game::new();
+    /// Renders the panel right of the board with clocks and game information.
+    fn draw_panel(&self, ctx: &mut Context) -> GameResult {
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            graphics::Rect::new(BOARD_SIZE.0, 0.0, PANEL_WIDTH, BOARD_SIZE.1),
+            PANEL_COLOR,
+        )?;
+        graphics::draw(ctx, &background, DrawParam::default())?;
 
-        let state = AppState {
-            sprites: sprites
-                .iter()
-                .map(|_sprite| {
-                    (
-                        _sprite.0,
-                        graphics::Image::new(ctx, _sprite.1.clone()).unwrap(),
-                    )
-                })
-                .collect::<Vec<((Team, Rank), graphics::Image)>>(),
-            board,
-            available_tiles: vec![],
-            available_actions: vec![],
-            selected_piece: None,
-            state: State::Active,
-            is_replay: false,
-            text:String::new(),
-        };
+        // Black sits at the top of the board and white at the bottom.
+        for (team, y) in [(Team::Black, 10f32), (Team::White, BOARD_SIZE.1 - 50f32)].iter() {
+            let mut line = capitalized(team_name(*team));
+            let mut color = STOPPED_CLOCK_COLOR;
+            if let Some(clock) = &self.clock {
+                line += &format!("  {}", format_duration(clock.remaining(*team)));
+                if clock.running() == Some(*team) {
+                    color = RUNNING_CLOCK_COLOR;
+                }
+            } else if self.outcome.is_none() && self.board.player == *team {
+                color = RUNNING_CLOCK_COLOR;
+            }
+            draw_text(ctx, &line, (PANEL_TEXT_X, *y), 32f32, color)?;
+        }
 
-        Ok(state)
+        let puzzle = self.puzzles.as_ref().and_then(|trainer| Some((trainer, trainer.puzzle()?)));
+        let mut lines = match puzzle {
+            Some((trainer, puzzle)) => vec![
+                format!("Puzzle {}, rated {:.0}", puzzle.id, puzzle.rating),
+                format!(
+                    "Your rating {:.0} ± {:.0}",
+                    trainer.stats.rating.rating, trainer.stats.rating.deviation
+                ),
+            ],
+            None => match &self.repertoire {
+                Some(trainer) => {
+                    let time = repertoire::now();
+                    vec![
+                        format!(
+                            "Line {} of {}",
+                            trainer.line_number().unwrap_or_default(),
+                            trainer.line_count()
+                        ),
+                        format!(
+                            "Due {}, learned {}",
+                            trainer.due_count(time),
+                            trainer.learned_count()
+                        ),
+                    ]
+                }
+                None => match (&self.drills, &self.engine_match) {
+                    (Some(trainer), _) => {
+                        let (drill, stats) = (trainer.drill(), trainer.drill_stats());
+                        let best = stats.best.map(|best| format!(", best {} moves", best));
+                        vec![
+                            drill.name.to_string(),
+                            format!(
+                                "Passed {} of {}{}",
+                                stats.successes,
+                                stats.attempts,
+                                best.unwrap_or_default()
+                            ),
+                        ]
+                    }
+                    (None, Some(engine_match)) => match_lines(engine_match),
+                    (None, None) => vec![self.opponent.description()],
+                },
+            },
+        };
+        if let Some(session) = &self.tournament {
+            lines = tournament_lines(session, self.engine_match.as_ref());
+        }
+        if self.history.is_some() || self.record.ply() > 0 {
+            lines.push(format!("Move {} of {}", self.displayed_ply(), self.record.ply()));
+        }
+        if let Some(outcome) = &self.outcome {
+            lines.push(format!("{} {}", outcome.result(), outcome.description()));
+        }
+        for (index, line) in lines.iter().enumerate() {
+            draw_text(
+                ctx,
+                line,
+                (PANEL_TEXT_X, 70f32 + 24f32 * index as f32),
+                20f32,
+                PANEL_TEXT_COLOR,
+            )?;
+        }
+        // In the endgame the tablebase result and best moves take the place of
+        // the opening name, with its variation below it.
+        for (index, line) in self.tablebase_lines.iter().enumerate() {
+            let y = 150f32 + 18f32 * index as f32;
+            draw_text(ctx, line, (PANEL_TEXT_X, y), 16f32, PANEL_TEXT_COLOR)?;
+        }
+        // Between tournament games the standings take their place.
+        let standings = self
+            .tournament
+            .as_ref()
+            .filter(|session| session.current.is_none() && self.tablebase_lines.is_empty())
+            .map(|session| standings_lines(&session.tournament))
+            .unwrap_or_default();
+        for (index, line) in standings.iter().enumerate() {
+            let y = 150f32 + 18f32 * index as f32;
+            draw_text(ctx, line, (PANEL_TEXT_X, y), 16f32, PANEL_TEXT_COLOR)?;
+        }
+        let opening = self.opening.as_ref().filter(|_| {
+            self.tablebase_lines.is_empty() && self.puzzles.is_none() && standings.is_empty()
+        });
+        // Themes of the puzzle, hidden until it is done as they give hints.
+        let themes = self
+            .puzzles
+            .as_ref()
+            .filter(|trainer| trainer.is_finished() && self.tablebase_lines.is_empty())
+            .and_then(|trainer| trainer.puzzle())
+            .map(|puzzle| puzzle.themes.join(" "));
+        let themes = wrap_text(&themes.unwrap_or_default(), EDITOR_TEXT_WIDTH);
+        for (index, line) in themes.iter().enumerate() {
+            let y = 150f32 + 18f32 * index as f32;
+            draw_text(ctx, line, (PANEL_TEXT_X, y), 16f32, PANEL_TEXT_COLOR)?;
+        }
+        if self.record.variant != Variant::Standard && self.tablebase_lines.is_empty() {
+            let mut variant = self.record.variant.name().to_string();
+            // Checks given so far by white and black in the position shown.
+            if self.record.variant == Variant::ThreeCheck {
+                let ply = self.displayed_ply();
+                let white = self.record.checks_given(Team::White, ply);
+                let black = self.record.checks_given(Team::Black, ply);
+                variant += &format!(", checks {}-{}", white, black);
+            }
+            draw_text(ctx, &variant, (PANEL_TEXT_X, 150f32), 16f32, PANEL_TEXT_COLOR)?;
+        }
+        if let Some(opening) = opening {
+            let mut parts = opening.name.splitn(2, ": ");
+            let name = format!("{} {}", opening.code, parts.next().unwrap_or(""));
+            draw_text(ctx, &name, (PANEL_TEXT_X, 150f32), 16f32, PANEL_TEXT_COLOR)?;
+            if let Some(variation) = parts.next() {
+                draw_text(ctx, variation, (PANEL_TEXT_X, 168f32), 16f32, PANEL_TEXT_COLOR)?;
+            }
+        }
+        // Two book moves to a line.
+        let book_moves = if standings.is_empty() { &self.book_moves[..] } else { &[] };
+        for (index, pair) in book_moves.chunks(2).enumerate() {
+            let prefix = if index == 0 { "Book: " } else { "" };
+            let line = format!("{}{}", prefix, pair.join(", "));
+            let y = 190f32 + 18f32 * index as f32;
+            draw_text(ctx, &line, (PANEL_TEXT_X, y), 16f32, PANEL_TEXT_COLOR)?;
+        }
+        if let Some(analyzer) = &self.analyzer {
+            self.draw_analysis(ctx, analyzer)?;
+        }
+        if let Some(review) = &self.review {
+            self.draw_review(ctx, review)?;
+        } else if self.opponent.is_network() {
+            self.draw_chat(ctx)?;
+        }
+        Ok(())
     }
 
-    /// Loads chess piese images into vector.
-    fn load_sprites() -> Vec<((Team, Rank), String)> {
-        let mut sprites = Vec::new();
-        sprites.push(((Team::Black, Rank::King), "/black_king.png".to_string()));
-        sprites.push(((Team::Black, Rank::Queen), "/black_queen.png".to_string()));
-        sprites.push(((Team::Black, Rank::Rook), "/black_rook.png".to_string()));
-        sprites.push(((Team::Black, Rank::Pawn), "/black_pawn.png".to_string()));
-        sprites.push(((Team::Black, Rank::Bishop), "/black_bishop.png".to_string()));
-        sprites.push(((Team::Black, Rank::Knight), "/black_knight.png".to_string()));
-        sprites.push(((Team::White, Rank::King), "/white_king.png".to_string()));
-        sprites.push(((Team::White, Rank::Queen), "/white_queen.png".to_string()));
-        sprites.push(((Team::White, Rank::Rook), "/white_rook.png".to_string()));
-        sprites.push(((Team::White, Rank::Pawn), "/white_pawn.png".to_string()));
-        sprites.push(((Team::White, Rank::Bishop), "/white_bishop.png".to_string()));
-        sprites.push(((Team::White, Rank::Knight), "/white_knight.png".to_string()));
-        sprites
+    /// Renders the last lines of the chat where the review graph goes, with
+    /// the line being typed below them.
+    fn draw_chat(&self, ctx: &mut Context) -> GameResult {
+        let (left, top, _, _) = EVAL_GRAPH_RECT;
+        let mut header = String::from("Chat (Enter)");
+        if self.chat.mute_players {
+            header += ", players muted";
+        }
+        if self.chat.mute_spectators {
+            header += ", spectators muted";
+        }
+        let mut rows: Vec<String> = self
+            .chat
+            .visible()
+            .flat_map(|line| {
+                let from = if line.own { "You" } else { line.from.as_str() };
+                wrap_text(&format!("{}: {}", capitalized(from), line.text), EDITOR_TEXT_WIDTH)
+            })
+            .collect();
+        if let Some(input) = &self.chat_input {
+            rows.push(format!("> {}_", input));
+        }
+        let skipped = rows.len().saturating_sub(CHAT_ROWS);
+        draw_text(ctx, &header, (left, top), 16f32, STOPPED_CLOCK_COLOR)?;
+        for (index, row) in rows.iter().skip(skipped).enumerate() {
+            let y = top + 18f32 * (index + 1) as f32;
+            draw_text(ctx, row, (left, y), 16f32, PANEL_TEXT_COLOR)?;
+        }
+        Ok(())
     }
 }
 
@@ -177,13 +3205,44 @@ impl event::EventHandler for AppState {
     /// For updating game logic, which front-end doesn't handle.
     fn update(&mut self, _ctx: &mut Context) -> GameResult {
         if self.is_replay {
-            self.board = Game::new();
-            self.available_tiles = vec![];
-            self.selected_piece = None;
-            self.available_actions = vec![];
-            self.state = State::Active;
             self.is_replay = false;
+            match self.opponent {
+                Opponent::Waiting { .. }
+                | Opponent::Network { .. }
+                | Opponent::Spectating { .. }
+                | Opponent::Review => {
+                    self.text = String::from("This game cannot be restarted");
+                }
+                _ => {
+                    let record = self.record.restarted();
+                    let board = record.start_game();
+                    self.archive_on_end = true;
+                    self.reset_game(record, board);
+                    autosave::clear();
+                }
+            }
+        }
+
+        if self.outcome.is_none() {
+            if let Some(team) = self.clock.as_ref().and_then(Clock::flagged) {
+                self.end_game(Outcome::Timeout {
+                    winner: opponent(team),
+                });
+            }
         }
+        self.poll_engine();
+        self.poll_host();
+        self.poll_network();
+        self.poll_spectators();
+        self.poll_watched_game();
+        self.poll_analysis();
+        self.poll_hint();
+        self.poll_review();
+        self.poll_trainer();
+        self.poll_match();
+        self.refresh_book_moves();
+        self.refresh_opening();
+        self.refresh_tablebase();
         Ok(())
     }
 
@@ -192,6 +3251,30 @@ impl event::EventHandler for AppState {
         // clear interface with gray background Team
 
         match self.state {
+            State::Settings => {
+                self.draw_settings(ctx)?;
+                graphics::present(ctx)?;
+                return Ok(());
+            }
+            State::Database => {
+                self.draw_browser(ctx)?;
+                graphics::present(ctx)?;
+                return Ok(());
+            }
+            State::Editor => {
+                if let Some(editor) = &self.editor {
+                    self.draw_editor(ctx, editor)?;
+                }
+                graphics::present(ctx)?;
+                return Ok(());
+            }
+            State::NewGame => {
+                if let Some(picker) = &self.new_game {
+                    self.draw_new_game(ctx, picker)?;
+                }
+                graphics::present(ctx)?;
+                return Ok(());
+            }
             State::Active => {
                 graphics::clear(ctx, [0.5, 0.5, 0.5, 1.0].into());
                 // create text representation
@@ -208,8 +3291,8 @@ impl event::EventHandler for AppState {
                     ctx,
                     DrawMode::fill(),
                     graphics::Rect::new(
-                        (SCREEN_SIZE.0 - text_dimensions.0 as f32) / 2f32 as f32 - 8.0,
-                        (SCREEN_SIZE.0 - text_dimensions.1 as f32) / 2f32 as f32,
+                        (BOARD_SIZE.0 - text_dimensions.0 as f32) / 2f32 as f32 - 8.0,
+                        (BOARD_SIZE.0 - text_dimensions.1 as f32) / 2f32 as f32,
                         text_dimensions.0 as f32 + 16.0,
                         text_dimensions.1 as f32,
                     ),
@@ -220,6 +3303,8 @@ impl event::EventHandler for AppState {
                 graphics::draw(ctx, &background_box, DrawParam::default());
 
                 // draw tiles
+                let light: Color = self.theme.light_square().into();
+                let dark: Color = self.theme.dark_square().into();
                 for i in 0..64 {
                     let rectangle = graphics::Mesh::new_rectangle(
                         ctx,
@@ -232,20 +3317,21 @@ impl event::EventHandler for AppState {
                         ),
                         match i % 2 {
                             0 => match i / 8 {
-                                _row if _row % 2 == 0 => WHITE,
-                                _ => BLACK,
+                                _row if _row % 2 == 0 => light,
+                                _ => dark,
                             },
                             _ => match i / 8 {
-                                _row if _row % 2 == 0 => BLACK,
-                                _ => WHITE,
+                                _row if _row % 2 == 0 => dark,
+                                _ => light,
                             },
                         },
                     )?;
                     graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },));
                 }
+                self.draw_variant_squares(ctx)?;
 
                 for available_tile in self.available_tiles.iter() {
-                    let board_position: ggez::mint::Point2<f32> = available_tile.pos.into();
+                    let board_position = self.square_position((available_tile.pos.x, available_tile.pos.y));
                     let rectangle = graphics::Mesh::new_rectangle(
                         ctx,
                         graphics::DrawMode::fill(),
@@ -255,13 +3341,13 @@ impl event::EventHandler for AppState {
                             GRID_CELL_SIZE.0 as f32,
                             GRID_CELL_SIZE.1 as f32,
                         ),
-                        AVAILABLE_TILE,
+                        self.theme.available_tile().into(),
                     )?;
                     graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },));
                 }
 
                 //draw pieces
-                for square_column in self.board.matrix.iter() {
+                for square_column in self.displayed_board().matrix.iter() {
                     for square in square_column {
                         if let Some(piece) = square.piece {
                             let team_rank = (piece.team, piece.rank);
@@ -272,7 +3358,7 @@ impl event::EventHandler for AppState {
                                 .next()
                                 .unwrap()
                                 .1;
-                            let board_position = BoardPosition::new(square.coordinate);
+                            let board_position = self.piece_position(square.coordinate);
                             graphics::draw(
                                 ctx,
                                 sprite,
@@ -287,6 +3373,16 @@ impl event::EventHandler for AppState {
                     }
                 }
 
+                self.draw_explosion(ctx)?;
+
+                match &self.history {
+                    Some((ply, _)) => self.draw_annotations(ctx, &self.record.notes[*ply].annotations)?,
+                    None => self.draw_annotations(ctx, &self.annotations)?,
+                }
+                if let Some(hint) = self.hint_annotations() {
+                    self.draw_annotations(ctx, &hint)?;
+                }
+
                 // draw text with dark gray Teaming and center position
                 graphics::draw(
                     ctx,
@@ -294,8 +3390,8 @@ impl event::EventHandler for AppState {
                     DrawParam::default()
                         .color([0.0, 0.0, 0.0, 1.0].into())
                         .dest(ggez::mint::Point2 {
-                            x: (SCREEN_SIZE.0 - text_dimensions.0 as f32) / 2f32 as f32,
-                            y: (SCREEN_SIZE.0 - text_dimensions.1 as f32) / 2f32 as f32,
+                            x: (BOARD_SIZE.0 - text_dimensions.0 as f32) / 2f32 as f32,
+                            y: (BOARD_SIZE.0 - text_dimensions.1 as f32) / 2f32 as f32,
                         }),
                 );
             }
@@ -307,8 +3403,8 @@ impl event::EventHandler for AppState {
                     graphics::Rect::new(
                         0 as f32,
                         0 as f32,
-                        SCREEN_SIZE.0 as f32,
-                        SCREEN_SIZE.1 as f32,
+                        BOARD_SIZE.0 as f32,
+                        BOARD_SIZE.1 as f32,
                     ),
                     Color::new(255.0 / 255.0, 255.0 / 255.0, 255.0 / 255.0, 0.5),
                 )?;
@@ -329,8 +3425,8 @@ impl event::EventHandler for AppState {
                     ctx,
                     DrawMode::fill(),
                     graphics::Rect::new(
-                        SCREEN_SIZE.0 as f32 / 2f32 - REPLAY_BUTTON_SIZE.0 as f32/2f32,
-                        SCREEN_SIZE.1 as f32 / 2f32 - REPLAY_BUTTON_SIZE.0 as f32/2f32,
+                        BOARD_SIZE.0 as f32 / 2f32 - REPLAY_BUTTON_SIZE.0 as f32/2f32,
+                        BOARD_SIZE.1 as f32 / 2f32 - REPLAY_BUTTON_SIZE.0 as f32/2f32,
                         REPLAY_BUTTON_SIZE.0 as f32,
                         REPLAY_BUTTON_SIZE.1 as f32,
                     ),
@@ -356,14 +3452,37 @@ impl event::EventHandler for AppState {
                     DrawParam::default()
                         .color([0.0, 0.0, 0.0, 1.0].into())
                         .dest(ggez::mint::Point2 {
-                            x: (SCREEN_SIZE.0 - text_dimension.0 as f32) / 2f32 as f32,
-                            y: (SCREEN_SIZE.0 - text_dimension.1 as f32) / 2f32 as f32,
+                            x: (BOARD_SIZE.0 - text_dimension.0 as f32) / 2f32 as f32,
+                            y: (BOARD_SIZE.0 - text_dimension.1 as f32) / 2f32 as f32,
                         }),
                 );
 
+                for (index, action) in self.menu_actions().into_iter().enumerate() {
+                    draw_button(ctx, menu_button_rect(index), action.label())?;
+                }
+
+                if self.outcome.is_some() {
+                    let outcome_text = graphics::Text::new(
+                        graphics::TextFragment::from(self.text.clone())
+                            .scale(graphics::Scale { x: 30.0, y: 30.0 }),
+                    );
+                    let outcome_dimensions = outcome_text.dimensions(ctx);
+                    graphics::draw(
+                        ctx,
+                        &outcome_text,
+                        DrawParam::default()
+                            .color([0.0, 0.0, 0.0, 1.0].into())
+                            .dest(ggez::mint::Point2 {
+                                x: (BOARD_SIZE.0 - outcome_dimensions.0 as f32) / 2f32,
+                                y: 20f32,
+                            }),
+                    )?;
+                }
+
                 
                     let promotion_ranks = [Rank::Queen, Rank::Bishop, Rank::Rook, Rank::Knight];
-                    for x in 0..4 {
+                    let promotion_choices = if self.settings.auto_queen { 0 } else { 4 };
+                    for x in 0..promotion_choices {
                         let team_rank = (self.board.player, promotion_ranks[x]);
                         let sprite = &self
                             .sprites
@@ -383,7 +3502,7 @@ impl event::EventHandler for AppState {
                                 })
                                 .dest(ggez::mint::Point2 {
                                     x: 10 as f32,
-                                    y: SCREEN_SIZE.1 as f32 / 2f32
+                                    y: BOARD_SIZE.1 as f32 / 2f32
                                         + GRID_CELL_SIZE.1 as f32 * x as f32,
                                 }),
                         );
@@ -392,21 +3511,86 @@ impl event::EventHandler for AppState {
             }
         }
 
+        self.draw_panel(ctx)?;
+
         // render updated graphics
         graphics::present(ctx)?;
 
         Ok(())
     }
 
+    /// Remember where a right-click drag starts so an arrow can be drawn on release
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        let (x, y) = (x / self.scale, y / self.scale);
+        if self.state == State::Active && self.history.is_none() && button == MouseButton::Right {
+            self.right_drag_start = self.screen_to_coordinate(x, y);
+        }
+        if self.state == State::Editor && button == MouseButton::Left {
+            let square = self.screen_to_coordinate(x, y);
+            if let Some(editor) = self.editor.as_mut() {
+                editor.drag_from = square.filter(|square| editor.fen.piece_at(*square).is_some());
+            }
+        }
+    }
+
     /// Update game on mouse click
     fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        let (x, y) = (x / self.scale, y / self.scale);
+        let on_board = self.state == State::Active || self.state == State::Gameover;
+        if on_board && button == MouseButton::Left && self.click_review(x, y) {
+            return;
+        }
         match self.state {
+            State::Settings => {
+                let row = (0..Field::ALL.len())
+                    .find(|index| settings_row_rect(*index).contains(ggez::mint::Point2 { x, y }));
+                if let Some(index) = row {
+                    if index != self.settings_editor.selected || self.settings_editor.editing.is_none() {
+                        self.settings_editor.select(index);
+                        let forward = button != MouseButton::Right;
+                        self.settings_editor.activate(&mut self.settings, forward);
+                        self.settings_changed();
+                    }
+                }
+            }
+            State::Database => {
+                let rows = SearchField::ALL.len() + BROWSER_RESULT_ROWS;
+                let row = (0..rows)
+                    .find(|index| browser_row_rect(*index).contains(ggez::mint::Point2 { x, y }));
+                match row {
+                    Some(row) if row < SearchField::ALL.len() => self.browser.selected = row,
+                    Some(row) => {
+                        let index = row + self.browser.scroll;
+                        if index < SearchField::ALL.len() + self.browser.results.len() {
+                            self.browser.selected = index;
+                            if let Some(entry) = self.browser.selected_entry() {
+                                self.load_from_database(entry);
+                            }
+                        }
+                    }
+                    None => {}
+                }
+            }
+            State::Editor => self.editor_click(x, y, button),
+            State::NewGame => self.new_game_click(x, y),
             State::Active => {
-                if button == MouseButton::Left {
+                if button == MouseButton::Right {
+                    let start = self.right_drag_start.take();
+                    if let (Some(from), Some(to)) = (start, self.screen_to_coordinate(x, y)) {
+                        let color = annotation_color(keyboard::active_mods(ctx));
+                        if from == to {
+                            self.annotations.toggle_square(SquareMark { square: to, color });
+                        } else {
+                            self.annotations.toggle_arrow(Arrow { from, to, color });
+                        }
+                    }
+                } else if button == MouseButton::Left {
                     /* check click position and update board accordingly */
-                
-                    let game_x = (x / GRID_CELL_SIZE.0 as f32) as isize;
-                    let game_y = 7 - (y / GRID_CELL_SIZE.1 as f32) as isize;
+                    self.clear_annotations();
+                    let (game_x, game_y) = match self.screen_to_coordinate(x, y) {
+                        Some(coordinate) if self.can_move() => coordinate,
+                        _ => return,
+                    };
                     let clicked_tile = Tile {
                         pos: BoardPosition::new((game_x, game_y)),
                     };
@@ -414,13 +3598,40 @@ impl event::EventHandler for AppState {
                     {
                         return;
                     }
+                    // Moves the board does not know, like Chess960 castling by
+                    // moving the king onto the rook, are played through the record.
+                    let variant_origin = self.selected_piece.filter(|_| {
+                        self.variant_targets.contains(&(game_x, game_y))
+                    });
+                    if let Some(origin) = variant_origin {
+                        let mover = self.board.player;
+                        let recorded = RecordedMove {
+                            from: (origin.pos.x, origin.pos.y),
+                            to: (game_x, game_y),
+                            promotion: None,
+                        };
+                        match self.record.play_move(&mut self.board, recorded) {
+                            Ok(()) => self.after_move(mover),
+                            Err(error) => self.text = error,
+                        }
+                        return;
+                    }
 
                     if let Ok(actions) = self
                         .board
                         .move_from_string(&coordinate_to_string((game_x, game_y)))
                     {
+                        self.selected_piece = Some(clicked_tile);
                         self.available_tiles.clear();
-                        self.available_actions = actions;
+                        let from = (game_x, game_y);
+                        let (variant, board) = (self.record.variant, &self.board);
+                        self.available_actions = actions
+                            .into_iter()
+                            .filter(|action| {
+                                let to = action.to.coordinate;
+                                variant.allows(board, RecordedMove { from, to, promotion: None })
+                            })
+                            .collect();
                         for a in &self.available_actions {
                             let board_position = BoardPosition::new(a.to.coordinate);
                             let this_available = Tile {
@@ -428,45 +3639,75 @@ impl event::EventHandler for AppState {
                             };
                             self.available_tiles.push(this_available)
                         }
+                        // The targets of moves the board does not know are marked
+                        // after the moves, which keeps the tiles and actions above in step.
+                        let mut unknown = self.record.castling_moves(&self.board);
+                        unknown.extend(self.record.variant.extra_moves(&self.board));
+                        self.variant_targets = unknown
+                            .into_iter()
+                            .filter(|(origin, _)| *origin == from)
+                            .map(|(_, target)| target)
+                            .collect();
+                        for target in &self.variant_targets {
+                            self.available_tiles.push(Tile {
+                                pos: BoardPosition::new(*target),
+                            });
+                        }
                     } else if !self.available_tiles.is_empty() {
                         for (i, a) in self.available_tiles.iter().enumerate() {
                             if clicked_tile == *a {
                                 if self.available_actions[i].action_type == ActionType::Promotion {      
-                                    if self.board.promotion_piece==None{
+                                    if self.settings.auto_queen {
+                                        self.board.set_promotion_piece(Rank::Queen);
+                                    } else if self.board.promotion_piece==None{
                                         self.text=String::from("Set promotion piece in menu. Press Q for menu.");
                                         self.available_tiles.clear();
                                         self.available_actions.clear();
                                         return;
                                     }    
                                 }
-                                self.board.perform_action(self.available_actions[i]);
-                                self.available_tiles.clear();
-                                self.available_actions.clear();
+                                let from = self.selected_piece.unwrap().pos;
+                                let mover = self.board.player;
+                                self.record.play(
+                                    &mut self.board,
+                                    (from.x, from.y),
+                                    self.available_actions[i],
+                                );
+                                self.after_move(mover);
                                 break;
                             }
                         }
                     }
-
-                    if self.board.get_game_state() == GameState::Checkmate {
-                        self.state = State::Gameover;
-                    }
-                    self.text=format!("Gamestate:{:?}",self.board.get_game_state())
                 }
             }
             _ => {
-                if x > SCREEN_SIZE.0 as f32 / 2f32 - REPLAY_BUTTON_SIZE.0 as f32/2.0
-                    && x < SCREEN_SIZE.0 as f32 / 2f32 + REPLAY_BUTTON_SIZE.0 as f32/2.0
+                if x > BOARD_SIZE.0 as f32 / 2f32 - REPLAY_BUTTON_SIZE.0 as f32/2.0
+                    && x < BOARD_SIZE.0 as f32 / 2f32 + REPLAY_BUTTON_SIZE.0 as f32/2.0
                 {
-                    if y > SCREEN_SIZE.1 as f32 / 2f32 - REPLAY_BUTTON_SIZE.1 as f32/2.0
-                        && y < SCREEN_SIZE.1 as f32 / 2f32 + REPLAY_BUTTON_SIZE.1 as f32/2.0
+                    if y > BOARD_SIZE.1 as f32 / 2f32 - REPLAY_BUTTON_SIZE.1 as f32/2.0
+                        && y < BOARD_SIZE.1 as f32 / 2f32 + REPLAY_BUTTON_SIZE.1 as f32/2.0
                     {
                         self.is_replay = true;
                     }
                 }
 
+                let clicked_action = self
+                    .menu_actions()
+                    .into_iter()
+                    .enumerate()
+                    .find(|(index, _)| menu_button_rect(*index).contains(ggez::mint::Point2 { x, y }));
+                if let Some((_, action)) = clicked_action {
+                    self.perform_menu_action(action);
+                    return;
+                }
+
                 let promotion_ranks = [Rank::Queen, Rank::Bishop, Rank::Rook, Rank::Knight];
-                    if 10f32 < x && x < (10f32 + GRID_CELL_SIZE.0 as f32) {
-                        let index_y = (y - (SCREEN_SIZE.1 as f32 / 2f32)) as i16 / GRID_CELL_SIZE.1;
+                    if self.state == State::Pause
+                        && !self.settings.auto_queen
+                        && 10f32 < x
+                        && x < (10f32 + GRID_CELL_SIZE.0 as f32)
+                    {
+                        let index_y = (y - (BOARD_SIZE.1 as f32 / 2f32)) as i16 / GRID_CELL_SIZE.1;
                         if index_y >= 0 && index_y < 4 {
                             self.board
                                 .set_promotion_piece(promotion_ranks[index_y as usize]);
@@ -478,6 +3719,14 @@ impl event::EventHandler for AppState {
     }
 
     fn key_down_event(&mut self, ctx: &mut Context, key: KeyCode, mods: KeyMods, _: bool) {
+        match self.state {
+            State::Settings => return self.settings_key(key),
+            State::Database => return self.browser_key(key),
+            State::Editor => return self.editor_key(key),
+            State::NewGame => return self.new_game_key(key),
+            _ if self.chat_input.is_some() => return self.chat_key(key),
+            _ => {}
+        }
         match key {
             // Quit if Shift+Ctrl+Q is pressed.
             KeyCode::Q => {
@@ -515,35 +3764,476 @@ impl event::EventHandler for AppState {
                 //     println!("Now you're not even trying!");
                 // }
             }
+            // Stop waiting for an opponent to join with Esc.
+            KeyCode::Escape if self.opponent.is_waiting() => self.stop_hosting(),
+            // Save or load the game as PGN with Ctrl+S / Ctrl+O.
+            KeyCode::S if mods.contains(KeyMods::CTRL) => self.save_pgn(),
+            KeyCode::O if mods.contains(KeyMods::CTRL) => self.load_pgn(),
+            // Resign with Ctrl+R, offer a draw with Ctrl+D and answer an offer with Y / N.
+            KeyCode::R if mods.contains(KeyMods::CTRL) => self.resign(),
+            KeyCode::D if mods.contains(KeyMods::CTRL) => self.offer_draw(),
+            // Answer the offer to resume the game from the last session.
+            KeyCode::Y if self.resume.is_some() => self.resume_game(),
+            KeyCode::N if self.resume.is_some() => {
+                autosave::clear();
+                self.decline_resume(status(&self.board));
+            }
+            KeyCode::Y if self.draw_offer.is_some() => self.accept_draw(),
+            KeyCode::N if self.draw_offer.is_some() => self.decline_draw(),
+            // Move on to the next puzzle with N, and pick the theme with F.
+            KeyCode::N if self.puzzles.is_some() => self.next_puzzle(),
+            KeyCode::F if self.puzzles.is_some() => self.next_puzzle_theme(),
+            // Move on to the next repertoire line with N.
+            KeyCode::N if self.repertoire.is_some() => self.next_repertoire_line(),
+            // Move on to the next endgame drill with N, or try it again with R.
+            KeyCode::N if self.drills.is_some() => self.change_drill(true),
+            KeyCode::R if self.drills.is_some() && mods.is_empty() => self.change_drill(false),
+            // Start the next tournament game with N once the last one is over.
+            KeyCode::N if self.tournament.is_some() => self.next_tournament_game(),
+            // Open the settings with F2.
+            KeyCode::F2 => self.state = State::Settings,
+            // Browse the stored games with F3.
+            KeyCode::F3 => self.open_browser(),
+            // Set up a position with F4.
+            KeyCode::F4 => self.open_editor(),
+            // Pick the rules of a new game with F5.
+            KeyCode::F5 => self.open_new_game(),
+            // Chat in a network game with Enter, and mute the other player or
+            // the spectators with F7 / F8.
+            KeyCode::Return | KeyCode::NumpadEnter
+                if self.opponent.is_network() && !self.opponent.is_waiting() =>
+            {
+                self.chat_input = Some(String::new());
+            }
+            KeyCode::F7 if self.opponent.is_network() => self.toggle_mute(false),
+            KeyCode::F8 if self.opponent.is_network() => self.toggle_mute(true),
+            // Analyse the position on screen with A.
+            KeyCode::A if mods.is_empty() => self.toggle_analysis(),
+            // Ask for a hint with H.
+            KeyCode::H if mods.is_empty() => self.ask_hint(),
+            // Check the game for blunders with B.
+            KeyCode::B if mods.is_empty() => self.start_review(),
+            // Play the best tablebase move with T.
+            KeyCode::T if mods.is_empty() => self.play_tablebase_move(),
+            // Step through the moves played so far.
+            KeyCode::Left => self.show_ply(self.displayed_ply().saturating_sub(1)),
+            KeyCode::Right => self.show_ply(self.displayed_ply() + 1),
+            KeyCode::Home => self.show_ply(0),
+            KeyCode::End => self.show_ply(self.record.ply()),
             _ => (),
         }
     }
+
+    /// Typing on the settings screen, in the game browser and in the chat.
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        match self.state {
+            State::Active => {
+                if let Some(input) = self.chat_input.as_mut() {
+                    if !character.is_control() {
+                        input.push(character);
+                    }
+                }
+            }
+            State::Settings => self.settings_editor.type_char(character),
+            State::NewGame => {
+                if let Some(picker) = self.new_game.as_mut() {
+                    picker.type_char(character);
+                }
+            }
+            State::Database => {
+                if self.browser.type_char(character) {
+                    self.refresh_browser();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Position of the `index`th button on the right side of the pause menu.
+fn menu_button_rect(index: usize) -> graphics::Rect {
+    graphics::Rect::new(
+        BOARD_SIZE.0 - MENU_BUTTON_SIZE.0 - 10f32,
+        BOARD_SIZE.1 / 2f32 + (MENU_BUTTON_SIZE.1 + 10f32) * index as f32,
+        MENU_BUTTON_SIZE.0,
+        MENU_BUTTON_SIZE.1,
+    )
+}
+
+/// Draws a filled button with a centered label.
+fn draw_button(ctx: &mut Context, rect: graphics::Rect, label: &str) -> GameResult {
+    let button = graphics::Mesh::new_rectangle(ctx, DrawMode::fill(), rect, MENU_BUTTON_COLOR)?;
+    graphics::draw(ctx, &button, DrawParam::default())?;
+
+    let text = graphics::Text::new(
+        graphics::TextFragment::from(label).scale(graphics::Scale { x: 20.0, y: 20.0 }),
+    );
+    let (width, height) = text.dimensions(ctx);
+    graphics::draw(
+        ctx,
+        &text,
+        DrawParam::default()
+            .color([0.0, 0.0, 0.0, 1.0].into())
+            .dest(ggez::mint::Point2 {
+                x: rect.x + (rect.w - width as f32) / 2f32,
+                y: rect.y + (rect.h - height as f32) / 2f32,
+            }),
+    )
+}
+
+fn draw_text(ctx: &mut Context, text: &str, position: (f32, f32), size: f32, color: Color) -> GameResult {
+    let text = graphics::Text::new(
+        graphics::TextFragment::from(text).scale(graphics::Scale { x: size, y: size }),
+    );
+    graphics::draw(
+        ctx,
+        &text,
+        DrawParam::default()
+            .color(color)
+            .dest(ggez::mint::Point2 {
+                x: position.0,
+                y: position.1,
+            }),
+    )
+}
+
+/// Column and row, counted from the top left, of the board square under a screen position.
+fn screen_to_square(x: f32, y: f32) -> Option<(isize, isize)> {
+    if x < 0.0 || y < 0.0 || x >= BOARD_SIZE.0 || y >= BOARD_SIZE.1 {
+        return None;
+    }
+    Some((
+        (x / GRID_CELL_SIZE.0 as f32) as isize,
+        (y / GRID_CELL_SIZE.1 as f32) as isize,
+    ))
+}
+
+/// Position of the `index`th row of the game browser, search fields first.
+fn browser_row_rect(index: usize) -> graphics::Rect {
+    graphics::Rect::new(20f32, 70f32 + 30f32 * index as f32, SCREEN_SIZE.0 - 40f32, 28f32)
+}
+
+/// Position of the `index`th piece of the board editor palette, two rows of six.
+fn editor_palette_rect(index: usize) -> graphics::Rect {
+    graphics::Rect::new(
+        BOARD_SIZE.0 + 8f32 + 34f32 * (index % 6) as f32,
+        10f32 + 34f32 * (index / 6) as f32,
+        34f32,
+        34f32,
+    )
+}
+
+/// The buttons of the board editor and where they are, below the palette.
+fn editor_buttons() -> Vec<(EditorAction, graphics::Rect)> {
+    let x = BOARD_SIZE.0 + 8f32;
+    let row = |y: f32| graphics::Rect::new(x, y, 204f32, 30f32);
+    let half = |column: f32, y: f32| graphics::Rect::new(x + 104f32 * column, y, 100f32, 30f32);
+    vec![
+        (EditorAction::SideToMove, row(90f32)),
+        (EditorAction::Castling('K'), half(0f32, 126f32)),
+        (EditorAction::Castling('Q'), half(1f32, 126f32)),
+        (EditorAction::Castling('k'), half(0f32, 160f32)),
+        (EditorAction::Castling('q'), half(1f32, 160f32)),
+        (EditorAction::Clear, row(200f32)),
+        (EditorAction::StartPosition, row(236f32)),
+        (EditorAction::Play, row(272f32)),
+        (EditorAction::Analyze, row(308f32)),
+    ]
+}
+
+/// The buttons of the new game screen: the variants down the left side and,
+/// for Chess960, the ways of picking the starting position on the right.
+fn new_game_buttons(variant: Variant) -> Vec<(NewGameAction, graphics::Rect)> {
+    let mut buttons: Vec<(NewGameAction, graphics::Rect)> = Variant::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, variant)| {
+            let rect = graphics::Rect::new(20f32, 90f32 + 44f32 * index as f32, 260f32, 36f32);
+            (NewGameAction::Variant(*variant), rect)
+        })
+        .collect();
+    if variant == Variant::Chess960 {
+        let x = NEW_GAME_POSITION_X;
+        buttons.extend(vec![
+            (NewGameAction::PreviousPosition, graphics::Rect::new(x, 136f32, 130f32, 36f32)),
+            (NewGameAction::NextPosition, graphics::Rect::new(x + 140f32, 136f32, 130f32, 36f32)),
+            (NewGameAction::RandomPosition, graphics::Rect::new(x, 182f32, 270f32, 36f32)),
+            (NewGameAction::StandardPosition, graphics::Rect::new(x, 228f32, 270f32, 36f32)),
+        ]);
+    }
+    let start = graphics::Rect::new(20f32, SCREEN_SIZE.1 - 110f32, 260f32, 40f32);
+    buttons.push((NewGameAction::Start, start));
+    buttons
+}
+
+/// Splits `text` into lines of at most `width` characters, breaking between words.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.len() + 1 + word.len() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
+
+/// How the game ended, for spectators.
+fn game_over_message(outcome: &Outcome) -> Message {
+    Message::GameOver {
+        winner: outcome.winner().map(|winner| team_name(winner).to_string()),
+        reason: outcome.reason().to_string(),
+    }
+}
+
+/// Panel lines of an engine match: the game, the score so far and the clocks,
+/// and once it is over the Elo difference the score gives.
+fn match_lines(engine_match: &EngineMatch) -> Vec<String> {
+    let [wins, losses, draws] = engine_match.results;
+    let score = |points: u32| f64::from(points) + f64::from(draws) / 2.0;
+    let mut lines = vec![
+        format!("Game {} of {}", engine_match.round, engine_match.games),
+        format!(
+            "{} {} - {} {}",
+            engine_match.engines[0].name,
+            score(wins),
+            score(losses),
+            engine_match.engines[1].name
+        ),
+    ];
+    if let Some(clock) = engine_match.game.as_ref().and_then(|game| game.clock.as_ref()) {
+        lines.push(format!(
+            "White {}  Black {}",
+            format_duration(clock.remaining(Team::White)),
+            format_duration(clock.remaining(Team::Black))
+        ));
+    }
+    if engine_match.is_finished() {
+        lines.extend(engine_match.summary().pop());
+    }
+    lines
 }
 
-fn coordinate_to_string(coordinate: (isize, isize)) -> String {
-    let row_letter: String = (coordinate.1 + 1).to_string();
-    let column_number = coordinate.0 + 1;
-    let column_letter = match column_number {
-        1 => "a",
-        2 => "b",
-        3 => "c",
-        4 => "d",
-        5 => "e",
-        6 => "f",
-        7 => "g",
-        8 => "h",
-        _ => panic!("there shouldnt be a out of bounds letter here"),
+/// Panel lines of a tournament: the round, the players and the clocks of an engine game.
+fn tournament_lines(session: &TournamentSession, engine_match: Option<&EngineMatch>) -> Vec<String> {
+    let tournament = &session.tournament;
+    let mut lines = vec![tournament.name.clone()];
+    let (round, board) = match session.current {
+        Some(game) => game,
+        None => {
+            lines.push(format!("{} rounds paired", tournament.rounds.len()));
+            return lines;
+        }
     };
+    let pairing = &tournament.rounds[round][board];
+    lines.push(format!("Round {}, board {}", round + 1, board + 1));
+    if let Some(black) = pairing.black {
+        let (white, black) = (&tournament.players[pairing.white], &tournament.players[black]);
+        lines.push(format!("{} - {}", white.name, black.name));
+    }
+    let game = engine_match.and_then(|engine_match| engine_match.game.as_ref());
+    if let Some(clock) = game.and_then(|game| game.clock.as_ref()) {
+        lines.push(format!(
+            "White {}  Black {}",
+            format_duration(clock.remaining(Team::White)),
+            format_duration(clock.remaining(Team::Black))
+        ));
+    }
+    lines
+}
+
+/// The standings in the panel, one short line per player.
+fn standings_lines(tournament: &Tournament) -> Vec<String> {
+    let mut lines = vec!["Standings (points, Buchholz, S-B)".to_string()];
+    for (rank, standing) in tournament.standings().iter().enumerate() {
+        lines.push(format!(
+            "{}. {} {}, {}, {}",
+            rank + 1,
+            tournament.players[standing.player].name,
+            standing.points,
+            standing.buchholz,
+            standing.sonneborn_berger
+        ));
+    }
+    lines
+}
+
+/// Position of the `index`th row on the settings screen.
+fn settings_row_rect(index: usize) -> graphics::Rect {
+    graphics::Rect::new(20f32, 80f32 + 40f32 * index as f32, SCREEN_SIZE.0 - 40f32, 36f32)
+}
 
-    String::from(column_letter) + &row_letter
+/// Annotation color picked by the modifier keys held while right-clicking.
+fn annotation_color(mods: KeyMods) -> AnnotationColor {
+    if mods.contains(KeyMods::SHIFT) {
+        AnnotationColor::Red
+    } else if mods.contains(KeyMods::ALT) {
+        AnnotationColor::Blue
+    } else if mods.contains(KeyMods::CTRL) {
+        AnnotationColor::Yellow
+    } else {
+        AnnotationColor::Green
+    }
 }
 
 
 pub fn main() -> GameResult {
-    let resource_dir = path::PathBuf::from("./custom_resources");
+    let mut options = Options::from_args();
+    let (settings, warnings) = Settings::load();
+    for warning in warnings {
+        eprintln!("{}", warning);
+    }
+    options.apply_settings(&settings);
+    let pick_variant = options.choose_start_position().unwrap_or_else(|error| exit_with(&error));
+    let opponent = Opponent::from_options(&mut options).unwrap_or_else(|error| exit_with(&error));
+    let trainer = match &options.mode {
+        Some(Mode::Puzzles { file, themes }) => {
+            let puzzles = puzzle::load(file).unwrap_or_else(|error| {
+                exit_with(&format!("Could not load {}: {}", file.display(), error))
+            });
+            Some(PuzzleTrainer::new(puzzles, themes.clone()))
+        }
+        _ => None,
+    };
+    let drill = match &options.mode {
+        Some(Mode::Endgames { drill, .. }) => {
+            Some(endgame::find(drill).unwrap_or_else(|error| exit_with(&error)))
+        }
+        _ => None,
+    };
+    let engine_match = match &options.mode {
+        Some(Mode::Match {
+            first,
+            second,
+            games,
+            openings,
+            pgn,
+            headless,
+            adjudication,
+        }) => {
+            let openings = match openings {
+                Some(path) => engine_match::load_openings(path).unwrap_or_else(|error| {
+                    exit_with(&format!("Could not load {}: {}", path.display(), error))
+                }),
+                None => Vec::new(),
+            };
+            let tablebase = if settings.syzygy.is_empty() {
+                None
+            } else {
+                Tablebase::open(&settings.syzygy)
+                    .map_err(|error| eprintln!("Could not open the Syzygy tablebases: {}", error))
+                    .ok()
+            };
+            let adjudication = Adjudication {
+                options: *adjudication,
+                tablebase,
+            };
+            let engines = [first.as_path(), second.as_path()];
+            let engine_match =
+                EngineMatch::start(engines, *games, openings, options.time, adjudication, pgn.clone())
+                    .unwrap_or_else(|error| exit_with(&error));
+            if *headless {
+                engine_match::run_headless(engine_match).unwrap_or_else(|error| exit_with(&error));
+                process::exit(0);
+            }
+            Some(engine_match)
+        }
+        _ => None,
+    };
+    let tournament = match &options.mode {
+        Some(Mode::Tournament {
+            file,
+            players,
+            engines,
+            swiss,
+            standings,
+            export,
+            adjudication,
+        }) => {
+            let tournament = if file.exists() {
+                Tournament::load(file).unwrap_or_else(|error| {
+                    exit_with(&format!("Could not load {}: {}", file.display(), error))
+                })
+            } else {
+                let players = players
+                    .iter()
+                    .map(|name| Player::human(name))
+                    .chain(engines.iter().map(|engine| Player::engine(engine)))
+                    .collect();
+                let format = match swiss {
+                    Some(rounds) => Format::Swiss { rounds: *rounds },
+                    None => Format::RoundRobin,
+                };
+                let name = match file.file_stem() {
+                    Some(stem) => stem.to_string_lossy().into_owned(),
+                    None => "Tournament".to_string(),
+                };
+                let tournament = Tournament::new(&name, players, format, options.time)
+                    .unwrap_or_else(|error| exit_with(&error));
+                tournament.save(file).unwrap_or_else(|error| exit_with(&error));
+                tournament
+            };
+            if let Some(path) = export {
+                tournament.export(path).unwrap_or_else(|error| exit_with(&error));
+            }
+            if *standings {
+                for line in tournament.standings_table() {
+                    println!("{}", line);
+                }
+            }
+            if *standings || export.is_some() {
+                process::exit(0);
+            }
+            Some(TournamentSession {
+                tournament,
+                path: file.clone(),
+                adjudication: *adjudication,
+                current: None,
+            })
+        }
+        _ => None,
+    };
+    let repertoire = match &options.mode {
+        Some(Mode::Repertoire { file, color }) => {
+            let lines = repertoire::load(file).unwrap_or_else(|error| {
+                exit_with(&format!("Could not load {}: {}", file.display(), error))
+            });
+            Some(RepertoireTrainer::new(lines, color.team(), file))
+        }
+        _ => None,
+    };
+
+    let (record, board) = match (&options.mode, &options.fen) {
+        (Some(Mode::Review { file }), _) => fs::read_to_string(file)
+            .map_err(|error| error.to_string())
+            .and_then(|text| GameRecord::from_pgn(&text))
+            .unwrap_or_else(|error| exit_with(&format!("Could not load {}: {}", file.display(), error))),
+        (_, Some(fen)) => {
+            let fen: Fen = fen.parse().unwrap_or_else(|error: String| exit_with(&error));
+            let mut record = GameRecord::starting_from(Some(fen));
+            record.set_variant(options.variant());
+            let board = record.start_game();
+            (record, board)
+        }
+        _ => {
+            let mut record = GameRecord::new();
+            record.set_variant(options.variant());
+            (record, Game::new())
+        }
+    };
+
+    // The screen is laid out for SCREEN_SIZE and scaled to fit the window.
+    let (width, height) = match options.window_size {
+        Some(size) => (size.width, size.height),
+        None => SCREEN_SIZE,
+    };
+    let scale = (width / SCREEN_SIZE.0).min(height / SCREEN_SIZE.1);
 
     let context_builder = ggez::ContextBuilder::new("schack", "vem vet")
-        .add_resource_path(resource_dir) // Import image files to GGEZ
+        .add_resource_path(options.resources.clone()) // Import image files to GGEZ
         .window_setup(
             ggez::conf::WindowSetup::default()
                 .title("Schack") // Set window title "Schack"
@@ -551,14 +4241,46 @@ pub fn main() -> GameResult {
         )
         .window_mode(
             ggez::conf::WindowMode::default()
-                .dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1) // Set window dimenstions
+                .dimensions(width, height) // Set window dimenstions
                 .resizable(false), // Fixate window size
         );
 
     let (contex, event_loop) = &mut context_builder.build()?;
+    graphics::set_screen_coordinates(
+        contex,
+        graphics::Rect::new(0.0, 0.0, width / scale, height / scale),
+    )?;
 
-    let state = &mut AppState::new(contex)?;
+    let state = &mut AppState::new(contex, &options, opponent, record, board, scale, settings)?;
+    if pick_variant {
+        state.open_new_game();
+    }
+    if trainer.is_some() {
+        state.puzzles = trainer;
+        state.next_puzzle();
+    }
+    if repertoire.is_some() {
+        state.repertoire = repertoire;
+        state.next_repertoire_line();
+    }
+    state.engine_match = engine_match;
+    if tournament.is_some() {
+        state.tournament = tournament;
+        state.next_tournament_game();
+    }
+    if let Some(drill) = drill {
+        let trainer = DrillTrainer::new(drill);
+        let fen = trainer.drill().start();
+        state.drills = Some(trainer);
+        state.start_drill(fen);
+    }
     event::run(contex, event_loop, state); // Run window event loop
 
     Ok(())
 }
+
+/// Reports a problem with the command line and quits before a window is opened.
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
@@ -0,0 +1,554 @@
+//! Games between two computers over TCP.
+//!
+//! Every message is a JSON object on its own line, e.g. `{"type":"move","uci":"e2e4"}`.
+//! A client says whether it joins or watches the game in its first line. Any
+//! number of spectators, up to the host's limit, may watch a hosted game:
+//! they are sent the game so far when they connect and then every move and
+//! chat line, and what they write in the chat is passed on to the players and
+//! the other spectators. Nothing else they send is read.
+//!
+//! A joined player whose connection drops keeps trying to connect again for
+//! as long as the host waits, see `Reconnection`. It then says which game it
+//! belongs to and how many moves it has, and the two sides send each other the
+//! moves the other one missed. Moves carry their number so that none is
+//! played twice.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PORT: u16 = 7878;
+/// How long a new connection has to say whether it joins or watches. Clients
+/// that say nothing, like those from before spectators, join.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_HELLO_LENGTH: usize = 256;
+/// Time between attempts to connect to the host again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How a game goes on when the connection drops, chosen by the host.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Reconnection {
+    /// Identifies the game to the host when reconnecting.
+    pub session: String,
+    /// Seconds to wait for the connection to come back before the game is
+    /// adjudicated.
+    pub timeout: u64,
+    /// Whether the clocks stop while the connection is down.
+    pub pause_clock: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// Sent by the host once a player has joined.
+    Start {
+        /// Color of the joining player, "white" or "black".
+        color: String,
+        fen: Option<String>,
+        time: Option<String>,
+        /// Rules of the game as in the PGN `Variant` tag, standard chess when missing.
+        #[serde(default)]
+        variant: Option<String>,
+        /// `None` from hosts that do not take players back.
+        #[serde(default)]
+        reconnection: Option<Reconnection>,
+    },
+    Move {
+        uci: String,
+        /// Number of the move in the game, from 1.
+        #[serde(default)]
+        seq: Option<usize>,
+    },
+    Resign,
+    DrawOffer,
+    DrawAccept,
+    DrawDecline,
+    /// First line of a player joining the game.
+    Join,
+    /// First line of a spectator.
+    Watch,
+    /// The game so far, sent to a spectator who has been let in.
+    History {
+        fen: Option<String>,
+        time: Option<String>,
+        #[serde(default)]
+        variant: Option<String>,
+        /// Moves in UCI notation.
+        moves: Vec<String>,
+        /// Milliseconds left for white and black.
+        clock: Option<[u64; 2]>,
+    },
+    /// Milliseconds left for white and black after a move, sent to spectators.
+    Clock {
+        remaining: [u64; 2],
+    },
+    /// End of the game, sent to spectators for the endings that cannot be
+    /// told from the board, like a resignation.
+    GameOver {
+        /// "white" or "black", `None` for a draw.
+        winner: Option<String>,
+        reason: String,
+    },
+    /// Sent to a spectator turned away because the game has as many as the host allows.
+    Full,
+    /// First line of a player reconnecting, with the number of moves it has.
+    Rejoin {
+        session: String,
+        ply: usize,
+    },
+    /// Asks for the moves after the first `ply`, when one has gone missing.
+    Sync {
+        ply: usize,
+    },
+    /// Answer to `Rejoin` or `Sync`: the moves the other side is missing, and
+    /// the number of moves of the sender so that the other side can send its own.
+    Resync {
+        ply: usize,
+        moves: Vec<String>,
+        /// Milliseconds left for white and black on the host's clock.
+        clock: Option<[u64; 2]>,
+    },
+    /// A line of the chat. The host passes it on to everyone else, labelled
+    /// with who wrote it.
+    Chat {
+        /// "white", "black" or "spectator".
+        from: String,
+        text: String,
+    },
+}
+
+pub enum NetworkEvent {
+    Message(Message),
+    Disconnected,
+    /// A joined player is connected to the host again and should send `Rejoin`.
+    Reconnected,
+}
+
+/// Where a move received with the number `seq` stands for a side that has
+/// played `ply` moves.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Order {
+    /// The move to play next, also any move from a side that does not number them.
+    Next,
+    /// Played already, sent again after reconnecting.
+    Played,
+    /// Moves before it went missing, to be asked for with `Sync`.
+    Ahead,
+}
+
+impl Order {
+    pub fn of(seq: Option<usize>, ply: usize) -> Order {
+        match seq {
+            Some(seq) if seq <= ply => Order::Played,
+            Some(seq) if seq > ply + 1 => Order::Ahead,
+            _ => Order::Next,
+        }
+    }
+}
+
+/// The moves in UCI notation after the first `ply` of `moves`, numbered for
+/// the other side to play each once.
+pub fn missed_moves(moves: &[String], ply: usize) -> Vec<Message> {
+    moves
+        .iter()
+        .enumerate()
+        .skip(ply)
+        .map(|(index, uci)| Message::Move {
+            uci: uci.clone(),
+            seq: Some(index + 1),
+        })
+        .collect()
+}
+
+enum Incoming {
+    /// An event from the reader of the `n`th stream of the connection.
+    Event(usize, NetworkEvent),
+    /// A new stream to the other player, with the first line read from it.
+    Stream(TcpStream, Option<Message>),
+}
+
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Receiver<Incoming>,
+    // Kept for the readers of the streams after a reconnection.
+    sender: Sender<Incoming>,
+    // Number of the current stream, events from earlier ones are dropped.
+    generation: usize,
+    /// `None` when the other side does not reconnect.
+    pub reconnection: Option<Reconnection>,
+    // Address of the host to reconnect to, `None` when hosting.
+    address: Option<String>,
+    lost_since: Option<Instant>,
+}
+
+/// A game hosted on a port, waiting for a player to join. Nothing blocks:
+/// connections are accepted by `player`, and each says hello on a thread of its own.
+pub struct Host {
+    listener: TcpListener,
+    port: u16,
+    limit: usize,
+    reconnection: Reconnection,
+    // Connections that said they join, or said nothing.
+    player_sender: Sender<TcpStream>,
+    players: Receiver<TcpStream>,
+    // Spectators, kept for the game to let in.
+    spectators: Sender<TcpStream>,
+    arrivals: Receiver<TcpStream>,
+}
+
+impl Host {
+    /// Listens on `port`, any free one for 0, for a player, and for
+    /// spectators to let in up to `limit` at a time.
+    pub fn bind(port: u16, limit: usize, reconnection: Reconnection) -> io::Result<Host> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        let (player_sender, players) = mpsc::channel();
+        let (spectators, arrivals) = mpsc::channel();
+        Ok(Host {
+            listener,
+            port,
+            limit,
+            reconnection,
+            player_sender,
+            players,
+            spectators,
+            arrivals,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Accepts the connections waiting on the port without blocking. Returns
+    /// the stream of the first one to join, see `start`.
+    pub fn player(&mut self) -> Option<TcpStream> {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(false).is_err() {
+                continue;
+            }
+            let (spectators, players) = (self.spectators.clone(), self.player_sender.clone());
+            greet(stream, move |stream, hello| {
+                let _ = match hello {
+                    Some(Message::Watch) => spectators.send(stream),
+                    _ => players.send(stream),
+                };
+            });
+        }
+        self.players.try_recv().ok()
+    }
+
+    /// Starts the game with the player on `stream`. Spectators connecting
+    /// from now on are kept for the game to let in, and the player is taken
+    /// back when reconnecting.
+    pub fn start(self, stream: TcpStream) -> io::Result<(Connection, Spectators)> {
+        let Host {
+            listener,
+            limit,
+            reconnection,
+            spectators: sender,
+            arrivals,
+            ..
+        } = self;
+        listener.set_nonblocking(false)?;
+        let mut connection = Connection::from_stream(stream, None)?;
+        connection.reconnection = Some(reconnection);
+        // Once the game has started only spectators and the player coming back
+        // are let in. The game is over once either can no longer be handed on.
+        let rejoins = connection.sender.clone();
+        let over = Arc::new(AtomicBool::new(false));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if over.load(Ordering::Relaxed) {
+                    return;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let (sender, rejoins, over) = (sender.clone(), rejoins.clone(), over.clone());
+                greet(stream, move |stream, hello| {
+                    let sent = match hello {
+                        Some(Message::Watch) => sender.send(stream).is_ok(),
+                        Some(hello @ Message::Rejoin { .. }) => {
+                            rejoins.send(Incoming::Stream(stream, Some(hello))).is_ok()
+                        }
+                        _ => true,
+                    };
+                    if !sent {
+                        over.store(true, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        let (chat_sender, chat) = mpsc::channel();
+        let spectators = Spectators {
+            arrivals,
+            waiting: Vec::new(),
+            watching: Vec::new(),
+            next_id: 0,
+            chat_sender,
+            chat,
+            limit,
+        };
+        Ok((connection, spectators))
+    }
+}
+
+impl Connection {
+    pub fn join(address: &str) -> io::Result<Connection> {
+        let stream = TcpStream::connect(address)?;
+        let mut connection = Connection::from_stream(stream, Some(address.to_string()))?;
+        connection.send(&Message::Join)?;
+        Ok(connection)
+    }
+
+    /// Connects to a hosted game as a spectator.
+    pub fn watch(address: &str) -> io::Result<Connection> {
+        let mut connection = Connection::from_stream(TcpStream::connect(address)?, None)?;
+        connection.send(&Message::Watch)?;
+        Ok(connection)
+    }
+
+    fn from_stream(stream: TcpStream, address: Option<String>) -> io::Result<Connection> {
+        let (sender, incoming) = mpsc::channel();
+        let mut connection = Connection {
+            stream: stream.try_clone()?,
+            incoming,
+            sender,
+            generation: 0,
+            reconnection: None,
+            address,
+            lost_since: None,
+        };
+        connection.attach(stream)?;
+        Ok(connection)
+    }
+
+    /// Makes `stream` the connection to the other player, reading from it on
+    /// a thread of its own.
+    fn attach(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        self.generation += 1;
+        self.stream = stream;
+        let (generation, sender) = (self.generation, self.sender.clone());
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                // Lines that are not understood are skipped.
+                if let Ok(message) = serde_json::from_str(&line) {
+                    let event = Incoming::Event(generation, NetworkEvent::Message(message));
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+            let _ = sender.send(Incoming::Event(generation, NetworkEvent::Disconnected));
+        });
+        Ok(())
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        send_line(&self.stream, message)
+    }
+
+    /// Returns the next received event without blocking. A player coming back
+    /// to the host is reported as the `Rejoin` it sent.
+    pub fn poll(&mut self) -> Option<NetworkEvent> {
+        loop {
+            match self.incoming.try_recv().ok()? {
+                Incoming::Event(generation, event) if generation == self.generation => {
+                    match event {
+                        // The connection counts as lost until a message comes through.
+                        NetworkEvent::Disconnected => {
+                            let lost_since = *self.lost_since.get_or_insert_with(Instant::now);
+                            self.reconnect(lost_since);
+                        }
+                        _ => self.lost_since = None,
+                    }
+                    return Some(event);
+                }
+                // Left over from a stream replaced since.
+                Incoming::Event(..) => {}
+                Incoming::Stream(stream, hello) => {
+                    let session = self.reconnection.as_ref().map(|terms| &terms.session);
+                    if let Some(Message::Rejoin { session: theirs, .. }) = &hello {
+                        if Some(theirs) != session {
+                            continue;
+                        }
+                    }
+                    if self.attach(stream).is_err() {
+                        continue;
+                    }
+                    return Some(match hello {
+                        Some(message) => {
+                            self.lost_since = None;
+                            NetworkEvent::Message(message)
+                        }
+                        None => NetworkEvent::Reconnected,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Blocks until the next event arrives.
+    pub fn wait(&mut self) -> NetworkEvent {
+        loop {
+            match self.incoming.recv() {
+                Ok(Incoming::Event(_, event)) => return event,
+                Ok(Incoming::Stream(..)) => {}
+                Err(_) => return NetworkEvent::Disconnected,
+            }
+        }
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.address.is_none()
+    }
+
+    /// When the connection dropped, `None` while it is up.
+    pub fn lost_since(&self) -> Option<Instant> {
+        self.lost_since
+    }
+
+    /// Tries to connect to the host again until it gives up on the game, which
+    /// it does `timeout` seconds after `lost_since`.
+    fn reconnect(&self, lost_since: Instant) {
+        let (address, timeout) = match (&self.address, &self.reconnection) {
+            (Some(address), Some(terms)) => (address.clone(), Duration::from_secs(terms.timeout)),
+            _ => return,
+        };
+        let sender = self.sender.clone();
+        let deadline = lost_since + timeout;
+        thread::spawn(move || {
+            while Instant::now() < deadline {
+                thread::sleep(RETRY_INTERVAL);
+                if let Ok(stream) = TcpStream::connect(&address) {
+                    let _ = sender.send(Incoming::Stream(stream, None));
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Spectators of a hosted game.
+pub struct Spectators {
+    arrivals: Receiver<TcpStream>,
+    // Connected and waiting to be sent the game so far.
+    waiting: Vec<TcpStream>,
+    // Spectators let in, numbered so that their chat is not sent back to them.
+    watching: Vec<(usize, TcpStream)>,
+    next_id: usize,
+    chat_sender: Sender<(usize, String)>,
+    chat: Receiver<(usize, String)>,
+    /// Most spectators watching at once.
+    pub limit: usize,
+}
+
+impl Spectators {
+    /// Whether someone is waiting to be let in, see `admit`.
+    pub fn poll(&mut self) -> bool {
+        self.waiting.extend(self.arrivals.try_iter());
+        !self.waiting.is_empty()
+    }
+
+    /// Sends `welcome` to the waiting spectators there is room for and turns
+    /// the others away. Returns the number let in.
+    pub fn admit(&mut self, welcome: &[Message]) -> usize {
+        let mut admitted = 0;
+        for stream in self.waiting.drain(..) {
+            if self.watching.len() >= self.limit {
+                let _ = send_line(&stream, &Message::Full);
+                continue;
+            }
+            if !welcome.iter().all(|message| send_line(&stream, message).is_ok()) {
+                continue;
+            }
+            let reader = match stream.try_clone() {
+                Ok(reader) => BufReader::new(reader),
+                Err(_) => continue,
+            };
+            let (id, sender) = (self.next_id, self.chat_sender.clone());
+            self.next_id += 1;
+            thread::spawn(move || {
+                for line in reader.lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => return,
+                    };
+                    if let Ok(Message::Chat { text, .. }) = serde_json::from_str(&line) {
+                        if sender.send((id, text)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+            self.watching.push((id, stream));
+            admitted += 1;
+        }
+        admitted
+    }
+
+    /// Sends `message` to every spectator, dropping those who have left.
+    pub fn broadcast(&mut self, message: &Message) {
+        self.relay(usize::MAX, message);
+    }
+
+    /// Sends `message` to every spectator but the one numbered `from`.
+    pub fn relay(&mut self, from: usize, message: &Message) {
+        self.watching
+            .retain(|(id, stream)| *id == from || send_line(stream, message).is_ok());
+    }
+
+    /// Returns the chat lines written by spectators since the last call,
+    /// with the number of the spectator who wrote them.
+    pub fn chat(&mut self) -> Vec<(usize, String)> {
+        self.chat.try_iter().collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.watching.len()
+    }
+}
+
+fn send_line(mut stream: &TcpStream, message: &Message) -> io::Result<()> {
+    let line = serde_json::to_string(message)?;
+    writeln!(stream, "{}", line)
+}
+
+/// Reads the first line of `stream` on a thread of its own, so that a client
+/// slow to say hello holds up no one else, then hands both to `then`.
+fn greet(stream: TcpStream, then: impl FnOnce(TcpStream, Option<Message>) + Send + 'static) {
+    thread::spawn(move || {
+        let hello = read_hello(&stream);
+        then(stream, hello);
+    });
+}
+
+/// Reads the first line of a new connection, a byte at a time so that nothing
+/// after it is taken away from the reader of the connection.
+fn read_hello(stream: &TcpStream) -> Option<Message> {
+    let _ = stream.set_nodelay(true);
+    let _ = stream.set_read_timeout(Some(HELLO_TIMEOUT));
+    let (mut reader, mut line, mut byte) = (stream, Vec::new(), [0; 1]);
+    while line.len() < MAX_HELLO_LENGTH {
+        match reader.read(&mut byte) {
+            Ok(1) if byte[0] != b'\n' => line.push(byte[0]),
+            _ => break,
+        }
+    }
+    let _ = stream.set_read_timeout(None);
+    serde_json::from_slice(&line).ok()
+}
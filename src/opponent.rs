@@ -0,0 +1,240 @@
+//! Who plays the other side of the board.
+
+use crate::cli::{with_default_port, Mode, Options};
+use crate::endgame;
+use crate::engine::Engine;
+use crate::network::{Connection, Host, Message, NetworkEvent, Reconnection, Spectators};
+use crate::outcome::{team_from_name, team_name};
+use crate::position::opponent;
+use crate::util;
+use crate::variant::Variant;
+use chess::game::Team;
+use std::mem;
+use std::path::Path;
+
+pub enum Opponent {
+    /// Both sides are played in this window.
+    HotSeat,
+    Engine {
+        engine: Engine,
+        color: Team,
+        /// Whether a search has been started and not answered yet.
+        thinking: bool,
+    },
+    /// A hosted game waiting for a player to join, who then plays `color`.
+    Waiting {
+        host: Host,
+        color: Team,
+        /// Sent to the player once joined.
+        start: Message,
+    },
+    Network {
+        connection: Connection,
+        color: Team,
+        /// Spectators of a hosted game, `None` for a joined one.
+        spectators: Option<Spectators>,
+    },
+    /// A game hosted on another computer is watched, no moves can be made.
+    Spectating {
+        connection: Connection,
+    },
+    /// A stored game is being stepped through, no moves can be made.
+    Review,
+    /// The moves of `color` are played by a trainer, from the solution of a
+    /// puzzle, the lines of a repertoire or the built-in endgame opponent.
+    Trainer {
+        color: Team,
+    },
+}
+
+impl Opponent {
+    /// Starts the opponent chosen on the command line. A joining player takes
+    /// the starting position and time control from the host.
+    pub fn from_options(options: &mut Options) -> Result<Opponent, String> {
+        match &options.mode {
+            // Tournament games start their own opponents.
+            None | Some(Mode::Play) | Some(Mode::Tournament { .. }) => Ok(Opponent::HotSeat),
+            // Engine matches are only watched.
+            Some(Mode::Review { .. }) | Some(Mode::Match { .. }) => Ok(Opponent::Review),
+            // The color is that of the side to move in each puzzle.
+            Some(Mode::Puzzles { .. }) => Ok(Opponent::Trainer { color: Team::Black }),
+            Some(Mode::Repertoire { color, .. }) => Ok(Opponent::Trainer {
+                color: opponent(color.team()),
+            }),
+            Some(Mode::VsEngine { engine, color }) => {
+                let engine = engine.as_ref().ok_or_else(|| {
+                    "No engine given, pass --engine or add one in the settings".to_string()
+                })?;
+                Ok(Opponent::Engine {
+                    engine: start_engine(engine)?,
+                    color: opponent(color.team()),
+                    thinking: false,
+                })
+            }
+            // The player takes the side to move in each drill.
+            Some(Mode::Endgames {
+                drill,
+                engine,
+                builtin,
+            }) => {
+                let color = opponent(endgame::DRILLS[endgame::find(drill)?].color());
+                match engine.as_ref().filter(|_| !*builtin) {
+                    Some(engine) => Ok(Opponent::Engine {
+                        engine: start_engine(engine)?,
+                        color,
+                        thinking: false,
+                    }),
+                    None => Ok(Opponent::Trainer { color }),
+                }
+            }
+            Some(Mode::Host {
+                port,
+                color,
+                spectators,
+                reconnect_timeout,
+                run_clock,
+            }) => {
+                let reconnection = Reconnection {
+                    session: format!("{:016x}", util::random()),
+                    timeout: *reconnect_timeout,
+                    pause_clock: !*run_clock,
+                };
+                let host = Host::bind(*port, *spectators, reconnection.clone())
+                    .map_err(|error| format!("Could not host on port {}: {}", port, error))?;
+                let remote = opponent(color.team());
+                Ok(Opponent::Waiting {
+                    host,
+                    color: remote,
+                    start: Message::Start {
+                        color: team_name(remote).to_string(),
+                        fen: options.fen.clone(),
+                        time: options.time.map(|time| time.to_string()),
+                        variant: Some(options.variant().name().to_string()),
+                        reconnection: Some(reconnection),
+                    },
+                })
+            }
+            Some(Mode::Join { address }) => {
+                let address = with_default_port(address);
+                let mut connection = Connection::join(&address)
+                    .map_err(|error| format!("Could not connect to {}: {}", address, error))?;
+                match connection.wait() {
+                    NetworkEvent::Message(Message::Start {
+                        color,
+                        fen,
+                        time,
+                        variant,
+                        reconnection,
+                    }) => {
+                        connection.reconnection = reconnection;
+                        let local = team_from_name(&color)
+                            .ok_or_else(|| format!("Host sent unknown color '{}'", color))?;
+                        options.variant = variant.map(|name| name.parse()).transpose()?;
+                        options.chess960 = match options.variant {
+                            Some(Variant::Chess960) => Some(None),
+                            _ => None,
+                        };
+                        options.fen = fen;
+                        options.time = match time {
+                            Some(time) => Some(time.parse()?),
+                            None => None,
+                        };
+                        Ok(Opponent::Network {
+                            connection,
+                            color: opponent(local),
+                            spectators: None,
+                        })
+                    }
+                    _ => Err(format!("{} did not start a game", address)),
+                }
+            }
+            // The game is set up from the history the host sends.
+            Some(Mode::Watch { address }) => {
+                let address = with_default_port(address);
+                let connection = Connection::watch(&address)
+                    .map_err(|error| format!("Could not connect to {}: {}", address, error))?;
+                Ok(Opponent::Spectating { connection })
+            }
+        }
+    }
+
+    /// Starts a hosted game once a player has joined, returning whether one has.
+    pub fn poll_host(&mut self) -> Result<bool, String> {
+        let stream = match self {
+            Opponent::Waiting { host, .. } => match host.player() {
+                Some(stream) => stream,
+                None => return Ok(false),
+            },
+            _ => return Ok(false),
+        };
+        if let Opponent::Waiting { host, color, start } = mem::replace(self, Opponent::HotSeat) {
+            let (mut connection, spectators) = host
+                .start(stream)
+                .map_err(|error| format!("Could not start the game: {}", error))?;
+            connection
+                .send(&start)
+                .map_err(|error| format!("Could not start the game: {}", error))?;
+            *self = Opponent::Network {
+                connection,
+                color,
+                spectators: Some(spectators),
+            };
+        }
+        Ok(true)
+    }
+
+    /// Color played by the opponent, `None` when both sides are played here.
+    pub fn color(&self) -> Option<Team> {
+        match self {
+            Opponent::Engine { color, .. }
+            | Opponent::Waiting { color, .. }
+            | Opponent::Network { color, .. }
+            | Opponent::Trainer { color } => Some(*color),
+            Opponent::HotSeat | Opponent::Review | Opponent::Spectating { .. } => None,
+        }
+    }
+
+    /// Whether the game is played with another computer, which rules out
+    /// loading or setting up another one.
+    pub fn is_network(&self) -> bool {
+        matches!(
+            self,
+            Opponent::Waiting { .. } | Opponent::Network { .. } | Opponent::Spectating { .. }
+        )
+    }
+
+    /// Whether a hosted game is still waiting for its player.
+    pub fn is_waiting(&self) -> bool {
+        matches!(self, Opponent::Waiting { .. })
+    }
+
+    /// Whether the pieces of `team` are moved with the mouse in this window.
+    pub fn is_local(&self, team: Team) -> bool {
+        match self {
+            Opponent::Review | Opponent::Waiting { .. } | Opponent::Spectating { .. } => false,
+            _ => self.color() != Some(team),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Opponent::HotSeat => "Hot-seat game".to_string(),
+            Opponent::Engine { engine, .. } => format!("Playing {}", engine.name),
+            Opponent::Waiting { host, .. } => {
+                format!("Waiting for an opponent on port {}, Esc to stop", host.port())
+            }
+            Opponent::Network {
+                spectators: Some(spectators),
+                ..
+            } if spectators.count() > 0 => format!("Network game, {} watching", spectators.count()),
+            Opponent::Network { .. } => "Network game".to_string(),
+            Opponent::Spectating { .. } => "Watching a network game".to_string(),
+            Opponent::Review => "Reviewing game".to_string(),
+            Opponent::Trainer { .. } => "Training".to_string(),
+        }
+    }
+}
+
+fn start_engine(path: &Path) -> Result<Engine, String> {
+    Engine::start(path).map_err(|error| format!("Could not start {}: {}", path.display(), error))
+}
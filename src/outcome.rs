@@ -0,0 +1,140 @@
+//! The ways a game can end and how they are reported.
+
+use crate::position::{in_check, insufficient_material, opponent};
+use crate::record::GameRecord;
+use crate::variant::Variant;
+use chess::game::{Game, Team};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Checkmate { winner: Team },
+    Resignation { winner: Team },
+    Timeout { winner: Team },
+    DrawAgreement,
+    Stalemate,
+    InsufficientMaterial,
+    /// Won by a rule of the variant played, e.g. the third check.
+    Variant { winner: Team, variant: Variant },
+    /// The network connection to the loser dropped and did not come back in time.
+    Disconnection { winner: Team },
+}
+
+impl Outcome {
+    /// Checks whether the position on the board, reached by the moves of
+    /// `record`, ends the game.
+    pub fn detect(game: &mut Game, record: &GameRecord) -> Option<Outcome> {
+        let variant = record.variant;
+        if let Some(winner) = variant.winner(record, game) {
+            return Some(Outcome::Variant { winner, variant });
+        }
+        if variant.is_checkmate(game) {
+            return Some(Outcome::Checkmate {
+                winner: opponent(game.player),
+            });
+        }
+        // Also ends games where the variant leaves no moves, e.g. a blocked horde.
+        if !variant.has_moves(game) {
+            return Some(if variant.in_check(game, game.player) {
+                Outcome::Checkmate {
+                    winner: opponent(game.player),
+                }
+            } else {
+                Outcome::Stalemate
+            });
+        }
+        if variant.has_standard_rules() && insufficient_material(game) {
+            return Some(Outcome::InsufficientMaterial);
+        }
+        None
+    }
+
+    pub fn winner(&self) -> Option<Team> {
+        match *self {
+            Outcome::Checkmate { winner }
+            | Outcome::Resignation { winner }
+            | Outcome::Timeout { winner }
+            | Outcome::Variant { winner, .. }
+            | Outcome::Disconnection { winner } => Some(winner),
+            Outcome::DrawAgreement | Outcome::Stalemate | Outcome::InsufficientMaterial => None,
+        }
+    }
+
+    /// Result as written in the PGN `Result` tag.
+    pub fn result(&self) -> &'static str {
+        match self.winner() {
+            Some(Team::White) => "1-0",
+            Some(Team::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+
+    /// How the game ended as sent over the network: "resignation",
+    /// "timeout", "agreement", "disconnection", or "board" for the endings
+    /// that can be told from the position.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Outcome::Resignation { .. } => "resignation",
+            Outcome::Timeout { .. } => "timeout",
+            Outcome::DrawAgreement => "agreement",
+            Outcome::Disconnection { .. } => "disconnection",
+            _ => "board",
+        }
+    }
+
+    /// Human readable description shown when the game ends.
+    pub fn description(&self) -> String {
+        match *self {
+            Outcome::Checkmate { winner } => format!("Checkmate, {} wins", team_name(winner)),
+            Outcome::Resignation { winner } => {
+                format!("{} resigns", capitalized(team_name(opponent(winner))))
+            }
+            Outcome::Timeout { winner } => {
+                format!("{} ran out of time", capitalized(team_name(opponent(winner))))
+            }
+            Outcome::DrawAgreement => "Draw by agreement".to_string(),
+            Outcome::Stalemate => "Stalemate, the game is drawn".to_string(),
+            Outcome::InsufficientMaterial => "Draw by insufficient material".to_string(),
+            Outcome::Variant { winner, variant } => {
+                format!("{}, {} wins", variant.win_description(), team_name(winner))
+            }
+            Outcome::Disconnection { winner } => format!(
+                "{} did not reconnect, {} wins",
+                capitalized(team_name(opponent(winner))),
+                team_name(winner)
+            ),
+        }
+    }
+}
+
+/// Short description of the running game, e.g. "Black to move, in check".
+pub fn status(game: &Game) -> String {
+    let to_move = format!("{} to move", capitalized(team_name(game.player)));
+    if in_check(game, game.player) {
+        to_move + ", in check"
+    } else {
+        to_move
+    }
+}
+
+pub fn team_name(team: Team) -> &'static str {
+    match team {
+        Team::White => "white",
+        Team::Black => "black",
+    }
+}
+
+pub fn team_from_name(name: &str) -> Option<Team> {
+    match name {
+        "white" => Some(Team::White),
+        "black" => Some(Team::Black),
+        _ => None,
+    }
+}
+
+pub fn capitalized(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,297 @@
+//! Position queries on top of `chess::game::Game`.
+//!
+//! The game only hands out moves one piece at a time, so anything that needs
+//! to reason about the whole position (notation, annotations, end of game
+//! detection) goes through the functions in here.
+
+use chess::game::{Game, Rank, Team};
+use chess::moves::Action;
+
+/// Board coordinate as used by the game, `(file, rank)` counted from a1 = (0, 0).
+pub type Coordinate = (isize, isize);
+
+const KNIGHT_STEPS: [Coordinate; 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [Coordinate; 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_DIRECTIONS: [Coordinate; 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [Coordinate; 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+/// Translates a coordinate into its algebraic name, e.g. (4, 1) -> "e2".
+pub fn coordinate_to_string(coordinate: Coordinate) -> String {
+    let row_letter: String = (coordinate.1 + 1).to_string();
+    let column_number = coordinate.0 + 1;
+    let column_letter = match column_number {
+        1 => "a",
+        2 => "b",
+        3 => "c",
+        4 => "d",
+        5 => "e",
+        6 => "f",
+        7 => "g",
+        8 => "h",
+        _ => panic!("there shouldnt be a out of bounds letter here"),
+    };
+
+    String::from(column_letter) + &row_letter
+}
+
+/// Parses an algebraic square name, e.g. "e2" -> (4, 1).
+pub fn string_to_coordinate(name: &str) -> Option<Coordinate> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 {
+        return None;
+    }
+    let file = bytes[0] as isize - b'a' as isize;
+    let rank = bytes[1] as isize - b'1' as isize;
+    if on_board((file, rank)) {
+        Some((file, rank))
+    } else {
+        None
+    }
+}
+
+pub fn on_board(coordinate: Coordinate) -> bool {
+    (0..8).contains(&coordinate.0) && (0..8).contains(&coordinate.1)
+}
+
+pub fn opponent(team: Team) -> Team {
+    match team {
+        Team::White => Team::Black,
+        Team::Black => Team::White,
+    }
+}
+
+/// Upper case piece letter used in algebraic notation, pawns have none.
+pub fn rank_letter(rank: Rank) -> &'static str {
+    match rank {
+        Rank::King => "K",
+        Rank::Queen => "Q",
+        Rank::Rook => "R",
+        Rank::Bishop => "B",
+        Rank::Knight => "N",
+        Rank::Pawn => "",
+    }
+}
+
+pub fn letter_rank(letter: char) -> Option<Rank> {
+    match letter.to_ascii_uppercase() {
+        'K' => Some(Rank::King),
+        'Q' => Some(Rank::Queen),
+        'R' => Some(Rank::Rook),
+        'B' => Some(Rank::Bishop),
+        'N' => Some(Rank::Knight),
+        'P' => Some(Rank::Pawn),
+        _ => None,
+    }
+}
+
+pub fn piece_at(game: &Game, coordinate: Coordinate) -> Option<(Team, Rank)> {
+    for column in game.matrix.iter() {
+        for square in column.iter() {
+            if square.coordinate == coordinate {
+                return square.piece.map(|piece| (piece.team, piece.rank));
+            }
+        }
+    }
+    None
+}
+
+/// Every occupied square on the board together with its piece.
+pub fn pieces(game: &Game) -> Vec<(Coordinate, Team, Rank)> {
+    let mut pieces = Vec::new();
+    for column in game.matrix.iter() {
+        for square in column.iter() {
+            if let Some(piece) = square.piece {
+                pieces.push((square.coordinate, piece.team, piece.rank));
+            }
+        }
+    }
+    pieces
+}
+
+/// All moves available to the player in turn, paired with the square they start from.
+pub fn legal_moves(game: &mut Game) -> Vec<(Coordinate, Action)> {
+    let player = game.player;
+    let origins: Vec<Coordinate> = pieces(game)
+        .into_iter()
+        .filter(|(_, team, _)| *team == player)
+        .map(|(coordinate, _, _)| coordinate)
+        .collect();
+
+    let mut moves = Vec::new();
+    for from in origins {
+        if let Ok(actions) = game.move_from_string(&coordinate_to_string(from)) {
+            moves.extend(actions.into_iter().map(|action| (from, action)));
+        }
+    }
+    moves
+}
+
+/// Checks whether any piece of team `by` attacks the square `target`.
+pub fn is_attacked(game: &Game, target: Coordinate, by: Team) -> bool {
+    let forward = match by {
+        Team::White => 1,
+        Team::Black => -1,
+    };
+    let is = |coordinate: Coordinate, ranks: &[Rank]| match piece_at(game, coordinate) {
+        Some((team, rank)) => team == by && ranks.contains(&rank),
+        None => false,
+    };
+
+    if is((target.0 - 1, target.1 - forward), &[Rank::Pawn])
+        || is((target.0 + 1, target.1 - forward), &[Rank::Pawn])
+    {
+        return true;
+    }
+    for step in KNIGHT_STEPS.iter() {
+        if is((target.0 + step.0, target.1 + step.1), &[Rank::Knight]) {
+            return true;
+        }
+    }
+    for step in KING_STEPS.iter() {
+        if is((target.0 + step.0, target.1 + step.1), &[Rank::King]) {
+            return true;
+        }
+    }
+
+    let sliders = [
+        (&ROOK_DIRECTIONS, Rank::Rook),
+        (&BISHOP_DIRECTIONS, Rank::Bishop),
+    ];
+    for (directions, rank) in sliders.iter() {
+        for direction in directions.iter() {
+            let mut coordinate = (target.0 + direction.0, target.1 + direction.1);
+            while on_board(coordinate) {
+                if let Some((team, found)) = piece_at(game, coordinate) {
+                    if team == by && (found == *rank || found == Rank::Queen) {
+                        return true;
+                    }
+                    break;
+                }
+                coordinate = (coordinate.0 + direction.0, coordinate.1 + direction.1);
+            }
+        }
+    }
+    false
+}
+
+pub fn king_position(game: &Game, team: Team) -> Option<Coordinate> {
+    pieces(game)
+        .into_iter()
+        .find(|(_, owner, rank)| *owner == team && *rank == Rank::King)
+        .map(|(coordinate, _, _)| coordinate)
+}
+
+pub fn in_check(game: &Game, team: Team) -> bool {
+    match king_position(game, team) {
+        Some(king) => is_attacked(game, king, opponent(team)),
+        None => false,
+    }
+}
+
+/// Whether two squares are the same or next to each other.
+pub fn touches(a: Coordinate, b: Coordinate) -> bool {
+    (a.0 - b.0).abs() <= 1 && (a.1 - b.1).abs() <= 1
+}
+
+/// Squares the piece on `from` moves to by the way it moves, whether or not
+/// that leaves its king in check. Castling and en passant are left out.
+pub fn reachable_squares(game: &Game, from: Coordinate) -> Vec<Coordinate> {
+    let (team, rank) = match piece_at(game, from) {
+        Some(piece) => piece,
+        None => return Vec::new(),
+    };
+    let enemy = |square: Coordinate| piece_at(game, square).map_or(false, |(owner, _)| owner != team);
+    let free = |square: Coordinate| on_board(square) && piece_at(game, square).is_none();
+    let mut squares = Vec::new();
+    let (steps, slides): (&[Coordinate], bool) = match rank {
+        Rank::Pawn => {
+            let (forward, home) = match team {
+                Team::White => (1, 1),
+                Team::Black => (-1, 6),
+            };
+            let ahead = (from.0, from.1 + forward);
+            if free(ahead) {
+                squares.push(ahead);
+                let double = (from.0, from.1 + 2 * forward);
+                if from.1 == home && free(double) {
+                    squares.push(double);
+                }
+            }
+            for side in [-1, 1].iter() {
+                let diagonal = (from.0 + side, from.1 + forward);
+                if enemy(diagonal) {
+                    squares.push(diagonal);
+                }
+            }
+            return squares;
+        }
+        Rank::Knight => (&KNIGHT_STEPS, false),
+        Rank::King => (&KING_STEPS, false),
+        Rank::Rook => (&ROOK_DIRECTIONS, true),
+        Rank::Bishop => (&BISHOP_DIRECTIONS, true),
+        Rank::Queen => (&KING_STEPS, true),
+    };
+    for step in steps.iter() {
+        let mut square = (from.0 + step.0, from.1 + step.1);
+        while free(square) {
+            squares.push(square);
+            if !slides {
+                break;
+            }
+            square = (square.0 + step.0, square.1 + step.1);
+        }
+        if enemy(square) {
+            squares.push(square);
+        }
+    }
+    squares
+}
+
+/// Finds the move of the piece on `from` that lands on `to`, if it is legal.
+pub fn find_action(game: &mut Game, from: Coordinate, to: Coordinate) -> Option<Action> {
+    match game.move_from_string(&coordinate_to_string(from)) {
+        Ok(actions) => actions.into_iter().find(|action| action.to.coordinate == to),
+        Err(_) => None,
+    }
+}
+
+/// Checks for positions where neither side can possibly checkmate: king against
+/// king, king and a single minor piece against king, or only bishops left that
+/// all stand on squares of the same color.
+pub fn insufficient_material(game: &Game) -> bool {
+    let material: Vec<(Coordinate, Rank)> = pieces(game)
+        .into_iter()
+        .filter(|(_, _, rank)| *rank != Rank::King)
+        .map(|(coordinate, _, rank)| (coordinate, rank))
+        .collect();
+
+    match material.as_slice() {
+        [] => true,
+        [(_, Rank::Knight)] | [(_, Rank::Bishop)] => true,
+        _ => {
+            let square_color = |coordinate: &Coordinate| (coordinate.0 + coordinate.1) % 2;
+            let first_color = square_color(&material[0].0);
+            material
+                .iter()
+                .all(|(coordinate, rank)| *rank == Rank::Bishop && square_color(coordinate) == first_color)
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! Tactics puzzles and the rating of the player solving them.
+//!
+//! Puzzles are read from CSV in the format of the Lichess puzzle database,
+//! where the first move of the solution is the opponent's move leading to
+//! the puzzle, or from EPD, where the player to move solves it. The rating is
+//! kept with the Glicko system, each puzzle counting as a game against a
+//! player of the puzzle's rating.
+
+use crate::fen::Fen;
+use crate::record::{GameRecord, RecordedMove};
+use chess::game::{Game, GameState};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Columns of the Lichess puzzle database, used when a CSV file has no header.
+const LICHESS_COLUMNS: [&str; 8] = [
+    "PuzzleId",
+    "FEN",
+    "Moves",
+    "Rating",
+    "RatingDeviation",
+    "Popularity",
+    "NbPlays",
+    "Themes",
+];
+/// Rating and deviation of puzzles that do not give theirs, and of a new player.
+const DEFAULT_RATING: f64 = 1500.0;
+const DEFAULT_DEVIATION: f64 = 350.0;
+/// Deviation of puzzles without one, which have been rated by many players.
+const PUZZLE_DEVIATION: f64 = 80.0;
+/// Growth of the player's deviation before each puzzle, as their strength may
+/// have changed since the last one. Keeps the rating from freezing.
+const DEVIATION_DRIFT: f64 = 15.0;
+const MIN_DEVIATION: f64 = 45.0;
+
+pub struct Puzzle {
+    pub id: String,
+    /// Position the puzzle starts from, kept as text until the puzzle is played.
+    pub fen: String,
+    /// Moves of the solution in UCI notation, taking turns between the sides.
+    pub solution: Vec<String>,
+    /// Whether the first move of the solution is the opponent's, played before
+    /// the player starts solving.
+    pub opponent_first: bool,
+    pub rating: f64,
+    pub deviation: f64,
+    pub themes: Vec<String>,
+}
+
+impl Puzzle {
+    pub fn has_theme(&self, theme: &str) -> bool {
+        self.themes
+            .iter()
+            .any(|own| own.eq_ignore_ascii_case(theme))
+    }
+}
+
+/// Reads the puzzles of a CSV or EPD file, telling them apart by the extension.
+pub fn load(path: &Path) -> Result<Vec<Puzzle>, String> {
+    let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let epd = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("epd"));
+    let puzzles = if epd {
+        parse_epd(&text)?
+    } else {
+        parse_csv(&text)?
+    };
+    if puzzles.is_empty() {
+        return Err("No puzzles in the file".to_string());
+    }
+    Ok(puzzles)
+}
+
+/// Fields of a CSV line, which may be quoted.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Reads puzzles in the Lichess CSV format. A header line naming the columns
+/// is optional; the FEN and Moves columns are required with one.
+pub fn parse_csv(text: &str) -> Result<Vec<Puzzle>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .peekable();
+    let mut columns: Vec<String> = LICHESS_COLUMNS
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    if let Some((_, first)) = lines.peek() {
+        let header = csv_fields(first);
+        if header.iter().any(|name| name.trim() == "FEN") {
+            columns = header.iter().map(|name| name.trim().to_string()).collect();
+            lines.next();
+        }
+    }
+    let column = |name: &str| columns.iter().position(|column| column == name);
+    let fen_column = column("FEN").ok_or("The CSV header has no FEN column")?;
+    let moves_column = column("Moves").ok_or("The CSV header has no Moves column")?;
+
+    let mut puzzles = Vec::new();
+    for (index, line) in lines {
+        let fields = csv_fields(line);
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| fields.get(column))
+                .map(|field| field.trim())
+        };
+        let number = |name: &str| field(column(name)).and_then(|field| field.parse::<f64>().ok());
+        let fen = field(Some(fen_column)).unwrap_or("");
+        let solution: Vec<String> = field(Some(moves_column))
+            .unwrap_or("")
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        if fen.is_empty() || solution.len() < 2 {
+            return Err(format!("Line {} is not a puzzle", index + 1));
+        }
+        let rating = number("Rating").unwrap_or(DEFAULT_RATING);
+        let deviation = number("RatingDeviation").unwrap_or(PUZZLE_DEVIATION);
+        if !rating.is_finite() || !deviation.is_finite() {
+            return Err(format!("Line {} has a rating that is not a number", index + 1));
+        }
+        puzzles.push(Puzzle {
+            id: field(column("PuzzleId")).map_or_else(|| (index + 1).to_string(), str::to_string),
+            fen: fen.to_string(),
+            solution,
+            opponent_first: true,
+            rating,
+            deviation,
+            themes: field(column("Themes"))
+                .unwrap_or("")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+        });
+    }
+    Ok(puzzles)
+}
+
+/// Reads puzzles in EPD. The solution is the `pv` operation, or the first
+/// move of `bm`, both in SAN; `id` names the puzzle, and the comments `c0`
+/// and `c1` give its themes and rating.
+pub fn parse_epd(text: &str) -> Result<Vec<Puzzle>, String> {
+    let mut puzzles = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.splitn(5, ' ').collect();
+        if fields.len() < 5 {
+            return Err(format!("Line {} is not a puzzle", index + 1));
+        }
+        let fen = fields[..4].join(" ");
+        let mut operations = Vec::new();
+        for operation in fields[4].split(';') {
+            let operation = operation.trim();
+            if let Some(split) = operation.find(' ') {
+                let operand = operation[split..].trim().trim_matches('"');
+                operations.push((&operation[..split], operand));
+            }
+        }
+        let operation = |code: &str| {
+            operations
+                .iter()
+                .find(|(found, _)| *found == code)
+                .map(|(_, operand)| *operand)
+        };
+        let line_moves: Vec<&str> = match (operation("pv"), operation("bm")) {
+            (Some(pv), _) => pv.split_whitespace().collect(),
+            (None, Some(bm)) => bm.split_whitespace().take(1).collect(),
+            (None, None) => return Err(format!("Line {} gives no solution", index + 1)),
+        };
+        let solution = uci_line(&fen, &line_moves)
+            .map_err(|error| format!("Line {}: {}", index + 1, error))?;
+        let rating = operation("c1")
+            .and_then(|rating| rating.parse::<f64>().ok())
+            .unwrap_or(DEFAULT_RATING);
+        if !rating.is_finite() {
+            return Err(format!("Line {} has a rating that is not a number", index + 1));
+        }
+        puzzles.push(Puzzle {
+            id: operation("id").map_or_else(|| (index + 1).to_string(), str::to_string),
+            fen,
+            solution,
+            opponent_first: false,
+            rating,
+            deviation: PUZZLE_DEVIATION,
+            themes: operation("c0")
+                .unwrap_or("")
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|theme| !theme.is_empty())
+                .map(str::to_string)
+                .collect(),
+        });
+    }
+    Ok(puzzles)
+}
+
+/// UCI notation of a line of SAN moves played from `fen`.
+fn uci_line(fen: &str, moves: &[&str]) -> Result<Vec<String>, String> {
+    let mut record = GameRecord::starting_from(Some(fen.parse()?));
+    let mut game = record.start_game();
+    for san in moves {
+        record.play_san(&mut game, san)?;
+    }
+    Ok(record.moves.iter().map(RecordedMove::to_uci).collect())
+}
+
+/// A Glicko rating: the estimated strength and how uncertain it is.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Rating {
+        Rating {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+        }
+    }
+}
+
+impl Rating {
+    /// The rating after one game against `opponent`, scoring 1 for a win and 0 for a loss.
+    pub fn after_game(self, opponent: Rating, score: f64) -> Rating {
+        let q = 10f64.ln() / 400.0;
+        let deviation = (self.deviation.powi(2) + DEVIATION_DRIFT.powi(2))
+            .sqrt()
+            .min(DEFAULT_DEVIATION);
+        let g = 1.0 / (1.0 + 3.0 * q.powi(2) * opponent.deviation.powi(2) / PI.powi(2)).sqrt();
+        let expected = 1.0 / (1.0 + 10f64.powf(-g * (self.rating - opponent.rating) / 400.0));
+        let d_squared = 1.0 / (q.powi(2) * g.powi(2) * expected * (1.0 - expected));
+        let precision = 1.0 / deviation.powi(2) + 1.0 / d_squared;
+        Rating {
+            rating: self.rating + q / precision * g * (score - expected),
+            deviation: (1.0 / precision).sqrt().max(MIN_DEVIATION),
+        }
+    }
+}
+
+/// The player's rating and the puzzles they have done, kept between sessions.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct PuzzleStats {
+    pub rating: Rating,
+    pub solved: u32,
+    pub failed: u32,
+    /// Ids of the puzzles played, which are not picked again while others are left.
+    pub played: HashSet<String>,
+}
+
+/// Location of the stats, `$XDG_DATA_HOME/oskhen-chess-gui/puzzles.json`.
+pub fn stats_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("oskhen-chess-gui").join("puzzles.json"))
+}
+
+impl PuzzleStats {
+    pub fn load() -> PuzzleStats {
+        stats_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = stats_path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no data directory on this system")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// What the move just made does to the puzzle.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Verdict {
+    /// The move of the solution, with more to come.
+    Correct,
+    Solved,
+    Wrong,
+}
+
+/// A session of puzzles from one file.
+pub struct PuzzleTrainer {
+    puzzles: Vec<Puzzle>,
+    /// Themes puzzles are picked from, any of them will do. Empty for all puzzles.
+    pub themes: Vec<String>,
+    pub stats: PuzzleStats,
+    /// Index of the puzzle being solved.
+    current: Option<usize>,
+    /// Whether the current puzzle has been solved or failed.
+    finished: bool,
+    /// Rating change of the last puzzle finished.
+    pub last_change: f64,
+}
+
+impl PuzzleTrainer {
+    pub fn new(puzzles: Vec<Puzzle>, themes: Vec<String>) -> PuzzleTrainer {
+        PuzzleTrainer {
+            puzzles,
+            themes,
+            stats: PuzzleStats::load(),
+            current: None,
+            finished: false,
+            last_change: 0.0,
+        }
+    }
+
+    pub fn puzzle(&self) -> Option<&Puzzle> {
+        self.current.map(|index| &self.puzzles[index])
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn matches_themes(&self, puzzle: &Puzzle) -> bool {
+        self.themes.is_empty() || self.themes.iter().any(|theme| puzzle.has_theme(theme))
+    }
+
+    /// Every theme of the loaded puzzles, sorted.
+    pub fn all_themes(&self) -> Vec<String> {
+        let mut themes: Vec<String> = self
+            .puzzles
+            .iter()
+            .flat_map(|puzzle| puzzle.themes.iter().cloned())
+            .collect();
+        themes.sort();
+        themes.dedup();
+        themes
+    }
+
+    /// Filters by the theme after the one filtered by now, in the order of
+    /// `all_themes`, and after the last one by no theme at all.
+    pub fn next_theme(&mut self) {
+        let themes = self.all_themes();
+        let next = match self.themes.as_slice() {
+            [] => themes.first().cloned(),
+            [theme, ..] => themes
+                .iter()
+                .position(|other| other == theme)
+                .and_then(|index| themes.get(index + 1).cloned()),
+        };
+        self.themes = next.into_iter().collect();
+    }
+
+    /// Picks the puzzle rated closest to the player among those with the
+    /// themes asked for, preferring ones not played before. The ratings are
+    /// finite, as `parse_csv` and `parse_epd` turn down any other. Returns its
+    /// starting position, `None` when no puzzle has the themes.
+    pub fn next_puzzle(&mut self) -> Option<Result<Fen, String>> {
+        let rating = self.stats.rating.rating;
+        let mut candidates: Vec<usize> = (0..self.puzzles.len())
+            .filter(|index| self.matches_themes(&self.puzzles[*index]))
+            .collect();
+        // The same puzzle again only when it is the only one left.
+        if candidates.len() > 1 {
+            candidates.retain(|index| Some(*index) != self.current);
+        }
+        let fresh: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|index| !self.stats.played.contains(&self.puzzles[*index].id))
+            .collect();
+        let pool = if fresh.is_empty() {
+            &candidates
+        } else {
+            &fresh
+        };
+        let index = *pool.iter().min_by(|a, b| {
+            let distance = |index: &usize| (self.puzzles[*index].rating - rating).abs();
+            distance(a).partial_cmp(&distance(b)).unwrap()
+        })?;
+        self.current = Some(index);
+        self.finished = false;
+        Some(self.puzzles[index].fen.parse())
+    }
+
+    /// The move the trainer plays for the opponent after the first `ply` moves, if it is its turn.
+    pub fn reply(&self, ply: usize) -> Option<RecordedMove> {
+        let puzzle = self.puzzle()?;
+        let opponent_turn = (ply % 2 == 0) == puzzle.opponent_first;
+        if self.finished || !opponent_turn {
+            return None;
+        }
+        puzzle
+            .solution
+            .get(ply)
+            .and_then(|uci| RecordedMove::from_uci(uci))
+    }
+
+    /// Judges the last move of `record`, made by the player on `game`, and
+    /// updates the rating once the puzzle is solved or failed. Any mate solves it.
+    pub fn judge(&mut self, record: &GameRecord, game: &Game) -> Verdict {
+        let puzzle = match self.puzzle() {
+            Some(puzzle) if !self.finished => puzzle,
+            _ => return Verdict::Wrong,
+        };
+        let ply = record.ply();
+        let played = record.moves.last().map(RecordedMove::to_uci);
+        let verdict = if game.get_game_state() == GameState::Checkmate {
+            Verdict::Solved
+        } else if played.as_ref() != puzzle.solution.get(ply - 1) {
+            Verdict::Wrong
+        } else if ply >= puzzle.solution.len() {
+            Verdict::Solved
+        } else {
+            Verdict::Correct
+        };
+        if verdict != Verdict::Correct {
+            self.finish(verdict == Verdict::Solved);
+        }
+        verdict
+    }
+
+    fn finish(&mut self, solved: bool) {
+        let puzzle = &self.puzzles[self.current.unwrap()];
+        let opponent = Rating {
+            rating: puzzle.rating,
+            deviation: puzzle.deviation,
+        };
+        let before = self.stats.rating;
+        self.stats.rating = before.after_game(opponent, if solved { 1.0 } else { 0.0 });
+        self.last_change = self.stats.rating.rating - before.rating;
+        if solved {
+            self.stats.solved += 1;
+        } else {
+            self.stats.failed += 1;
+        }
+        self.stats.played.insert(puzzle.id.clone());
+        self.finished = true;
+    }
+
+    /// Gives up the puzzle being solved, which counts as failing it.
+    pub fn give_up(&mut self) {
+        if self.current.is_some() && !self.finished {
+            self.finish(false);
+        }
+    }
+}
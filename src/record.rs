@@ -0,0 +1,892 @@
+//! Move list of the game being played and its PGN representation.
+
+use crate::annotations::{strip_commands, Annotations};
+use crate::chess960;
+use crate::fen::Fen;
+use crate::position::{
+    coordinate_to_string, find_action, legal_moves, letter_rank, opponent, piece_at,
+    rank_letter, string_to_coordinate, Coordinate,
+};
+use crate::variant::{self, Variant};
+use chess::game::{Game, Rank, Team};
+use chess::moves::{Action, ActionType};
+
+/// Longest line written in the PGN movetext section.
+const PGN_LINE_WIDTH: usize = 80;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct RecordedMove {
+    pub from: Coordinate,
+    pub to: Coordinate,
+    pub promotion: Option<Rank>,
+}
+
+/// Comment and board annotations attached to a position.
+#[derive(Clone, Debug, Default)]
+pub struct Notes {
+    pub annotations: Annotations,
+    pub comment: String,
+    /// Numeric annotation glyphs of the move leading here, e.g. 2 for "?".
+    pub nags: Vec<u8>,
+}
+
+impl Notes {
+    fn is_empty(&self) -> bool {
+        self.annotations.is_empty() && self.comment.is_empty()
+    }
+
+    fn to_comment(&self) -> String {
+        let commands = self.annotations.to_commands();
+        match (commands.is_empty(), self.comment.is_empty()) {
+            (_, true) => format!("{{{}}}", commands),
+            (true, false) => format!("{{{}}}", self.comment),
+            (false, false) => format!("{{{} {}}}", commands, self.comment),
+        }
+    }
+}
+
+/// Everything needed to write the game as PGN.
+#[derive(Clone)]
+pub struct GameRecord {
+    pub headers: Vec<(String, String)>,
+    pub moves: Vec<RecordedMove>,
+    pub san: Vec<String>,
+    /// Notes for each position, index 0 is the starting position and
+    /// index `n` the position after the `n`th move.
+    pub notes: Vec<Notes>,
+    /// Halfmove clock of each position, counting moves since the last capture or pawn move.
+    pub halfmove_clocks: Vec<u32>,
+    pub result: String,
+    /// Starting position, `None` for the standard one.
+    pub start: Option<Fen>,
+    pub variant: Variant,
+}
+
+impl RecordedMove {
+    /// Long algebraic form as used by UCI, e.g. "e7e8q".
+    pub fn to_uci(&self) -> String {
+        let mut uci = coordinate_to_string(self.from) + &coordinate_to_string(self.to);
+        if let Some(rank) = self.promotion {
+            uci += &rank_letter(rank).to_lowercase();
+        }
+        uci
+    }
+
+    pub fn from_uci(uci: &str) -> Option<RecordedMove> {
+        if uci.len() < 4 || uci.len() > 5 || !uci.is_ascii() {
+            return None;
+        }
+        let promotion = match uci.chars().nth(4) {
+            Some(letter) if "qrbn".contains(letter) => letter_rank(letter),
+            Some(_) => return None,
+            None => None,
+        };
+        Some(RecordedMove {
+            from: string_to_coordinate(&uci[..2])?,
+            to: string_to_coordinate(&uci[2..4])?,
+            promotion,
+        })
+    }
+}
+
+impl Default for GameRecord {
+    fn default() -> GameRecord {
+        GameRecord::new()
+    }
+}
+
+impl GameRecord {
+    pub fn new() -> GameRecord {
+        let date = chrono::Local::now().format("%Y.%m.%d").to_string();
+        GameRecord {
+            headers: vec![
+                ("Event".to_string(), "Casual game".to_string()),
+                ("Site".to_string(), "?".to_string()),
+                ("Date".to_string(), date),
+                ("Round".to_string(), "-".to_string()),
+                ("White".to_string(), "White".to_string()),
+                ("Black".to_string(), "Black".to_string()),
+                ("Result".to_string(), "*".to_string()),
+            ],
+            moves: vec![],
+            san: vec![],
+            notes: vec![Notes::default()],
+            halfmove_clocks: vec![0],
+            result: "*".to_string(),
+            start: None,
+            variant: Variant::Standard,
+        }
+    }
+
+    /// A new record for a game starting from `start`, or the standard position.
+    pub fn starting_from(start: Option<Fen>) -> GameRecord {
+        let mut record = GameRecord::new();
+        if let Some(fen) = start {
+            record.set_header("SetUp", "1");
+            record.set_header("FEN", &fen.to_string());
+            record.halfmove_clocks = vec![fen.halfmove_clock];
+            record.start = Some(fen);
+        }
+        record
+    }
+
+    /// Plays the game by the rules of `variant`, recorded in the `Variant` tag.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        match variant {
+            Variant::Standard => self.headers.retain(|(key, _)| key != "Variant"),
+            _ => self.set_header("Variant", variant.name()),
+        }
+    }
+
+    /// A record of the same game with no moves played yet, to replay moves on.
+    pub fn restarted(&self) -> GameRecord {
+        let mut record = GameRecord::starting_from(self.start.clone());
+        record.set_variant(self.variant);
+        record
+    }
+
+    /// The position the game started from.
+    pub fn start_game(&self) -> Game {
+        match &self.start {
+            Some(fen) => fen.setup(),
+            None => Game::new(),
+        }
+    }
+
+    fn first_player(&self) -> Team {
+        self.start.as_ref().map_or(Team::White, |fen| fen.player)
+    }
+
+    /// Side to move in the position after the first `ply` moves.
+    pub fn player_at(&self, ply: usize) -> Team {
+        if ply % 2 == 0 {
+            self.first_player()
+        } else {
+            opponent(self.first_player())
+        }
+    }
+
+    /// Index of the first move counted in half moves from the standard start,
+    /// used to number moves of games set up from a FEN.
+    pub fn first_ply(&self) -> usize {
+        let fullmove = self.start.as_ref().map_or(1, |fen| fen.fullmove_number.max(1));
+        let black = if self.first_player() == Team::Black { 1 } else { 0 };
+        (fullmove as usize - 1) * 2 + black
+    }
+
+    /// Full FEN of `game`, which must be the position after the last recorded move.
+    pub fn fen(&self, game: &Game) -> Fen {
+        let mut fen = Fen::from_game(game);
+        let mut castling = self
+            .start
+            .as_ref()
+            .map_or("KQkq".to_string(), |start| start.castling.clone());
+        for recorded in self.moves.iter() {
+            for square in [recorded.from, recorded.to].iter() {
+                let lost = match *square {
+                    (4, 0) => "KQ",
+                    (7, 0) => "K",
+                    (0, 0) => "Q",
+                    (4, 7) => "kq",
+                    (7, 7) => "k",
+                    (0, 7) => "q",
+                    _ => "",
+                };
+                castling.retain(|c| !lost.contains(c));
+            }
+        }
+        fen.castling = if self.variant == Variant::Chess960 {
+            chess960::castling_field(&fen.placement, &self.castling_rooks(), false)
+        } else if castling.is_empty() || castling == "-" {
+            "-".to_string()
+        } else {
+            castling
+        };
+
+        if let Some(last) = self.moves.last() {
+            let pawn = piece_at(game, last.to).map(|(_, rank)| rank) == Some(Rank::Pawn);
+            if pawn && (last.to.1 - last.from.1).abs() == 2 {
+                fen.en_passant = Some((last.to.0, (last.to.1 + last.from.1) / 2));
+            }
+        }
+        fen.halfmove_clock = *self.halfmove_clocks.last().unwrap();
+        fen.fullmove_number = ((self.first_ply() + self.ply()) / 2 + 1) as u32;
+        fen
+    }
+
+    /// Like `fen`, for the position after the first `ply` moves.
+    pub fn fen_at(&self, ply: usize) -> Result<Fen, String> {
+        let mut game = self.start_game();
+        let mut scratch = self.restarted();
+        for recorded in self.moves.iter().take(ply) {
+            scratch.play_move(&mut game, *recorded)?;
+        }
+        Ok(scratch.fen(&game))
+    }
+
+    /// Position in the form expected by the UCI `position` command.
+    pub fn uci_position(&self) -> String {
+        self.uci_position_at(self.ply())
+    }
+
+    /// Like `uci_position`, for the position after the first `ply` moves.
+    pub fn uci_position_at(&self, ply: usize) -> String {
+        let mut position = match &self.start {
+            // Chess960 engines are given Shredder-FEN, which names the rooks
+            // that may castle by their files.
+            Some(fen) if self.variant == Variant::Chess960 => {
+                let mut fen = fen.clone();
+                let rooks = chess960::castling_rooks(&fen);
+                fen.castling = chess960::castling_field(&fen.placement, &rooks, true);
+                format!("fen {}", fen)
+            }
+            Some(fen) => format!("fen {}", fen),
+            None => "startpos".to_string(),
+        };
+        if ply > 0 {
+            let moves: Vec<String> = self.moves.iter().take(ply).map(RecordedMove::to_uci).collect();
+            position += " moves ";
+            position += &moves.join(" ");
+        }
+        position
+    }
+
+    /// SAN of `moves`, given in UCI notation, played from the position after
+    /// the first `ply` moves. Stops at the first move that cannot be played.
+    pub fn san_line(&self, ply: usize, moves: &[String]) -> Vec<String> {
+        let mut game = self.start_game();
+        let mut scratch = self.restarted();
+        for recorded in self.moves.iter().take(ply) {
+            if scratch.play_move(&mut game, *recorded).is_err() {
+                return vec![];
+            }
+        }
+        for uci in moves {
+            match RecordedMove::from_uci(uci) {
+                Some(recorded) if scratch.play_move(&mut game, recorded).is_ok() => {}
+                _ => break,
+            }
+        }
+        scratch.san.split_off(ply.min(scratch.san.len()))
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_header(&mut self, key: &str, value: &str) {
+        match self.headers.iter_mut().find(|(k, _)| k == key) {
+            Some(header) => header.1 = value.to_string(),
+            None => self.headers.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn set_result(&mut self, result: &str) {
+        self.result = result.to_string();
+        self.set_header("Result", result);
+    }
+
+    /// Number of half moves played.
+    pub fn ply(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn current_notes(&mut self) -> &mut Notes {
+        self.notes.last_mut().unwrap()
+    }
+
+    /// Performs `action` on the game and appends it to the record.
+    pub fn play(&mut self, game: &mut Game, from: Coordinate, action: Action) {
+        let promotion = if action.action_type == ActionType::Promotion {
+            game.promotion_piece
+        } else {
+            None
+        };
+        let to = action.to.coordinate;
+        let recorded = RecordedMove { from, to, promotion };
+        let captured = variant::is_capture(game, recorded);
+        let irreversible = captured || piece_at(game, from).map(|(_, rank)| rank) == Some(Rank::Pawn);
+        let san = san_before(game, self.variant, recorded);
+        game.perform_action(action);
+        self.variant.after_move(game, recorded, captured);
+        self.push_move(game, recorded, san, irreversible);
+    }
+
+    /// Plays one of the `extra_moves` of the variant, a move the board does
+    /// not know, and appends it to the record. A pawn reaching the last rank
+    /// becomes the piece asked for, or else the board's promotion piece or a queen.
+    fn play_extra(&mut self, game: &mut Game, mut recorded: RecordedMove) {
+        let (from, to) = (recorded.from, recorded.to);
+        let pawn = piece_at(game, from).map(|(_, rank)| rank) == Some(Rank::Pawn);
+        recorded.promotion = if pawn && (to.1 == 0 || to.1 == 7) {
+            Some(recorded.promotion.or(game.promotion_piece).unwrap_or(Rank::Queen))
+        } else {
+            None
+        };
+        let captured = variant::is_capture(game, recorded);
+        let san = san_before(game, self.variant, recorded);
+        let mut moving = None;
+        for square in game.matrix.iter_mut().flat_map(|column| column.iter_mut()) {
+            if square.coordinate == from {
+                moving = square.piece.take();
+            }
+        }
+        if let (Some(piece), Some(rank)) = (moving.as_mut(), recorded.promotion) {
+            piece.rank = rank;
+        }
+        for square in game.matrix.iter_mut().flat_map(|column| column.iter_mut()) {
+            if square.coordinate == to {
+                square.piece = moving;
+            }
+        }
+        game.player = opponent(game.player);
+        self.variant.after_move(game, recorded, captured);
+        self.push_move(game, recorded, san, captured || pawn);
+    }
+
+    /// Castles the king on `king` with the rook on `rook` in a Chess960 game
+    /// and appends the move to the record as the king taking the rook.
+    fn castle(&mut self, game: &mut Game, king: Coordinate, rook: Coordinate) {
+        let san = if chess960::is_kingside(king, rook) { "O-O" } else { "O-O-O" };
+        chess960::castle(game, king, rook);
+        let recorded = RecordedMove {
+            from: king,
+            to: rook,
+            promotion: None,
+        };
+        self.push_move(game, recorded, san.to_string(), false);
+    }
+
+    /// Appends a move just made on `game`, adding the check or mate suffix to `san`.
+    fn push_move(&mut self, game: &mut Game, recorded: RecordedMove, mut san: String, irreversible: bool) {
+        if self.variant.is_checkmate(game) {
+            san.push('#');
+        } else if self.variant.in_check(game, game.player) {
+            san.push('+');
+        }
+
+        self.moves.push(recorded);
+        self.san.push(san);
+        self.notes.push(Notes::default());
+        let clock = *self.halfmove_clocks.last().unwrap();
+        self.halfmove_clocks.push(if irreversible { 0 } else { clock + 1 });
+    }
+
+    /// Plays a recorded move, e.g. one read from a file or received from an opponent.
+    pub fn play_move(&mut self, game: &mut Game, recorded: RecordedMove) -> Result<(), String> {
+        if self.castling_moves(game).contains(&(recorded.from, recorded.to)) {
+            self.castle(game, recorded.from, recorded.to);
+            return Ok(());
+        }
+        if self.variant.extra_moves(game).contains(&(recorded.from, recorded.to)) {
+            self.play_extra(game, recorded);
+            return Ok(());
+        }
+        let variant = self.variant;
+        let action = find_action(game, recorded.from, recorded.to)
+            .filter(|_| variant.allows(game, recorded))
+            .ok_or_else(|| {
+                format!(
+                    "Illegal move {} at ply {}",
+                    recorded.to_uci(),
+                    self.ply() + 1
+                )
+            })?;
+        let previous_promotion = game.promotion_piece;
+        if let Some(rank) = recorded.promotion {
+            game.set_promotion_piece(rank);
+        }
+        self.play(game, recorded.from, action);
+        game.promotion_piece = previous_promotion;
+        Ok(())
+    }
+
+    /// Squares of the rooks that may still castle after the moves played so
+    /// far. Only kept track of in Chess960 games.
+    pub fn castling_rooks(&self) -> Vec<Coordinate> {
+        let start = match &self.start {
+            Some(fen) if self.variant == Variant::Chess960 => fen,
+            _ => return Vec::new(),
+        };
+        let king_start = |rank: isize| {
+            start
+                .placement
+                .iter()
+                .find(|(square, _, piece)| *piece == Rank::King && square.1 == rank)
+                .map(|(square, _, _)| *square)
+        };
+        let mut rooks = chess960::castling_rooks(start);
+        for recorded in self.moves.iter() {
+            rooks.retain(|rook| {
+                let touched = *rook == recorded.from || *rook == recorded.to;
+                !touched && king_start(rook.1) != Some(recorded.from)
+            });
+        }
+        rooks
+    }
+
+    /// Castling moves of the player in turn in a Chess960 game, as pairs of
+    /// king and rook square.
+    pub fn castling_moves(&self, game: &Game) -> Vec<(Coordinate, Coordinate)> {
+        chess960::castling_moves(game, &self.castling_rooks())
+    }
+
+    /// Number of checks `team` gave in the first `ply` moves.
+    pub fn checks_given(&self, team: Team, ply: usize) -> usize {
+        self.san
+            .iter()
+            .take(ply)
+            .enumerate()
+            .filter(|(index, san)| self.player_at(*index) == team && san.ends_with(&['+', '#'][..]))
+            .count()
+    }
+
+    /// Rebuilds the game as it was after the first `ply` moves.
+    pub fn replay(&self, ply: usize) -> Result<Game, String> {
+        let mut game = self.start_game();
+        let mut scratch = self.restarted();
+        for recorded in self.moves.iter().take(ply) {
+            scratch.play_move(&mut game, *recorded)?;
+        }
+        Ok(game)
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (key, value) in self.headers.iter() {
+            pgn += &format!("[{} \"{}\"]\n", key, value.replace('\\', "\\\\").replace('"', "\\\""));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        if !self.notes[0].is_empty() {
+            tokens.push(self.notes[0].to_comment());
+        }
+        let first_ply = self.first_ply();
+        let mut after_comment = false;
+        for (index, san) in self.san.iter().enumerate() {
+            let ply = first_ply + index;
+            if ply % 2 == 0 {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            } else if index == 0 || after_comment {
+                tokens.push(format!("{}...", ply / 2 + 1));
+            }
+            tokens.push(san.clone());
+
+            let notes = &self.notes[index + 1];
+            tokens.extend(notes.nags.iter().map(|nag| format!("${}", nag)));
+            after_comment = !notes.is_empty();
+            if after_comment {
+                tokens.push(notes.to_comment());
+            }
+        }
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() + 1 > PGN_LINE_WIDTH {
+                pgn += &line;
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &token;
+        }
+        pgn += &line;
+        pgn.push('\n');
+        pgn
+    }
+
+    /// Reads the first game of a PGN text, returning its record and final position.
+    /// Variations are skipped.
+    pub fn from_pgn(text: &str) -> Result<(GameRecord, Game), String> {
+        GameRecord::read_pgn(text, false).map(|(record, game, _)| (record, game))
+    }
+
+    /// Reads the first game of a PGN text with its variations, returning a
+    /// record for every line of moves: the main line, then each variation
+    /// played out from the start, in the order they close.
+    pub fn lines_from_pgn(text: &str) -> Result<Vec<GameRecord>, String> {
+        let (main, _, mut lines) = GameRecord::read_pgn(text, true)?;
+        lines.push(main);
+        Ok(lines)
+    }
+
+    /// Reads the first game of a PGN text. With `variations`, the moves of
+    /// variations are played too, on copies of the record taken back to
+    /// where they branch off, which are returned once the variations close.
+    fn read_pgn(text: &str, variations: bool) -> Result<(GameRecord, Game, Vec<GameRecord>), String> {
+        let mut record = GameRecord::new();
+        record.headers.clear();
+        let mut game = Game::new();
+        let previous_promotion = game.promotion_piece;
+        // The starting position is known once all tags have been read.
+        let mut started = false;
+
+        let mut chars = text.chars().peekable();
+        let mut depth = 0;
+        // Records left to go back to when the variations being read close.
+        let mut outer: Vec<GameRecord> = Vec::new();
+        let mut lines = Vec::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '[' if depth == 0 => {
+                    let tag = read_tag(&mut chars);
+                    let tag = tag.trim();
+                    if let Some(split) = tag.find(char::is_whitespace) {
+                        // Only the quotes around the value, an escaped one may end it.
+                        let value = tag[split..].trim();
+                        let value = value.strip_prefix('"').unwrap_or(value);
+                        let value = value.strip_suffix('"').unwrap_or(value);
+                        let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+                        record.set_header(&tag[..split], &value);
+                    }
+                }
+                '{' => {
+                    let comment: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    if depth == 0 || variations {
+                        let notes = record.current_notes();
+                        let annotations = Annotations::from_comment(&comment);
+                        notes.annotations.arrows.extend(annotations.arrows);
+                        notes.annotations.squares.extend(annotations.squares);
+                        let text = strip_commands(&comment);
+                        if !text.is_empty() {
+                            if !notes.comment.is_empty() {
+                                notes.comment.push(' ');
+                            }
+                            notes.comment += &text;
+                        }
+                    }
+                }
+                ';' => {
+                    chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+                }
+                '(' if variations => {
+                    // A variation replaces the move before it.
+                    let mut branch = record.clone();
+                    if !branch.take_back() {
+                        return Err("A variation comes before any move".to_string());
+                    }
+                    game = branch.replay(branch.ply())?;
+                    outer.push(std::mem::replace(&mut record, branch));
+                    depth += 1;
+                }
+                '(' => depth += 1,
+                ')' if depth == 0 => return Err("Unmatched ')' in the moves".to_string()),
+                ')' if variations => {
+                    let main = outer.pop().unwrap();
+                    game = main.replay(main.ply())?;
+                    lines.push(std::mem::replace(&mut record, main));
+                    depth -= 1;
+                }
+                ')' => depth -= 1,
+                c if c.is_whitespace() => {}
+                c => {
+                    let mut token = c.to_string();
+                    while let Some(next) = chars.peek() {
+                        if next.is_whitespace() || "{}()[];".contains(*next) {
+                            break;
+                        }
+                        token.push(chars.next().unwrap());
+                    }
+                    if depth > 0 && !variations {
+                        continue;
+                    }
+                    if let Some(nag) = token.strip_prefix('$') {
+                        if let Ok(nag) = nag.parse() {
+                            record.current_notes().nags.push(nag);
+                        }
+                        continue;
+                    }
+                    match token.as_str() {
+                        "1-0" | "0-1" | "1/2-1/2" | "*" if depth == 0 => {
+                            record.set_result(&token);
+                            break;
+                        }
+                        "1-0" | "0-1" | "1/2-1/2" | "*" => continue,
+                        _ => {}
+                    }
+
+                    // Move numbers end in dots, unlike castling written with zeros.
+                    let number = token.trim_start_matches(|c: char| c.is_ascii_digit());
+                    let san = if number.is_empty() || number.starts_with('.') {
+                        number.trim_start_matches('.')
+                    } else {
+                        token.as_str()
+                    };
+                    if san.is_empty() {
+                        continue;
+                    }
+                    if !started {
+                        started = true;
+                        game = record.set_up_from_tags(previous_promotion)?;
+                    }
+                    record.play_san(&mut game, san)?;
+                }
+            }
+        }
+        if !started {
+            game = record.set_up_from_tags(previous_promotion)?;
+        }
+        game.promotion_piece = previous_promotion;
+
+        if depth > 0 {
+            return Err("Unmatched '(' in the moves".to_string());
+        }
+
+        if let Some(result) = record.header("Result").map(str::to_string) {
+            record.result = result;
+        }
+        Ok((record, game, lines))
+    }
+
+    /// Takes back the last move, returning whether there was one.
+    fn take_back(&mut self) -> bool {
+        if self.moves.pop().is_none() {
+            return false;
+        }
+        self.san.pop();
+        self.notes.pop();
+        self.halfmove_clocks.pop();
+        true
+    }
+
+    /// Plays a move given in SAN, e.g. one read from a PGN file.
+    pub fn play_san(&mut self, game: &mut Game, san: &str) -> Result<(), String> {
+        let ply = self.ply() + 1;
+        let illegal = || format!("Illegal move {} at ply {}", san, ply);
+        if let (Variant::Chess960, Some(kingside)) = (self.variant, castling_side(san)) {
+            let (king, rook) = self
+                .castling_moves(game)
+                .into_iter()
+                .find(|(king, rook)| chess960::is_kingside(*king, *rook) == kingside)
+                .ok_or_else(illegal)?;
+            self.castle(game, king, rook);
+            return Ok(());
+        }
+        let variant = self.variant;
+        let resolved = resolve_san(game, san).filter(|(from, action, _)| {
+            let recorded = RecordedMove { from: *from, to: action.to.coordinate, promotion: None };
+            variant.allows(game, recorded)
+        });
+        if resolved.is_none() {
+            let extra = SanMove::parse(san).and_then(|parsed| {
+                variant
+                    .extra_moves(game)
+                    .into_iter()
+                    .find(|(from, to)| parsed.matches(game, *from, *to))
+                    .map(|(from, to)| RecordedMove { from, to, promotion: parsed.promotion })
+            });
+            if let Some(recorded) = extra {
+                self.play_extra(game, recorded);
+                return Ok(());
+            }
+        }
+        let (from, action, promotion) = resolved.ok_or_else(illegal)?;
+        let previous_promotion = game.promotion_piece;
+        if let Some(rank) = promotion {
+            game.set_promotion_piece(rank);
+        }
+        self.play(game, from, action);
+        game.promotion_piece = previous_promotion;
+        Ok(())
+    }
+
+    /// Applies the `Variant` and `FEN` tags of a record being read, returning the starting game.
+    fn set_up_from_tags(&mut self, promotion: Option<Rank>) -> Result<Game, String> {
+        if let Some(variant) = self.header("Variant") {
+            self.variant = variant.parse()?;
+        }
+        if let Some(fen) = self.header("FEN") {
+            let fen: Fen = fen.parse()?;
+            self.halfmove_clocks = vec![fen.halfmove_clock];
+            self.start = Some(fen);
+        } else {
+            // Without a FEN the game starts where the variant does, e.g. Horde.
+            self.start = self.variant.start_position();
+        }
+        let mut game = self.start_game();
+        game.promotion_piece = promotion;
+        Ok(game)
+    }
+}
+
+/// Reads a tag pair up to its closing ']', which may also stand inside the
+/// quoted value, e.g. `[Event "Cup [final]"]`.
+fn read_tag(chars: &mut impl Iterator<Item = char>) -> String {
+    let mut tag = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in chars {
+        match c {
+            ']' if !quoted => break,
+            '"' if !escaped => quoted = !quoted,
+            _ => {}
+        }
+        escaped = c == '\\' && !escaped;
+        tag.push(c);
+    }
+    tag
+}
+
+/// Whether a SAN token castles short, long, or is no castling at all.
+fn castling_side(san: &str) -> Option<bool> {
+    match san.trim_end_matches(|c| "+#!?".contains(c)) {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Standard algebraic notation of a move, without the check suffix which
+/// is only known once the move has been made.
+fn san_before(game: &mut Game, variant: Variant, recorded: RecordedMove) -> String {
+    let (from, to) = (recorded.from, recorded.to);
+    let rank = match piece_at(game, from) {
+        Some((_, rank)) => rank,
+        None => return String::new(),
+    };
+    if rank == Rank::King && (to.0 - from.0).abs() == 2 {
+        return if to.0 > from.0 { "O-O" } else { "O-O-O" }.to_string();
+    }
+
+    let capture = piece_at(game, to).is_some() || (rank == Rank::Pawn && to.0 != from.0);
+    let from_name = coordinate_to_string(from);
+    let mut san = String::new();
+    if rank == Rank::Pawn {
+        if capture {
+            san += &from_name[..1];
+        }
+    } else {
+        san += rank_letter(rank);
+        let mut moves: Vec<(Coordinate, Coordinate)> = legal_moves(game)
+            .into_iter()
+            .map(|(other, action)| (other, action.to.coordinate))
+            .filter(|(other, target)| {
+                variant.allows(game, RecordedMove { from: *other, to: *target, promotion: None })
+            })
+            .collect();
+        moves.extend(variant.extra_moves(game));
+        let rivals: Vec<Coordinate> = moves
+            .into_iter()
+            .filter(|(other, target)| {
+                *other != from && *target == to && piece_at(game, *other).map(|(_, r)| r) == Some(rank)
+            })
+            .map(|(other, _)| other)
+            .collect();
+        if !rivals.is_empty() {
+            if rivals.iter().all(|other| other.0 != from.0) {
+                san += &from_name[..1];
+            } else if rivals.iter().all(|other| other.1 != from.1) {
+                san += &from_name[1..];
+            } else {
+                san += &from_name;
+            }
+        }
+    }
+    if capture {
+        san.push('x');
+    }
+    san += &coordinate_to_string(to);
+    if let Some(rank) = recorded.promotion {
+        san.push('=');
+        san += rank_letter(rank);
+    }
+    san
+}
+
+/// A SAN token other than castling taken apart.
+struct SanMove {
+    rank: Rank,
+    to: Coordinate,
+    /// File and rank given to tell pieces that can reach `to` apart.
+    file: Option<isize>,
+    row: Option<isize>,
+    promotion: Option<Rank>,
+}
+
+impl SanMove {
+    /// Reads a token such as "Nbd7", "exd5" or "e8=Q+".
+    fn parse(san: &str) -> Option<SanMove> {
+        let san = san.trim_end_matches(|c| "+#!?".contains(c));
+        let mut chars: Vec<char> = san.chars().filter(|c| *c != 'x' && *c != '=').collect();
+        let promotion = match chars.last() {
+            Some(c) if chars.len() > 2 && "QRBN".contains(*c) => {
+                let rank = letter_rank(*c);
+                chars.pop();
+                rank
+            }
+            _ => None,
+        };
+        let rank = match chars.first() {
+            Some(c) if c.is_ascii_uppercase() => {
+                let rank = letter_rank(*c)?;
+                chars.remove(0);
+                rank
+            }
+            _ => Rank::Pawn,
+        };
+        if chars.len() < 2 {
+            return None;
+        }
+        let target: String = chars[chars.len() - 2..].iter().collect();
+        let to = string_to_coordinate(&target)?;
+        let hints = &chars[..chars.len() - 2];
+        let file = hints
+            .iter()
+            .find(|c| ('a'..='h').contains(*c))
+            .map(|c| *c as isize - 'a' as isize);
+        let row = hints
+            .iter()
+            .find(|c| ('1'..='8').contains(*c))
+            .map(|c| *c as isize - '1' as isize);
+        Some(SanMove {
+            rank,
+            to,
+            file,
+            row,
+            promotion,
+        })
+    }
+
+    /// Whether the move of the piece on `from` to `to` is the one described.
+    fn matches(&self, game: &Game, from: Coordinate, to: Coordinate) -> bool {
+        to == self.to
+            && piece_at(game, from).map(|(_, rank)| rank) == Some(self.rank)
+            && self.file.map_or(true, |file| from.0 == file)
+            && self.row.map_or(true, |row| from.1 == row)
+    }
+}
+
+/// Finds the legal move described by a SAN token such as "Nbd7", "exd5" or "e8=Q+".
+pub fn resolve_san(game: &mut Game, san: &str) -> Option<(Coordinate, Action, Option<Rank>)> {
+    let san = san.trim_end_matches(|c| "+#!?".contains(c));
+    let moves = legal_moves(game);
+    let rank_of = |coordinate: Coordinate| piece_at(game, coordinate).map(|(_, rank)| rank);
+
+    let castle = match san {
+        "O-O" | "0-0" => Some(2),
+        "O-O-O" | "0-0-0" => Some(-2),
+        _ => None,
+    };
+    if let Some(direction) = castle {
+        return moves
+            .into_iter()
+            .find(|(from, action)| {
+                rank_of(*from) == Some(Rank::King) && action.to.coordinate.0 - from.0 == direction
+            })
+            .map(|(from, action)| (from, action, None));
+    }
+
+    let parsed = SanMove::parse(san)?;
+    moves
+        .into_iter()
+        .find(|(from, action)| parsed.matches(game, *from, action.to.coordinate))
+        .map(|(from, action)| (from, action, parsed.promotion))
+}
@@ -0,0 +1,296 @@
+//! Opening repertoires and drilling them with spaced repetition.
+//!
+//! A repertoire is a PGN file whose games and variations make up a tree of
+//! moves. Every path from the root to a leaf is a line to learn. Lines are
+//! scheduled in the manner of a Leitner box: a line played without mistakes
+//! moves up a box and comes back after a longer interval, a mistake sends it
+//! back to the first box to be drilled again straight away.
+
+use crate::database::split_games;
+use crate::fen::Fen;
+use crate::outcome::team_name;
+use crate::record::{GameRecord, RecordedMove};
+use chess::game::Team;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Days until a line in each box is due again; lines in the last box stay there.
+const BOX_INTERVALS: [u64; 7] = [0, 1, 3, 7, 14, 30, 90];
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub struct RepertoireLine {
+    /// Position the line starts from, `None` for the standard one.
+    pub start: Option<Fen>,
+    /// Moves in UCI notation.
+    pub moves: Vec<String>,
+    pub san: Vec<String>,
+}
+
+impl RepertoireLine {
+    /// Name of the line in the progress file.
+    fn key(&self) -> String {
+        let moves = self.moves.join(" ");
+        match &self.start {
+            Some(fen) => format!("{} {}", fen, moves),
+            None => moves,
+        }
+    }
+}
+
+/// Reads the lines of every game in a PGN file.
+pub fn load(path: &Path) -> Result<Vec<RepertoireLine>, String> {
+    let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let games = split_games(&text);
+    let games: Vec<&str> = if games.is_empty() {
+        vec![text.as_str()]
+    } else {
+        games.into_iter().map(|(_, game)| game).collect()
+    };
+    let mut lines: Vec<RepertoireLine> = Vec::new();
+    for (number, game) in games.iter().enumerate() {
+        let records = GameRecord::lines_from_pgn(game)
+            .map_err(|error| format!("Game {}: {}", number + 1, error))?;
+        lines.extend(records.into_iter().map(|record| RepertoireLine {
+            start: record.start.clone(),
+            moves: record.moves.iter().map(RecordedMove::to_uci).collect(),
+            san: record.san,
+        }));
+    }
+    // Lines leading into longer ones, e.g. from an empty variation, are not
+    // lines of their own, and neither are the same lines given twice.
+    let keys: Vec<String> = lines.iter().map(RepertoireLine::key).collect();
+    let mut kept = Vec::new();
+    for (index, line) in lines.into_iter().enumerate() {
+        let key = &keys[index];
+        let covered = keys.iter().enumerate().any(|(other, longer)| {
+            let extends = longer.len() > key.len() && longer.starts_with(&format!("{} ", key));
+            extends || (other < index && longer == key)
+        });
+        if !covered && !line.moves.is_empty() {
+            kept.push(line);
+        }
+    }
+    if kept.is_empty() {
+        return Err("No moves in the repertoire".to_string());
+    }
+    Ok(kept)
+}
+
+/// How well a line has been learned.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub struct LineProgress {
+    /// Index into `BOX_INTERVALS`.
+    pub level: usize,
+    /// When the line is to be drilled again, in seconds since the Unix epoch.
+    pub due: u64,
+    pub reviews: u32,
+    pub lapses: u32,
+}
+
+/// Location of the progress, `$XDG_DATA_HOME/oskhen-chess-gui/repertoire.json`.
+pub fn progress_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("oskhen-chess-gui").join("repertoire.json"))
+}
+
+/// Progress of the lines of every repertoire drilled, by `repertoire_key`.
+type Progress = HashMap<String, HashMap<String, LineProgress>>;
+
+fn load_progress() -> Progress {
+    progress_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Name of a repertoire in the progress file: the full path of its file and
+/// the side learned, as the same lines are learned apart for either side.
+pub fn repertoire_key(path: &Path, color: Team) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    format!("{} {}", path.display(), team_name(color))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// What the move just made does to the line being drilled.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Verdict {
+    /// The repertoire move, with more of the line to come.
+    Correct,
+    /// The last move of the line.
+    Completed,
+    Wrong,
+}
+
+/// A session drilling the lines of one repertoire.
+pub struct RepertoireTrainer {
+    lines: Vec<RepertoireLine>,
+    /// Side the player learns the moves of.
+    pub color: Team,
+    /// The repertoire's `repertoire_key`.
+    repertoire: String,
+    progress: HashMap<String, LineProgress>,
+    /// Index of the line being drilled.
+    current: Option<usize>,
+    /// Mistakes made in the current line.
+    pub mistakes: u32,
+    finished: bool,
+}
+
+impl RepertoireTrainer {
+    /// Drills `lines`, read from the file at `path`, for `color`.
+    pub fn new(lines: Vec<RepertoireLine>, color: Team, path: &Path) -> RepertoireTrainer {
+        let repertoire = repertoire_key(path, color);
+        RepertoireTrainer {
+            lines,
+            color,
+            progress: load_progress().remove(&repertoire).unwrap_or_default(),
+            repertoire,
+            current: None,
+            mistakes: 0,
+            finished: false,
+        }
+    }
+
+    pub fn line(&self) -> Option<&RepertoireLine> {
+        self.current.map(|index| &self.lines[index])
+    }
+
+    /// Position of the current line among all lines, counting from 1.
+    pub fn line_number(&self) -> Option<usize> {
+        self.current.map(|index| index + 1)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn progress_of(&self, index: usize) -> Option<&LineProgress> {
+        self.progress.get(&self.lines[index].key())
+    }
+
+    /// Number of lines due at `time`, lines never drilled included.
+    pub fn due_count(&self, time: u64) -> usize {
+        (0..self.lines.len())
+            .filter(|index| {
+                self.progress_of(*index)
+                    .map_or(true, |progress| progress.due <= time)
+            })
+            .count()
+    }
+
+    /// Number of lines past the first box.
+    pub fn learned_count(&self) -> usize {
+        (0..self.lines.len())
+            .filter(|index| {
+                self.progress_of(*index)
+                    .map_or(false, |progress| progress.level > 0)
+            })
+            .count()
+    }
+
+    /// Picks the line to drill at `time`: the one overdue the longest, then
+    /// new lines in the order of the file, and when no line is due the one
+    /// due first. Returns whether it is due.
+    pub fn next_line(&mut self, time: u64) -> bool {
+        let due = |index: usize| self.progress_of(index).map(|progress| progress.due);
+        let rank = |index: usize| match due(index) {
+            Some(due) if due <= time => (0, due),
+            None => (1, 0),
+            Some(due) => (2, due),
+        };
+        // The line just drilled comes last, unless it is the only one.
+        let index = (0..self.lines.len())
+            .min_by_key(|index| {
+                (
+                    Some(*index) == self.current && self.lines.len() > 1,
+                    rank(*index),
+                )
+            })
+            .unwrap();
+        let is_due = rank(index).0 < 2;
+        self.current = Some(index);
+        self.mistakes = 0;
+        self.finished = false;
+        is_due
+    }
+
+    /// The move the trainer plays after the first `ply` moves, if it is the
+    /// opponent's turn, `player` being the side to move.
+    pub fn reply(&self, ply: usize, player: Team) -> Option<RecordedMove> {
+        if self.finished || player == self.color {
+            return None;
+        }
+        self.line()?
+            .moves
+            .get(ply)
+            .and_then(|uci| RecordedMove::from_uci(uci))
+    }
+
+    /// The repertoire move after the first `ply` moves.
+    pub fn expected(&self, ply: usize) -> Option<&str> {
+        self.line()?.moves.get(ply).map(String::as_str)
+    }
+
+    /// Judges the player's move `played`, in UCI notation, made after the first `ply` moves.
+    pub fn judge(&mut self, ply: usize, played: &str, time: u64) -> Verdict {
+        if self.expected(ply) != Some(played) {
+            self.mistakes += 1;
+            return Verdict::Wrong;
+        }
+        if self.complete_at(ply + 1, time) {
+            Verdict::Completed
+        } else {
+            Verdict::Correct
+        }
+    }
+
+    /// Finishes the line if `ply` moves complete it, scheduling it again.
+    pub fn complete_at(&mut self, ply: usize, time: u64) -> bool {
+        let key = match self.line() {
+            Some(line) if !self.finished && ply >= line.moves.len() => line.key(),
+            _ => return false,
+        };
+        let progress = self.progress.entry(key).or_default();
+        progress.reviews += 1;
+        if self.mistakes == 0 {
+            progress.level = (progress.level + 1).min(BOX_INTERVALS.len() - 1);
+        } else {
+            progress.level = 0;
+            progress.lapses += 1;
+        }
+        progress.due = time + BOX_INTERVALS[progress.level] * SECONDS_PER_DAY;
+        self.finished = true;
+        true
+    }
+
+    /// Days until the current line is due again.
+    pub fn days_until_due(&self, time: u64) -> Option<u64> {
+        let due = self.progress_of(self.current?)?.due;
+        Some(due.saturating_sub(time) / SECONDS_PER_DAY)
+    }
+
+    /// Writes the progress of this repertoire, keeping that of the others.
+    pub fn save(&self) -> io::Result<()> {
+        let path = progress_path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no data directory on this system")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut progress = load_progress();
+        progress.insert(self.repertoire.clone(), self.progress.clone());
+        fs::write(path, serde_json::to_string_pretty(&progress)?)
+    }
+}
@@ -0,0 +1,739 @@
+//! Games hosted without a window, for web front-ends and other GUIs.
+//!
+//! Clients connect over WebSocket and send one JSON object per text message,
+//! e.g. `{"type":"move","uci":"e2e4"}`, see `Request` and `Reply`. One player
+//! creates a game and is told its number, the other joins it by that number
+//! or joins the oldest game waiting for a player. The server keeps the board
+//! and the clocks, turns down illegal moves, ends games and tells both
+//! players about every move with the clocks after it.
+//!
+//! The GUI connects to the same port with `join <address>`, speaking the
+//! protocol of `network`: it joins the oldest game waiting for a player, or
+//! opens a game of its own for a web player to join.
+
+use crate::chess960;
+use crate::clock::{Clock, TimeControl};
+use crate::fen::Fen;
+use crate::network::Message;
+use crate::outcome::{team_from_name, team_name, Outcome};
+use crate::position::opponent;
+use crate::record::{GameRecord, RecordedMove};
+use crate::util;
+use crate::variant::Variant;
+use crate::websocket::WebSocket;
+use chess::game::{Game, Team};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+/// How often the running clocks are checked for a flag fall.
+const TICK: Duration = Duration::from_millis(100);
+/// How long a new connection has to show which protocol it speaks.
+const GREETING_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest a write to a client may take before the client is let go.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Replies waiting to be written to a client. One that falls this far behind
+/// in reading them is let go, as if it had left.
+const OUTBOX_LENGTH: usize = 256;
+
+/// Messages from a client to the server.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Opens a game and waits for someone to join it.
+    Create {
+        /// Minutes + increment in seconds, e.g. "5+3", `None` for no clock.
+        time: Option<String>,
+        /// "white" or "black", `None` for a random color.
+        color: Option<String>,
+        fen: Option<String>,
+        /// Any name `--variant` takes, `None` for standard chess.
+        variant: Option<String>,
+    },
+    /// Joins the game numbered `game`, or the oldest game waiting for a player.
+    Join {
+        game: Option<usize>,
+    },
+    Move {
+        uci: String,
+    },
+    Resign,
+    /// Offers a draw, or accepts the one the opponent has offered.
+    Draw,
+    DeclineDraw,
+    /// Asks for the clocks, answered with `Reply::Clock`.
+    Clock,
+}
+
+/// Messages from the server to a client.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reply {
+    /// The game is open and waits for a player to join.
+    Created {
+        game: usize,
+        color: String,
+    },
+    /// Both players are there, sent to each with the color it plays.
+    Started {
+        game: usize,
+        color: String,
+        /// Starting position, `None` for the standard one.
+        fen: Option<String>,
+        variant: String,
+        time: Option<String>,
+    },
+    /// A move made by "white" or "black", with the position after it.
+    Moved {
+        color: String,
+        uci: String,
+        san: String,
+        ply: usize,
+        fen: String,
+    },
+    /// Milliseconds left for white and black, and whose clock is running.
+    Clock {
+        remaining: [u64; 2],
+        running: Option<String>,
+    },
+    DrawOffered {
+        by: String,
+    },
+    DrawDeclined {
+        by: String,
+    },
+    GameOver {
+        /// As in the PGN `Result` tag, e.g. "1-0".
+        result: String,
+        winner: Option<String>,
+        /// How the game ended, see `Outcome::reason`.
+        reason: String,
+        description: String,
+    },
+    /// The request could not be carried out.
+    Error {
+        message: String,
+    },
+}
+
+pub struct Server {
+    listener: TcpListener,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(address)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves games for as long as the program runs.
+    pub fn run(self) {
+        let (sender, events) = mpsc::channel();
+        let listener = self.listener;
+        // Every connection is read and written on threads of its own, the games
+        // are kept on this one.
+        thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                if let Ok(stream) = stream {
+                    let sender = sender.clone();
+                    thread::spawn(move || serve(id, stream, sender));
+                }
+            }
+        });
+        let mut lobby = Lobby::default();
+        loop {
+            if let Ok(event) = events.recv_timeout(TICK) {
+                lobby.handle(event);
+            }
+            lobby.check_clocks();
+            lobby.let_go_of_stalled();
+        }
+    }
+}
+
+enum Event {
+    Connected(usize, Peer),
+    Request(usize, Request),
+    /// A message that could not be read, with the reason.
+    Invalid(usize, String),
+    Left(usize),
+}
+
+/// The side of a connection the server writes to, from a thread of its own.
+enum Peer {
+    Web(WebSocket),
+    /// The GUI, speaking the protocol of `network`.
+    Line(TcpStream),
+}
+
+/// Tells the protocol of a new connection by its first byte, the "G" of a
+/// WebSocket handshake or the "{" of a line, and reads it until it closes.
+fn serve(id: usize, stream: TcpStream, events: Sender<Event>) {
+    let _ = stream.set_read_timeout(Some(GREETING_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+    let mut first = [0; 1];
+    let web = matches!(stream.peek(&mut first), Ok(1) if first[0] == b'G');
+    if web {
+        let _ = stream.set_read_timeout(None);
+        serve_web(id, stream, &events);
+    } else {
+        serve_line(id, stream, &events);
+    }
+}
+
+fn serve_web(id: usize, stream: TcpStream, events: &Sender<Event>) {
+    let mut socket = match WebSocket::accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    let writer = match socket.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    if events
+        .send(Event::Connected(id, Peer::Web(writer)))
+        .is_err()
+    {
+        return;
+    }
+    while let Ok(Some(text)) = socket.read_text() {
+        let event = match serde_json::from_str(&text) {
+            Ok(request) => Event::Request(id, request),
+            Err(error) => Event::Invalid(id, error.to_string()),
+        };
+        if events.send(event).is_err() {
+            return;
+        }
+    }
+    let _ = events.send(Event::Left(id));
+}
+
+/// Reads a GUI joining the game. Spectators are not served.
+fn serve_line(id: usize, stream: TcpStream, events: &Sender<Event>) {
+    let writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    let mut hello = String::new();
+    let _ = reader.read_line(&mut hello);
+    let _ = reader.get_ref().set_read_timeout(None);
+    if let Ok(Message::Watch) = serde_json::from_str(&hello) {
+        return;
+    }
+    if events
+        .send(Event::Connected(id, Peer::Line(writer)))
+        .is_err()
+    {
+        return;
+    }
+    let _ = events.send(Event::Request(id, Request::Join { game: None }));
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        // Lines that are not understood are skipped, as in `network`.
+        let request = serde_json::from_str(&line).ok().and_then(line_request);
+        if let Some(request) = request {
+            if events.send(Event::Request(id, request)).is_err() {
+                return;
+            }
+        }
+    }
+    let _ = events.send(Event::Left(id));
+}
+
+/// Writes the texts of `outbox` to `peer` until the lobby lets go of the
+/// client or the client cannot be written to, then closes the connection so
+/// that its reader stops too.
+fn write(mut peer: Peer, outbox: Receiver<String>) {
+    for text in outbox {
+        let written = match &mut peer {
+            Peer::Web(socket) => socket.send_text(&text),
+            Peer::Line(stream) => writeln!(stream, "{}", text),
+        };
+        if written.is_err() {
+            break;
+        }
+    }
+    let _ = match &peer {
+        Peer::Web(socket) => socket.shutdown(),
+        Peer::Line(stream) => stream.shutdown(Shutdown::Both),
+    };
+}
+
+/// The request a message of the GUI makes.
+fn line_request(message: Message) -> Option<Request> {
+    match message {
+        Message::Move { uci, .. } => Some(Request::Move { uci }),
+        Message::Resign => Some(Request::Resign),
+        Message::DrawOffer | Message::DrawAccept => Some(Request::Draw),
+        Message::DrawDecline => Some(Request::DeclineDraw),
+        _ => None,
+    }
+}
+
+/// The message of the GUI telling the player of `team` about `reply`, if it
+/// needs telling. It sees the end of the game on the board or its own clock
+/// unless the opponent resigned or agreed to a draw.
+fn line_message(reply: &Reply, team: Team) -> Option<Message> {
+    let own = team_name(team);
+    match reply {
+        Reply::Started {
+            color,
+            fen,
+            variant,
+            time,
+            ..
+        } => Some(Message::Start {
+            color: color.clone(),
+            fen: fen.clone(),
+            time: time.clone(),
+            variant: Some(variant.clone()),
+            reconnection: None,
+        }),
+        Reply::Moved {
+            color, uci, ply, ..
+        } if color != own => Some(Message::Move {
+            uci: uci.clone(),
+            seq: Some(*ply),
+        }),
+        Reply::DrawOffered { by } if by != own => Some(Message::DrawOffer),
+        Reply::DrawDeclined { by } if by != own => Some(Message::DrawDecline),
+        Reply::GameOver { reason, winner, .. } => match reason.as_str() {
+            "resignation" if winner.as_deref() == Some(own) => Some(Message::Resign),
+            "agreement" => Some(Message::DrawAccept),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+struct Client {
+    /// Texts for the writer of the connection, see `write`.
+    outbox: SyncSender<String>,
+    /// Whether the client is the GUI, which is told only what `line_message` gives.
+    gui: bool,
+    /// Game the client plays or waits in.
+    game: Option<usize>,
+}
+
+struct Table {
+    record: GameRecord,
+    board: Game,
+    clock: Option<Clock>,
+    /// Clients playing white and black.
+    players: [Option<usize>; 2],
+    draw_offer: Option<Team>,
+}
+
+impl Table {
+    fn team_of(&self, id: usize) -> Option<Team> {
+        [Team::White, Team::Black]
+            .iter()
+            .copied()
+            .find(|team| self.players[seat(*team)] == Some(id))
+    }
+
+    fn is_full(&self) -> bool {
+        self.players.iter().all(Option::is_some)
+    }
+
+    fn clock_reply(&self) -> Option<Reply> {
+        self.clock.as_ref().map(|clock| Reply::Clock {
+            remaining: clock.remaining_millis(),
+            running: clock.running().map(|team| team_name(team).to_string()),
+        })
+    }
+}
+
+fn seat(team: Team) -> usize {
+    match team {
+        Team::White => 0,
+        Team::Black => 1,
+    }
+}
+
+/// The clients connected and the games they play, finished games are let go.
+#[derive(Default)]
+struct Lobby {
+    clients: BTreeMap<usize, Client>,
+    // By number, which is also the order they were created in.
+    tables: BTreeMap<usize, Table>,
+    last_game: usize,
+    /// Clients too far behind in reading their replies, to be let go.
+    stalled: Vec<usize>,
+}
+
+impl Lobby {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Connected(id, peer) => {
+                let gui = matches!(peer, Peer::Line(_));
+                let (outbox, texts) = mpsc::sync_channel(OUTBOX_LENGTH);
+                thread::spawn(move || write(peer, texts));
+                let client = Client {
+                    outbox,
+                    gui,
+                    game: None,
+                };
+                self.clients.insert(id, client);
+            }
+            Event::Request(id, request) => {
+                if let Err(message) = self.request(id, request) {
+                    self.reply(id, &Reply::Error { message });
+                }
+            }
+            Event::Invalid(id, error) => {
+                let message = format!("Could not read the request: {}", error);
+                self.reply(id, &Reply::Error { message });
+            }
+            Event::Left(id) => {
+                self.leave(id);
+                self.clients.remove(&id);
+            }
+        }
+    }
+
+    fn request(&mut self, id: usize, request: Request) -> Result<(), String> {
+        match request {
+            Request::Create {
+                time,
+                color,
+                fen,
+                variant,
+            } => self.create(id, time, color, fen, variant),
+            Request::Join { game } => self.join(id, game),
+            Request::Move { uci } => self.play(id, &uci),
+            Request::Resign => {
+                let (game, team) = self.seat_of(id)?;
+                self.finish(
+                    game,
+                    Outcome::Resignation {
+                        winner: opponent(team),
+                    },
+                );
+                Ok(())
+            }
+            Request::Draw => self.offer_draw(id),
+            Request::DeclineDraw => self.decline_draw(id),
+            Request::Clock => {
+                let (game, _) = self.seat_of(id)?;
+                let reply = self.tables[&game]
+                    .clock_reply()
+                    .ok_or("The game has no clock")?;
+                self.reply(id, &reply);
+                Ok(())
+            }
+        }
+    }
+
+    fn create(
+        &mut self,
+        id: usize,
+        time: Option<String>,
+        color: Option<String>,
+        fen: Option<String>,
+        variant: Option<String>,
+    ) -> Result<(), String> {
+        self.check_free(id)?;
+        let time: Option<TimeControl> = time.map(|time| time.parse()).transpose()?;
+        let variant: Variant = variant
+            .map(|name| name.parse())
+            .transpose()?
+            .unwrap_or(Variant::Standard);
+        let team = match color.as_deref() {
+            None | Some("random") if util::random() % 2 == 0 => Team::White,
+            None | Some("random") => Team::Black,
+            Some(name) => {
+                team_from_name(name).ok_or_else(|| format!("Unknown color '{}'", name))?
+            }
+        };
+        let start = match fen {
+            Some(fen) => Some(fen.parse::<Fen>()?),
+            None if variant == Variant::Chess960 => {
+                Some(chess960::start_position(chess960::random_position()))
+            }
+            None => variant.start_position(),
+        };
+        let mut record = GameRecord::starting_from(start);
+        record.set_variant(variant);
+        if let Some(time) = time {
+            record.set_header("TimeControl", &time.pgn_tag());
+        }
+        let mut players = [None, None];
+        players[seat(team)] = Some(id);
+        self.last_game += 1;
+        let game = self.last_game;
+        self.tables.insert(
+            game,
+            Table {
+                board: record.start_game(),
+                record,
+                clock: time.map(Clock::new),
+                players,
+                draw_offer: None,
+            },
+        );
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.game = Some(game);
+        }
+        let color = team_name(team).to_string();
+        self.reply(id, &Reply::Created { game, color });
+        Ok(())
+    }
+
+    fn join(&mut self, id: usize, game: Option<usize>) -> Result<(), String> {
+        self.check_free(id)?;
+        let waiting = |table: &Table| !table.is_full();
+        let game = match game {
+            Some(game) if self.tables.get(&game).map_or(false, waiting) => game,
+            Some(game) => return Err(format!("Game {} is not waiting for a player", game)),
+            None => match self.tables.iter().find(|(_, table)| waiting(table)) {
+                Some((game, _)) => *game,
+                // The GUI cannot create a game, so it opens one when none is waiting.
+                None if self.clients.get(&id).map_or(false, |client| client.gui) => {
+                    return self.create(id, None, None, None, None);
+                }
+                None => return Err(String::from("No game is waiting for a player")),
+            },
+        };
+        let table = self.tables.get_mut(&game).unwrap();
+        if let Some(empty) = table.players.iter_mut().find(|player| player.is_none()) {
+            *empty = Some(id);
+        }
+        if let Some(clock) = table.clock.as_mut() {
+            clock.start(table.board.player);
+        }
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.game = Some(game);
+        }
+
+        let table = &self.tables[&game];
+        let fen = table.record.start.as_ref().map(|fen| fen.to_string());
+        let variant = table.record.variant.name().to_string();
+        let time = table.clock.as_ref().map(|clock| clock.control.to_string());
+        let clock = table.clock_reply();
+        let players = table.players;
+        for (team, player) in [Team::White, Team::Black].iter().zip(players.iter()) {
+            let started = Reply::Started {
+                game,
+                color: team_name(*team).to_string(),
+                fen: fen.clone(),
+                variant: variant.clone(),
+                time: time.clone(),
+            };
+            self.reply(player.unwrap(), &started);
+        }
+        if let Some(clock) = clock {
+            self.tell(game, &clock);
+        }
+        Ok(())
+    }
+
+    fn play(&mut self, id: usize, uci: &str) -> Result<(), String> {
+        let (game, team) = self.seat_of(id)?;
+        let table = self.tables.get_mut(&game).unwrap();
+        if table.board.player != team {
+            return Err(String::from("It is not your move"));
+        }
+        let recorded =
+            RecordedMove::from_uci(uci).ok_or_else(|| format!("Invalid move {}", uci))?;
+        table.record.play_move(&mut table.board, recorded)?;
+        if let Some(clock) = table.clock.as_mut() {
+            clock.press(team);
+        }
+        // Moving instead of answering a draw offer declines it.
+        if table.draw_offer == Some(opponent(team)) {
+            table.draw_offer = None;
+        }
+        let moved = Reply::Moved {
+            color: team_name(team).to_string(),
+            uci: recorded.to_uci(),
+            san: table.record.san.last().cloned().unwrap_or_default(),
+            ply: table.record.ply(),
+            fen: table.record.fen(&table.board).to_string(),
+        };
+        let outcome = Outcome::detect(&mut table.board, &table.record);
+        let clock = table.clock_reply();
+        self.tell(game, &moved);
+        if let Some(clock) = clock {
+            self.tell(game, &clock);
+        }
+        if let Some(outcome) = outcome {
+            self.finish(game, outcome);
+        }
+        Ok(())
+    }
+
+    fn offer_draw(&mut self, id: usize) -> Result<(), String> {
+        let (game, team) = self.seat_of(id)?;
+        let table = self.tables.get_mut(&game).unwrap();
+        if table.draw_offer == Some(opponent(team)) {
+            self.finish(game, Outcome::DrawAgreement);
+        } else {
+            table.draw_offer = Some(team);
+            let by = team_name(team).to_string();
+            self.tell(game, &Reply::DrawOffered { by });
+        }
+        Ok(())
+    }
+
+    fn decline_draw(&mut self, id: usize) -> Result<(), String> {
+        let (game, team) = self.seat_of(id)?;
+        let table = self.tables.get_mut(&game).unwrap();
+        if table.draw_offer != Some(opponent(team)) {
+            return Err(String::from("There is no draw offer to decline"));
+        }
+        table.draw_offer = None;
+        let by = team_name(team).to_string();
+        self.tell(game, &Reply::DrawDeclined { by });
+        Ok(())
+    }
+
+    /// Ends the game, telling both players, and lets the table go.
+    fn finish(&mut self, game: usize, outcome: Outcome) {
+        let table = match self.tables.get_mut(&game) {
+            Some(table) => table,
+            None => return,
+        };
+        if let Some(clock) = table.clock.as_mut() {
+            clock.stop();
+        }
+        table.record.set_result(outcome.result());
+        let players = table.players;
+        self.tell(
+            game,
+            &Reply::GameOver {
+                result: outcome.result().to_string(),
+                winner: outcome.winner().map(|winner| team_name(winner).to_string()),
+                reason: outcome.reason().to_string(),
+                description: outcome.description(),
+            },
+        );
+        self.tables.remove(&game);
+        for id in players.iter().flatten() {
+            if let Some(client) = self.clients.get_mut(id) {
+                client.game = None;
+            }
+        }
+    }
+
+    /// A player who leaves a game in progress loses it, one who leaves a
+    /// game nobody joined takes it away.
+    fn leave(&mut self, id: usize) {
+        let game = match self.clients.get(&id).and_then(|client| client.game) {
+            Some(game) => game,
+            None => return,
+        };
+        let table = &self.tables[&game];
+        match table.team_of(id) {
+            Some(team) if table.is_full() => self.finish(
+                game,
+                Outcome::Disconnection {
+                    winner: opponent(team),
+                },
+            ),
+            _ => {
+                self.tables.remove(&game);
+            }
+        }
+    }
+
+    fn check_clocks(&mut self) {
+        let flagged: Vec<(usize, Team)> = self
+            .tables
+            .iter()
+            .filter_map(|(game, table)| Some((*game, table.clock.as_ref()?.flagged()?)))
+            .collect();
+        for (game, team) in flagged {
+            self.finish(
+                game,
+                Outcome::Timeout {
+                    winner: opponent(team),
+                },
+            );
+        }
+    }
+
+    /// The game the client plays and its color, once both players are there.
+    fn seat_of(&self, id: usize) -> Result<(usize, Team), String> {
+        let game = self
+            .clients
+            .get(&id)
+            .and_then(|client| client.game)
+            .ok_or("You are not playing a game")?;
+        let table = &self.tables[&game];
+        match table.team_of(id) {
+            Some(team) if table.is_full() => Ok((game, team)),
+            _ => Err(String::from("Nobody has joined the game yet")),
+        }
+    }
+
+    fn check_free(&self, id: usize) -> Result<(), String> {
+        match self.clients.get(&id).and_then(|client| client.game) {
+            Some(game) => Err(format!("You are already in game {}", game)),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends `reply` to both players of `game`.
+    fn tell(&mut self, game: usize, reply: &Reply) {
+        let players = match self.tables.get(&game) {
+            Some(table) => table.players,
+            None => return,
+        };
+        for id in players.iter().flatten() {
+            self.reply(*id, reply);
+        }
+    }
+
+    /// Hands `reply` to the writer of a client. Clients that cannot be
+    /// written to are let go once their reader sees the connection closed,
+    /// those that fall behind in reading once the lobby is done handling.
+    fn reply(&mut self, id: usize, reply: &Reply) {
+        let team = self
+            .clients
+            .get(&id)
+            .and_then(|client| client.game)
+            .and_then(|game| self.tables.get(&game))
+            .and_then(|table| table.team_of(id));
+        let client = match self.clients.get(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        let text = if client.gui {
+            match team.and_then(|team| line_message(reply, team)) {
+                Some(message) => serde_json::to_string(&message),
+                None => return,
+            }
+        } else {
+            serde_json::to_string(reply)
+        };
+        if let Ok(text) = text {
+            if let Err(TrySendError::Full(_)) = client.outbox.try_send(text) {
+                self.stalled.push(id);
+            }
+        }
+    }
+
+    /// Lets go of the clients that fell behind in reading, which loses the
+    /// games they play. Their writers close the connections once they have
+    /// written what is left.
+    fn let_go_of_stalled(&mut self) {
+        while let Some(id) = self.stalled.pop() {
+            self.leave(id);
+            self.clients.remove(&id);
+        }
+    }
+}
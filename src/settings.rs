@@ -0,0 +1,544 @@
+//! User preferences, stored as TOML in the XDG config directory.
+//!
+//! Settings files carry a `version` key, so that later versions can tell
+//! which files they need to bring up to date. Values that cannot be used fall
+//! back to their defaults with a warning instead of making the whole file
+//! unreadable.
+
+use crate::cli::WindowSize;
+use crate::clock::TimeControl;
+use crate::theme::Theme;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use toml::value::Table;
+use toml::Value;
+
+/// Version written into new settings files.
+pub const CURRENT_VERSION: i64 = 1;
+/// Windows smaller than this are not usable.
+const MIN_WINDOW_SIZE: f32 = 200.0;
+
+/// Which side of the board is shown at the bottom.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    White,
+    Black,
+    /// The local player's side; in hot-seat games the side to move.
+    Player,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 3] = [Orientation::White, Orientation::Black, Orientation::Player];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Orientation::White => "white at bottom",
+            Orientation::Black => "black at bottom",
+            Orientation::Player => "player at bottom",
+        }
+    }
+}
+
+/// How fast pieces slide to their new square.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationSpeed {
+    Off,
+    Slow,
+    Normal,
+    Fast,
+}
+
+impl AnimationSpeed {
+    pub const ALL: [AnimationSpeed; 4] = [
+        AnimationSpeed::Off,
+        AnimationSpeed::Slow,
+        AnimationSpeed::Normal,
+        AnimationSpeed::Fast,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnimationSpeed::Off => "off",
+            AnimationSpeed::Slow => "slow",
+            AnimationSpeed::Normal => "normal",
+            AnimationSpeed::Fast => "fast",
+        }
+    }
+
+    /// Time a move takes on screen.
+    pub fn duration(self) -> Duration {
+        match self {
+            AnimationSpeed::Off => Duration::from_millis(0),
+            AnimationSpeed::Slow => Duration::from_millis(400),
+            AnimationSpeed::Normal => Duration::from_millis(200),
+            AnimationSpeed::Fast => Duration::from_millis(100),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Settings {
+    pub theme: Theme,
+    pub orientation: Orientation,
+    pub animation_speed: AnimationSpeed,
+    /// Promote to a queen without looking at the piece chosen in the menu.
+    pub auto_queen: bool,
+    pub sound: bool,
+    /// Time control of games started without `--time`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_control: Option<TimeControl>,
+    /// UCI engines, the first one is used when `vs-engine` is given no `--engine`.
+    pub engines: Vec<PathBuf>,
+    /// Polyglot book the engine opponent plays its first moves from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opening_book: Option<PathBuf>,
+    /// Directories holding Syzygy tablebase files.
+    pub syzygy: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_size: Option<WindowSize>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            theme: Theme::Gray,
+            orientation: Orientation::White,
+            animation_speed: AnimationSpeed::Normal,
+            auto_queen: false,
+            sound: true,
+            time_control: None,
+            engines: Vec::new(),
+            opening_book: None,
+            syzygy: Vec::new(),
+            window_size: None,
+        }
+    }
+}
+
+/// Location of the settings file, `$XDG_CONFIG_HOME/oskhen-chess-gui/settings.toml`.
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("oskhen-chess-gui").join("settings.toml"))
+}
+
+impl Settings {
+    /// Reads the settings file, returning the defaults if there is none.
+    /// Problems with the file are returned as warnings next to the usable settings.
+    pub fn load() -> (Settings, Vec<String>) {
+        let path = match settings_path() {
+            Some(path) => path,
+            None => return (Settings::default(), vec![]),
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                let (settings, mut warnings) = Settings::from_toml(&text);
+                for warning in warnings.iter_mut() {
+                    *warning = format!("{}: {}", path.display(), warning);
+                }
+                (settings, warnings)
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (Settings::default(), vec![]),
+            Err(error) => (
+                Settings::default(),
+                vec![format!("Could not read {}: {}", path.display(), error)],
+            ),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> (Settings, Vec<String>) {
+        let mut warnings = Vec::new();
+        let table = match text.parse::<Value>() {
+            Ok(Value::Table(table)) => table,
+            Ok(_) => Table::new(),
+            Err(error) => {
+                warnings.push(format!("not valid TOML ({}), using the default settings", error));
+                return (Settings::default(), warnings);
+            }
+        };
+        check_version(&table, &mut warnings);
+
+        let mut settings = Settings::default();
+        let read = |key: &str| table.get(key).cloned();
+        if let Some(theme) = field(read("theme"), "theme", &mut warnings) {
+            settings.theme = theme;
+        }
+        if let Some(orientation) = field(read("orientation"), "orientation", &mut warnings) {
+            settings.orientation = orientation;
+        }
+        if let Some(speed) = field(read("animation_speed"), "animation_speed", &mut warnings) {
+            settings.animation_speed = speed;
+        }
+        if let Some(auto_queen) = field(read("auto_queen"), "auto_queen", &mut warnings) {
+            settings.auto_queen = auto_queen;
+        }
+        if let Some(sound) = field(read("sound"), "sound", &mut warnings) {
+            settings.sound = sound;
+        }
+        if let Some(time) = field::<String>(read("time_control"), "time_control", &mut warnings) {
+            match time.parse() {
+                Ok(time) => settings.time_control = Some(time),
+                Err(error) => warnings.push(format!("time_control: {}", error)),
+            }
+        }
+        if let Some(engines) = field::<Vec<PathBuf>>(read("engines"), "engines", &mut warnings) {
+            for engine in engines.iter().filter(|engine| !engine.exists()) {
+                warnings.push(format!("engines: {} does not exist", engine.display()));
+            }
+            settings.engines = engines;
+        }
+        // A book on a drive that is not mounted is kept, it is only not opened.
+        if let Some(book) = field::<PathBuf>(read("opening_book"), "opening_book", &mut warnings) {
+            if !book.exists() {
+                warnings.push(format!("opening_book: {} does not exist", book.display()));
+            }
+            settings.opening_book = Some(book);
+        }
+        if let Some(directories) = field::<Vec<PathBuf>>(read("syzygy"), "syzygy", &mut warnings) {
+            for directory in directories.iter().filter(|directory| !directory.is_dir()) {
+                warnings.push(format!("syzygy: {} is not a directory", directory.display()));
+            }
+            settings.syzygy = directories;
+        }
+        if let Some(size) = field::<String>(read("window_size"), "window_size", &mut warnings) {
+            match size.parse::<WindowSize>() {
+                Ok(size) if size.width < MIN_WINDOW_SIZE || size.height < MIN_WINDOW_SIZE => {
+                    warnings.push(format!("window_size: {} is too small", size))
+                }
+                Ok(size) => settings.window_size = Some(size),
+                Err(error) => warnings.push(format!("window_size: {}", error)),
+            }
+        }
+
+        let known = [
+            "version",
+            "theme",
+            "orientation",
+            "animation_speed",
+            "auto_queen",
+            "sound",
+            "time_control",
+            "engines",
+            "opening_book",
+            "syzygy",
+            "window_size",
+        ];
+        for key in table.keys().filter(|key| !known.contains(&key.as_str())) {
+            warnings.push(format!("unknown setting '{}' ignored", key));
+        }
+        (settings, warnings)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut table = match Value::try_from(self) {
+            Ok(Value::Table(table)) => table,
+            _ => unreachable!("settings serialize to a table"),
+        };
+        table.insert("version".to_string(), Value::Integer(CURRENT_VERSION));
+        toml::to_string(&table).expect("settings serialize to TOML")
+    }
+
+    /// Writes the settings file, creating its directory if needed.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = settings_path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no config directory on this system")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, self.to_toml())?;
+        Ok(path)
+    }
+}
+
+/// Reads one setting, warning about values of the wrong type.
+fn field<T: DeserializeOwned>(value: Option<Value>, key: &str, warnings: &mut Vec<String>) -> Option<T> {
+    let value = value?;
+    let shown = value.to_string();
+    match value.try_into() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warnings.push(format!("invalid value {} for {}, using the default", shown, key));
+            None
+        }
+    }
+}
+
+/// Warns about files written by a newer version, whose new settings are ignored.
+fn check_version(table: &Table, warnings: &mut Vec<String>) {
+    match table.get("version") {
+        Some(Value::Integer(version)) if *version > CURRENT_VERSION => warnings.push(format!(
+            "written by a newer version ({}), some settings may be ignored",
+            version
+        )),
+        None | Some(Value::Integer(_)) => {}
+        Some(other) => warnings.push(format!("invalid version {}, reading it as the current one", other)),
+    }
+}
+
+/// A row of the settings screen.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Field {
+    Theme,
+    Orientation,
+    AnimationSpeed,
+    AutoQueen,
+    Sound,
+    TimeControl,
+    Engines,
+    OpeningBook,
+    Syzygy,
+    WindowSize,
+}
+
+impl Field {
+    pub const ALL: [Field; 10] = [
+        Field::Theme,
+        Field::Orientation,
+        Field::AnimationSpeed,
+        Field::AutoQueen,
+        Field::Sound,
+        Field::TimeControl,
+        Field::Engines,
+        Field::OpeningBook,
+        Field::Syzygy,
+        Field::WindowSize,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::Theme => "Theme",
+            Field::Orientation => "Board",
+            Field::AnimationSpeed => "Animation",
+            Field::AutoQueen => "Auto-queen",
+            Field::Sound => "Sound",
+            Field::TimeControl => "Time control",
+            Field::Engines => "Engines",
+            Field::OpeningBook => "Opening book",
+            Field::Syzygy => "Tablebases",
+            Field::WindowSize => "Window size",
+        }
+    }
+
+    pub fn value(self, settings: &Settings) -> String {
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+        match self {
+            Field::Theme => settings.theme.name().to_string(),
+            Field::Orientation => settings.orientation.name().to_string(),
+            Field::AnimationSpeed => settings.animation_speed.name().to_string(),
+            Field::AutoQueen => on_off(settings.auto_queen),
+            Field::Sound => on_off(settings.sound),
+            Field::TimeControl
+            | Field::Engines
+            | Field::OpeningBook
+            | Field::Syzygy
+            | Field::WindowSize => {
+                let text = self.text(settings);
+                if text.is_empty() {
+                    "none".to_string()
+                } else {
+                    text
+                }
+            }
+        }
+    }
+
+    /// Whether the value is typed in rather than picked from a list.
+    pub fn is_text(self) -> bool {
+        match self {
+            Field::TimeControl
+            | Field::Engines
+            | Field::OpeningBook
+            | Field::Syzygy
+            | Field::WindowSize => true,
+            _ => false,
+        }
+    }
+
+    /// Whether changes only take effect the next time the program starts.
+    pub fn needs_restart(self) -> bool {
+        match self {
+            Field::TimeControl
+            | Field::Engines
+            | Field::OpeningBook
+            | Field::Syzygy
+            | Field::WindowSize => true,
+            _ => false,
+        }
+    }
+
+    /// The editable text of a typed-in value. Engine and tablebase paths are separated by ';'.
+    pub fn text(self, settings: &Settings) -> String {
+        match self {
+            Field::TimeControl => settings.time_control.map(|time| time.to_string()).unwrap_or_default(),
+            Field::Engines => settings
+                .engines
+                .iter()
+                .map(|engine| engine.display().to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            Field::OpeningBook => settings
+                .opening_book
+                .as_ref()
+                .map(|book| book.display().to_string())
+                .unwrap_or_default(),
+            Field::Syzygy => settings
+                .syzygy
+                .iter()
+                .map(|directory| directory.display().to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            Field::WindowSize => settings.window_size.map(|size| size.to_string()).unwrap_or_default(),
+            _ => self.value(settings),
+        }
+    }
+
+    /// Sets a typed-in value, an empty text clears it.
+    pub fn set_text(self, settings: &mut Settings, text: &str) -> Result<(), String> {
+        let text = text.trim();
+        match self {
+            Field::TimeControl if text.is_empty() => settings.time_control = None,
+            Field::TimeControl => settings.time_control = Some(text.parse()?),
+            Field::Engines => {
+                let engines: Vec<PathBuf> = text
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|engine| !engine.is_empty())
+                    .map(PathBuf::from)
+                    .collect();
+                if let Some(missing) = engines.iter().find(|engine| !engine.exists()) {
+                    return Err(format!("{} does not exist", missing.display()));
+                }
+                settings.engines = engines;
+            }
+            Field::OpeningBook if text.is_empty() => settings.opening_book = None,
+            Field::OpeningBook => {
+                let book = PathBuf::from(text);
+                if !book.exists() {
+                    return Err(format!("{} does not exist", book.display()));
+                }
+                settings.opening_book = Some(book);
+            }
+            Field::Syzygy => {
+                let directories: Vec<PathBuf> = text
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|directory| !directory.is_empty())
+                    .map(PathBuf::from)
+                    .collect();
+                if let Some(missing) = directories.iter().find(|directory| !directory.is_dir()) {
+                    return Err(format!("{} is not a directory", missing.display()));
+                }
+                settings.syzygy = directories;
+            }
+            Field::WindowSize if text.is_empty() => settings.window_size = None,
+            Field::WindowSize => {
+                let size: WindowSize = text.parse()?;
+                if size.width < MIN_WINDOW_SIZE || size.height < MIN_WINDOW_SIZE {
+                    return Err(format!("{} is too small", size));
+                }
+                settings.window_size = Some(size);
+            }
+            _ => return Err(format!("{} is not typed in", self.label())),
+        }
+        Ok(())
+    }
+
+    /// Moves a picked value to the next (or previous) choice.
+    pub fn step(self, settings: &mut Settings, forward: bool) {
+        fn cycle<T: Copy + PartialEq>(all: &[T], current: T, forward: bool) -> T {
+            let index = all.iter().position(|item| *item == current).unwrap_or(0);
+            let next = if forward {
+                (index + 1) % all.len()
+            } else {
+                (index + all.len() - 1) % all.len()
+            };
+            all[next]
+        }
+        match self {
+            Field::Theme => settings.theme = cycle(&Theme::ALL, settings.theme, forward),
+            Field::Orientation => {
+                settings.orientation = cycle(&Orientation::ALL, settings.orientation, forward)
+            }
+            Field::AnimationSpeed => {
+                settings.animation_speed = cycle(&AnimationSpeed::ALL, settings.animation_speed, forward)
+            }
+            Field::AutoQueen => settings.auto_queen = !settings.auto_queen,
+            Field::Sound => settings.sound = !settings.sound,
+            Field::TimeControl
+            | Field::Engines
+            | Field::OpeningBook
+            | Field::Syzygy
+            | Field::WindowSize => {}
+        }
+    }
+}
+
+/// State of the settings screen: the selected row and the text being typed.
+#[derive(Default)]
+pub struct SettingsEditor {
+    pub selected: usize,
+    pub editing: Option<String>,
+    pub error: Option<String>,
+}
+
+impl SettingsEditor {
+    pub fn field(&self) -> Field {
+        Field::ALL[self.selected]
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < Field::ALL.len() {
+            self.selected = index;
+            self.editing = None;
+            self.error = None;
+        }
+    }
+
+    pub fn up(&mut self) {
+        self.select((self.selected + Field::ALL.len() - 1) % Field::ALL.len());
+    }
+
+    pub fn down(&mut self) {
+        self.select((self.selected + 1) % Field::ALL.len());
+    }
+
+    /// Changes the selected row: steps a choice or starts typing a value.
+    pub fn activate(&mut self, settings: &mut Settings, forward: bool) {
+        let field = self.field();
+        if field.is_text() {
+            self.editing = Some(field.text(settings));
+        } else {
+            field.step(settings, forward);
+        }
+        self.error = None;
+    }
+
+    /// Finishes typing, keeping the text open if it is not a valid value.
+    pub fn commit(&mut self, settings: &mut Settings) {
+        if let Some(text) = self.editing.take() {
+            if let Err(error) = self.field().set_text(settings, &text) {
+                self.error = Some(error);
+                self.editing = Some(text);
+            }
+        }
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        if let Some(text) = self.editing.as_mut() {
+            if !c.is_control() {
+                text.push(c);
+            }
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(text) = self.editing.as_mut() {
+            text.pop();
+        }
+    }
+}
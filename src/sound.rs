@@ -0,0 +1,40 @@
+//! Sound effects, synthesised so that no audio files have to be shipped.
+
+const SAMPLE_RATE: u32 = 22050;
+
+/// A short wooden click played when a piece is put down, as a WAV file.
+pub fn move_click() -> Vec<u8> {
+    let length = SAMPLE_RATE as usize / 20;
+    let samples: Vec<i16> = (0..length)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let envelope = (-t * 90.0).exp();
+            let tone = (t * 2.0 * std::f32::consts::PI * 330.0).sin()
+                + 0.5 * (t * 2.0 * std::f32::consts::PI * 870.0).sin();
+            (tone * envelope * 0.4 * i16::MAX as f32) as i16
+        })
+        .collect();
+    wav(&samples)
+}
+
+/// Wraps 16 bit mono samples in a WAV header.
+fn wav(samples: &[i16]) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut bytes = Vec::with_capacity(44 + data_size as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    bytes
+}
@@ -0,0 +1,1434 @@
+//! Probing of Syzygy endgame tablebases stored on disk.
+//!
+//! Follows the probing code published with the tables (as used by Stockfish
+//! and Fathom). WDL tables (`.rtbw`) give the result for the side to move,
+//! DTZ tables (`.rtbz`) the distance in plies to the next capture or pawn
+//! move that keeps that result. Tables leave out positions in which a
+//! capture is best, so captures are searched before a table is trusted, and
+//! positions with castling rights are not covered at all.
+
+use crate::fen::Fen;
+use crate::position::coordinate_to_string;
+use chess::game::{Rank, Team};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Flags of the compressed data of one table.
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// Piece codes as stored in the tables: color * 8 + type.
+const PAWN: u8 = 1;
+const KNIGHT: u8 = 2;
+const BISHOP: u8 = 3;
+const ROOK: u8 = 4;
+const QUEEN: u8 = 5;
+const KING: u8 = 6;
+const BLACK: u8 = 8;
+
+/// Result of a position for the side to move. Cursed wins and blessed losses
+/// are wins and losses that the fifty-move rule turns into draws.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Wdl::Loss => "loss",
+            Wdl::BlessedLoss => "blessed loss",
+            Wdl::Draw => "draw",
+            Wdl::CursedWin => "cursed win",
+            Wdl::Win => "win",
+        }
+    }
+}
+
+/// A legal move with the result it leads to for the side making it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TablebaseMove {
+    pub uci: String,
+    pub wdl: Wdl,
+    /// Plies to the next capture or pawn move, negative when losing, 0 for draws.
+    pub dtz: i32,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TablebaseResult {
+    /// Result for the side to move.
+    pub wdl: Wdl,
+    pub dtz: i32,
+    /// Every legal move, best first.
+    pub moves: Vec<TablebaseMove>,
+}
+
+/// Search state beyond the score, see `search`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Probe {
+    Ok,
+    /// The best move is a capture or pawn move, so the DTZ table cannot be used.
+    ZeroingBestMove,
+}
+
+/// Compressed values of one side of a table, for one file of the leading pawn.
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; 7],
+    group_len: [usize; 8],
+    group_idx: [u64; 8],
+    size_of_block: u64,
+    span: u64,
+    sparse_index_size: u64,
+    num_blocks: u64,
+    block_length_size: u64,
+    min_sym_len: u8,
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u32>,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    map_idx: [usize; 4],
+}
+
+struct Table {
+    bytes: Vec<u8>,
+    /// Both sides have the same pieces, only white to move is stored.
+    symmetric: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    /// Pawns of the leading color and of the other one.
+    pawn_count: [usize; 2],
+    /// Indexed by side to move (WDL tables of unequal material only) and file.
+    items: Vec<Vec<PairsData>>,
+    /// Start of the value maps of DTZ tables.
+    map: usize,
+}
+
+/// Lookup tables used to turn a position into an index, built once.
+struct Encoding {
+    map_b1h1h7: [usize; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    map_pawns: [usize; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+pub struct Tablebase {
+    paths: Vec<PathBuf>,
+    /// Tables read so far by file name, `None` for tables that are not on disk.
+    tables: HashMap<String, Option<Table>>,
+    encoding: Encoding,
+    /// Most pieces, kings included, of any table found.
+    pub max_pieces: usize,
+}
+
+impl Tablebase {
+    /// Uses the tables in the given directories. Tables are read from disk
+    /// the first time they are needed.
+    pub fn open(paths: &[PathBuf]) -> Result<Tablebase, String> {
+        let mut max_pieces = 0;
+        for path in paths {
+            let entries = fs::read_dir(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+            for entry in entries.filter_map(Result::ok) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if let Some(material) = name.strip_suffix(".rtbw") {
+                    max_pieces = max_pieces.max(material.len() - 1);
+                }
+            }
+        }
+        if max_pieces == 0 {
+            return Err("no Syzygy tables (.rtbw files) found".to_string());
+        }
+        Ok(Tablebase {
+            paths: paths.to_vec(),
+            tables: HashMap::new(),
+            encoding: Encoding::new(),
+            max_pieces,
+        })
+    }
+
+    /// Result and ranked moves of the position described by `fen`, or `None`
+    /// when the position has too many pieces, castling rights or no moves.
+    pub fn probe(&mut self, fen: &Fen) -> Result<Option<TablebaseResult>, String> {
+        if fen.castling != "-" || fen.placement.len() > self.max_pieces {
+            return Ok(None);
+        }
+        let board = Board::from_fen(fen);
+        let legal = board.legal_moves();
+        if legal.is_empty() {
+            return Ok(None);
+        }
+
+        let mut moves = Vec::new();
+        for m in legal {
+            let next = board.play(m);
+            let mut dtz = if board.is_zeroing(m) {
+                dtz_before_zeroing(-self.search(&next, false)?.0)
+            } else {
+                let dtz = -self.probe_dtz(&next)?;
+                dtz + dtz.signum()
+            };
+            // A mating move is one ply from the end.
+            if dtz == 2 && next.in_check(next.stm) && next.legal_moves().is_empty() {
+                dtz = 1;
+            }
+            let wdl = match dtz {
+                0 => Wdl::Draw,
+                dtz if dtz > 100 => Wdl::CursedWin,
+                dtz if dtz > 0 => Wdl::Win,
+                dtz if dtz < -100 => Wdl::BlessedLoss,
+                _ => Wdl::Loss,
+            };
+            moves.push(TablebaseMove {
+                uci: m.to_uci(),
+                wdl,
+                dtz: if dtz.abs() > 100 {
+                    dtz - 100 * dtz.signum()
+                } else {
+                    dtz
+                },
+            });
+        }
+        // Best result first; wins as quick as possible, losses as slow as possible.
+        moves.sort_by_key(|m| (std::cmp::Reverse(m.wdl), m.dtz));
+
+        let wdl = Wdl::from_value(self.search(&board, false)?.0);
+        let dtz = self.probe_dtz(&board)?;
+        Ok(Some(TablebaseResult {
+            wdl,
+            dtz: if dtz.abs() > 100 {
+                dtz - 100 * dtz.signum()
+            } else {
+                dtz
+            },
+            moves,
+        }))
+    }
+
+    /// WDL value of `board` between -2 and 2, searching captures (and with
+    /// `check_zeroing` pawn moves) since the table may not hold their result.
+    fn search(&mut self, board: &Board, check_zeroing: bool) -> Result<(i32, Probe), String> {
+        let moves = board.legal_moves();
+        let mut best = -2;
+        let mut searched = 0;
+        for m in moves.iter() {
+            if !board.is_capture(*m) && (!check_zeroing || !board.is_pawn_move(*m)) {
+                continue;
+            }
+            searched += 1;
+            let value = -self.search(&board.play(*m), false)?.0;
+            if value > best {
+                best = value;
+                if value >= 2 {
+                    return Ok((value, Probe::ZeroingBestMove));
+                }
+            }
+        }
+
+        // With every legal move searched the table is not needed, and could be
+        // wrong, e.g. for positions with en passant rights.
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            self.probe_table(board, None)?.unwrap_or(0)
+        };
+        if best >= value {
+            let probe = if best > 0 || no_more_moves {
+                Probe::ZeroingBestMove
+            } else {
+                Probe::Ok
+            };
+            return Ok((best, probe));
+        }
+        Ok((value, Probe::Ok))
+    }
+
+    /// Distance to zeroing of `board` in plies, negative when losing.
+    fn probe_dtz(&mut self, board: &Board) -> Result<i32, String> {
+        let (wdl, probe) = self.search(board, true)?;
+        if wdl == 0 {
+            return Ok(0);
+        }
+        if probe == Probe::ZeroingBestMove {
+            return Ok(dtz_before_zeroing(wdl));
+        }
+        if let Some(dtz) = self.probe_table(board, Some(wdl))? {
+            let cursed = if wdl.abs() == 1 { 100 } else { 0 };
+            return Ok((dtz + cursed) * wdl.signum());
+        }
+
+        // The table holds the other side to move, look one ply ahead.
+        let mut min_dtz = i32::MAX;
+        for m in board.legal_moves() {
+            let zeroing = board.is_zeroing(m);
+            let next = board.play(m);
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&next, false)?.0)
+            } else {
+                -self.probe_dtz(&next)?
+            };
+            if dtz == 1 && next.in_check(next.stm) && next.legal_moves().is_empty() {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+        Ok(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    /// Looks `board` up in its WDL table, or its DTZ table when given the WDL
+    /// value. `None` means the DTZ table only holds the other side to move.
+    fn probe_table(&mut self, board: &Board, wdl: Option<i32>) -> Result<Option<i32>, String> {
+        let (white, black) = board.material();
+        if white == "K" && black == "K" {
+            return Ok(Some(0));
+        }
+        let extension = if wdl.is_some() { ".rtbz" } else { ".rtbw" };
+        let straight = format!("{}v{}{}", white, black, extension);
+        let (name, black_stronger) = if self.load(&straight)? {
+            (straight, false)
+        } else {
+            let swapped = format!("{}v{}{}", black, white, extension);
+            if !self.load(&swapped)? {
+                return Err(format!("missing table {}", straight));
+            }
+            (swapped, true)
+        };
+        let table = self.tables[&name].as_ref().unwrap();
+        let encoding = &self.encoding;
+
+        // Tables are stored with the stronger side as white and, when both
+        // sides are equal, for white to move. Otherwise colors are swapped and
+        // the board mirrored vertically.
+        let flip = (table.symmetric && board.stm == 1) || black_stronger;
+        let flip_color = if flip { BLACK } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = flip as usize ^ board.stm;
+
+        let mut squares = Vec::with_capacity(7);
+        let mut pieces = Vec::with_capacity(7);
+        let mut lead_pawns = 0u64;
+        let mut tb_file = 0;
+        if table.has_pawns {
+            let lead = table.items[0][0].pieces[0] ^ flip_color;
+            for square in 0..64 {
+                if board.squares[square] == lead {
+                    lead_pawns |= 1 << square;
+                    squares.push(square ^ flip_squares);
+                    pieces.push(lead ^ flip_color);
+                }
+            }
+            // The leading pawn is the one nearest the edge, then the lowest.
+            let mut leading = 0;
+            for (index, square) in squares.iter().enumerate() {
+                if encoding.map_pawns[*square] > encoding.map_pawns[squares[leading]] {
+                    leading = index;
+                }
+            }
+            squares.swap(0, leading);
+            tb_file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+        let lead_pawn_count = squares.len();
+
+        if wdl.is_some() {
+            let flags = table.items[0][tb_file].flags;
+            let both_sides_stored = table.symmetric && !table.has_pawns;
+            if !both_sides_stored && (flags & FLAG_STM) as usize != stm {
+                return Ok(None);
+            }
+        }
+
+        for square in 0..64 {
+            if board.squares[square] != 0 && lead_pawns & 1 << square == 0 {
+                squares.push(square ^ flip_squares);
+                pieces.push(board.squares[square] ^ flip_color);
+            }
+        }
+        let size = squares.len();
+        let d = &table.items[stm % table.items.len()][tb_file];
+
+        // Order the pieces as the table lists them.
+        for i in lead_pawn_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror so that the leading piece is on files a-d.
+        if squares[0] % 8 > 3 {
+            for square in squares.iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx;
+        if table.has_pawns {
+            idx = encoding.lead_pawn_idx[lead_pawn_count][squares[0]];
+            squares[1..lead_pawn_count].sort_by_key(|square| encoding.map_pawns[*square]);
+            for (i, square) in squares[..lead_pawn_count].iter().enumerate().skip(1) {
+                idx += encoding.binomial[i][encoding.map_pawns[*square]];
+            }
+        } else {
+            // Without pawns the leading piece is also mirrored onto ranks 1-4
+            // and below the a1-h8 diagonal.
+            if squares[0] / 8 > 3 {
+                for square in squares.iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in squares[i..].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if table.has_unique_pieces {
+                let adjust1 = (squares[1] > squares[0]) as usize;
+                let adjust2 = (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+                let rank = |square: usize| square / 8;
+                idx = if off_diagonal(squares[0]) != 0 {
+                    ((encoding.map_a1d1d4[squares[0]] * 63 + squares[1] - adjust1) * 62 + squares[2]
+                        - adjust2) as u64
+                } else if off_diagonal(squares[1]) != 0 {
+                    ((6 * 63 + rank(squares[0]) * 28 + encoding.map_b1h1h7[squares[1]]) * 62 + squares[2]
+                        - adjust2) as u64
+                } else if off_diagonal(squares[2]) != 0 {
+                    (6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(squares[0]) * 7 * 28
+                        + (rank(squares[1]) - adjust1) * 28
+                        + encoding.map_b1h1h7[squares[2]]) as u64
+                } else {
+                    (6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(squares[0]) * 7 * 6
+                        + (rank(squares[1]) - adjust1) * 6
+                        + (rank(squares[2]) - adjust2)) as u64
+                };
+            } else {
+                idx = encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]];
+            }
+        }
+
+        // The remaining groups, each sorted by square and counted among the
+        // squares left free by the groups before it.
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = table.has_pawns && table.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let length = d.group_len[next];
+            squares[group_start..group_start + length].sort_unstable();
+            let mut n = 0;
+            for i in 0..length {
+                let square = squares[group_start + i];
+                let adjust = squares[..group_start]
+                    .iter()
+                    .filter(|other| square > **other)
+                    .count();
+                n += encoding.binomial[i + 1][square - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start += length;
+            next += 1;
+        }
+
+        let value = decompress_pairs(d, &table.bytes, idx)
+            .map_err(|error| format!("{} {}", name, error))?;
+        Ok(Some(match wdl {
+            None => value - 2,
+            Some(wdl) => map_dtz(table, tb_file, value, wdl),
+        }))
+    }
+
+    /// Reads a table into memory unless already done, returning whether it exists.
+    fn load(&mut self, name: &str) -> Result<bool, String> {
+        if !self.tables.contains_key(name) {
+            let path = self
+                .paths
+                .iter()
+                .map(|path| path.join(name))
+                .find(|path| path.exists());
+            let table = match path {
+                Some(path) => {
+                    let bytes = fs::read(&path).map_err(|error| format!("{}: {}", path.display(), error))?;
+                    Some(Table::parse(bytes, name, &self.encoding)?)
+                }
+                None => None,
+            };
+            self.tables.insert(name.to_string(), table);
+        }
+        Ok(self.tables[name].is_some())
+    }
+}
+
+/// DTZ of a position whose best move is a capture or pawn move.
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        2 => 1,
+        1 => 101,
+        -1 => -101,
+        -2 => -1,
+        _ => 0,
+    }
+}
+
+/// How far a square is above (positive) or below the a1-h8 diagonal.
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+/// Turns a stored DTZ value into plies.
+fn map_dtz(table: &Table, file: usize, value: i32, wdl: i32) -> i32 {
+    const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+    let d = &table.items[0][file];
+    let mut value = value;
+    if d.flags & FLAG_MAPPED != 0 {
+        let index = d.map_idx[WDL_MAP[(wdl + 2) as usize]] + value as usize;
+        value = if d.flags & FLAG_WIDE != 0 {
+            read_u16(&table.bytes, table.map + 2 * index) as i32
+        } else {
+            table.bytes.get(table.map + index).copied().unwrap_or(0) as i32
+        };
+    }
+    let in_moves = (wdl == 2 && d.flags & FLAG_WIN_PLIES == 0)
+        || (wdl == -2 && d.flags & FLAG_LOSS_PLIES == 0)
+        || wdl == 1
+        || wdl == -1;
+    if in_moves {
+        value *= 2;
+    }
+    value + 1
+}
+
+impl Table {
+    fn parse(bytes: Vec<u8>, name: &str, encoding: &Encoding) -> Result<Table, String> {
+        let dtz = name.ends_with(".rtbz");
+        let material = &name[..name.len() - 5];
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.len() < 5 || bytes[..4] != magic {
+            return Err(format!("{} is not a Syzygy table", name));
+        }
+        let mut sides = material.split('v');
+        let (white, black) = (sides.next().unwrap_or(""), sides.next().unwrap_or(""));
+        let count = |side: &str, letter: char| side.chars().filter(|c| *c == letter).count();
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        // With pawns on both sides the side with fewer pawns leads.
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let mut table = Table {
+            bytes: Vec::new(),
+            symmetric: white == black,
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: [white, black]
+                .iter()
+                .any(|side| "PNBRQ".chars().any(|letter| count(side, letter) == 1)),
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            items: Vec::new(),
+            map: 0,
+        };
+        let piece_count = material.len() - 1;
+        let truncated = || format!("{} is truncated", name);
+        let byte = |pos: usize| bytes.get(pos).copied().ok_or_else(truncated);
+
+        let mut pos = 4;
+        if (byte(pos)? & 2 != 0) != table.has_pawns {
+            return Err(format!("{} does not hold the material its name says", name));
+        }
+        pos += 1;
+        let sides = if !dtz && !table.symmetric { 2 } else { 1 };
+        let files = if table.has_pawns { 4 } else { 1 };
+        let both_pawns = table.has_pawns && table.pawn_count[1] > 0;
+        table.items = vec![vec![PairsData::default(); files]; sides];
+        for file in 0..files {
+            let second = if both_pawns { byte(pos + 1)? } else { 0xFF };
+            let order = [[byte(pos)? & 0xF, second & 0xF], [byte(pos)? >> 4, second >> 4]];
+            pos += 1 + both_pawns as usize;
+            for k in 0..piece_count {
+                let packed = byte(pos)?;
+                for side in 0..sides {
+                    table.items[side][file].pieces[k] = if side == 1 { packed >> 4 } else { packed & 0xF };
+                }
+                pos += 1;
+            }
+            for (side, order) in order.iter().enumerate().take(sides) {
+                table.set_groups(side, file, piece_count, *order, encoding);
+            }
+        }
+        pos += pos & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                pos = set_sizes(&mut table.items[side][file], &bytes, pos)
+                    .map_err(|error| format!("{} {}", name, error))?;
+            }
+        }
+        if dtz {
+            table.map = pos;
+            for file in 0..files {
+                let d = &mut table.items[0][file];
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = (pos - table.map) / 2 + 1;
+                        pos += 2 * read_u16(&bytes, pos) as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = pos - table.map + 1;
+                        pos += byte(pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut table.items[side][file];
+                d.sparse_index = pos;
+                pos += d.sparse_index_size as usize * 6;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut table.items[side][file];
+                d.block_length = pos;
+                pos += d.block_length_size as usize * 2;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut table.items[side][file];
+                pos = (pos + 0x3F) & !0x3F;
+                d.data = pos;
+                pos += d.num_blocks.checked_mul(d.size_of_block).ok_or_else(truncated)? as usize;
+            }
+        }
+        if pos > bytes.len() {
+            return Err(truncated());
+        }
+        table.bytes = bytes;
+        Ok(table)
+    }
+
+    /// Splits the pieces into the groups encoded together and works out the
+    /// factor each group's index is multiplied by.
+    fn set_groups(
+        &mut self,
+        side: usize,
+        file: usize,
+        piece_count: usize,
+        order: [u8; 2],
+        encoding: &Encoding,
+    ) {
+        let (has_pawns, has_unique_pieces) = (self.has_pawns, self.has_unique_pieces);
+        let both_pawns = has_pawns && self.pawn_count[1] > 0;
+        let d = &mut self.items[side][file];
+        let mut n = 0;
+        let mut first_len: i32 = if has_pawns {
+            0
+        } else if has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        d.group_len[0] = 1;
+        for i in 1..piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+        let mut idx = 1u64;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                d.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    encoding.lead_pawns_size[d.group_len[0]][file]
+                } else if has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                d.group_idx[1] = idx;
+                idx *= encoding.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= encoding.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+}
+
+/// Reads the block layout and Huffman code of one side of a table. Errors
+/// are to follow the name of the table.
+fn set_sizes(d: &mut PairsData, bytes: &[u8], mut pos: usize) -> Result<usize, String> {
+    let truncated = || "is truncated".to_string();
+    let byte = |pos: usize| bytes.get(pos).copied().ok_or_else(truncated);
+    d.flags = byte(pos)?;
+    pos += 1;
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        // Every position has the same value, kept in place of the symbol length.
+        d.min_sym_len = byte(pos)?;
+        return Ok(pos + 1);
+    }
+
+    let groups = d.group_len.iter().position(|length| *length == 0).unwrap_or(7);
+    let table_size = d.group_idx[groups];
+    let power_of_two = |exponent: u8| {
+        1u64.checked_shl(exponent as u32)
+            .ok_or_else(|| format!("has a block size or span of 2^{}", exponent))
+    };
+    d.size_of_block = power_of_two(byte(pos)?)?;
+    d.span = power_of_two(byte(pos + 1)?)?;
+    d.sparse_index_size = table_size.div_ceil(d.span);
+    let padding = byte(pos + 2)? as u64;
+    d.num_blocks = read_u32_le(bytes, pos + 3).ok_or_else(truncated)? as u64;
+    d.block_length_size = d.num_blocks + padding;
+    let (max_sym_len, min_sym_len) = (byte(pos + 7)?, byte(pos + 8)?);
+    d.min_sym_len = min_sym_len;
+    pos += 9;
+    d.lowest_sym = pos;
+
+    // Canonical Huffman code: longer symbols have lower values, so base64[i]
+    // is the lowest 64 bit padded code of a symbol of length min + i. Codes
+    // are read from a 64 bit buffer, so no symbol may be longer.
+    let bad_lengths = || format!("has symbols of {} to {} bits", min_sym_len, max_sym_len);
+    if min_sym_len == 0 || max_sym_len > 64 {
+        return Err(bad_lengths());
+    }
+    let lengths = max_sym_len.checked_sub(min_sym_len).ok_or_else(bad_lengths)? as usize + 1;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        let lowest = read_u16(bytes, d.lowest_sym + 2 * i) as u64;
+        let lower = read_u16(bytes, d.lowest_sym + 2 * (i + 1)) as u64;
+        d.base64[i] = d.base64[i + 1].wrapping_add(lowest).wrapping_sub(lower) / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        let shift = 64 - i as u32 - d.min_sym_len as u32;
+        *base = base.checked_shl(shift).unwrap_or(0);
+    }
+    pos += lengths * 2;
+
+    // Symbols stand for pairs of symbols, recursively; count the values each expands to.
+    let symbols = read_u16(bytes, pos) as usize;
+    pos += 2;
+    d.btree = pos;
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for symbol in 0..symbols {
+        if !visited[symbol] {
+            d.symlen[symbol] = symbol_length(d, bytes, symbol, &mut visited);
+        }
+    }
+    Ok(pos + symbols * 3 + (symbols & 1))
+}
+
+fn symbol_length(d: &mut PairsData, bytes: &[u8], symbol: usize, visited: &mut Vec<bool>) -> u32 {
+    visited[symbol] = true;
+    let (left, right) = pair(bytes, d.btree, symbol);
+    if right == 0xFFF || left >= visited.len() || right >= visited.len() {
+        return 0;
+    }
+    if !visited[left] {
+        d.symlen[left] = symbol_length(d, bytes, left, visited);
+    }
+    if !visited[right] {
+        d.symlen[right] = symbol_length(d, bytes, right, visited);
+    }
+    d.symlen[left] + d.symlen[right] + 1
+}
+
+/// The two symbols a symbol stands for, packed into three bytes.
+fn pair(bytes: &[u8], btree: usize, symbol: usize) -> (usize, usize) {
+    let at = |offset: usize| bytes.get(btree + 3 * symbol + offset).copied().unwrap_or(0) as usize;
+    let left = (at(1) & 0xF) << 8 | at(0);
+    let right = at(2) << 4 | at(1) >> 4;
+    (left, right)
+}
+
+/// Value stored at index `idx`. Errors are to follow the name of the table.
+fn decompress_pairs(d: &PairsData, bytes: &[u8], idx: u64) -> Result<i32, String> {
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        return Ok(d.min_sym_len as i32);
+    }
+    let broken_index = || "has a broken block index".to_string();
+    let broken_code = || "has a broken Huffman code".to_string();
+
+    // The sparse index points into the block list near the value, which is
+    // then found by walking the block lengths (each block holds length + 1 values).
+    let k = (idx / d.span) as usize;
+    let mut block = read_u32_le(bytes, d.sparse_index + 6 * k).unwrap_or(0) as usize;
+    let mut offset = read_u16(bytes, d.sparse_index + 6 * k + 4) as i64;
+    offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+    let block_length = |block: usize| read_u16(bytes, d.block_length + 2 * block) as i64;
+    while offset < 0 {
+        block = block.checked_sub(1).ok_or_else(broken_index)?;
+        offset += block_length(block) + 1;
+    }
+    while offset > block_length(block) {
+        offset -= block_length(block) + 1;
+        block += 1;
+    }
+
+    let mut ptr = d.data + block * d.size_of_block as usize;
+    let mut buf64 = read_u64_be(bytes, ptr);
+    ptr += 8;
+    let mut buf64_size: usize = 64;
+    let min_sym_len = d.min_sym_len as usize;
+    let mut symbol;
+    loop {
+        let mut length = 0;
+        while length + 1 < d.base64.len() && buf64 < d.base64[length] {
+            length += 1;
+        }
+        // set_sizes keeps min_sym_len + length between 1 and 64.
+        let shift = 64usize.checked_sub(length + min_sym_len).ok_or_else(broken_code)?;
+        let padded = buf64.checked_sub(d.base64[length]).ok_or_else(broken_code)?;
+        let code = padded.checked_shr(shift as u32).unwrap_or(0) as u16;
+        symbol = code.wrapping_add(read_u16(bytes, d.lowest_sym + 2 * length)) as usize;
+        let expands = d.symlen.get(symbol).copied().unwrap_or(0) as i64 + 1;
+        if offset < expands {
+            break;
+        }
+        offset -= expands;
+        length += min_sym_len;
+        buf64 = buf64.checked_shl(length as u32).unwrap_or(0);
+        buf64_size = buf64_size.checked_sub(length).ok_or_else(broken_code)?;
+        if buf64_size <= 32 {
+            buf64_size += 32;
+            buf64 |= (read_u32_be(bytes, ptr) as u64) << (64 - buf64_size);
+            ptr += 4;
+        }
+    }
+
+    // Walk down the pairs to the single value at the offset.
+    while d.symlen.get(symbol).copied().unwrap_or(0) != 0 {
+        let (left, right) = pair(bytes, d.btree, symbol);
+        let left_values = d.symlen.get(left).copied().unwrap_or(0) as i64 + 1;
+        if offset < left_values {
+            symbol = left;
+        } else {
+            offset -= left_values;
+            symbol = right;
+        }
+    }
+    Ok(pair(bytes, d.btree, symbol).0 as i32)
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> u16 {
+    match bytes.get(pos..pos + 2) {
+        Some(b) => u16::from_le_bytes([b[0], b[1]]),
+        None => 0,
+    }
+}
+
+fn read_u32_le(bytes: &[u8], pos: usize) -> Option<u32> {
+    let b = bytes.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u32_be(bytes: &[u8], pos: usize) -> u32 {
+    match bytes.get(pos..pos + 4) {
+        Some(b) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+        None => 0,
+    }
+}
+
+fn read_u64_be(bytes: &[u8], pos: usize) -> u64 {
+    (read_u32_be(bytes, pos) as u64) << 32 | read_u32_be(bytes, pos + 4) as u64
+}
+
+impl Encoding {
+    fn new() -> Encoding {
+        let mut encoding = Encoding {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+        let file = |square: usize| square % 8;
+
+        // Squares below the a1-h8 diagonal numbered 0..27.
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                encoding.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        // The a1-d1-d4 triangle numbered 0..9, diagonal squares last.
+        let mut diagonal = Vec::new();
+        code = 0;
+        for square in 0..28 {
+            if off_diagonal(square) < 0 && file(square) <= 3 {
+                encoding.map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 && file(square) <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            encoding.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // The 462 placements of two kings with the first in the triangle,
+        // placements with both on the diagonal last.
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0u64;
+        for idx in 0..10 {
+            for first in 0..28 {
+                let in_triangle = file(first) <= 3 && off_diagonal(first) <= 0;
+                if !in_triangle || encoding.map_a1d1d4[first] != idx || (idx == 0 && first != 1) {
+                    continue;
+                }
+                for second in 0..64 {
+                    let file_distance = (file(first) as i32 - file(second) as i32).abs();
+                    let rank_distance = (first as i32 / 8 - second as i32 / 8).abs();
+                    if file_distance <= 1 && rank_distance <= 1 {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) > 0 {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((idx, second));
+                    } else {
+                        encoding.map_kk[idx][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            encoding.map_kk[idx][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                let with = if k > 0 { encoding.binomial[k - 1][n - 1] } else { 0 };
+                let without = if k < n { encoding.binomial[k][n - 1] } else { 0 };
+                encoding.binomial[k][n] = with + without;
+            }
+        }
+
+        // Pawn squares numbered from the edges inwards and from rank 2 up,
+        // with the leading pawn placed first within each file.
+        let mut available = 48;
+        for lead_pawns in 1..=5 {
+            for f in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + f;
+                    if lead_pawns == 1 {
+                        available -= 2;
+                        encoding.map_pawns[square] = available + 1;
+                        encoding.map_pawns[square ^ 7] = available;
+                    }
+                    encoding.lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += encoding.binomial[lead_pawns - 1][encoding.map_pawns[square]];
+                }
+                encoding.lead_pawns_size[lead_pawns][f] = idx;
+            }
+        }
+        encoding
+    }
+}
+
+/// A move on the probing board, squares numbered a1 = 0 to h8 = 63.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Move {
+    from: usize,
+    to: usize,
+    promotion: u8,
+}
+
+impl Move {
+    fn to_uci(self) -> String {
+        let coordinate = |square: usize| ((square % 8) as isize, (square / 8) as isize);
+        let mut uci =
+            coordinate_to_string(coordinate(self.from)) + &coordinate_to_string(coordinate(self.to));
+        match self.promotion {
+            QUEEN => uci.push('q'),
+            ROOK => uci.push('r'),
+            BISHOP => uci.push('b'),
+            KNIGHT => uci.push('n'),
+            _ => {}
+        }
+        uci
+    }
+}
+
+/// Small board used for the searches during probing, independent of the game
+/// on screen. Castling is left out as tables do not cover it.
+#[derive(Clone)]
+struct Board {
+    squares: [u8; 64],
+    /// 0 for white to move, 1 for black.
+    stm: usize,
+    en_passant: Option<usize>,
+}
+
+const KNIGHT_STEPS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+fn step(square: usize, (df, dr): (i32, i32)) -> Option<usize> {
+    let (file, rank) = ((square % 8) as i32 + df, (square / 8) as i32 + dr);
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((rank * 8 + file) as usize)
+    } else {
+        None
+    }
+}
+
+impl Board {
+    fn from_fen(fen: &Fen) -> Board {
+        let mut squares = [0; 64];
+        for ((file, rank), team, piece) in fen.placement.iter() {
+            let kind = match piece {
+                Rank::Pawn => PAWN,
+                Rank::Knight => KNIGHT,
+                Rank::Bishop => BISHOP,
+                Rank::Rook => ROOK,
+                Rank::Queen => QUEEN,
+                Rank::King => KING,
+            };
+            let color = if *team == Team::White { 0 } else { BLACK };
+            squares[(*rank * 8 + *file) as usize] = color | kind;
+        }
+        Board {
+            squares,
+            stm: if fen.player == Team::White { 0 } else { 1 },
+            en_passant: fen.en_passant.map(|(file, rank)| (rank * 8 + file) as usize),
+        }
+    }
+
+    /// Pieces of each side as in table names, e.g. ("KRP", "KR").
+    fn material(&self) -> (String, String) {
+        let side = |color: u8| {
+            let mut code = String::new();
+            for (kind, letter) in [
+                (KING, 'K'),
+                (QUEEN, 'Q'),
+                (ROOK, 'R'),
+                (BISHOP, 'B'),
+                (KNIGHT, 'N'),
+                (PAWN, 'P'),
+            ]
+            .iter()
+            {
+                let count = self
+                    .squares
+                    .iter()
+                    .filter(|piece| **piece == color | kind)
+                    .count();
+                for _ in 0..count {
+                    code.push(*letter);
+                }
+            }
+            code
+        };
+        (side(0), side(BLACK))
+    }
+
+    fn color(&self, stm: usize) -> u8 {
+        if stm == 0 {
+            0
+        } else {
+            BLACK
+        }
+    }
+
+    fn is_capture(&self, m: Move) -> bool {
+        self.squares[m.to] != 0 || (self.is_pawn_move(m) && Some(m.to) == self.en_passant)
+    }
+
+    fn is_pawn_move(&self, m: Move) -> bool {
+        self.squares[m.from] & 7 == PAWN
+    }
+
+    fn is_zeroing(&self, m: Move) -> bool {
+        self.is_capture(m) || self.is_pawn_move(m)
+    }
+
+    /// Whether a piece of side `by` attacks `target`.
+    fn attacked(&self, target: usize, by: usize) -> bool {
+        let color = self.color(by);
+        let forward = if by == 0 { 1 } else { -1 };
+        for df in [-1, 1].iter() {
+            if let Some(square) = step(target, (*df, -forward)) {
+                if self.squares[square] == color | PAWN {
+                    return true;
+                }
+            }
+        }
+        for (steps, kind) in [(&KNIGHT_STEPS, KNIGHT), (&KING_STEPS, KING)].iter() {
+            for delta in steps.iter() {
+                if let Some(square) = step(target, *delta) {
+                    if self.squares[square] == color | kind {
+                        return true;
+                    }
+                }
+            }
+        }
+        for (directions, slider) in [(&ROOK_DIRECTIONS, ROOK), (&BISHOP_DIRECTIONS, BISHOP)].iter() {
+            for delta in directions.iter() {
+                let mut square = target;
+                while let Some(next) = step(square, *delta) {
+                    let piece = self.squares[next];
+                    if piece != 0 {
+                        if piece == color | slider || piece == color | QUEEN {
+                            return true;
+                        }
+                        break;
+                    }
+                    square = next;
+                }
+            }
+        }
+        false
+    }
+
+    fn in_check(&self, stm: usize) -> bool {
+        let king = self.color(stm) | KING;
+        match self.squares.iter().position(|piece| *piece == king) {
+            Some(square) => self.attacked(square, 1 - stm),
+            None => false,
+        }
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        let color = self.color(self.stm);
+        let mut moves = Vec::new();
+        let add = |from: usize, to: usize, moves: &mut Vec<Move>| {
+            let promotes = self.squares[from] & 7 == PAWN && (to / 8 == 0 || to / 8 == 7);
+            if promotes {
+                for promotion in [QUEEN, ROOK, BISHOP, KNIGHT].iter() {
+                    moves.push(Move {
+                        from,
+                        to,
+                        promotion: *promotion,
+                    });
+                }
+            } else {
+                moves.push(Move {
+                    from,
+                    to,
+                    promotion: 0,
+                });
+            }
+        };
+        for from in 0..64 {
+            let piece = self.squares[from];
+            if piece == 0 || piece & BLACK != color {
+                continue;
+            }
+            let free_or_enemy =
+                |square: usize| self.squares[square] == 0 || self.squares[square] & BLACK != color;
+            match piece & 7 {
+                PAWN => {
+                    let forward = if self.stm == 0 { 1 } else { -1 };
+                    if let Some(to) = step(from, (0, forward)) {
+                        if self.squares[to] == 0 {
+                            add(from, to, &mut moves);
+                            let start_rank = if self.stm == 0 { 1 } else { 6 };
+                            if from / 8 == start_rank {
+                                if let Some(double) = step(to, (0, forward)) {
+                                    if self.squares[double] == 0 {
+                                        add(from, double, &mut moves);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    for df in [-1, 1].iter() {
+                        if let Some(to) = step(from, (*df, forward)) {
+                            let enemy = self.squares[to] != 0 && self.squares[to] & BLACK != color;
+                            if enemy || Some(to) == self.en_passant {
+                                add(from, to, &mut moves);
+                            }
+                        }
+                    }
+                }
+                KNIGHT | KING => {
+                    let steps = if piece & 7 == KNIGHT {
+                        &KNIGHT_STEPS
+                    } else {
+                        &KING_STEPS
+                    };
+                    for delta in steps.iter() {
+                        if let Some(to) = step(from, *delta) {
+                            if free_or_enemy(to) {
+                                add(from, to, &mut moves);
+                            }
+                        }
+                    }
+                }
+                kind => {
+                    let mut directions = Vec::new();
+                    if kind != BISHOP {
+                        directions.extend_from_slice(&ROOK_DIRECTIONS);
+                    }
+                    if kind != ROOK {
+                        directions.extend_from_slice(&BISHOP_DIRECTIONS);
+                    }
+                    for delta in directions {
+                        let mut square = from;
+                        while let Some(to) = step(square, delta) {
+                            if free_or_enemy(to) {
+                                add(from, to, &mut moves);
+                            }
+                            if self.squares[to] != 0 {
+                                break;
+                            }
+                            square = to;
+                        }
+                    }
+                }
+            }
+        }
+        moves.retain(|m| !self.play(*m).in_check(self.stm));
+        moves
+    }
+
+    fn play(&self, m: Move) -> Board {
+        let mut board = self.clone();
+        let piece = self.squares[m.from];
+        if piece & 7 == PAWN && Some(m.to) == self.en_passant && self.squares[m.to] == 0 {
+            // The captured pawn stands behind the square moved to.
+            let behind = if self.stm == 0 { m.to - 8 } else { m.to + 8 };
+            board.squares[behind] = 0;
+        }
+        board.squares[m.from] = 0;
+        board.squares[m.to] = if m.promotion != 0 {
+            piece & BLACK | m.promotion
+        } else {
+            piece
+        };
+        board.en_passant = if piece & 7 == PAWN && (m.to as i32 - m.from as i32).abs() == 16 {
+            Some((m.from + m.to) / 2)
+        } else {
+            None
+        };
+        board.stm = 1 - self.stm;
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(&fen.parse().unwrap())
+    }
+
+    fn moves(board: &Board) -> Vec<String> {
+        let mut moves: Vec<String> = board.legal_moves().into_iter().map(Move::to_uci).collect();
+        moves.sort();
+        moves
+    }
+
+    fn square(name: &str) -> usize {
+        let bytes = name.as_bytes();
+        (bytes[1] - b'1') as usize * 8 + (bytes[0] - b'a') as usize
+    }
+
+    #[test]
+    fn numbers_the_462_king_placements() {
+        let encoding = Encoding::new();
+        let mut codes: Vec<u64> = encoding.map_kk.iter().flatten().copied().collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes, (0..462).collect::<Vec<u64>>());
+
+        // The a1-d1-d4 triangle, diagonal squares last.
+        let triangle = ["b1", "c1", "d1", "c2", "d2", "d3", "a1", "b2", "c3", "d4"];
+        for (code, name) in triangle.iter().enumerate() {
+            assert_eq!(encoding.map_a1d1d4[square(name)], code, "{}", name);
+        }
+        let mut below: Vec<usize> = (0..64)
+            .filter(|square| off_diagonal(*square) < 0)
+            .map(|square| encoding.map_b1h1h7[square])
+            .collect();
+        below.sort_unstable();
+        assert_eq!(below, (0..28).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn fills_the_binomial_table() {
+        let encoding = Encoding::new();
+        for n in 0..64u64 {
+            for k in 0..6u64 {
+                let expected = if k > n {
+                    0
+                } else {
+                    (0..k).fold(1, |product, i| product * (n - i) / (i + 1))
+                };
+                assert_eq!(encoding.binomial[k as usize][n as usize], expected, "{} over {}", n, k);
+            }
+        }
+        assert_eq!(encoding.binomial[5][63], 7_028_847);
+    }
+
+    #[test]
+    fn numbers_pawn_squares_from_the_edges() {
+        let encoding = Encoding::new();
+        let mut codes: Vec<usize> = (8..56).map(|square| encoding.map_pawns[square]).collect();
+        codes.sort_unstable();
+        assert_eq!(codes, (0..48).collect::<Vec<usize>>());
+        assert_eq!(encoding.map_pawns[square("a2")], 47);
+        assert_eq!(encoding.map_pawns[square("h2")], 46);
+        assert_eq!(encoding.map_pawns[square("d7")], 1);
+        assert_eq!(encoding.map_pawns[square("e7")], 0);
+        for file in 0..4 {
+            // A lone leading pawn can stand on any of the six ranks of its file.
+            assert_eq!(encoding.lead_pawns_size[1][file], 6);
+            for rank in 1..7 {
+                assert_eq!(encoding.lead_pawn_idx[1][rank * 8 + file], rank as u64 - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn generates_the_moves_of_the_starting_position() {
+        let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1");
+        assert_eq!(moves(&start).len(), 20);
+        let after = start.play(Move {
+            from: square("e2"),
+            to: square("e4"),
+            promotion: 0,
+        });
+        assert_eq!(after.en_passant, Some(square("e3")));
+        assert_eq!(after.stm, 1);
+        assert_eq!(moves(&after).len(), 20);
+    }
+
+    #[test]
+    fn captures_en_passant() {
+        let position = board("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        assert!(moves(&position).contains(&"e5d6".to_string()));
+        let m = Move {
+            from: square("e5"),
+            to: square("d6"),
+            promotion: 0,
+        };
+        assert!(position.is_capture(m));
+        let after = position.play(m);
+        assert_eq!(after.squares[square("d5")], 0);
+        assert_eq!(after.squares[square("d6")], PAWN);
+        assert_eq!(after.material(), ("KP".to_string(), "K".to_string()));
+    }
+
+    #[test]
+    fn promotes_to_every_piece() {
+        let position = board("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        let promotions: Vec<String> = moves(&position)
+            .into_iter()
+            .filter(|uci| uci.starts_with("a7"))
+            .collect();
+        assert_eq!(promotions, ["a7a8b", "a7a8n", "a7a8q", "a7a8r"]);
+    }
+
+    #[test]
+    fn keeps_the_king_out_of_check() {
+        // The knight is pinned and the king may not stay on the first rank.
+        let position = board("4k3/4r3/8/8/8/8/4N3/r3K3 w - - 0 1");
+        assert!(position.in_check(0));
+        assert_eq!(moves(&position), ["e1d2", "e1f2"]);
+        assert_eq!(position.material(), ("KN".to_string(), "KRR".to_string()));
+    }
+}
@@ -0,0 +1,73 @@
+//! Board color schemes.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// RGBA color with components between 0 and 1.
+pub type Rgba = [f32; 4];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Gray,
+    Brown,
+    Green,
+    Blue,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 4] = [Theme::Gray, Theme::Brown, Theme::Green, Theme::Blue];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Gray => "gray",
+            Theme::Brown => "brown",
+            Theme::Green => "green",
+            Theme::Blue => "blue",
+        }
+    }
+
+    pub fn light_square(self) -> Rgba {
+        match self {
+            Theme::Gray => rgba(120, 120, 120, 1.0),
+            Theme::Brown => rgba(240, 217, 181, 1.0),
+            Theme::Green => rgba(238, 238, 210, 1.0),
+            Theme::Blue => rgba(222, 227, 230, 1.0),
+        }
+    }
+
+    pub fn dark_square(self) -> Rgba {
+        match self {
+            Theme::Gray => rgba(60, 60, 60, 1.0),
+            Theme::Brown => rgba(181, 136, 99, 1.0),
+            Theme::Green => rgba(118, 150, 86, 1.0),
+            Theme::Blue => rgba(140, 162, 173, 1.0),
+        }
+    }
+
+    /// Overlay marking the squares the selected piece can move to.
+    pub fn available_tile(self) -> Rgba {
+        match self {
+            Theme::Gray => rgba(190, 120, 100, 0.5),
+            Theme::Brown => rgba(130, 151, 105, 0.6),
+            Theme::Green => rgba(246, 246, 105, 0.6),
+            Theme::Blue => rgba(155, 199, 0, 0.5),
+        }
+    }
+}
+
+fn rgba(r: u8, g: u8, b: u8, a: f32) -> Rgba {
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a]
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Theme, String> {
+        Theme::ALL
+            .iter()
+            .copied()
+            .find(|theme| theme.name() == text.to_lowercase())
+            .ok_or_else(|| format!("Unknown theme '{}', expected gray, brown, green or blue", text))
+    }
+}
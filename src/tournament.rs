@@ -0,0 +1,474 @@
+//! Round-robin and Swiss tournaments between human and engine players.
+//!
+//! A round-robin is paired in full when the tournament is created, by the
+//! circle method. A Swiss tournament is paired a round at a time once the
+//! previous one is over: players are ranked by score and each is paired with
+//! the highest ranked player below them they have not met yet, as long as
+//! everyone else can still be paired without a rematch. Finding out whether
+//! they can tries every way of pairing the rest, so Swiss tournaments take at
+//! most `MAX_SWISS_PLAYERS` players. In an odd
+//! round the lowest ranked player who has not had a bye sits out, which
+//! scores a point in a Swiss tournament and nothing in a round-robin.
+//!
+//! Standings are broken by Buchholz, the sum of the opponents' scores, and
+//! then Sonneborn-Berger, the scores of the opponents beaten plus half those
+//! of the opponents drawn. Byes count for neither.
+
+use crate::cli::AdjudicationOptions;
+use crate::clock::TimeControl;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Most players of a Swiss tournament: pairing a round without rematches
+/// tries up to 15 × 13 × … × 1, about two million, ways for sixteen.
+pub const MAX_SWISS_PLAYERS: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Player {
+    pub name: String,
+    /// Executable of an engine player, `None` for a human.
+    pub engine: Option<PathBuf>,
+}
+
+impl Player {
+    /// Reads a human player's name, or an engine as `path` or `name=path`.
+    pub fn engine(text: &str) -> Player {
+        let (name, path) = match text.find('=') {
+            Some(split) => (text[..split].to_string(), PathBuf::from(&text[split + 1..])),
+            None => {
+                let path = PathBuf::from(text);
+                let name = path.file_stem().map_or_else(
+                    || text.to_string(),
+                    |stem| stem.to_string_lossy().into_owned(),
+                );
+                (name, path)
+            }
+        };
+        Player {
+            name,
+            engine: Some(path),
+        }
+    }
+
+    pub fn human(name: &str) -> Player {
+        Player {
+            name: name.to_string(),
+            engine: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    RoundRobin,
+    Swiss { rounds: usize },
+}
+
+/// A game of a round, or a bye when there is no black player.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Pairing {
+    pub white: usize,
+    pub black: Option<usize>,
+    /// Result as written in PGN, `None` until the game has been played.
+    pub result: Option<String>,
+}
+
+impl Pairing {
+    /// Points scored by `player` in this pairing, if it is one of theirs and decided.
+    fn points(&self, player: usize) -> Option<f64> {
+        let white = match self.result.as_deref()? {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            _ => 0.5,
+        };
+        if player == self.white {
+            Some(white)
+        } else if Some(player) == self.black {
+            Some(1.0 - white)
+        } else {
+            None
+        }
+    }
+
+    fn opponent_of(&self, player: usize) -> Option<usize> {
+        if player == self.white {
+            self.black
+        } else if Some(player) == self.black {
+            Some(self.white)
+        } else {
+            None
+        }
+    }
+}
+
+/// Score and tiebreaks of a player.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Standing {
+    pub player: usize,
+    pub points: f64,
+    pub buchholz: f64,
+    pub sonneborn_berger: f64,
+    pub games: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Tournament {
+    pub name: String,
+    pub format: Format,
+    /// Time control of every game, as "minutes+increment".
+    pub time: Option<String>,
+    pub players: Vec<Player>,
+    /// Pairings of the rounds paired so far.
+    pub rounds: Vec<Vec<Pairing>>,
+}
+
+impl Tournament {
+    pub fn new(
+        name: &str,
+        players: Vec<Player>,
+        format: Format,
+        time: Option<TimeControl>,
+    ) -> Result<Tournament, String> {
+        if players.len() < 2 {
+            return Err("A tournament needs at least two players".to_string());
+        }
+        if format == (Format::Swiss { rounds: 0 }) {
+            return Err("A Swiss tournament needs at least one round".to_string());
+        }
+        if matches!(format, Format::Swiss { .. }) && players.len() > MAX_SWISS_PLAYERS {
+            return Err(format!(
+                "A Swiss tournament takes at most {} players",
+                MAX_SWISS_PLAYERS
+            ));
+        }
+        let rounds = match format {
+            Format::RoundRobin => round_robin(players.len()),
+            Format::Swiss { .. } => Vec::new(),
+        };
+        Ok(Tournament {
+            name: name.to_string(),
+            format,
+            time: time.map(|time| time.to_string()),
+            players,
+            rounds,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Tournament, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        serde_json::from_str(&text).map_err(|error| error.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|error| error.to_string())?;
+        fs::write(path, text)
+            .map_err(|error| format!("Could not save {}: {}", path.display(), error))
+    }
+
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.time.as_ref().and_then(|time| time.parse().ok())
+    }
+
+    fn round_count(&self) -> usize {
+        match self.format {
+            Format::RoundRobin => self.rounds.len(),
+            Format::Swiss { rounds } => rounds,
+        }
+    }
+
+    /// Round and board of the next game to play, pairing the next Swiss round
+    /// when the last one is over. `None` once every game has been played.
+    pub fn next_game(&mut self) -> Option<(usize, usize)> {
+        let unplayed = |round: &Vec<Pairing>| {
+            round
+                .iter()
+                .position(|pairing| pairing.black.is_some() && pairing.result.is_none())
+        };
+        for (index, round) in self.rounds.iter().enumerate() {
+            if let Some(board) = unplayed(round) {
+                return Some((index, board));
+            }
+        }
+        if self.rounds.len() >= self.round_count() {
+            return None;
+        }
+        let round = self.pair_swiss_round();
+        self.rounds.push(round);
+        let index = self.rounds.len() - 1;
+        unplayed(&self.rounds[index]).map(|board| (index, board))
+    }
+
+    pub fn set_result(&mut self, round: usize, board: usize, result: &str) {
+        self.rounds[round][board].result = Some(result.to_string());
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.rounds.len() >= self.round_count()
+            && self
+                .rounds
+                .iter()
+                .flatten()
+                .all(|pairing| pairing.black.is_none() || pairing.result.is_some())
+    }
+
+    /// Points of `player` in the games played so far, byes included.
+    pub fn points(&self, player: usize) -> f64 {
+        let bye = match self.format {
+            Format::RoundRobin => 0.0,
+            Format::Swiss { .. } => 1.0,
+        };
+        self.rounds
+            .iter()
+            .flatten()
+            .map(|pairing| match pairing.black {
+                None if pairing.white == player => bye,
+                _ => pairing.points(player).unwrap_or(0.0),
+            })
+            .sum()
+    }
+
+    fn have_met(&self, first: usize, second: usize) -> bool {
+        self.rounds
+            .iter()
+            .flatten()
+            .any(|pairing| pairing.opponent_of(first) == Some(second))
+    }
+
+    /// White games minus black games of `player`.
+    fn color_balance(&self, player: usize) -> i32 {
+        self.rounds
+            .iter()
+            .flatten()
+            .filter(|pairing| pairing.black.is_some())
+            .map(|pairing| match pairing.opponent_of(player) {
+                Some(_) if pairing.white == player => 1,
+                Some(_) => -1,
+                None => 0,
+            })
+            .sum()
+    }
+
+    fn pair_swiss_round(&self) -> Vec<Pairing> {
+        let mut ranking: Vec<usize> = (0..self.players.len()).collect();
+        ranking.sort_by(|a, b| {
+            self.points(*b)
+                .partial_cmp(&self.points(*a))
+                .unwrap_or(Ordering::Equal)
+        });
+        let mut round = Vec::new();
+        if ranking.len() % 2 == 1 {
+            let had_bye = |player: usize| {
+                self.rounds
+                    .iter()
+                    .flatten()
+                    .any(|pairing| pairing.white == player && pairing.black.is_none())
+            };
+            let index = ranking
+                .iter()
+                .rposition(|player| !had_bye(*player))
+                .unwrap_or(ranking.len() - 1);
+            round.push(Pairing {
+                white: ranking.remove(index),
+                black: None,
+                result: None,
+            });
+        }
+        // Rematches only when there is no other way to pair everyone.
+        let pairs = self
+            .pair_unmet(&ranking)
+            .unwrap_or_else(|| ranking.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+        for (first, second) in pairs {
+            // The player who has had white less often gets it.
+            let (white, black) = if self.color_balance(first) <= self.color_balance(second) {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            round.push(Pairing {
+                white,
+                black: Some(black),
+                result: None,
+            });
+        }
+        // Byes last, as in a round-robin.
+        round.rotate_left(self.players.len() % 2);
+        round
+    }
+
+    /// Pairs the players in `ranking` so that nobody meets an opponent twice,
+    /// each with the highest ranked player that still allows it. Backtracks
+    /// through every pairing when there is none, hence `MAX_SWISS_PLAYERS`.
+    fn pair_unmet(&self, ranking: &[usize]) -> Option<Vec<(usize, usize)>> {
+        let (first, rest) = match ranking.split_first() {
+            Some(split) => split,
+            None => return Some(Vec::new()),
+        };
+        for (index, second) in rest.iter().enumerate() {
+            if self.have_met(*first, *second) {
+                continue;
+            }
+            let mut others = rest.to_vec();
+            others.remove(index);
+            if let Some(mut pairs) = self.pair_unmet(&others) {
+                pairs.insert(0, (*first, *second));
+                return Some(pairs);
+            }
+        }
+        None
+    }
+
+    /// Players by points, then Buchholz, then Sonneborn-Berger.
+    pub fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = (0..self.players.len())
+            .map(|player| {
+                let mut standing = Standing {
+                    player,
+                    points: self.points(player),
+                    buchholz: 0.0,
+                    sonneborn_berger: 0.0,
+                    games: 0,
+                };
+                for pairing in self.rounds.iter().flatten() {
+                    let (opponent, points) =
+                        match (pairing.opponent_of(player), pairing.points(player)) {
+                            (Some(opponent), Some(points)) => (opponent, points),
+                            _ => continue,
+                        };
+                    let opponent_points = self.points(opponent);
+                    standing.games += 1;
+                    standing.buchholz += opponent_points;
+                    standing.sonneborn_berger += points * opponent_points;
+                }
+                standing
+            })
+            .collect();
+        standings.sort_by(|a, b| {
+            let key = |standing: &Standing| {
+                (
+                    standing.points,
+                    standing.buchholz,
+                    standing.sonneborn_berger,
+                )
+            };
+            key(b).partial_cmp(&key(a)).unwrap_or(Ordering::Equal)
+        });
+        standings
+    }
+
+    /// The standings as a table with a header line.
+    pub fn standings_table(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{:>2} {:<20} {:>6} {:>8} {:>6} {:>5}",
+            "#", "Player", "Points", "Buchholz", "S-B", "Games"
+        )];
+        for (rank, standing) in self.standings().iter().enumerate() {
+            lines.push(format!(
+                "{:>2} {:<20} {:>6} {:>8} {:>6} {:>5}",
+                rank + 1,
+                self.players[standing.player].name,
+                standing.points,
+                standing.buchholz,
+                standing.sonneborn_berger,
+                standing.games
+            ));
+        }
+        lines
+    }
+
+    /// Writes the standings and the results of every round to `path`.
+    pub fn export(&self, path: &Path) -> Result<(), String> {
+        let mut text = format!("{}\n\n", self.name);
+        for line in self.standings_table() {
+            text += &line;
+            text.push('\n');
+        }
+        for (index, round) in self.rounds.iter().enumerate() {
+            text += &format!("\nRound {}\n", index + 1);
+            for pairing in round {
+                let white = &self.players[pairing.white].name;
+                text += &match pairing.black {
+                    Some(black) => format!(
+                        "{} - {} {}\n",
+                        white,
+                        self.players[black].name,
+                        pairing.result.as_deref().unwrap_or("*")
+                    ),
+                    None => format!("{} has a bye\n", white),
+                };
+            }
+        }
+        fs::write(path, text)
+            .map_err(|error| format!("Could not write {}: {}", path.display(), error))
+    }
+}
+
+/// Pairings of every round of a round-robin between `count` players.
+fn round_robin(count: usize) -> Vec<Vec<Pairing>> {
+    // With an odd number of players, the one paired with the extra seat sits out.
+    let seats = count + count % 2;
+    let mut rounds = Vec::new();
+    for round in 0..seats - 1 {
+        // The last seat stays put while the others turn around it.
+        let seat = |index: usize| {
+            if index == seats - 1 {
+                seats - 1
+            } else {
+                (round + index) % (seats - 1)
+            }
+        };
+        let mut pairings = Vec::new();
+        for index in 0..seats / 2 {
+            let (mut white, mut black) = (seat(index), seat(seats - 1 - index));
+            // The player in the last seat alternates colors, the others have
+            // white in the first half of the circle.
+            if index == 0 && round % 2 == 1 {
+                std::mem::swap(&mut white, &mut black);
+            }
+            let pairing = match (white < count, black < count) {
+                (true, true) => Pairing {
+                    white,
+                    black: Some(black),
+                    result: None,
+                },
+                (true, false) => Pairing {
+                    white,
+                    black: None,
+                    result: None,
+                },
+                _ => Pairing {
+                    white: black,
+                    black: None,
+                    result: None,
+                },
+            };
+            pairings.push(pairing);
+        }
+        // The bye is on the first board, with the last seat.
+        pairings.rotate_left(count % 2);
+        rounds.push(pairings);
+    }
+    rounds
+}
+
+/// A tournament being played in the window.
+pub struct TournamentSession {
+    pub tournament: Tournament,
+    pub path: PathBuf,
+    /// Adjudication of games between two engines.
+    pub adjudication: AdjudicationOptions,
+    /// Round and board of the game on the board.
+    pub current: Option<(usize, usize)>,
+}
+
+impl TournamentSession {
+    /// File the games are appended to, next to the tournament file.
+    pub fn pgn_path(&self) -> PathBuf {
+        self.path.with_extension("pgn")
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        self.tournament.save(&self.path)
+    }
+}
@@ -0,0 +1,15 @@
+//! Small helpers shared by modules that have nothing else in common.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A random number, good enough for varying play and for nonces, not for secrets.
+pub fn random() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+    // One round of splitmix64 spreads the clock bits over the whole number.
+    let mut x = nanos.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
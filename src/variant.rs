@@ -0,0 +1,445 @@
+//! Rule sets other than standard chess, named as in the PGN `Variant` tag.
+//!
+//! Variants are played on the board of the `chess` crate, which knows the
+//! standard rules only. Each variant can take away moves the board offers
+//! (`allows`), add moves it does not know (`extra_moves`), change what a move
+//! does to the board (`after_move`) and end the game on its own terms
+//! (`winner`). In Atomic chess the board's own idea of check is wrong once
+//! captures explode: moves it offers are judged on the board after the
+//! explosion, and moves it refuses, like a king stepping next to the other
+//! one or a capture blowing up the other king while in check, are added.
+//! Only en passant the board refuses is not added.
+
+use crate::chess960::{self, POSITION_COUNT, STANDARD_POSITION};
+use crate::fen::Fen;
+use crate::position::{
+    in_check, is_attacked, king_position, legal_moves, opponent, piece_at, pieces, reachable_squares,
+    touches, Coordinate,
+};
+use crate::record::{GameRecord, RecordedMove};
+use chess::game::{Game, GameState, Rank, Team};
+use std::str::FromStr;
+
+/// Starting position of Horde: 36 white pawns against the usual black army.
+const HORDE_FEN: &str = "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1";
+/// The four center squares a king wins on in King of the Hill.
+pub const HILL: [Coordinate; 4] = [(3, 3), (4, 3), (3, 4), (4, 4)];
+/// Checks that win a game of Three-check.
+pub const CHECKS_TO_WIN: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Variant {
+    Standard,
+    /// Fischer Random: the pieces behind the pawns are shuffled and castling
+    /// puts king and rook on the squares they take in standard chess.
+    Chess960,
+    /// Bringing the king to one of the four center squares wins.
+    KingOfTheHill,
+    /// Giving check for the third time wins.
+    ThreeCheck,
+    /// White has pawns only and wins by mate, black wins by taking them all.
+    Horde,
+    /// Captures explode, taking every piece but pawns next to the capture
+    /// square with them. Blowing up the king wins, and kings may touch.
+    Atomic,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 6] = [
+        Variant::Standard,
+        Variant::Chess960,
+        Variant::KingOfTheHill,
+        Variant::ThreeCheck,
+        Variant::Horde,
+        Variant::Atomic,
+    ];
+
+    /// Value of the PGN `Variant` tag.
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::Chess960 => "Chess960",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Horde => "Horde",
+            Variant::Atomic => "Atomic",
+        }
+    }
+
+    /// The rules in a few sentences, shown when picking a variant.
+    pub fn rules(self) -> &'static str {
+        match self {
+            Variant::Standard => "The rules of chess.",
+            Variant::Chess960 => {
+                "The pieces behind the pawns are shuffled. Castle by moving the king onto the rook."
+            }
+            Variant::KingOfTheHill => {
+                "Win by mate or by bringing your king to one of the four center squares."
+            }
+            Variant::ThreeCheck => "Win by mate or by giving check three times.",
+            Variant::Horde => {
+                "White has 36 pawns and no king, and wins by mate. Black wins by taking every pawn. \
+                 Pawns on the first rank may step two squares."
+            }
+            Variant::Atomic => {
+                "Captures explode, taking every piece but pawns next to them along with the capturing \
+                 piece. Kings cannot capture, so touching kings give no check. Win by mate or by \
+                 blowing up the other king, even while in check."
+            }
+        }
+    }
+
+    /// Value of the `UCI_Variant` option of engines playing several variants.
+    pub fn uci_name(self) -> &'static str {
+        match self {
+            Variant::Standard | Variant::Chess960 => "chess",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::ThreeCheck => "3check",
+            Variant::Horde => "horde",
+            Variant::Atomic => "atomic",
+        }
+    }
+
+    /// Whether the game is decided as in standard chess once it is under way,
+    /// so that opening books and endgame tablebases know the answers.
+    pub fn has_standard_rules(self) -> bool {
+        match self {
+            Variant::Standard | Variant::Chess960 => true,
+            _ => false,
+        }
+    }
+
+    /// Position the variant starts from, `None` for the standard one. Chess960
+    /// positions are picked with `chess960::start_position`.
+    pub fn start_position(self) -> Option<Fen> {
+        match self {
+            Variant::Horde => Some(HORDE_FEN.parse().unwrap()),
+            _ => None,
+        }
+    }
+
+    /// Squares marked on the board.
+    pub fn highlighted_squares(self) -> &'static [Coordinate] {
+        match self {
+            Variant::KingOfTheHill => &HILL,
+            _ => &[],
+        }
+    }
+
+    /// Whether a move the board offers may be played in this variant.
+    pub fn allows(self, game: &Game, recorded: RecordedMove) -> bool {
+        let (from, to) = (recorded.from, recorded.to);
+        let moving = match piece_at(game, from) {
+            Some(piece) => piece,
+            None => return false,
+        };
+        match self {
+            // The board castles only as in standard chess, moving the king two files.
+            Variant::Chess960 => moving.1 != Rank::King || (to.0 - from.0).abs() != 2,
+            // Castling is the same as in standard chess.
+            Variant::Atomic if moving.1 == Rank::King && (to.0 - from.0).abs() == 2 => true,
+            Variant::Atomic => atomic_legal(game, from, to),
+            _ => true,
+        }
+    }
+
+    /// Moves of the player in turn that the board does not know, as pairs of
+    /// the squares moved from and to: in Horde, pawns on the first rank may
+    /// step two squares ahead like those on the second, and in Atomic, moves
+    /// that standard chess forbids for leaving the king in check.
+    pub fn extra_moves(self, game: &Game) -> Vec<(Coordinate, Coordinate)> {
+        match self {
+            Variant::Horde if game.player == Team::White => pieces(game)
+                .into_iter()
+                .filter(|(square, team, rank)| *team == Team::White && *rank == Rank::Pawn && square.1 == 0)
+                .map(|(square, _, _)| square)
+                .filter(|square| (1..=2).all(|rank| piece_at(game, (square.0, rank)).is_none()))
+                .map(|square| (square, (square.0, 2)))
+                .collect(),
+            Variant::Atomic => pieces(game)
+                .into_iter()
+                .filter(|(_, team, _)| *team == game.player)
+                .flat_map(|(from, _, _)| {
+                    reachable_squares(game, from)
+                        .into_iter()
+                        .map(move |to| (from, to))
+                })
+                .filter(|(from, to)| atomic_legal(game, *from, *to) && !standard_legal(game, *from, *to))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the player in turn has any move, counting those the variant
+    /// takes away and adds. Chess960 castling is not counted.
+    pub fn has_moves(self, game: &mut Game) -> bool {
+        let moves = legal_moves(game);
+        let allowed = moves.into_iter().any(|(from, action)| {
+            let to = action.to.coordinate;
+            self.allows(game, RecordedMove { from, to, promotion: None })
+        });
+        allowed || !self.extra_moves(game).is_empty()
+    }
+
+    /// Whether the king of `team` is in check. Kings only ever touch in Atomic
+    /// chess, where that rules out check since a king cannot capture.
+    pub fn in_check(self, game: &Game, team: Team) -> bool {
+        if self == Variant::Atomic {
+            let kings = (king_position(game, team), king_position(game, opponent(team)));
+            if let (Some(king), Some(other)) = kings {
+                if touches(king, other) {
+                    return false;
+                }
+            }
+        }
+        in_check(game, team)
+    }
+
+    /// Whether the player in turn is checkmated. A side without a king, the
+    /// horde or one just blown up, cannot be. The board knows only standard
+    /// checks, so Atomic games are judged by the moves the variant allows.
+    pub fn is_checkmate(self, game: &mut Game) -> bool {
+        if king_position(game, game.player).is_none() {
+            return false;
+        }
+        match self {
+            Variant::Atomic => self.in_check(game, game.player) && !self.has_moves(game),
+            _ => game.get_game_state() == GameState::Checkmate,
+        }
+    }
+
+    /// Changes the board after `recorded` has been played on it, where
+    /// `captured` tells whether the move took a piece.
+    pub fn after_move(self, game: &mut Game, recorded: RecordedMove, captured: bool) {
+        if self == Variant::Atomic && captured {
+            explode(game, recorded.to);
+        }
+    }
+
+    /// Winner of the game in `game`, the position after the moves of
+    /// `record`, if the rules of the variant end it.
+    pub fn winner(self, record: &GameRecord, game: &Game) -> Option<Team> {
+        let teams = [Team::White, Team::Black];
+        match self {
+            Variant::KingOfTheHill => teams
+                .iter()
+                .copied()
+                .find(|team| king_position(game, *team).map_or(false, |king| HILL.contains(&king))),
+            Variant::ThreeCheck => teams
+                .iter()
+                .copied()
+                .find(|team| record.checks_given(*team, record.ply()) >= CHECKS_TO_WIN),
+            Variant::Atomic => teams
+                .iter()
+                .copied()
+                .find(|team| king_position(game, opponent(*team)).is_none()),
+            Variant::Horde => {
+                let white_left = pieces(game).iter().any(|(_, team, _)| *team == Team::White);
+                if white_left {
+                    None
+                } else {
+                    Some(Team::Black)
+                }
+            }
+            Variant::Standard | Variant::Chess960 => None,
+        }
+    }
+
+    /// How a game won by the rules of the variant was won.
+    pub fn win_description(self) -> &'static str {
+        match self {
+            Variant::KingOfTheHill => "King on the hill",
+            Variant::ThreeCheck => "Third check",
+            Variant::Horde => "The horde is wiped out",
+            Variant::Atomic => "The king exploded",
+            Variant::Standard | Variant::Chess960 => "Game won",
+        }
+    }
+}
+
+/// Whether `recorded` takes a piece, en passant included.
+pub fn is_capture(game: &Game, recorded: RecordedMove) -> bool {
+    let pawn = piece_at(game, recorded.from).map(|(_, rank)| rank) == Some(Rank::Pawn);
+    piece_at(game, recorded.to).is_some() || (pawn && recorded.to.0 != recorded.from.0)
+}
+
+/// Atomic explosion at `center`: the capturing piece goes, and so does every
+/// piece but pawns on the squares around it.
+fn explode(game: &mut Game, center: Coordinate) {
+    for square in game.matrix.iter_mut().flat_map(|column| column.iter_mut()) {
+        let blown_up = match square.piece {
+            Some(_) if square.coordinate == center => true,
+            Some(piece) => piece.rank != Rank::Pawn && touches(square.coordinate, center),
+            None => false,
+        };
+        if blown_up {
+            square.piece = None;
+        }
+    }
+}
+
+/// A copy of `game` with the piece on `from` moved to `to` and the turn
+/// passed, capturing en passant and promoting to a queen, and exploding
+/// captures if `atomic`.
+fn played(game: &Game, from: Coordinate, to: Coordinate, atomic: bool) -> Game {
+    let capture = is_capture(game, RecordedMove { from, to, promotion: None });
+    // Only a pawn taking en passant captures on an empty square.
+    let passed = Some((to.0, from.1)).filter(|_| capture && piece_at(game, to).is_none());
+    let mut after = Fen::from_game(game).setup();
+    let mut moving = None;
+    for square in after.matrix.iter_mut().flat_map(|column| column.iter_mut()) {
+        if square.coordinate == from {
+            moving = square.piece.take();
+        } else if Some(square.coordinate) == passed {
+            square.piece = None;
+        }
+    }
+    if let Some(piece) = moving.as_mut() {
+        if piece.rank == Rank::Pawn && (to.1 == 0 || to.1 == 7) {
+            piece.rank = Rank::Queen;
+        }
+    }
+    for square in after.matrix.iter_mut().flat_map(|column| column.iter_mut()) {
+        if square.coordinate == to {
+            square.piece = moving;
+        }
+    }
+    after.player = opponent(game.player);
+    if atomic && capture {
+        explode(&mut after, to);
+    }
+    after
+}
+
+/// Whether moving the piece on `from` to `to` leaves its king safe by
+/// standard rules, where touching the other king is check.
+fn standard_legal(game: &Game, from: Coordinate, to: Coordinate) -> bool {
+    let team = game.player;
+    let after = played(game, from, to, false);
+    king_position(&after, team).map_or(true, |king| !is_attacked(&after, king, opponent(team)))
+}
+
+/// Whether moving the piece on `from` to `to` is legal in Atomic chess: kings
+/// cannot capture and the own king must survive the explosion. Blowing up
+/// the other king wins whatever the position, and touching kings give no check.
+fn atomic_legal(game: &Game, from: Coordinate, to: Coordinate) -> bool {
+    let (team, rank) = match piece_at(game, from) {
+        Some(piece) => piece,
+        None => return false,
+    };
+    let capture = is_capture(game, RecordedMove { from, to, promotion: None });
+    if rank == Rank::King && capture {
+        return false;
+    }
+    let after = played(game, from, to, true);
+    match (king_position(&after, team), king_position(&after, opponent(team))) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(_), Some(_)) => !Variant::Atomic.in_check(&after, team),
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    /// Reads the names other programs use too, e.g. "Fischerandom" or "3check".
+    fn from_str(text: &str) -> Result<Variant, String> {
+        let name: String = text
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        match name.as_str() {
+            "standard" | "chess" => Ok(Variant::Standard),
+            "chess960" | "960" | "fischerandom" | "fischerrandom" => Ok(Variant::Chess960),
+            "kingofthehill" | "koth" => Ok(Variant::KingOfTheHill),
+            "threecheck" | "3check" => Ok(Variant::ThreeCheck),
+            "horde" => Ok(Variant::Horde),
+            "atomic" => Ok(Variant::Atomic),
+            _ => Err(format!("Unknown variant '{}'", text)),
+        }
+    }
+}
+
+/// Choices made on the screen starting a new game.
+pub struct VariantPicker {
+    pub variant: Variant,
+    /// Chess960 starting position.
+    pub position: u16,
+    /// Digits of a position number being typed.
+    pub typed: String,
+}
+
+impl VariantPicker {
+    pub fn new(variant: Variant, position: u16) -> VariantPicker {
+        VariantPicker {
+            variant,
+            position,
+            typed: String::new(),
+        }
+    }
+
+    /// Selects the variant above or below the selected one.
+    pub fn step_variant(&mut self, forward: bool) {
+        let index = Variant::ALL
+            .iter()
+            .position(|variant| *variant == self.variant)
+            .unwrap_or(0);
+        let count = Variant::ALL.len();
+        let index = if forward {
+            index + 1
+        } else {
+            index + count - 1
+        };
+        self.variant = Variant::ALL[index % count];
+    }
+
+    /// Moves on to the next or previous Chess960 position.
+    pub fn step_position(&mut self, forward: bool) {
+        self.typed.clear();
+        self.position = if forward {
+            (self.position + 1) % POSITION_COUNT
+        } else {
+            (self.position + POSITION_COUNT - 1) % POSITION_COUNT
+        };
+    }
+
+    pub fn random_position(&mut self) {
+        self.typed.clear();
+        self.position = chess960::random_position();
+    }
+
+    pub fn standard_position(&mut self) {
+        self.typed.clear();
+        self.position = STANDARD_POSITION;
+    }
+
+    /// Adds a digit to the position number being typed, taking the number as
+    /// soon as it names a position. A fourth digit starts a new number.
+    pub fn type_char(&mut self, c: char) {
+        if !c.is_ascii_digit() {
+            return;
+        }
+        if self.typed.len() >= 3 {
+            self.typed.clear();
+        }
+        self.typed.push(c);
+        if let Ok(number) = self.typed.parse::<u16>() {
+            if number < POSITION_COUNT {
+                self.position = number;
+            }
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.typed.pop();
+    }
+
+    /// The position the new game starts from, `None` for the standard one.
+    pub fn start_position(&self) -> Option<Fen> {
+        match self.variant {
+            Variant::Chess960 => Some(chess960::start_position(self.position)),
+            other => other.start_position(),
+        }
+    }
+}
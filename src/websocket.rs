@@ -0,0 +1,337 @@
+//! WebSocket connections (RFC 6455) carrying text messages, as used by the
+//! game server and the clients talking to it.
+//!
+//! Only what browsers need is supported: the opening handshake, text
+//! messages split over any number of frames, pings and closing. Binary
+//! messages are skipped.
+
+use crate::util;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+
+/// Appended to the key of the client to prove the server speaks WebSocket.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Longest request or response head read during the handshake.
+const MAX_HEAD_LENGTH: usize = 8192;
+/// Longest message read, larger ones close the connection.
+const MAX_MESSAGE_LENGTH: usize = 1 << 16;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+pub struct WebSocket {
+    stream: TcpStream,
+    // Frames sent by clients are masked, those sent by servers are not.
+    client: bool,
+}
+
+impl WebSocket {
+    /// Answers the opening handshake of a client that has connected to a server.
+    pub fn accept(stream: TcpStream) -> io::Result<WebSocket> {
+        let head = read_head(&stream)?;
+        let mut lines = head.lines();
+        let is_get = lines.next().map_or(false, |line| line.starts_with("GET "));
+        let key = lines.find_map(|line| header_value(line, "sec-websocket-key"));
+        let key = match key {
+            Some(key) if is_get => key,
+            _ => {
+                let _ =
+                    (&stream).write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+                return Err(invalid("not a WebSocket handshake"));
+            }
+        };
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        );
+        (&stream).write_all(response.as_bytes())?;
+        stream.set_nodelay(true)?;
+        Ok(WebSocket {
+            stream,
+            client: false,
+        })
+    }
+
+    /// Connects to the WebSocket server at `address`, e.g. "localhost:7878".
+    pub fn connect(address: &str) -> io::Result<WebSocket> {
+        let stream = TcpStream::connect(address)?;
+        let mut nonce = [0; 16];
+        nonce[..8].copy_from_slice(&util::random().to_be_bytes());
+        nonce[8..].copy_from_slice(&util::random().to_be_bytes());
+        let key = base64(&nonce);
+        let request = format!(
+            "GET / HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\n\
+             Sec-WebSocket-Version: 13\r\n\r\n",
+            address, key
+        );
+        (&stream).write_all(request.as_bytes())?;
+        let head = read_head(&stream)?;
+        let mut lines = head.lines();
+        let switching = lines.next().map_or(false, |line| line.contains(" 101 "));
+        let accepted = lines.find_map(|line| header_value(line, "sec-websocket-accept"));
+        if !switching || accepted != Some(accept_key(&key).as_str()) {
+            return Err(invalid("the server refused the WebSocket handshake"));
+        }
+        stream.set_nodelay(true)?;
+        Ok(WebSocket {
+            stream,
+            client: true,
+        })
+    }
+
+    /// Another handle to the same connection, e.g. to read from on another thread.
+    pub fn try_clone(&self) -> io::Result<WebSocket> {
+        Ok(WebSocket {
+            stream: self.stream.try_clone()?,
+            client: self.client,
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    /// Closes the connection without a goodbye, waking up any reader of it.
+    pub fn shutdown(&self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+
+    pub fn send_text(&mut self, text: &str) -> io::Result<()> {
+        self.send_frame(TEXT, text.as_bytes())
+    }
+
+    /// Says goodbye to the other side, which answers by closing the connection.
+    pub fn close(&mut self) -> io::Result<()> {
+        self.send_frame(CLOSE, &1000u16.to_be_bytes())
+    }
+
+    /// Waits for the next text message, answering pings on the way. Returns
+    /// `None` once the other side has closed the connection.
+    pub fn read_text(&mut self) -> io::Result<Option<String>> {
+        let mut message = Vec::new();
+        // Opcode of the message being read, continued by frames without one.
+        let mut opcode = None;
+        loop {
+            let (fin, frame_opcode, payload) = self.read_frame()?;
+            match frame_opcode {
+                CLOSE => {
+                    let _ = self.send_frame(CLOSE, &payload[..payload.len().min(2)]);
+                    return Ok(None);
+                }
+                PING => {
+                    self.send_frame(PONG, &payload)?;
+                    continue;
+                }
+                CONTINUATION if opcode.is_some() => {}
+                CONTINUATION => return Err(invalid("continuation frame without a message")),
+                PONG => continue,
+                code => opcode = Some(code),
+            }
+            if message.len() + payload.len() > MAX_MESSAGE_LENGTH {
+                return Err(invalid("message too long"));
+            }
+            message.extend_from_slice(&payload);
+            if !fin {
+                continue;
+            }
+            if opcode == Some(TEXT) {
+                return String::from_utf8(message)
+                    .map(Some)
+                    .map_err(|_| invalid("text message is not UTF-8"));
+            }
+            message.clear();
+            opcode = None;
+        }
+    }
+
+    fn read_frame(&mut self) -> io::Result<(bool, u8, Vec<u8>)> {
+        let mut head = [0; 2];
+        self.stream.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        let masked = head[1] & 0x80 != 0;
+        // Clients have to mask every frame, a server closes on one that is not.
+        if !self.client && !masked {
+            let _ = self.send_frame(CLOSE, &1002u16.to_be_bytes());
+            return Err(invalid("unmasked frame from a client"));
+        }
+        let length = match head[1] & 0x7F {
+            126 => {
+                let mut length = [0; 2];
+                self.stream.read_exact(&mut length)?;
+                u64::from(u16::from_be_bytes(length))
+            }
+            127 => {
+                let mut length = [0; 8];
+                self.stream.read_exact(&mut length)?;
+                u64::from_be_bytes(length)
+            }
+            length => u64::from(length),
+        };
+        if length > MAX_MESSAGE_LENGTH as u64 {
+            return Err(invalid("frame too long"));
+        }
+        let mut mask = [0; 4];
+        if masked {
+            self.stream.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; length as usize];
+        self.stream.read_exact(&mut payload)?;
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[index % 4];
+        }
+        Ok((fin, opcode, payload))
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        let mask_bit = if self.client { 0x80 } else { 0 };
+        match payload.len() {
+            length if length < 126 => frame.push(mask_bit | length as u8),
+            length if length <= 0xFFFF => {
+                frame.push(mask_bit | 126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(mask_bit | 127);
+                frame.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        if self.client {
+            let mask = (util::random() as u32).to_be_bytes();
+            frame.extend_from_slice(&mask);
+            frame.extend(
+                payload
+                    .iter()
+                    .enumerate()
+                    .map(|(index, byte)| byte ^ mask[index % 4]),
+            );
+        } else {
+            frame.extend_from_slice(payload);
+        }
+        self.stream.write_all(&frame)
+    }
+}
+
+/// The `Sec-WebSocket-Accept` value answering the `Sec-WebSocket-Key` `key`.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// Reads an HTTP head up to the empty line ending it, a byte at a time so
+/// that no frame after it is read along.
+fn read_head(mut stream: &TcpStream) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD_LENGTH {
+            return Err(invalid("handshake too long"));
+        }
+        stream.read_exact(&mut byte)?;
+        head.push(byte[0]);
+    }
+    String::from_utf8(head).map_err(|_| invalid("handshake is not UTF-8"))
+}
+
+/// The value of `line` if it is the header `name`, which is matched ignoring case.
+fn header_value<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let mut parts = line.splitn(2, ':');
+    let key = parts.next()?.trim();
+    let value = parts.next()?.trim();
+    if key.eq_ignore_ascii_case(name) {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (index, word) in block.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            words[index] =
+                (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16])
+                    .rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, added) in state.iter_mut().zip(&[a, b, c, d, e]) {
+            *value = value.wrapping_add(*added);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(&state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,108 @@
+//! Scores turned to white's point of view and shown.
+
+mod common;
+
+use chess::game::Team;
+use oskhen_chess_gui::analysis::{format_nodes, format_score, white_score, white_share};
+use oskhen_chess_gui::engine::Score;
+
+#[test]
+fn turns_scores_to_whites_point_of_view() {
+    assert_eq!(
+        white_score(Score::Centipawns(35), Team::White),
+        Score::Centipawns(35)
+    );
+    assert_eq!(
+        white_score(Score::Centipawns(35), Team::Black),
+        Score::Centipawns(-35)
+    );
+    assert_eq!(white_score(Score::Mate(2), Team::Black), Score::Mate(-2));
+    assert_eq!(white_score(Score::Mate(-2), Team::Black), Score::Mate(2));
+}
+
+#[test]
+fn shows_the_mated_side_as_lost() {
+    // Black to move and mated: white has won.
+    let black_mated = white_score(Score::Mated, Team::Black);
+    assert_eq!(format_score(black_mated), "+M0");
+    assert_eq!(white_share(black_mated), 1.0);
+    // White to move and mated.
+    let white_mated = white_score(Score::Mated, Team::White);
+    assert_eq!(format_score(white_mated), "-M0");
+    assert_eq!(white_share(white_mated), 0.0);
+}
+
+#[test]
+fn formats_scores() {
+    assert_eq!(format_score(Score::Centipawns(35)), "+0.35");
+    assert_eq!(format_score(Score::Centipawns(-120)), "-1.20");
+    assert_eq!(format_score(Score::Mate(3)), "+M3");
+    assert_eq!(format_score(Score::Mate(-3)), "-M3");
+    assert_eq!(white_share(Score::Centipawns(0)), 0.5);
+    assert!(white_share(Score::Centipawns(400)) > 0.9);
+    assert_eq!(white_share(Score::Mate(-3)), 0.0);
+}
+
+#[test]
+fn formats_node_counts() {
+    assert_eq!(format_nodes(850), "850");
+    assert_eq!(format_nodes(35_000), "35k");
+    assert_eq!(format_nodes(1_200_000), "1.2M");
+}
+
+/// Variations of a scripted stand-in for an engine, shown in SAN.
+#[cfg(unix)]
+mod variations {
+    use super::*;
+    use common::{fake_engine, TempDir};
+    use oskhen_chess_gui::analysis::Analyzer;
+    use oskhen_chess_gui::record::GameRecord;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Answers 1... e5 2. Bc4 and a second later, one ply deeper, 1... e5 2. Nf3.
+    const SCRIPT: &str = "    case \"$line\" in
+        'go infinite') echo 'info depth 1 multipv 1 score cp -20 pv e7e5 f1c4'
+            sleep 1
+            echo 'info depth 2 multipv 1 score cp -25 pv e7e5 g1f3' ;;
+    esac";
+
+    /// Polls `analyzer` with `record` until it has reached `depth`, failing after five seconds.
+    fn wait_for_depth(analyzer: &mut Analyzer, record: &GameRecord, depth: u32) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            analyzer.poll(record);
+            if analyzer.depth >= depth && !analyzer.lines.is_empty() {
+                return;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "the engine did not reach depth {}",
+                depth
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn translates_a_variation_again_only_once_it_changes() {
+        let dir = TempDir::new("variations");
+        let mut analyzer = Analyzer::start(&fake_engine(&dir, SCRIPT)).unwrap();
+        let (record, _) = GameRecord::from_pgn("1. e4 *").unwrap();
+        analyzer.analyze(&record, 1, Team::Black).unwrap();
+        wait_for_depth(&mut analyzer, &record, 1);
+        assert_eq!(analyzer.lines[0].san, ["e5", "Bc4"]);
+        assert_eq!(analyzer.best_move(1), Some("e7e5"));
+
+        // The same line is not played through again: after 1. d4 the bishop
+        // could not reach c4.
+        let (other, _) = GameRecord::from_pgn("1. d4 *").unwrap();
+        analyzer.poll(&other);
+        assert_eq!(analyzer.depth, 1);
+        assert_eq!(analyzer.lines[0].san, ["e5", "Bc4"]);
+
+        wait_for_depth(&mut analyzer, &record, 2);
+        assert_eq!(analyzer.lines[0].san, ["e5", "Nf3"]);
+        assert_eq!(analyzer.score(), Some(Score::Centipawns(25)));
+    }
+}
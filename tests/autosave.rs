@@ -0,0 +1,119 @@
+//! Saving the unfinished game and picking it up again.
+
+mod common;
+
+use chess::game::Team;
+use common::TempDir;
+use oskhen_chess_gui::autosave::{Autosave, SavedOpponent};
+use oskhen_chess_gui::clock::Clock;
+use oskhen_chess_gui::opponent::Opponent;
+use oskhen_chess_gui::record::GameRecord;
+use std::fs;
+use std::time::Duration;
+
+const PGN: &str = "1. e4 e5 2. Nf3 {Developing} Nc6 *";
+
+/// A five minute clock with some time used by both sides.
+fn clock() -> Clock {
+    let mut clock = Clock::new("5+3".parse().unwrap());
+    clock.set_remaining(Team::White, Duration::from_millis(281_250));
+    clock.set_remaining(Team::Black, Duration::from_millis(290_500));
+    clock
+}
+
+#[test]
+fn picks_up_the_game_where_it_was_saved() {
+    let dir = TempDir::new("autosave-round-trip");
+    let path = dir.0.join("autosave.json");
+    let (record, _) = GameRecord::from_pgn(PGN).unwrap();
+    let saved = Autosave::new(&record, Some(&clock()), &Opponent::HotSeat).unwrap();
+    saved.save_to(&path).unwrap();
+
+    let loaded = Autosave::load_from(&path).unwrap();
+    assert_eq!(loaded, saved);
+    assert_eq!(loaded.opponent, SavedOpponent::HotSeat);
+    let (restored, _) = GameRecord::from_pgn(&loaded.pgn).unwrap();
+    assert_eq!(restored.to_pgn(), record.to_pgn());
+    let restored = loaded.restore_clock().unwrap().unwrap();
+    assert_eq!(restored.control, clock().control);
+    assert_eq!(restored.remaining_millis(), [281_250, 290_500]);
+    assert!(restored.running().is_none());
+}
+
+#[test]
+fn saves_without_a_clock() {
+    let dir = TempDir::new("autosave-no-clock");
+    let path = dir.0.join("autosave.json");
+    let saved = Autosave::new(&GameRecord::new(), None, &Opponent::HotSeat).unwrap();
+    saved.save_to(&path).unwrap();
+    let loaded = Autosave::load_from(&path).unwrap();
+    assert!(loaded.restore_clock().unwrap().is_none());
+}
+
+#[test]
+fn replaces_the_file_in_one_step() {
+    let dir = TempDir::new("autosave-replace");
+    let path = dir.0.join("saves").join("autosave.json");
+    let (record, _) = GameRecord::from_pgn(PGN).unwrap();
+    let first = Autosave::new(&GameRecord::new(), None, &Opponent::HotSeat).unwrap();
+    let second = Autosave::new(&record, Some(&clock()), &Opponent::HotSeat).unwrap();
+    first.save_to(&path).unwrap();
+    second.save_to(&path).unwrap();
+
+    // Only the finished file is left, with nothing of the temporary one.
+    let files: Vec<_> = fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(files, vec!["autosave.json"]);
+    assert_eq!(Autosave::load_from(&path), Some(second));
+}
+
+#[test]
+fn ignores_a_damaged_recovery_file() {
+    let dir = TempDir::new("autosave-damaged");
+    let path = dir.0.join("autosave.json");
+    assert!(Autosave::load_from(&path).is_none());
+
+    let saved = Autosave::new(&GameRecord::new(), None, &Opponent::HotSeat).unwrap();
+    saved.save_to(&path).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    fs::write(&path, &text[..text.len() / 2]).unwrap();
+    assert!(Autosave::load_from(&path).is_none());
+    fs::write(&path, "not json at all").unwrap();
+    assert!(Autosave::load_from(&path).is_none());
+}
+
+/// Games against an engine, started again from its path.
+#[cfg(unix)]
+mod engine {
+    use super::*;
+    use common::fake_engine;
+    use oskhen_chess_gui::engine::Engine;
+
+    #[test]
+    fn remembers_the_engine_and_its_color() {
+        let dir = TempDir::new("autosave-engine");
+        let path = dir.0.join("autosave.json");
+        let engine_path = fake_engine(&dir, "");
+        let opponent = Opponent::Engine {
+            engine: Engine::start(&engine_path).unwrap(),
+            color: Team::Black,
+            thinking: false,
+        };
+        let (record, _) = GameRecord::from_pgn(PGN).unwrap();
+        Autosave::new(&record, None, &opponent)
+            .unwrap()
+            .save_to(&path)
+            .unwrap();
+
+        let loaded = Autosave::load_from(&path).unwrap();
+        assert_eq!(
+            loaded.opponent,
+            SavedOpponent::Engine {
+                path: engine_path,
+                color: "black".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Polyglot keys and books.
+
+mod common;
+
+use common::TempDir;
+use oskhen_chess_gui::book::{decode_move, Book, BookMove, PolyglotKeys};
+use oskhen_chess_gui::fen::Fen;
+use std::fs;
+
+/// The positions and keys given in the description of the Polyglot format.
+const DOCUMENTED_KEYS: [(&str, u64); 9] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        0x463b96181691fc9c,
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        0x823c9b50fd114196,
+    ),
+    (
+        "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        0x0756b94461c50fb0,
+    ),
+    (
+        "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+        0x662fafb965db29d4,
+    ),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        0x22a48b5a8e47ff78,
+    ),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+        0x652a607ca3f242c1,
+    ),
+    (
+        "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+        0x00fdd303c946bdd9,
+    ),
+    (
+        "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+        0x3c8123ea7b067637,
+    ),
+    (
+        "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+        0x5c3f9b829b279560,
+    ),
+];
+
+fn fen(text: &str) -> Fen {
+    text.parse().unwrap()
+}
+
+/// A Polyglot move from `from` to `to`, squares numbered a1 = 0 to h8 = 63.
+fn raw_move(from: u16, to: u16, promotion: u16) -> u16 {
+    promotion << 12 | from << 6 | to
+}
+
+#[test]
+fn gives_the_documented_keys() {
+    let keys = PolyglotKeys::bundled();
+    for (position, key) in DOCUMENTED_KEYS.iter() {
+        assert_eq!(keys.key(&fen(position)), *key, "{}", position);
+    }
+}
+
+#[test]
+fn reads_castling_rights_written_as_files() {
+    let keys = PolyglotKeys::bundled();
+    let standard = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let shredder = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1";
+    assert_eq!(keys.key(&fen(shredder)), keys.key(&fen(standard)));
+    // Only the rooks still there count.
+    let rookless = "1nbqkbn1/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBN1 w KQkq - 0 1";
+    let no_rights = "1nbqkbn1/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBN1 w - - 0 1";
+    assert_eq!(keys.key(&fen(rookless)), keys.key(&fen(no_rights)));
+}
+
+#[test]
+fn decodes_castling_for_standard_chess_and_chess960() {
+    let position = fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+    let short = raw_move(4, 7, 0);
+    let long = raw_move(4, 0, 0);
+    assert_eq!(decode_move(short, &position, false).unwrap(), "e1g1");
+    assert_eq!(decode_move(long, &position, false).unwrap(), "e1c1");
+    assert_eq!(decode_move(short, &position, true).unwrap(), "e1h1");
+    assert_eq!(decode_move(long, &position, true).unwrap(), "e1a1");
+    // A king stepping next to its rook is not castling.
+    let position = fen("4k3/8/8/8/8/8/8/5RK1 w - - 0 1");
+    assert_eq!(
+        decode_move(raw_move(6, 7, 0), &position, true).unwrap(),
+        "g1h1"
+    );
+}
+
+#[test]
+fn decodes_promotions() {
+    let position = fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+    let promote = |piece| decode_move(raw_move(48, 56, piece), &position, false);
+    assert_eq!(promote(0).unwrap(), "a7a8");
+    assert_eq!(promote(1).unwrap(), "a7a8n");
+    assert_eq!(promote(4).unwrap(), "a7a8q");
+    assert!(promote(5).is_none());
+}
+
+#[test]
+fn looks_up_the_moves_of_a_position() {
+    let dir = TempDir::new("book");
+    let start = DOCUMENTED_KEYS[0].1;
+    let after_e4 = DOCUMENTED_KEYS[1].1;
+    let mut bytes = Vec::new();
+    // Sorted by key, as in every Polyglot book.
+    let mut entries = [
+        (start, raw_move(12, 28, 0), 10),
+        (start, raw_move(11, 27, 0), 30),
+        (after_e4, raw_move(50, 34, 0), 5),
+    ];
+    entries.sort_by_key(|entry| entry.0);
+    for (key, raw, weight) in entries.iter() {
+        bytes.extend_from_slice(&key.to_be_bytes());
+        bytes.extend_from_slice(&raw.to_be_bytes());
+        bytes.extend_from_slice(&(*weight as u16).to_be_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+    }
+    let path = dir.0.join("book.bin");
+    fs::write(&path, &bytes).unwrap();
+
+    let mut book = Book::open(&path).unwrap();
+    let moves = book.moves(&fen(DOCUMENTED_KEYS[0].0), false).unwrap();
+    let expected = [
+        BookMove {
+            uci: "d2d4".to_string(),
+            weight: 30,
+        },
+        BookMove {
+            uci: "e2e4".to_string(),
+            weight: 10,
+        },
+    ];
+    assert_eq!(moves, expected);
+    let moves = book.moves(&fen(DOCUMENTED_KEYS[1].0), false).unwrap();
+    assert_eq!(moves.len(), 1);
+    assert_eq!(moves[0].uci, "c7c5");
+    assert!(book
+        .moves(&fen(DOCUMENTED_KEYS[2].0), false)
+        .unwrap()
+        .is_empty());
+
+    fs::write(&path, &bytes[..20]).unwrap();
+    assert!(Book::open(&path).is_err());
+}
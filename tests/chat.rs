@@ -0,0 +1,152 @@
+//! The chat of network games, kept with the game or in a log of its own.
+
+mod common;
+
+use common::TempDir;
+use oskhen_chess_gui::chat::{clean, Chat, SPECTATOR};
+use oskhen_chess_gui::network::{Connection, Host, Message, NetworkEvent, Reconnection};
+use oskhen_chess_gui::record::GameRecord;
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest wait for a message before a test fails.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn keeps_lines_in_the_comment_of_the_position() {
+    let (mut chat, mut record) = (Chat::new(None), GameRecord::new());
+    chat.add("white", "good luck", true, &mut record).unwrap();
+    chat.add(SPECTATOR, "have fun", false, &mut record).unwrap();
+    chat.add("black", "  ", false, &mut record).unwrap();
+    assert_eq!(
+        record.current_notes().comment,
+        "White: good luck Spectator: have fun"
+    );
+    assert_eq!(chat.lines.len(), 2);
+    assert!(chat.lines[0].own && !chat.lines[1].own);
+}
+
+#[test]
+fn keeps_lines_in_the_log_when_given() {
+    let dir = TempDir::new("chat-log");
+    let log = dir.0.join("chat.log");
+    let (mut chat, mut record) = (Chat::new(Some(log.clone())), GameRecord::new());
+    chat.add("white", "good luck", true, &mut record).unwrap();
+    chat.add("black", "you too", false, &mut record).unwrap();
+    assert!(record.current_notes().comment.is_empty());
+    let written = fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = written.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with('[') && lines[0].ends_with("] White: good luck"));
+    assert!(lines[1].ends_with("] Black: you too"));
+}
+
+#[test]
+fn cleans_lines_to_fit_in_a_comment() {
+    assert_eq!(clean("{nice} move"), "(nice) move");
+    assert_eq!(clean(" one\nline\t\u{7} "), "oneline");
+    assert_eq!(clean(&"a".repeat(250)).chars().count(), 200);
+    assert_eq!(clean(&"é".repeat(250)).chars().count(), 200);
+}
+
+#[test]
+fn mutes_others_but_never_ones_own_lines() {
+    let (mut chat, mut record) = (Chat::new(None), GameRecord::new());
+    chat.add("white", "mine", true, &mut record).unwrap();
+    chat.add("black", "theirs", false, &mut record).unwrap();
+    chat.add(SPECTATOR, "watching", false, &mut record).unwrap();
+    let visible =
+        |chat: &Chat| -> Vec<String> { chat.visible().map(|line| line.text.clone()).collect() };
+    assert_eq!(visible(&chat), vec!["mine", "theirs", "watching"]);
+
+    chat.mute_players = true;
+    assert!(chat.is_muted(&chat.lines[1]) && !chat.is_muted(&chat.lines[0]));
+    assert_eq!(visible(&chat), vec!["mine", "watching"]);
+
+    chat.mute_players = false;
+    chat.mute_spectators = true;
+    assert!(chat.is_muted(&chat.lines[2]));
+    assert_eq!(visible(&chat), vec!["mine", "theirs"]);
+}
+
+/// The next message of `connection`, failing after `TIMEOUT`.
+fn wait_for_message(connection: &mut Connection) -> Message {
+    let deadline = Instant::now() + TIMEOUT;
+    while Instant::now() < deadline {
+        match connection.poll() {
+            Some(NetworkEvent::Message(message)) => return message,
+            Some(_) => panic!("the connection dropped"),
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    panic!("no message arrived");
+}
+
+#[test]
+fn passes_on_what_a_spectator_writes_to_the_player() {
+    let reconnection = Reconnection {
+        session: "0123456789abcdef".to_string(),
+        timeout: 60,
+        pause_clock: true,
+    };
+    let mut host = Host::bind(0, 2, reconnection).unwrap();
+    let address = format!("127.0.0.1:{}", host.port());
+    let mut joined = Connection::join(&address).unwrap();
+    let mut writer = Connection::watch(&address).unwrap();
+    let mut reader = Connection::watch(&address).unwrap();
+    let deadline = Instant::now() + TIMEOUT;
+    let stream = loop {
+        if let Some(stream) = host.player() {
+            break stream;
+        }
+        assert!(Instant::now() < deadline, "no player joined");
+        thread::sleep(Duration::from_millis(10));
+    };
+    let (mut connection, mut spectators) = host.start(stream).unwrap();
+    let mut admitted = 0;
+    while admitted < 2 {
+        assert!(Instant::now() < deadline, "the spectators were not kept");
+        if spectators.poll() {
+            admitted += spectators.admit(&[Message::Watch]);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(wait_for_message(&mut writer), Message::Watch);
+    assert_eq!(wait_for_message(&mut reader), Message::Watch);
+
+    let said = Message::Chat {
+        from: SPECTATOR.to_string(),
+        text: "{nice}\n move".to_string(),
+    };
+    writer.send(&said).unwrap();
+    let (id, text) = loop {
+        if let Some(line) = spectators.chat().pop() {
+            break line;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "the spectator's line did not arrive"
+        );
+        thread::sleep(Duration::from_millis(10));
+    };
+    let relayed = Message::Chat {
+        from: SPECTATOR.to_string(),
+        text: clean(&text),
+    };
+    connection.send(&relayed).unwrap();
+    spectators.relay(id, &relayed);
+    spectators.broadcast(&Message::Resign);
+
+    assert_eq!(wait_for_message(&mut joined), relayed);
+    assert_eq!(wait_for_message(&mut reader), relayed);
+    // The spectator who wrote the line is not sent it back.
+    assert_eq!(wait_for_message(&mut writer), Message::Resign);
+
+    let mut record = GameRecord::new();
+    let mut chat = Chat::new(None);
+    if let Message::Chat { from, text } = relayed {
+        chat.add(&from, &text, false, &mut record).unwrap();
+    }
+    assert_eq!(record.current_notes().comment, "Spectator: (nice) move");
+}
@@ -0,0 +1,105 @@
+//! Chess960 starting positions and castling rights in X-FEN and Shredder-FEN.
+
+use chess::game::Rank;
+use oskhen_chess_gui::chess960::{
+    back_rank, castling_field, castling_rooks, position_number, start_position, POSITION_COUNT,
+    STANDARD_POSITION,
+};
+use oskhen_chess_gui::fen::{Fen, START_FEN};
+use std::collections::HashSet;
+
+fn fen(text: &str) -> Fen {
+    text.parse().unwrap()
+}
+
+fn letters(number: u16) -> String {
+    back_rank(number)
+        .iter()
+        .map(|piece| match piece {
+            Rank::King => 'K',
+            Rank::Queen => 'Q',
+            Rank::Rook => 'R',
+            Rank::Bishop => 'B',
+            Rank::Knight => 'N',
+            Rank::Pawn => 'P',
+        })
+        .collect()
+}
+
+/// Castling field of `text` written back in X-FEN and in Shredder-FEN.
+fn rewritten(text: &str) -> (String, String) {
+    let position = fen(text);
+    let rooks = castling_rooks(&position);
+    (
+        castling_field(&position.placement, &rooks, false),
+        castling_field(&position.placement, &rooks, true),
+    )
+}
+
+#[test]
+fn numbers_the_standard_position_518() {
+    assert_eq!(STANDARD_POSITION, 518);
+    assert_eq!(start_position(STANDARD_POSITION).to_string(), START_FEN);
+    assert_eq!(position_number(&fen(START_FEN)), Some(STANDARD_POSITION));
+}
+
+#[test]
+fn numbers_positions_as_scharnagl() {
+    assert_eq!(letters(0), "BBQNNRKR");
+    assert_eq!(letters(1), "BQNBNRKR");
+    assert_eq!(letters(959), "RKRNNQBB");
+}
+
+#[test]
+fn gives_960_different_valid_positions() {
+    let mut seen = HashSet::new();
+    for number in 0..POSITION_COUNT {
+        let rank = letters(number);
+        let bishops: Vec<usize> = rank.match_indices('B').map(|(file, _)| file).collect();
+        assert_ne!(bishops[0] % 2, bishops[1] % 2, "{}", rank);
+        let king = rank.find('K').unwrap();
+        assert!(rank.find('R').unwrap() < king && king < rank.rfind('R').unwrap());
+        seen.insert(rank);
+    }
+    assert_eq!(seen.len(), POSITION_COUNT as usize);
+    for number in [0, 1, 517, 959].iter() {
+        assert_eq!(position_number(&start_position(*number)), Some(*number));
+    }
+}
+
+#[test]
+fn round_trips_castling_rights() {
+    let standard = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w";
+    for field in ["KQkq", "HAha"].iter() {
+        let text = format!("{} {} - 0 1", standard, field);
+        assert_eq!(rewritten(&text), ("KQkq".to_string(), "HAha".to_string()));
+    }
+    let first = "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w";
+    for field in ["KQkq", "HFhf"].iter() {
+        let text = format!("{} {} - 0 1", first, field);
+        assert_eq!(rewritten(&text), ("KQkq".to_string(), "HFhf".to_string()));
+    }
+    let partial = format!("{} Kq - 0 1", first);
+    assert_eq!(rewritten(&partial), ("Kq".to_string(), "Hf".to_string()));
+}
+
+#[test]
+fn names_the_file_of_an_inner_rook_in_x_fen() {
+    // With two rooks on the queenside, "Q" is the outer one and the inner
+    // one has to be named by its file.
+    let position = "4k3/8/8/8/8/8/8/RR2K3 w";
+    let inner = format!("{} B - 0 1", position);
+    assert_eq!(castling_rooks(&fen(&inner)), [(1, 0)]);
+    assert_eq!(rewritten(&inner), ("B".to_string(), "B".to_string()));
+    let outer = format!("{} Q - 0 1", position);
+    assert_eq!(castling_rooks(&fen(&outer)), [(0, 0)]);
+    assert_eq!(rewritten(&outer), ("Q".to_string(), "A".to_string()));
+}
+
+#[test]
+fn drops_rights_without_a_rook() {
+    let text = "4k3/8/8/8/8/8/8/R3K3 w KQk - 0 1";
+    assert_eq!(castling_rooks(&fen(text)), [(0, 0)]);
+    assert_eq!(rewritten(text), ("Q".to_string(), "A".to_string()));
+    assert_eq!(rewritten("4k3/8/8/8/8/8/8/4K3 w - - 0 1").0, "-");
+}
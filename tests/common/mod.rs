@@ -0,0 +1,51 @@
+//! Helpers shared by the tests.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// A directory of its own for a test, removed when dropped.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let dir = env::temp_dir().join(format!("oskhen-chess-gui-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes `script`, a shell script standing in for a UCI engine, to `dir`
+/// and returns its path. It is started with every command of the GUI as a
+/// line on its input; `uci` and `isready` are answered before it is run.
+#[cfg(unix)]
+pub fn fake_engine(dir: &TempDir, script: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let path = dir.0.join("engine");
+    let script = format!(
+        "#!/bin/sh
+while read -r line; do
+    case \"$line\" in
+        uci) echo 'id name Fake'; echo 'uciok' ;;
+        isready) echo 'readyok' ;;
+        quit) exit 0 ;;
+    esac
+{}
+done
+",
+        script
+    );
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
@@ -0,0 +1,131 @@
+//! The archive of finished games, its index and searches in it.
+
+mod common;
+
+use common::TempDir;
+use oskhen_chess_gui::database::{split_games, Database, GameEntry, Query};
+use std::fs;
+
+const ARCHIVE: &str = "[White \"Anna\"]
+[Black \"Bo\"]
+[Result \"1-0\"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+
+[White \"Cleo\"]
+[Black \"Dan\"]
+
+1. e5 *
+
+[White \"Bo\"]
+[Black \"Cleo\"]
+[Result \"1/2-1/2\"]
+
+1. d4 d5 1/2-1/2
+";
+
+fn entry(white: &str, black: &str, result: &str, opening: &str) -> GameEntry {
+    GameEntry {
+        offset: 0,
+        length: 0,
+        date: "2020.10.05".to_string(),
+        white: white.to_string(),
+        black: black.to_string(),
+        result: result.to_string(),
+        opening: opening.to_string(),
+        positions: vec![
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR".to_string(),
+        ],
+        skipped: false,
+    }
+}
+
+#[test]
+fn splits_an_archive_into_games() {
+    let games = split_games(ARCHIVE);
+    assert_eq!(games.len(), 3);
+    for (offset, pgn) in games.iter() {
+        assert_eq!(&ARCHIVE[*offset..*offset + pgn.len()], *pgn);
+        assert!(pgn.starts_with("[White"));
+    }
+    assert!(games[0].1.ends_with("1-0\n\n"));
+    assert!(games[1].1.contains("1. e5 *"));
+    assert_eq!(games[2].0 + games[2].1.len(), ARCHIVE.len());
+}
+
+#[test]
+fn splits_games_with_comments_on_their_own_lines() {
+    let archive = "[White \"Anna\"]\n\n{Opening}\n1. e4 *\n[White \"Bo\"]\n\n1. d4 *\n";
+    let games = split_games(archive);
+    assert_eq!(games.len(), 2);
+    assert_eq!(games[1].1, "[White \"Bo\"]\n\n1. d4 *\n");
+    assert!(split_games("").is_empty());
+}
+
+#[test]
+fn matches_every_field_ignoring_case() {
+    let game = entry("Anna", "Bo", "1-0", "C20 King's Pawn Game");
+    assert!(Query::default().matches(&game));
+    let by_player = |player: &str| Query {
+        player: player.to_string(),
+        ..Query::default()
+    };
+    assert!(by_player("anna").matches(&game));
+    assert!(by_player(" BO ").matches(&game));
+    assert!(!by_player("Cleo").matches(&game));
+    let query = Query {
+        date: "2020.10".to_string(),
+        result: "1-0".to_string(),
+        opening: "king's pawn".to_string(),
+        ..Query::default()
+    };
+    assert!(query.matches(&game));
+    let query = Query {
+        result: "0-1".to_string(),
+        ..query
+    };
+    assert!(!query.matches(&game));
+}
+
+#[test]
+fn matches_part_of_a_position() {
+    let game = entry("Anna", "Bo", "1-0", "");
+    let by_position = |position: &str| Query {
+        position: position.to_string(),
+        ..Query::default()
+    };
+    assert!(by_position("4P3/8/PPPP1PPP").matches(&game));
+    assert!(!by_position("3P4/8/PPP1PPPP").matches(&game));
+}
+
+#[test]
+fn never_matches_skipped_games() {
+    let mut game = entry("Anna", "Bo", "1-0", "");
+    game.skipped = true;
+    assert!(!Query::default().matches(&game));
+}
+
+#[test]
+fn indexes_unreadable_games_as_skipped() {
+    let dir = TempDir::new("skipped");
+    fs::write(dir.0.join("games.pgn"), ARCHIVE).unwrap();
+    let database = Database::open(dir.0.clone()).unwrap();
+    let skipped: Vec<bool> = database.entries.iter().map(|entry| entry.skipped).collect();
+    assert_eq!(skipped, [false, true, false]);
+    let results = database.search(&Query::default());
+    assert_eq!(results, [2, 0]);
+    assert_eq!(database.entries[2].result, "1/2-1/2");
+    assert_eq!(
+        database.pgn(&database.entries[2]).unwrap(),
+        split_games(ARCHIVE)[2].1
+    );
+
+    // The index covers the whole archive, so it is read rather than rebuilt:
+    // a change made to it is still there when the database is opened again.
+    let index = dir.0.join("games.index");
+    let text = fs::read_to_string(&index).unwrap();
+    fs::write(&index, text.replace("Anna", "Annie")).unwrap();
+    let database = Database::open(dir.0.clone()).unwrap();
+    assert_eq!(database.entries[0].white, "Annie");
+}
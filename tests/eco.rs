@@ -0,0 +1,32 @@
+//! Naming openings from the ECO table.
+
+use oskhen_chess_gui::eco::EcoTable;
+use oskhen_chess_gui::record::GameRecord;
+
+#[test]
+fn names_a_transposition_after_the_position() {
+    let table = EcoTable::bundled();
+    let (knight_first, _) = GameRecord::from_pgn("1. Nf3 d5 2. d4 *").unwrap();
+    let (pawn_first, _) = GameRecord::from_pgn("1. d4 d5 2. Nf3 *").unwrap();
+    let opening = table.classify(&knight_first, 3).unwrap();
+    assert_eq!(opening.code, "D02");
+    assert_eq!(opening.name, "Queen's Pawn Game: Zukertort Variation");
+    assert_eq!(table.classify(&pawn_first, 3), Some(opening));
+    // Before the transposition the game is still a Zukertort Opening.
+    assert_eq!(table.classify(&knight_first, 2).unwrap().code, "A06");
+}
+
+#[test]
+fn writes_the_opening_in_the_tags() {
+    let table = EcoTable::bundled();
+    let (mut record, _) = GameRecord::from_pgn("1. Nf3 d5 2. d4 *").unwrap();
+    table.tag(&mut record);
+    assert_eq!(record.header("ECO"), Some("D02"));
+    assert_eq!(
+        record.header("Opening"),
+        Some("Queen's Pawn Game: Zukertort Variation")
+    );
+    let pgn = record.to_pgn();
+    assert!(pgn.contains("[ECO \"D02\"]"));
+    assert!(pgn.contains("[Opening \"Queen's Pawn Game: Zukertort Variation\"]"));
+}
@@ -0,0 +1,79 @@
+//! Checking positions set up by hand before a game starts from them.
+
+use oskhen_chess_gui::editor::Editor;
+
+fn editor(fen: &str) -> Editor {
+    Editor::new(fen.parse().unwrap())
+}
+
+#[test]
+fn turns_down_kings_next_to_each_other() {
+    // Each king attacks the other, so the side not to move is in check.
+    let error = editor("8/8/8/3kK3/8/8/8/8 w - - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "Black is in check with white to move");
+    assert!(editor("8/8/3k4/8/4K3/8/8/8 w - - 0 1").validate().is_ok());
+}
+
+#[test]
+fn needs_exactly_one_king_a_side() {
+    let error = editor("8/8/8/8/8/8/8/4K3 w - - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "Black needs exactly one king");
+    let error = editor("k7/8/8/8/8/8/8/3KK3 w - - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "White needs exactly one king");
+}
+
+#[test]
+fn turns_down_pawns_on_the_back_ranks() {
+    let error = editor("k7/8/8/8/8/8/8/4K2P w - - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "Pawns cannot stand on h1");
+    let error = editor("k2p4/8/8/8/8/8/8/4K3 w - - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "Pawns cannot stand on d8");
+}
+
+#[test]
+fn turns_down_castling_without_its_king_and_rook() {
+    let error = editor("4k3/8/8/8/8/8/8/4K3 w K - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "Castling K needs the king on e1 and a rook on h1");
+    let error = editor("4k2r/8/8/8/8/8/8/4K3 w q - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "Castling q needs the king on e8 and a rook on a8");
+    assert!(editor("r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1")
+        .validate()
+        .is_ok());
+}
+
+#[test]
+fn turns_down_the_side_not_to_move_in_check() {
+    let error = editor("4r2k/8/8/8/8/8/8/4K3 b - - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "White is in check with black to move");
+    let error = editor("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1")
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, "Black is in check with white to move");
+    // The side to move may be in check.
+    assert!(editor("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1").validate().is_ok());
+}
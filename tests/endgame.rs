@@ -0,0 +1,157 @@
+//! The drill positions and judging the player's moves in them.
+
+use chess::game::{Game, Team};
+use oskhen_chess_gui::endgame::{builtin_move, find, DrillTrainer, Goal, Verdict, DRILLS};
+use oskhen_chess_gui::outcome::Outcome;
+use oskhen_chess_gui::position::{in_check, king_position, legal_moves, opponent};
+use oskhen_chess_gui::record::GameRecord;
+use std::collections::HashMap;
+
+/// A trainer for the drill called `key`, with no stats from earlier sessions.
+fn drill_trainer(key: &str) -> DrillTrainer {
+    let mut trainer = DrillTrainer::new(find(key).unwrap());
+    trainer.stats = HashMap::new();
+    trainer
+}
+
+/// Plays `moves`, given in SAN, from the start of the drill, judging the
+/// drill after each with the outcome of the game, and returns the verdicts.
+fn play(trainer: &mut DrillTrainer, moves: &[&str]) -> Vec<Verdict> {
+    let mut record = GameRecord::starting_from(Some(trainer.restart()));
+    let mut game = record.start_game();
+    let mut verdicts = Vec::new();
+    for san in moves {
+        record.play_san(&mut game, san).unwrap();
+        let outcome = Outcome::detect(&mut game, &record);
+        verdicts.push(trainer.judge(&record, &game, outcome.as_ref()));
+    }
+    verdicts
+}
+
+/// `cycle` played over and over for `plies` plies.
+fn repeated<'a>(cycle: &[&'a str], plies: usize) -> Vec<&'a str> {
+    cycle.iter().copied().cycle().take(plies).collect()
+}
+
+#[test]
+fn every_drill_position_is_legal() {
+    for drill in DRILLS.iter() {
+        let fen = drill.start();
+        assert_eq!(fen.to_string(), drill.fen, "{}", drill.key);
+        let mut game: Game = fen.setup();
+        for team in [Team::White, Team::Black] {
+            assert!(king_position(&game, team).is_some(), "{}", drill.key);
+        }
+        assert!(
+            !in_check(&game, opponent(drill.color())),
+            "{}: the side not to move is in check",
+            drill.key
+        );
+        assert!(!legal_moves(&mut game).is_empty(), "{}", drill.key);
+        assert!(builtin_move(&mut game).is_some(), "{}", drill.key);
+        assert_eq!(
+            find(&drill.key.to_uppercase()),
+            Ok(find(drill.key).unwrap())
+        );
+    }
+    assert!(DRILLS[find("philidor").unwrap()].color() == Team::Black);
+    assert_eq!(DRILLS[find("philidor").unwrap()].goal, Goal::Hold);
+    assert!(find("kpk").is_err());
+}
+
+#[test]
+fn mate_passes_a_mate_drill() {
+    let mut trainer = drill_trainer("kqk");
+    let mut record = GameRecord::starting_from(Some(trainer.restart()));
+    let mut game = record.start_game();
+    record.play_san(&mut game, "Qg5+").unwrap();
+    assert_eq!(trainer.judge(&record, &game, None), Verdict::Playing);
+    record.play_san(&mut game, "Kd6").unwrap();
+    record.play_san(&mut game, "Qg7").unwrap();
+    let mate = Outcome::Checkmate {
+        winner: Team::White,
+    };
+    assert_eq!(
+        trainer.judge(&record, &game, Some(&mate)),
+        Verdict::Passed(format!("{} in 2 moves", mate.description()))
+    );
+    assert!(trainer.is_finished());
+    let stats = trainer.drill_stats();
+    assert_eq!(
+        (stats.attempts, stats.successes, stats.best),
+        (1, 1, Some(2))
+    );
+    // Once over, a drill is not judged again.
+    assert_eq!(trainer.judge(&record, &game, None), Verdict::Playing);
+    assert_eq!(trainer.drill_stats().attempts, 1);
+}
+
+#[test]
+fn stalemate_fails_a_mate_drill() {
+    let mut trainer = drill_trainer("krk");
+    trainer.restart();
+    let record = GameRecord::new();
+    let game = Game::new();
+    let verdict = trainer.judge(&record, &game, Some(&Outcome::Stalemate));
+    assert_eq!(verdict, Verdict::Failed(Outcome::Stalemate.description()));
+    assert_eq!(trainer.drill_stats().successes, 0);
+}
+
+#[test]
+fn a_mate_drill_fails_after_its_last_move() {
+    let mut trainer = drill_trainer("krk");
+    let limit = trainer.drill().move_limit;
+    let moves = repeated(&["Rh2", "Kd6", "Rh1", "Kd5"], 2 * limit - 1);
+    let verdicts = play(&mut trainer, &moves);
+    let (last, before) = verdicts.split_last().unwrap();
+    assert!(before.iter().all(|verdict| *verdict == Verdict::Playing));
+    assert_eq!(
+        *last,
+        Verdict::Failed(format!("Out of moves after {}", limit))
+    );
+    let stats = trainer.drill_stats();
+    assert_eq!((stats.attempts, stats.successes, stats.best), (1, 0, None));
+}
+
+#[test]
+fn the_queen_has_to_survive_the_reply() {
+    let mut trainer = drill_trainer("lucena");
+    let verdicts = play(&mut trainer, &["Rd1+", "Ke7", "Kc7", "Ke6", "b8=Q", "Rc2+"]);
+    assert!(verdicts[..5]
+        .iter()
+        .all(|verdict| *verdict == Verdict::Playing));
+    assert_eq!(
+        verdicts[5],
+        Verdict::Passed("Pawn queened in 3 moves".to_string())
+    );
+    assert_eq!(trainer.drill_stats().best, Some(3));
+}
+
+#[test]
+fn holding_out_passes_a_drawing_drill() {
+    let mut trainer = drill_trainer("philidor");
+    let limit = trainer.drill().move_limit;
+    let moves = repeated(&["Rc6", "Kf5", "Rb6", "Ke5"], 2 * limit - 1);
+    let verdicts = play(&mut trainer, &moves);
+    let (last, before) = verdicts.split_last().unwrap();
+    assert!(before.iter().all(|verdict| *verdict == Verdict::Playing));
+    assert_eq!(*last, Verdict::Passed(format!("Held for {} moves", limit)));
+    let stats = trainer.drill_stats();
+    assert_eq!((stats.attempts, stats.successes, stats.best), (1, 1, None));
+
+    // A draw before the limit holds too, a loss does not.
+    let mut trainer = drill_trainer("philidor");
+    let record = GameRecord::new();
+    let game = Game::new();
+    let verdict = trainer.judge(&record, &game, Some(&Outcome::DrawAgreement));
+    assert_eq!(
+        verdict,
+        Verdict::Passed(Outcome::DrawAgreement.description())
+    );
+    trainer.restart();
+    let mate = Outcome::Checkmate {
+        winner: Team::White,
+    };
+    let verdict = trainer.judge(&record, &game, Some(&mate));
+    assert_eq!(verdict, Verdict::Failed(mate.description()));
+}
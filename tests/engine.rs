@@ -0,0 +1,74 @@
+//! Talking UCI to a scripted stand-in for an engine.
+
+mod common;
+
+use oskhen_chess_gui::engine::{parse_score, Score};
+
+#[test]
+fn parses_scores() {
+    assert_eq!(
+        parse_score("info depth 12 score cp -35 nodes 1000 pv e2e4"),
+        Some(Score::Centipawns(-35))
+    );
+    assert_eq!(
+        parse_score("info depth 9 score mate 3 pv d1h5"),
+        Some(Score::Mate(3))
+    );
+    assert_eq!(
+        parse_score("info depth 9 score mate -2 pv g8f6"),
+        Some(Score::Mate(-2))
+    );
+    assert_eq!(parse_score("info depth 0 score mate 0"), Some(Score::Mated));
+    assert_eq!(parse_score("info depth 10 nodes 5000"), None);
+    assert_eq!(parse_score("info score wdl 500 300 200"), None);
+}
+
+/// Searches of an engine stopped while the GUI goes on.
+#[cfg(unix)]
+mod stopping {
+    use super::*;
+    use common::{fake_engine, TempDir};
+    use oskhen_chess_gui::engine::Engine;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Answers `go infinite` only when stopped, and slowly, with e2e4, and any
+    /// other search right away with d2d4.
+    const SCRIPT: &str = "    case \"$line\" in
+        'go infinite') echo 'info depth 1 score cp 50 pv e2e4' ;;
+        go*) echo 'info depth 1 score cp 20 pv d2d4'; echo 'bestmove d2d4' ;;
+        stop) sleep 1; echo 'info depth 2 score cp 60 pv e2e4 e7e5'; echo 'bestmove e2e4' ;;
+    esac";
+
+    /// Polls `engine` until it has found a move, failing after five seconds.
+    fn best_move(engine: &mut Engine) -> String {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Some(best_move) = engine.poll() {
+                return best_move;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the engine found no move");
+    }
+
+    #[test]
+    fn stops_without_waiting_and_skips_the_stopped_search() {
+        let dir = TempDir::new("stopping");
+        let mut engine = Engine::start(&fake_engine(&dir, SCRIPT)).unwrap();
+        assert_eq!(engine.name, "Fake");
+        engine.go_infinite("startpos").unwrap();
+        let stopped = Instant::now();
+        engine.stop().unwrap();
+        assert!(stopped.elapsed() < Duration::from_millis(500));
+        // Stopping again does not wait for a second best move.
+        engine.stop().unwrap();
+
+        engine
+            .go_movetime("startpos", Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(best_move(&mut engine), "d2d4");
+        assert_eq!(engine.info.variations[0].moves, ["d2d4"]);
+        assert_eq!(engine.score, Some(Score::Centipawns(20)));
+    }
+}
@@ -0,0 +1,114 @@
+//! The Elo difference a match score gives and games between scripted engines.
+
+mod common;
+
+use oskhen_chess_gui::engine_match::elo_difference;
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "{} is not {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn gives_the_elo_difference_with_its_margin() {
+    let (elo, margin) = elo_difference(30, 20, 50).unwrap();
+    assert_close(elo, 34.86007028756008);
+    assert_close(margin, 48.47022844733192);
+    let (elo, margin) = elo_difference(6, 4, 0).unwrap();
+    assert_close(elo, 70.43650362227247);
+    assert_close(margin, 269.520710353867);
+    // The other side of the same score.
+    let (elo, other_margin) = elo_difference(4, 6, 0).unwrap();
+    assert_close(elo, -70.43650362227247);
+    assert_close(other_margin, margin);
+    // Only draws: even, and no doubt about it.
+    let (elo, margin) = elo_difference(0, 0, 10).unwrap();
+    assert_close(elo, 0.0);
+    assert_close(margin, 0.0);
+}
+
+#[test]
+fn clamps_the_interval_at_no_and_every_point() {
+    // The interval reaches past 100%, where the difference is taken at the
+    // largest score short of it rather than being infinite.
+    let (elo, margin) = elo_difference(9, 1, 0).unwrap();
+    assert_close(elo, 381.69700377572985);
+    assert!(margin.is_finite());
+    assert_close(margin, 3051.219362993078);
+    let (elo, other_margin) = elo_difference(1, 9, 0).unwrap();
+    assert_close(elo, -381.69700377572985);
+    assert_close(other_margin, margin);
+}
+
+#[test]
+fn measures_nothing_at_no_or_every_point() {
+    assert!(elo_difference(0, 0, 0).is_none());
+    assert!(elo_difference(5, 0, 0).is_none());
+    assert!(elo_difference(0, 5, 0).is_none());
+    assert!(elo_difference(3, 0, 1).is_some());
+}
+
+/// Games played out between scripted engines.
+#[cfg(unix)]
+mod games {
+    use super::common::{fake_engine, TempDir};
+    use oskhen_chess_gui::cli::AdjudicationOptions;
+    use oskhen_chess_gui::engine_match::{run_headless, Adjudication, EngineMatch, Opening};
+    use oskhen_chess_gui::record::{GameRecord, RecordedMove};
+    use std::fs;
+
+    /// Shuffles the knights out and back: Nf6, Nf3, Ng8, Ng1, by the number of
+    /// moves played, the first of them being 1. e4.
+    const KNIGHTS: &str = "    case \"$line\" in
+        position*) moves=$(echo \"$line\" | sed 's/.* moves //' | wc -w) ;;
+        go*) case $(( (moves - 1) % 4 )) in
+            0) echo 'bestmove g8f6' ;;
+            1) echo 'bestmove g1f3' ;;
+            2) echo 'bestmove f6g8' ;;
+            3) echo 'bestmove f3g1' ;;
+        esac ;;
+    esac";
+
+    #[test]
+    fn an_en_passant_square_nobody_can_take_on_is_no_new_position() {
+        let dir = TempDir::new("repetition");
+        let engine = fake_engine(&dir, KNIGHTS);
+        let opening = Opening {
+            start: None,
+            moves: vec![RecordedMove::from_uci("e2e4").unwrap()],
+        };
+        let adjudication = Adjudication {
+            options: AdjudicationOptions {
+                resign_score: 1000,
+                resign_moves: 3,
+                draw_score: 10,
+                draw_moves: 8,
+                draw_after: 40,
+                no_adjudication: true,
+            },
+            tablebase: None,
+        };
+        let pgn = dir.0.join("games.pgn");
+        let engine_match = EngineMatch::start(
+            [&engine, &engine],
+            1,
+            vec![opening],
+            None,
+            adjudication,
+            pgn.clone(),
+        )
+        .unwrap();
+        run_headless(engine_match).unwrap();
+
+        // The position after 1. e4 comes back after 3. Ng1 and 5. Ng1, no pawn
+        // being there to take on e3 the first time.
+        let (record, _) = GameRecord::from_pgn(&fs::read_to_string(pgn).unwrap()).unwrap();
+        assert_eq!(record.ply(), 9);
+        assert_eq!(record.header("Result"), Some("1/2-1/2"));
+        assert!(record.to_pgn().contains("Draw by threefold repetition"));
+    }
+}
@@ -0,0 +1,54 @@
+//! Games reviewed by a scripted stand-in for an engine.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{fake_engine, TempDir};
+use oskhen_chess_gui::engine::Score;
+use oskhen_chess_gui::game_review::{GameReview, EVAL_CAP};
+use oskhen_chess_gui::record::GameRecord;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Likes every position a little for white, and reports the mate of the
+/// game the way engines do: a score without a line, and no move.
+const SCRIPT: &str = "    case \"$line\" in
+        position*h5f7) mated=1 ;;
+        position*) mated= ;;
+        go*) if [ -n \"$mated\" ]; then
+                echo 'info depth 0 score mate 0'; echo 'bestmove (none)'
+            else
+                echo 'info depth 1 score cp 10 pv a2a3'; echo 'bestmove a2a3'
+            fi ;;
+    esac";
+
+fn review(pgn: &str) -> GameReview {
+    let dir = TempDir::new(&format!("review-{}", pgn.len()));
+    let (record, _) = GameRecord::from_pgn(pgn).unwrap();
+    let mut review = GameReview::start(&fake_engine(&dir, SCRIPT), &record).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !review.is_done() {
+        assert!(Instant::now() < deadline, "the review did not finish");
+        review.poll().unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+    review
+}
+
+#[test]
+fn scores_a_mate_on_the_board_as_won() {
+    let review = review("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0");
+    assert_eq!(review.scores.len(), 8);
+    // Black is to move and mated.
+    assert_eq!(review.scores[7], Score::Mate(0));
+    assert_eq!(review.capped_eval(7), Some(EVAL_CAP));
+    assert_eq!(review.capped_eval(6), Some(10));
+}
+
+#[test]
+fn scores_a_game_ended_off_the_board_by_the_engine() {
+    let review = review("1. e4 e5 2. Qh5 Nc6 1-0");
+    assert_eq!(review.scores.len(), 5);
+    assert_eq!(review.scores[4], Score::Centipawns(10));
+}
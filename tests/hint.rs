@@ -0,0 +1,87 @@
+//! Hints found by a scripted stand-in for an engine.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{fake_engine, TempDir};
+use oskhen_chess_gui::hint::Hints;
+use oskhen_chess_gui::record::{GameRecord, RecordedMove};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Answers e7e5 after 1. e4 and e2e4 anywhere else.
+const SCRIPT: &str = "    case \"$line\" in
+        position*e2e4) best=e7e5 ;;
+        position*) best=e2e4 ;;
+        go*) echo 'info depth 1 score cp 20'; echo \"bestmove $best\" ;;
+    esac";
+
+/// Polls `hints` until the engine has answered for the position after `ply`
+/// moves, failing after five seconds.
+fn wait_for_hint(hints: &mut Hints, ply: usize) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !hints.poll(ply) {
+        assert!(Instant::now() < deadline, "the engine found no hint");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn shows_the_piece_then_the_move_and_counts_hints_per_side() {
+    let dir = TempDir::new("hints");
+    let engine = fake_engine(&dir, SCRIPT);
+    let (mut hints, mut record) = (Hints::default(), GameRecord::new());
+    let mut game = record.start_game();
+
+    hints.ask(&mut record, None, Some(&engine)).unwrap();
+    assert_eq!(hints.text().unwrap(), "Looking for a hint...");
+    assert!(hints.annotations(0).is_none());
+    wait_for_hint(&mut hints, 0);
+    assert_eq!(
+        hints.text().unwrap(),
+        "Hint: move the piece on e2. H again for the move"
+    );
+    let marks = hints.annotations(0).unwrap();
+    assert!(marks.arrows.is_empty() && marks.squares.len() == 1);
+
+    // Asking again in the same position shows the move without counting twice.
+    hints.ask(&mut record, None, Some(&engine)).unwrap();
+    assert_eq!(hints.text().unwrap(), "Hint: e2-e4");
+    assert_eq!(hints.annotations(0).unwrap().arrows.len(), 1);
+    assert_eq!(record.header("WhiteHints"), Some("1"));
+
+    let e4 = RecordedMove::from_uci("e2e4").unwrap();
+    record.play_move(&mut game, e4).unwrap();
+    assert!(hints.annotations(1).is_none());
+    hints.ask(&mut record, None, Some(&engine)).unwrap();
+    wait_for_hint(&mut hints, 1);
+    assert_eq!(
+        hints.text().unwrap(),
+        "Hint: move the piece on e7. H again for the move"
+    );
+
+    // A move the analysis has found already needs no engine.
+    let e5 = RecordedMove::from_uci("e7e5").unwrap();
+    record.play_move(&mut game, e5).unwrap();
+    let known = RecordedMove::from_uci("g1f3");
+    hints.ask(&mut record, known, None).unwrap();
+    assert_eq!(
+        hints.text().unwrap(),
+        "Hint: move the piece on g1. H again for the move"
+    );
+
+    assert_eq!(record.header("WhiteHints"), Some("2"));
+    assert_eq!(record.header("BlackHints"), Some("1"));
+    let pgn = record.to_pgn();
+    assert!(pgn.contains("[WhiteHints \"2\"]") && pgn.contains("[BlackHints \"1\"]"));
+}
+
+#[test]
+fn needs_an_engine_for_a_move_not_found_yet() {
+    let (mut hints, mut record) = (Hints::default(), GameRecord::new());
+    let error = hints.ask(&mut record, None, None).unwrap_err();
+    assert_eq!(error, "add an engine in the settings (F2)");
+    assert!(hints.text().is_none());
+    assert!(record.header("WhiteHints").is_none());
+}
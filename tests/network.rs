@@ -0,0 +1,237 @@
+//! Games hosted and joined on loopback.
+
+use oskhen_chess_gui::network::{
+    missed_moves, Connection, Host, Message, NetworkEvent, Order, Reconnection,
+};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest wait for a connection or message before a test fails.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const SESSION: &str = "0123456789abcdef";
+
+fn host() -> Host {
+    let reconnection = Reconnection {
+        session: SESSION.to_string(),
+        timeout: 60,
+        pause_clock: true,
+    };
+    Host::bind(0, 1, reconnection).unwrap()
+}
+
+fn address(host: &Host) -> String {
+    format!("127.0.0.1:{}", host.port())
+}
+
+/// Polls `host` until a player has joined, failing after `TIMEOUT`.
+fn wait_for_player(host: &mut Host) -> TcpStream {
+    let deadline = Instant::now() + TIMEOUT;
+    while Instant::now() < deadline {
+        if let Some(stream) = host.player() {
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("no player joined");
+}
+
+/// The next message of `connection`, failing after `TIMEOUT`.
+fn wait_for_message(connection: &mut Connection) -> Message {
+    let deadline = Instant::now() + TIMEOUT;
+    while Instant::now() < deadline {
+        match connection.poll() {
+            Some(NetworkEvent::Message(message)) => return message,
+            Some(_) => panic!("the connection dropped"),
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    panic!("no message arrived");
+}
+
+/// The next event of `connection`, failing after `TIMEOUT`.
+fn wait_for_event(connection: &mut Connection) -> NetworkEvent {
+    let deadline = Instant::now() + TIMEOUT;
+    while Instant::now() < deadline {
+        match connection.poll() {
+            Some(event) => return event,
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    panic!("nothing happened on the connection");
+}
+
+/// Plays the move `message` on `moves` as the game does, unless it was played
+/// already. Returns whether it was.
+fn receive(moves: &mut Vec<String>, message: Message) -> bool {
+    match message {
+        Message::Move { uci, seq } => match Order::of(seq, moves.len()) {
+            Order::Next => {
+                moves.push(uci);
+                true
+            }
+            Order::Played => false,
+            Order::Ahead => panic!("a move went missing before {}", uci),
+        },
+        other => panic!("expected a move, got {:?}", other),
+    }
+}
+
+#[test]
+fn numbers_only_the_moves_after_those_the_other_side_has() {
+    let moves = vec!["e2e4".to_string(), "e7e5".to_string(), "g1f3".to_string()];
+    assert_eq!(
+        missed_moves(&moves, 1),
+        vec![
+            Message::Move {
+                uci: "e7e5".to_string(),
+                seq: Some(2)
+            },
+            Message::Move {
+                uci: "g1f3".to_string(),
+                seq: Some(3)
+            },
+        ]
+    );
+    assert!(missed_moves(&moves, 3).is_empty());
+    assert_eq!(Order::of(Some(2), 2), Order::Played);
+    assert_eq!(Order::of(Some(3), 2), Order::Next);
+    assert_eq!(Order::of(Some(4), 2), Order::Ahead);
+    assert_eq!(Order::of(None, 2), Order::Next);
+}
+
+#[test]
+fn waits_for_a_player_without_blocking() {
+    let mut host = host();
+    let started = Instant::now();
+    assert!(host.player().is_none());
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    let mut joined = Connection::join(&address(&host)).unwrap();
+    let stream = wait_for_player(&mut host);
+    let (mut connection, _) = host.start(stream).unwrap();
+    connection.send(&Message::Resign).unwrap();
+    assert_eq!(wait_for_message(&mut joined), Message::Resign);
+}
+
+#[test]
+fn keeps_spectators_arriving_before_the_player() {
+    let mut host = host();
+    let mut watcher = Connection::watch(&address(&host)).unwrap();
+    let _joined = Connection::join(&address(&host)).unwrap();
+    let stream = wait_for_player(&mut host);
+    let (_, mut spectators) = host.start(stream).unwrap();
+    let deadline = Instant::now() + TIMEOUT;
+    while !spectators.poll() {
+        assert!(Instant::now() < deadline, "the spectator was not kept");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(spectators.admit(&[Message::Full]), 1);
+    assert_eq!(wait_for_message(&mut watcher), Message::Full);
+}
+
+#[test]
+fn a_silent_connection_holds_up_no_spectator_during_the_game() {
+    let mut host = host();
+    let address = address(&host);
+    let _joined = Connection::join(&address).unwrap();
+    let stream = wait_for_player(&mut host);
+    let (_, mut spectators) = host.start(stream).unwrap();
+    // Says nothing, so its hello is only given up on after a while.
+    let _silent = TcpStream::connect(&address).unwrap();
+    let started = Instant::now();
+    let _watcher = Connection::watch(&address).unwrap();
+    while !spectators.poll() {
+        assert!(
+            Instant::now() < started + TIMEOUT,
+            "the spectator was not kept"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn a_player_reconnecting_gets_each_missed_move_once() {
+    let mut host = host();
+    let mut joined = Connection::join(&address(&host)).unwrap();
+    let stream = wait_for_player(&mut host);
+    let player = stream.try_clone().unwrap();
+    let (mut connection, _) = host.start(stream).unwrap();
+    joined.reconnection = connection.reconnection.clone();
+    let (mut hosted, mut joiner) = (Vec::new(), Vec::new());
+
+    hosted.push("e2e4".to_string());
+    connection.send(&missed_moves(&hosted, 0)[0]).unwrap();
+    assert!(receive(&mut joiner, wait_for_message(&mut joined)));
+
+    // The host plays on while the connection is down.
+    player.shutdown(Shutdown::Both).unwrap();
+    assert!(matches!(
+        wait_for_event(&mut connection),
+        NetworkEvent::Disconnected
+    ));
+    assert!(connection.lost_since().is_some());
+    hosted.extend(vec!["e7e5".to_string(), "g1f3".to_string()]);
+
+    assert!(matches!(
+        wait_for_event(&mut joined),
+        NetworkEvent::Disconnected
+    ));
+    assert!(matches!(
+        wait_for_event(&mut joined),
+        NetworkEvent::Reconnected
+    ));
+    let rejoin = Message::Rejoin {
+        session: SESSION.to_string(),
+        ply: joiner.len(),
+    };
+    joined.send(&rejoin).unwrap();
+    let ply = match wait_for_message(&mut connection) {
+        Message::Rejoin { ply, .. } => ply,
+        other => panic!("expected the player back, got {:?}", other),
+    };
+    assert!(connection.lost_since().is_none());
+
+    // Moves that came through before are sent again along with the missed ones.
+    for message in missed_moves(&hosted, ply - 1) {
+        connection.send(&message).unwrap();
+    }
+    let played: Vec<bool> = (0..3)
+        .map(|_| receive(&mut joiner, wait_for_message(&mut joined)))
+        .collect();
+    assert_eq!(played, vec![false, true, true]);
+    assert_eq!(joiner, hosted);
+}
+
+#[test]
+fn turns_away_a_player_from_another_game() {
+    let mut host = host();
+    let address = address(&host);
+    let mut joined = Connection::join(&address).unwrap();
+    let stream = wait_for_player(&mut host);
+    let (mut connection, _) = host.start(stream).unwrap();
+
+    let mut stranger = TcpStream::connect(&address).unwrap();
+    let rejoin = Message::Rejoin {
+        session: "fedcba9876543210".to_string(),
+        ply: 0,
+    };
+    writeln!(stranger, "{}", serde_json::to_string(&rejoin).unwrap()).unwrap();
+    let deadline = Instant::now() + Duration::from_millis(500);
+    while Instant::now() < deadline {
+        assert!(connection.poll().is_none(), "the stranger was let in");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    connection.send(&Message::Resign).unwrap();
+    assert_eq!(wait_for_message(&mut joined), Message::Resign);
+    stranger
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let mut line = String::new();
+    let _ = BufReader::new(stranger).read_line(&mut line);
+    assert!(line.is_empty(), "the stranger was sent {}", line);
+}
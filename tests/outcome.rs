@@ -0,0 +1,82 @@
+//! Games of standard chess ending on the board.
+
+use chess::game::{Game, Team};
+use oskhen_chess_gui::fen::Fen;
+use oskhen_chess_gui::outcome::Outcome;
+use oskhen_chess_gui::position::insufficient_material;
+use oskhen_chess_gui::record::GameRecord;
+
+fn setup(fen: &str) -> Game {
+    fen.parse::<Fen>().unwrap().setup()
+}
+
+/// How a standard game in the position `fen` has ended, if it has.
+fn detect(fen: &str) -> Option<Outcome> {
+    let record = GameRecord::starting_from(Some(fen.parse().unwrap()));
+    Outcome::detect(&mut setup(fen), &record)
+}
+
+#[test]
+fn kings_with_a_single_minor_piece_cannot_mate() {
+    assert!(insufficient_material(&setup(
+        "8/8/8/4k3/8/8/8/4K3 w - - 0 1"
+    )));
+    assert!(insufficient_material(&setup(
+        "8/8/8/4k3/8/8/8/4KN2 w - - 0 1"
+    )));
+    assert!(insufficient_material(&setup(
+        "8/8/8/4k3/8/8/8/4KB2 b - - 0 1"
+    )));
+    assert!(insufficient_material(&setup(
+        "8/8/2n5/4k3/8/8/8/4K3 w - - 0 1"
+    )));
+    assert!(detect("8/8/8/4k3/8/8/8/4KN2 w - - 0 1") == Some(Outcome::InsufficientMaterial));
+}
+
+#[test]
+fn bishops_on_one_color_cannot_mate() {
+    // The bishops on c1 and f8 both stand on dark squares.
+    let same = "5b2/8/8/4k3/8/8/8/2B1K3 w - - 0 1";
+    assert!(insufficient_material(&setup(same)));
+    assert!(insufficient_material(&setup(
+        "5b2/8/8/4k3/8/8/8/B1B1K3 w - - 0 1"
+    )));
+    assert!(detect(same) == Some(Outcome::InsufficientMaterial));
+}
+
+#[test]
+fn bishops_on_both_colors_can_mate() {
+    // The bishop on c8 stands on a light square, the one on c1 on a dark one.
+    let opposite = "2b5/8/8/4k3/8/8/8/2B1K3 w - - 0 1";
+    assert!(!insufficient_material(&setup(opposite)));
+    assert!(detect(opposite).is_none());
+}
+
+#[test]
+fn other_material_can_mate() {
+    assert!(!insufficient_material(&setup(
+        "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1"
+    )));
+    assert!(!insufficient_material(&setup(
+        "8/8/8/4k3/8/8/8/4KR2 w - - 0 1"
+    )));
+    assert!(!insufficient_material(&setup(
+        "8/8/8/4k3/8/8/8/3NKN2 w - - 0 1"
+    )));
+    assert!(!insufficient_material(&setup(
+        "8/8/8/4k3/8/8/8/3BKN2 w - - 0 1"
+    )));
+}
+
+#[test]
+fn a_side_without_moves_out_of_check_is_stalemated() {
+    // The black king on h8 has nowhere to go but is not attacked.
+    assert!(detect("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1") == Some(Outcome::Stalemate));
+    assert!(detect("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1").is_none());
+    assert!(
+        detect("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1")
+            == Some(Outcome::Checkmate {
+                winner: Team::White
+            })
+    );
+}
@@ -0,0 +1,190 @@
+//! Puzzle files, the Glicko rating and judging the moves of a puzzle.
+
+use oskhen_chess_gui::puzzle::{
+    parse_csv, parse_epd, Puzzle, PuzzleStats, PuzzleTrainer, Rating, Verdict,
+};
+use oskhen_chess_gui::record::{GameRecord, RecordedMove};
+
+/// Two rooks, each mating on the back rank.
+const BACK_RANK: &str = "7k/6pp/8/8/8/8/8/RR4K1 w - -";
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "{} is not {}",
+        actual,
+        expected
+    );
+}
+
+fn rating(rating: f64, deviation: f64) -> Rating {
+    Rating { rating, deviation }
+}
+
+fn trainer(puzzles: Vec<Puzzle>) -> PuzzleTrainer {
+    let mut trainer = PuzzleTrainer::new(puzzles, Vec::new());
+    trainer.stats = PuzzleStats::default();
+    trainer
+}
+
+/// Plays `moves` from the start of the puzzle being solved and judges the last one.
+fn judge(trainer: &mut PuzzleTrainer, moves: &[&str]) -> Verdict {
+    let fen = trainer.puzzle().unwrap().fen.parse().unwrap();
+    let mut record = GameRecord::starting_from(Some(fen));
+    let mut game = record.start_game();
+    for uci in moves {
+        let recorded = RecordedMove::from_uci(uci).unwrap();
+        record.play_move(&mut game, recorded).unwrap();
+    }
+    trainer.judge(&record, &game)
+}
+
+#[test]
+fn updates_the_rating_by_glicko() {
+    // One game of the example in Glickman's paper, after the deviation has
+    // grown by 15 for the time since the last puzzle.
+    let player = rating(1500.0, 200.0);
+    let won = player.after_game(rating(1400.0, 30.0), 1.0);
+    assert_close(won.rating, 1563.705558013535);
+    assert_close(won.deviation, 175.5975888502254);
+    let lost = player.after_game(rating(1400.0, 30.0), 0.0);
+    assert_close(lost.rating, 1387.0069255301614);
+    assert_close(lost.deviation, won.deviation);
+
+    let new = Rating::default().after_game(rating(1500.0, 80.0), 1.0);
+    assert_close(new.rating, 1674.9501082599347);
+    assert_close(new.deviation, 250.42456917694054);
+    // The deviation settles where its growth between puzzles makes up for
+    // what a puzzle tells, and never drops below 45.
+    let mut settled = player;
+    for _ in 0..100 {
+        settled = settled.after_game(rating(1500.0, 30.0), 0.5);
+    }
+    assert_close(settled.rating, 1500.0);
+    assert_close(settled.deviation, 71.59120789407763);
+    let sure = rating(1500.0, 40.0).after_game(rating(1500.0, 30.0), 1.0);
+    assert_close(sure.deviation, 45.0);
+}
+
+#[test]
+fn reads_lichess_csv_with_and_without_a_header() {
+    let line = "00sHx,q3k1nr/1pp1nQpp/3p4/1P2p3/4P3/B1PP1b2/B5PP/5K2 b k - 0 17,e8d7 a2e6 d7d8 f7f8,1760,80,83,72,mate mateIn2 middlegame short,https://lichess.org/yyznGmXs/black#34";
+    let header = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl";
+    for text in [line.to_string(), format!("{}\n{}\n", header, line)] {
+        let puzzles = parse_csv(&text).unwrap();
+        assert_eq!(puzzles.len(), 1);
+        let puzzle = &puzzles[0];
+        assert_eq!(puzzle.id, "00sHx");
+        assert_eq!(puzzle.solution, ["e8d7", "a2e6", "d7d8", "f7f8"]);
+        assert!(puzzle.opponent_first);
+        assert_close(puzzle.rating, 1760.0);
+        assert_close(puzzle.deviation, 80.0);
+        assert!(puzzle.has_theme("MateIn2"));
+        assert!(!puzzle.has_theme("endgame"));
+    }
+}
+
+#[test]
+fn reads_csv_columns_in_any_order() {
+    let text = "Moves,FEN,Themes\n\"e2e4 e7e5\",\"8/8/8/8/8/8/8/K6k w - - 0 1\",\"fork pin\"\n";
+    let puzzles = parse_csv(text).unwrap();
+    assert_eq!(puzzles[0].id, "2");
+    assert_eq!(puzzles[0].solution, ["e2e4", "e7e5"]);
+    assert_eq!(puzzles[0].themes, ["fork", "pin"]);
+    assert_close(puzzles[0].rating, 1500.0);
+}
+
+#[test]
+fn turns_down_broken_csv() {
+    assert!(parse_csv("Id,Moves\n1,e2e4 e7e5\n").is_err());
+    assert_eq!(
+        parse_csv("a,8/8/8/8/8/8/8/K6k w - - 0 1,e2e4\n")
+            .err()
+            .unwrap(),
+        "Line 1 is not a puzzle"
+    );
+    for rating in ["NaN", "inf", "-inf"] {
+        let text = format!("a,8/8/8/8/8/8/8/K6k w - - 0 1,e2e4 e7e5,{}\n", rating);
+        assert_eq!(
+            parse_csv(&text).err().unwrap(),
+            "Line 1 has a rating that is not a number"
+        );
+    }
+}
+
+#[test]
+fn reads_epd_with_the_solution_in_san() {
+    let text = format!(
+        "# Back rank mates\n{} bm Ra8#; id \"back rank\"; c0 \"mate, mateIn1\"; c1 \"1200\";\n\n\
+         {} pv Rb8#;\n",
+        BACK_RANK, BACK_RANK
+    );
+    let puzzles = parse_epd(&text).unwrap();
+    assert_eq!(puzzles.len(), 2);
+    assert_eq!(puzzles[0].id, "back rank");
+    assert_eq!(puzzles[0].fen, BACK_RANK);
+    assert_eq!(puzzles[0].solution, ["a1a8"]);
+    assert!(!puzzles[0].opponent_first);
+    assert_eq!(puzzles[0].themes, ["mate", "mateIn1"]);
+    assert_close(puzzles[0].rating, 1200.0);
+    assert_eq!(puzzles[1].id, "4");
+    assert_eq!(puzzles[1].solution, ["b1b8"]);
+
+    assert!(parse_epd(&format!("{} id \"none\";", BACK_RANK)).is_err());
+    assert!(parse_epd(&format!("{} bm Ra9;", BACK_RANK)).is_err());
+    assert!(parse_epd(&format!("{} bm Ra8#; c1 \"NaN\";", BACK_RANK)).is_err());
+}
+
+#[test]
+fn any_mate_solves_the_puzzle() {
+    let epd = format!("{} bm Ra8#;", BACK_RANK);
+    let mut trainer = trainer(parse_epd(&epd).unwrap());
+    assert!(trainer.next_puzzle().unwrap().is_ok());
+    assert_eq!(judge(&mut trainer, &["b1b8"]), Verdict::Solved);
+    assert_eq!(trainer.stats.solved, 1);
+    assert!(trainer.last_change > 0.0);
+    assert!(trainer.is_finished());
+}
+
+#[test]
+fn a_move_off_the_solution_fails_the_puzzle() {
+    let epd = format!("{} pv Ra8#;", BACK_RANK);
+    let mut trainer = trainer(parse_epd(&epd).unwrap());
+    trainer.next_puzzle();
+    assert_eq!(judge(&mut trainer, &["a1a7"]), Verdict::Wrong);
+    assert_eq!(trainer.stats.failed, 1);
+    assert!(trainer.last_change < 0.0);
+}
+
+#[test]
+fn plays_the_opponent_and_goes_on_after_a_correct_move() {
+    let csv = "x,4k3/8/8/8/8/8/8/R3K3 b - - 0 1,e8d8 a1a7 d8c8 a7a8\n";
+    let mut trainer = trainer(parse_csv(csv).unwrap());
+    trainer.next_puzzle();
+    let reply = |trainer: &PuzzleTrainer, ply| trainer.reply(ply).map(|m| m.to_uci());
+    assert_eq!(reply(&trainer, 0).as_deref(), Some("e8d8"));
+    assert_eq!(reply(&trainer, 1), None);
+    assert_eq!(judge(&mut trainer, &["e8d8", "a1a7"]), Verdict::Correct);
+    assert_eq!(reply(&trainer, 2).as_deref(), Some("d8c8"));
+    assert!(!trainer.is_finished());
+    assert_eq!(
+        judge(&mut trainer, &["e8d8", "a1a7", "d8c8", "a7a8"]),
+        Verdict::Solved
+    );
+}
+
+#[test]
+fn picks_the_unplayed_puzzle_rated_closest() {
+    let csv = "easy,8/8/8/8/8/8/8/K6k w - - 0 1,a1a2 h1h2,1000\n\
+               close,8/8/8/8/8/8/8/K6k w - - 0 1,a1a2 h1h2,1550\n\
+               hard,8/8/8/8/8/8/8/K6k w - - 0 1,a1a2 h1h2,2200\n";
+    let mut trainer = trainer(parse_csv(csv).unwrap());
+    let next = |trainer: &mut PuzzleTrainer| {
+        trainer.next_puzzle();
+        trainer.puzzle().unwrap().id.clone()
+    };
+    assert_eq!(next(&mut trainer), "close");
+    trainer.give_up();
+    // Failing it lowered the rating, and it is not picked again while others are left.
+    assert_eq!(next(&mut trainer), "easy");
+}
@@ -0,0 +1,179 @@
+//! Games written as PGN and read back.
+
+use chess::game::Rank;
+use oskhen_chess_gui::annotations::{AnnotationColor, Annotations, Arrow, SquareMark};
+use oskhen_chess_gui::record::{GameRecord, RecordedMove};
+
+const SCHOLARS_MATE: &str = "[Event \"Club \\\"Open\\\"\"]
+[Site \"Stockholm\"]
+[White \"Anna\"]
+[Black \"Bo\"]
+[Result \"1-0\"]
+
+{Scholar's mate} 1. e4 {Best by test} 1... e5 2. Bc4 Nc6 3. Qh5 Nf6 $4
+{Defends nothing} 4. Qxf7# 1-0
+";
+
+#[test]
+fn round_trips_tags_moves_comments_and_nags() {
+    let (record, _) = GameRecord::from_pgn(SCHOLARS_MATE).unwrap();
+    assert_eq!(record.header("Event"), Some("Club \"Open\""));
+    assert_eq!(record.header("White"), Some("Anna"));
+    assert_eq!(
+        record.san,
+        ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6", "Qxf7#"]
+    );
+    assert_eq!(record.notes[0].comment, "Scholar's mate");
+    assert_eq!(record.notes[1].comment, "Best by test");
+    assert_eq!(record.notes[6].nags, [4]);
+    assert_eq!(record.notes[6].comment, "Defends nothing");
+    assert_eq!(record.result, "1-0");
+    assert_eq!(record.to_pgn(), SCHOLARS_MATE);
+}
+
+#[test]
+fn round_trips_games_set_up_from_a_fen() {
+    let pgn = "[SetUp \"1\"]
+[FEN \"7k/P7/8/8/8/8/8/K7 w - - 0 1\"]
+[Result \"*\"]
+
+1. a8=Q+ Kh7 *
+";
+    let (record, _) = GameRecord::from_pgn(pgn).unwrap();
+    assert_eq!(record.san, ["a8=Q+", "Kh7"]);
+    assert!(record.moves[0].promotion == Some(Rank::Queen));
+    assert_eq!(record.to_pgn(), pgn);
+}
+
+#[test]
+fn reads_every_result() {
+    for result in ["1-0", "0-1", "1/2-1/2", "*"] {
+        let (record, _) = GameRecord::from_pgn(&format!("1. e4 e5 {}", result)).unwrap();
+        assert_eq!(record.result, result);
+        assert_eq!(record.header("Result"), Some(result));
+        assert!(record.to_pgn().ends_with(&format!("1. e4 e5 {}\n", result)));
+    }
+}
+
+#[test]
+fn skips_variations() {
+    let (record, _) = GameRecord::from_pgn("1. e4 (1. d4 d5 (1... Nf6)) 1... e5 *").unwrap();
+    assert_eq!(record.san, ["e4", "e5"]);
+}
+
+#[test]
+fn rejects_unmatched_parentheses() {
+    assert!(GameRecord::from_pgn("1. e4 ) e5 *").is_err());
+    assert!(GameRecord::from_pgn("1. e4 (1. d4)) e5 *").is_err());
+}
+
+#[test]
+fn rejects_illegal_moves() {
+    assert!(GameRecord::from_pgn("1. e5 *").is_err());
+}
+
+#[test]
+fn promotes_only_to_queen_rook_bishop_or_knight() {
+    for (letter, rank) in [
+        ('q', Rank::Queen),
+        ('r', Rank::Rook),
+        ('b', Rank::Bishop),
+        ('n', Rank::Knight),
+    ] {
+        let uci = format!("e7e8{}", letter);
+        let recorded = RecordedMove::from_uci(&uci).unwrap();
+        assert!(recorded.promotion == Some(rank));
+        assert_eq!(recorded.to_uci(), uci);
+    }
+    assert!(RecordedMove::from_uci("e7e8k").is_none());
+    assert!(RecordedMove::from_uci("e7e8p").is_none());
+    assert!(RecordedMove::from_uci("e7e8").unwrap().promotion.is_none());
+}
+
+#[test]
+fn round_trips_arrows_and_squares() {
+    let (mut record, _) = GameRecord::from_pgn("1. e4 e5 *").unwrap();
+    let annotations = Annotations {
+        arrows: vec![
+            Arrow {
+                from: (6, 0),
+                to: (5, 2),
+                color: AnnotationColor::Green,
+            },
+            Arrow {
+                from: (3, 7),
+                to: (7, 3),
+                color: AnnotationColor::Red,
+            },
+        ],
+        squares: vec![
+            SquareMark {
+                square: (3, 3),
+                color: AnnotationColor::Yellow,
+            },
+            SquareMark {
+                square: (5, 6),
+                color: AnnotationColor::Blue,
+            },
+        ],
+    };
+    record.notes[2].annotations = annotations.clone();
+    record.notes[2].comment = "Both sides want the center".to_string();
+    record.notes[0].annotations.squares.push(SquareMark {
+        square: (4, 1),
+        color: AnnotationColor::Green,
+    });
+
+    let pgn = record.to_pgn();
+    assert!(pgn.contains("{[%csl Yd4,Bf7][%cal Gg1f3,Rd8h4] Both sides want the center}"));
+    let (read, _) = GameRecord::from_pgn(&pgn).unwrap();
+    assert_eq!(read.notes[2].annotations, annotations);
+    assert_eq!(read.notes[2].comment, "Both sides want the center");
+    assert_eq!(read.notes[0].annotations, record.notes[0].annotations);
+    assert!(read.notes[1].annotations.is_empty());
+    assert_eq!(read.to_pgn(), pgn);
+}
+
+#[test]
+fn skips_malformed_arrows_and_squares() {
+    let pgn = "1. e4 {[%cal Gx€,Ge2e4,Xa1a2,Ga1a9,G€€] [%csl Rd4,G€1,Gz9,R] [%cal] Fine} *";
+    let (record, _) = GameRecord::from_pgn(pgn).unwrap();
+    let annotations = &record.notes[1].annotations;
+    assert_eq!(
+        annotations.arrows,
+        [Arrow {
+            from: (4, 1),
+            to: (4, 3),
+            color: AnnotationColor::Green,
+        }]
+    );
+    assert_eq!(
+        annotations.squares,
+        [SquareMark {
+            square: (3, 3),
+            color: AnnotationColor::Red,
+        }]
+    );
+    assert_eq!(record.notes[1].comment, "Fine");
+}
+
+#[test]
+fn reads_castling_written_with_zeros() {
+    let (record, _) =
+        GameRecord::from_pgn("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. 0-0 Nf6 5.d3 0-0 *").unwrap();
+    assert_eq!(record.san[6], "O-O");
+    assert_eq!(record.san[9], "O-O");
+    let pgn = "[SetUp \"1\"]\n[FEN \"r3k3/3p4/8/8/8/8/8/R3K3 w Qq - 0 1\"]\n\n1. 0-0-0 0-0-0 *";
+    let (record, _) = GameRecord::from_pgn(pgn).unwrap();
+    assert_eq!(record.san, ["O-O-O", "O-O-O"]);
+}
+
+#[test]
+fn reads_brackets_inside_tag_values() {
+    let pgn = "[Event \"Cup [final]\"]\n[Site \"Room \\\"]\\\" 2\"]\n[Result \"*\"]\n\n1. e4 *\n";
+    let (record, _) = GameRecord::from_pgn(pgn).unwrap();
+    assert_eq!(record.header("Event"), Some("Cup [final]"));
+    assert_eq!(record.header("Site"), Some("Room \"]\" 2"));
+    assert_eq!(record.san, ["e4"]);
+    assert_eq!(record.to_pgn(), pgn);
+}
@@ -0,0 +1,165 @@
+//! Lines read from a repertoire and scheduling them by spaced repetition.
+
+mod common;
+
+use chess::game::Team;
+use common::TempDir;
+use oskhen_chess_gui::record::GameRecord;
+use oskhen_chess_gui::repertoire::{load, repertoire_key, RepertoireTrainer, Verdict};
+use std::fs;
+use std::path::PathBuf;
+
+const REPERTOIRE: &str = "[Event \"Sicilian\"]
+
+1. e4 c5 {The Sicilian} (1... e5 $1 2. Nf3 Nc6 (2... d6 3. d4) 3. Bb5 *) 2. Nf3 d6 1-0
+";
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Writes `pgn` to a file of its own and reads its lines.
+fn lines(dir: &TempDir, pgn: &str) -> Result<Vec<Vec<String>>, String> {
+    let path = dir.0.join("repertoire.pgn");
+    fs::write(&path, pgn).unwrap();
+    load(&path).map(|lines| lines.into_iter().map(|line| line.san).collect())
+}
+
+/// A trainer for the lines of `pgn` with nothing learned yet: no progress is
+/// ever kept for a file in a directory removed after the test.
+fn trainer(dir: &TempDir, pgn: &str, color: Team) -> RepertoireTrainer {
+    let path: PathBuf = dir.0.join("repertoire.pgn");
+    fs::write(&path, pgn).unwrap();
+    RepertoireTrainer::new(load(&path).unwrap(), color, &path)
+}
+
+#[test]
+fn reads_every_path_through_the_variations() {
+    let dir = TempDir::new("repertoire-lines");
+    assert_eq!(
+        lines(&dir, REPERTOIRE).unwrap(),
+        [
+            vec!["e4", "e5", "Nf3", "d6", "d4"],
+            vec!["e4", "e5", "Nf3", "Nc6", "Bb5"],
+            vec!["e4", "c5", "Nf3", "d6"],
+        ]
+    );
+    // A line that another one continues, or one given twice, is only learned once.
+    let pgn = "[Event \"1\"]\n\n1. d4 (1. d4 d5) d5 2. c4 *\n\n\
+               [Event \"2\"]\n\n1. d4 d5 2. c4 *\n";
+    assert_eq!(lines(&dir, pgn).unwrap(), [vec!["d4", "d5", "c4"]]);
+}
+
+#[test]
+fn starts_lines_from_the_fen_tag() {
+    let pgn =
+        "[SetUp \"1\"]\n[FEN \"7k/6pp/8/8/8/8/8/R3K3 w - - 0 1\"]\n\n1. Ra7 (1. Ra8#) Kg8 *\n";
+    let records = GameRecord::lines_from_pgn(pgn).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].san, ["Ra8#"]);
+    assert_eq!(records[1].san, ["Ra7", "Kg8"]);
+    for record in records.iter() {
+        assert_eq!(
+            record.start.as_ref().unwrap().to_string(),
+            "7k/6pp/8/8/8/8/8/R3K3 w - - 0 1"
+        );
+    }
+}
+
+#[test]
+fn turns_down_broken_variations() {
+    let dir = TempDir::new("repertoire-broken");
+    assert!(lines(&dir, "1. e4 (1. d4 d5 *\n").is_err());
+    assert!(lines(&dir, "(1. d4) 1. e4 *\n").is_err());
+    assert!(lines(&dir, "[Event \"Empty\"]\n\n*\n").is_err());
+    // Outside of a repertoire, variations are skipped but must still close.
+    let (record, _) = GameRecord::from_pgn(REPERTOIRE).unwrap();
+    assert_eq!(record.san, ["e4", "c5", "Nf3", "d6"]);
+    assert!(GameRecord::from_pgn("1. e4 (1. d4 *\n").is_err());
+}
+
+#[test]
+fn keys_progress_by_file_and_colour() {
+    let dir = TempDir::new("repertoire-key");
+    let path = dir.0.join("repertoire.pgn");
+    fs::write(&path, REPERTOIRE).unwrap();
+    let white = repertoire_key(&path, Team::White);
+    assert_ne!(white, repertoire_key(&path, Team::Black));
+    assert_ne!(white, repertoire_key(&dir.0.join("other.pgn"), Team::White));
+    // The same file reached by another path is the same repertoire.
+    assert_eq!(
+        white,
+        repertoire_key(&dir.0.join(".").join("repertoire.pgn"), Team::White)
+    );
+}
+
+#[test]
+fn plays_the_other_side_and_judges_the_player() {
+    let dir = TempDir::new("repertoire-judge");
+    let mut trainer = trainer(&dir, "1. e4 e5 2. Nf3 *\n", Team::Black);
+    assert!(trainer.next_line(0));
+    assert_eq!(trainer.reply(0, Team::White).unwrap().to_uci(), "e2e4");
+    assert!(trainer.reply(1, Team::Black).is_none());
+    assert_eq!(trainer.judge(1, "c7c5", 0), Verdict::Wrong);
+    assert_eq!(trainer.mistakes, 1);
+    assert_eq!(trainer.judge(1, "e7e5", 0), Verdict::Correct);
+    assert_eq!(trainer.reply(2, Team::White).unwrap().to_uci(), "g1f3");
+    // The opponent's move ends the line, with the mistake sending it back.
+    assert!(trainer.complete_at(3, 0));
+    assert!(trainer.is_finished());
+    assert_eq!(trainer.days_until_due(0), Some(0));
+    assert_eq!(trainer.learned_count(), 0);
+}
+
+#[test]
+fn moves_lines_up_the_boxes() {
+    let dir = TempDir::new("repertoire-boxes");
+    let mut trainer = trainer(&dir, "1. e4 e5 *\n", Team::White);
+    let mut time = 0;
+    for days in [1, 3, 7, 14, 30, 90, 90] {
+        assert!(trainer.next_line(time));
+        assert_eq!(trainer.judge(0, "e2e4", time), Verdict::Correct);
+        assert!(trainer.complete_at(2, time));
+        assert_eq!(trainer.days_until_due(time), Some(days));
+        assert_eq!(trainer.learned_count(), 1);
+        assert_eq!(trainer.due_count(time), 0);
+        // Not due the second before, due on the day.
+        assert!(!trainer.next_line(time + days * DAY - 1));
+        time += days * DAY;
+        assert_eq!(trainer.due_count(time), 1);
+    }
+    // A mistake sends the line back to the first box.
+    assert!(trainer.next_line(time));
+    assert_eq!(trainer.judge(0, "d2d4", time), Verdict::Wrong);
+    assert_eq!(trainer.judge(0, "e2e4", time), Verdict::Correct);
+    assert!(trainer.complete_at(2, time));
+    assert_eq!(trainer.days_until_due(time), Some(0));
+    assert_eq!(trainer.learned_count(), 0);
+    assert!(
+        !trainer.complete_at(2, time),
+        "a line is only finished once"
+    );
+}
+
+#[test]
+fn drills_overdue_lines_before_new_ones() {
+    let dir = TempDir::new("repertoire-order");
+    let pgn = "[Event \"e4\"]\n\n1. e4 e5 *\n\n[Event \"d4\"]\n\n1. d4 d5 *\n\n\
+               [Event \"c4\"]\n\n1. c4 c5 *\n";
+    let mut trainer = trainer(&dir, pgn, Team::White);
+    let finish = |trainer: &mut RepertoireTrainer, time| {
+        trainer.next_line(time);
+        let first = trainer.expected(0).unwrap().to_string();
+        assert_eq!(trainer.judge(0, &first, time), Verdict::Correct);
+        assert!(trainer.complete_at(2, time));
+        first
+    };
+    assert_eq!(finish(&mut trainer, 0), "e2e4");
+    assert_eq!(finish(&mut trainer, 10), "d2d4");
+    assert_eq!(trainer.due_count(10), 1);
+    // A day on, the lines drilled are due again and come before the new one,
+    // the one overdue the longest first.
+    assert_eq!(trainer.due_count(DAY + 10), 3);
+    assert!(trainer.next_line(DAY + 10));
+    assert_eq!(trainer.expected(0), Some("e2e4"));
+    assert_eq!(trainer.line_number(), Some(1));
+    assert_eq!(trainer.line_count(), 3);
+}
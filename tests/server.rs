@@ -0,0 +1,349 @@
+//! Games played through the headless server on loopback, by WebSocket clients
+//! and by the network connection of the GUI.
+
+use oskhen_chess_gui::network::{Connection, Message, NetworkEvent};
+use oskhen_chess_gui::server::{Reply, Request, Server};
+use oskhen_chess_gui::websocket::{self, WebSocket};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest wait for a message before a test fails.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn start_server() -> String {
+    let server = Server::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap().to_string();
+    thread::spawn(move || server.run());
+    address
+}
+
+struct Client {
+    socket: WebSocket,
+}
+
+impl Client {
+    fn connect(address: &str) -> Client {
+        let socket = WebSocket::connect(address).unwrap();
+        socket.set_read_timeout(Some(TIMEOUT)).unwrap();
+        Client { socket }
+    }
+
+    fn send(&mut self, request: Request) {
+        let text = serde_json::to_string(&request).unwrap();
+        self.socket.send_text(&text).unwrap();
+    }
+
+    fn receive(&mut self) -> Reply {
+        let text = self
+            .socket
+            .read_text()
+            .unwrap()
+            .expect("the server closed the connection");
+        serde_json::from_str(&text).unwrap()
+    }
+
+    /// The next reply that is not a clock update.
+    fn receive_skipping_clocks(&mut self) -> Reply {
+        loop {
+            match self.receive() {
+                Reply::Clock { .. } => {}
+                reply => return reply,
+            }
+        }
+    }
+
+    fn create(&mut self, color: &str, time: Option<&str>) -> usize {
+        self.send(Request::Create {
+            time: time.map(String::from),
+            color: Some(color.to_string()),
+            fen: None,
+            variant: None,
+        });
+        match self.receive() {
+            Reply::Created { game, color: given } => {
+                assert_eq!(given, color);
+                game
+            }
+            reply => panic!("expected the game to be created, got {:?}", reply),
+        }
+    }
+
+    fn expect_started(&mut self, game: usize, color: &str) {
+        match self.receive() {
+            Reply::Started {
+                game: started,
+                color: given,
+                ..
+            } => {
+                assert_eq!(started, game);
+                assert_eq!(given, color);
+            }
+            reply => panic!("expected the game to start, got {:?}", reply),
+        }
+    }
+
+    fn expect_error(&mut self) {
+        match self.receive_skipping_clocks() {
+            Reply::Error { .. } => {}
+            reply => panic!("expected an error, got {:?}", reply),
+        }
+    }
+}
+
+/// A game between two WebSocket clients, white having created it.
+fn start_game(address: &str, time: Option<&str>) -> (Client, Client) {
+    let mut white = Client::connect(address);
+    let game = white.create("white", time);
+    let mut black = Client::connect(address);
+    black.send(Request::Join { game: Some(game) });
+    white.expect_started(game, "white");
+    black.expect_started(game, "black");
+    (white, black)
+}
+
+/// Plays `uci` for `mover` and checks that both players are told about it.
+fn play(mover: &mut Client, other: &mut Client, uci: &str) {
+    mover.send(Request::Move {
+        uci: uci.to_string(),
+    });
+    for client in [mover, other] {
+        match client.receive_skipping_clocks() {
+            Reply::Moved { uci: played, .. } => assert_eq!(played, uci),
+            reply => panic!("expected {} to be played, got {:?}", uci, reply),
+        }
+    }
+}
+
+fn expect_game_over(client: &mut Client, result: &str, reason: &str) {
+    match client.receive_skipping_clocks() {
+        Reply::GameOver {
+            result: given,
+            reason: why,
+            ..
+        } => {
+            assert_eq!(given, result);
+            assert_eq!(why, reason);
+        }
+        reply => panic!("expected the game to end, got {:?}", reply),
+    }
+}
+
+/// The next message of the GUI's connection, failing after `TIMEOUT`.
+fn wait_for_message(connection: &mut Connection) -> Message {
+    let deadline = Instant::now() + TIMEOUT;
+    while Instant::now() < deadline {
+        match connection.poll() {
+            Some(NetworkEvent::Message(message)) => return message,
+            Some(_) => panic!("the connection to the server dropped"),
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    panic!("no message from the server");
+}
+
+#[test]
+fn accept_key_matches_the_rfc_example() {
+    assert_eq!(
+        websocket::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+        "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+}
+
+#[test]
+fn closes_on_an_unmasked_frame_from_a_client() {
+    let address = start_server();
+    let mut stream = TcpStream::connect(&address).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream
+        .write_all(
+            b"GET / HTTP/1.1\r\n\
+              Upgrade: websocket\r\n\
+              Connection: Upgrade\r\n\
+              Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+              Sec-WebSocket-Version: 13\r\n\r\n",
+        )
+        .unwrap();
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        let mut byte = [0; 1];
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    assert!(String::from_utf8(head)
+        .unwrap()
+        .starts_with("HTTP/1.1 101 "));
+    // "hi" as a text frame without a mask.
+    stream.write_all(&[0x81, 0x02, b'h', b'i']).unwrap();
+    // A close frame giving 1002, a protocol error, and then the end of the connection.
+    let mut close = [0; 4];
+    stream.read_exact(&mut close).unwrap();
+    assert_eq!(close, [0x88, 0x02, 0x03, 0xEA]);
+    assert_eq!(stream.read(&mut close).unwrap(), 0);
+}
+
+#[test]
+fn plays_a_game_to_checkmate() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address, None);
+    play(&mut white, &mut black, "f2f3");
+    play(&mut black, &mut white, "e7e5");
+    play(&mut white, &mut black, "g2g4");
+    play(&mut black, &mut white, "d8h4");
+    expect_game_over(&mut white, "0-1", "board");
+    expect_game_over(&mut black, "0-1", "board");
+}
+
+#[test]
+fn turns_down_illegal_moves_and_moves_out_of_turn() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address, None);
+    black.send(Request::Move {
+        uci: "e7e5".to_string(),
+    });
+    black.expect_error();
+    white.send(Request::Move {
+        uci: "e2e5".to_string(),
+    });
+    white.expect_error();
+    play(&mut white, &mut black, "e2e4");
+}
+
+#[test]
+fn agrees_to_a_draw() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address, None);
+    white.send(Request::Draw);
+    for client in [&mut white, &mut black] {
+        match client.receive() {
+            Reply::DrawOffered { by } => assert_eq!(by, "white"),
+            reply => panic!("expected a draw offer, got {:?}", reply),
+        }
+    }
+    black.send(Request::Draw);
+    expect_game_over(&mut white, "1/2-1/2", "agreement");
+    expect_game_over(&mut black, "1/2-1/2", "agreement");
+}
+
+#[test]
+fn resigns_and_plays_again() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address, None);
+    black.send(Request::Resign);
+    expect_game_over(&mut white, "1-0", "resignation");
+    expect_game_over(&mut black, "1-0", "resignation");
+    // Both are free to play another game once one has ended.
+    let game = black.create("white", None);
+    white.send(Request::Join { game: Some(game) });
+    black.expect_started(game, "white");
+    white.expect_started(game, "black");
+}
+
+#[test]
+fn syncs_the_clocks() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address, Some("1+2"));
+    white.send(Request::Clock);
+    // The clocks are sent once the game starts, then on request.
+    for _ in 0..2 {
+        match white.receive() {
+            Reply::Clock { remaining, running } => {
+                assert!(remaining[0] <= 60_000 && remaining[0] > 50_000);
+                assert_eq!(remaining[1], 60_000);
+                assert_eq!(running.as_deref(), Some("white"));
+            }
+            reply => panic!("expected the clocks, got {:?}", reply),
+        }
+    }
+    play(&mut white, &mut black, "e2e4");
+    match black.receive() {
+        Reply::Clock { remaining, running } => {
+            // White got the increment after moving.
+            assert!(remaining[0] > 60_000);
+            assert_eq!(running.as_deref(), Some("black"));
+        }
+        reply => panic!("expected the clocks, got {:?}", reply),
+    }
+}
+
+#[test]
+fn reports_bad_requests() {
+    let address = start_server();
+    let mut client = Client::connect(&address);
+    client.send(Request::Join { game: None });
+    client.expect_error();
+    client.send(Request::Clock);
+    client.expect_error();
+    client.socket.send_text("{\"type\":\"castle\"}").unwrap();
+    client.expect_error();
+}
+
+#[test]
+fn leaving_a_game_loses_it() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address, None);
+    black.socket.close().unwrap();
+    expect_game_over(&mut white, "1-0", "disconnection");
+}
+
+#[test]
+fn a_player_who_stops_reading_loses_the_game() {
+    let address = start_server();
+    let (mut white, black) = start_game(&address, None);
+    white.socket.set_read_timeout(Some(6 * TIMEOUT)).unwrap();
+    // Black sends requests that are turned down without ever reading the
+    // errors, until the server lets go of it.
+    let mut socket = black.socket;
+    thread::spawn(move || {
+        for _ in 0..10_000_000 {
+            if socket.send_text("x").is_err() {
+                return;
+            }
+        }
+    });
+    expect_game_over(&mut white, "1-0", "disconnection");
+}
+
+#[test]
+fn plays_against_the_gui() {
+    let address = start_server();
+    let mut web = Client::connect(&address);
+    let game = web.create("white", None);
+    let mut gui = Connection::join(&address).unwrap();
+    match wait_for_message(&mut gui) {
+        Message::Start { color, .. } => assert_eq!(color, "black"),
+        message => panic!("expected the game to start, got {:?}", message),
+    }
+    web.expect_started(game, "white");
+
+    web.send(Request::Move {
+        uci: "e2e4".to_string(),
+    });
+    match web.receive() {
+        Reply::Moved { uci, .. } => assert_eq!(uci, "e2e4"),
+        reply => panic!("expected e2e4 to be played, got {:?}", reply),
+    }
+    assert_eq!(
+        wait_for_message(&mut gui),
+        Message::Move {
+            uci: "e2e4".to_string(),
+            seq: Some(1)
+        }
+    );
+    gui.send(&Message::Move {
+        uci: "e7e5".to_string(),
+        seq: Some(2),
+    })
+    .unwrap();
+    match web.receive() {
+        Reply::Moved {
+            color, uci, ply, ..
+        } => {
+            assert_eq!((color.as_str(), uci.as_str(), ply), ("black", "e7e5", 2));
+        }
+        reply => panic!("expected e7e5 to be played, got {:?}", reply),
+    }
+    gui.send(&Message::Resign).unwrap();
+    expect_game_over(&mut web, "1-0", "resignation");
+}
@@ -0,0 +1,89 @@
+//! Settings files read and written back.
+
+use oskhen_chess_gui::settings::{AnimationSpeed, Orientation, Settings, CURRENT_VERSION};
+use oskhen_chess_gui::theme::Theme;
+use std::path::PathBuf;
+
+/// A file that exists wherever the tests run, standing in for an engine.
+fn engine() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml")
+}
+
+#[test]
+fn reads_every_setting_and_writes_them_back() {
+    let file = format!(
+        "version = {}\n\
+         theme = \"green\"\n\
+         orientation = \"black\"\n\
+         engines = [{:?}]\n\
+         animation_speed = \"fast\"\n\
+         auto_queen = true\n\
+         sound = false\n\
+         time_control = \"5+3\"\n\
+         window_size = \"800x600\"\n",
+        CURRENT_VERSION,
+        engine().display().to_string()
+    );
+    let (settings, warnings) = Settings::from_toml(&file);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(settings.theme, Theme::Green);
+    assert_eq!(settings.engines, [engine()]);
+    assert_eq!(settings.orientation, Orientation::Black);
+    assert_eq!(settings.animation_speed, AnimationSpeed::Fast);
+    assert!(settings.auto_queen);
+    assert!(!settings.sound);
+    assert_eq!(settings.time_control, Some("5+3".parse().unwrap()));
+    assert_eq!(settings.window_size, Some("800x600".parse().unwrap()));
+
+    let text = settings.to_toml();
+    assert!(text.contains(&format!("version = {}", CURRENT_VERSION)));
+    let (reread, warnings) = Settings::from_toml(&text);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(reread, settings);
+}
+
+#[test]
+fn keeps_an_opening_book_that_is_missing_for_now() {
+    let book = PathBuf::from("/nonexistent/books/performance.bin");
+    let file = format!("opening_book = {:?}\n", book.display().to_string());
+    let (settings, warnings) = Settings::from_toml(&file);
+    assert_eq!(settings.opening_book, Some(book.clone()));
+    assert_eq!(
+        warnings,
+        [format!("opening_book: {} does not exist", book.display())]
+    );
+    let (reread, _) = Settings::from_toml(&settings.to_toml());
+    assert_eq!(reread.opening_book, Some(book));
+}
+
+#[test]
+fn warns_about_a_file_from_a_newer_version() {
+    let file = format!("version = {}\nsound = false\n", CURRENT_VERSION + 1);
+    let (settings, warnings) = Settings::from_toml(&file);
+    assert!(!settings.sound);
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert!(warnings[0].contains("newer version"));
+}
+
+#[test]
+fn round_trips_the_defaults() {
+    let (settings, warnings) = Settings::from_toml(&Settings::default().to_toml());
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(settings, Settings::default());
+}
+
+#[test]
+fn keeps_the_usable_values_of_a_broken_file() {
+    let (settings, warnings) =
+        Settings::from_toml("version = 1\ntheme = \"purple\"\nsound = false\ncolour = 3\n");
+    assert_eq!(settings.theme, Settings::default().theme);
+    assert!(!settings.sound);
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+}
+
+#[test]
+fn falls_back_to_the_defaults_for_invalid_toml() {
+    let (settings, warnings) = Settings::from_toml("theme = ");
+    assert_eq!(settings, Settings::default());
+    assert_eq!(warnings.len(), 1);
+}
@@ -0,0 +1,141 @@
+//! Tablebase probing against small hand-written tables.
+
+mod common;
+
+use common::TempDir;
+use oskhen_chess_gui::syzygy::{Tablebase, Wdl};
+use std::fs;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+const SINGLE_VALUE: u8 = 128;
+
+/// A pawnless table of three pieces, given as the codes of the tables
+/// (kings 6 and 14, queen 5, knight 2), holding the layout of each side.
+fn table(magic: [u8; 4], pieces: [u8; 3], sides: &[&[u8]]) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    // No pawns, and the single group of three pieces comes first.
+    bytes.extend_from_slice(&[0, 0]);
+    for piece in pieces.iter() {
+        bytes.push(piece | piece << 4);
+    }
+    if bytes.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    for side in sides {
+        bytes.extend_from_slice(side);
+    }
+    // The values start at the next multiple of 64 bytes.
+    bytes.resize(bytes.len().div_ceil(64) * 64, 0);
+    bytes
+}
+
+/// Tables saying that white, to move, wins KQvK in 9 plies and that black,
+/// to move, loses.
+fn write_kqvk(dir: &TempDir) {
+    let wdl = table(
+        WDL_MAGIC,
+        [6, 5, 14],
+        &[&[SINGLE_VALUE, 4], &[SINGLE_VALUE, 0]],
+    );
+    fs::write(dir.0.join("KQvK.rtbw"), wdl).unwrap();
+    let dtz = table(DTZ_MAGIC, [6, 5, 14], &[&[SINGLE_VALUE, 4]]);
+    fs::write(dir.0.join("KQvK.rtbz"), dtz).unwrap();
+}
+
+fn open(dir: &TempDir) -> Tablebase {
+    Tablebase::open(std::slice::from_ref(&dir.0)).unwrap()
+}
+
+#[test]
+fn probes_the_result_and_ranks_the_moves() {
+    let dir = TempDir::new("syzygy-kqvk");
+    write_kqvk(&dir);
+    let mut tablebase = open(&dir);
+    assert_eq!(tablebase.max_pieces, 3);
+
+    let result = tablebase
+        .probe(&"7k/Q7/6K1/8/8/8/8/8 w - - 0 1".parse().unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.wdl, Wdl::Win);
+    assert_eq!(result.dtz, 9);
+    // The four mates come first, a ply from the end.
+    let mut mates: Vec<&str> = result.moves[..4].iter().map(|m| m.uci.as_str()).collect();
+    mates.sort_unstable();
+    assert_eq!(mates, ["a7a8", "a7b8", "a7g7", "a7h7"]);
+    assert!(result.moves[..4].iter().all(|m| m.dtz == 1));
+    assert!(result.moves[4..].iter().all(|m| m.dtz > 1));
+}
+
+#[test]
+fn draws_when_the_queen_is_lost() {
+    let dir = TempDir::new("syzygy-blunder");
+    write_kqvk(&dir);
+    let mut tablebase = open(&dir);
+    // Black to move takes the queen, leaving the bare kings.
+    let result = tablebase
+        .probe(&"8/8/8/8/8/8/6Qk/4K3 b - - 0 1".parse().unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.wdl, Wdl::Draw);
+    assert_eq!(result.moves[0].uci, "h2g2");
+    assert_eq!(result.moves[0].wdl, Wdl::Draw);
+    assert!(result.moves[1..].iter().all(|m| m.wdl == Wdl::Loss));
+}
+
+#[test]
+fn leaves_out_positions_the_tables_do_not_cover() {
+    let dir = TempDir::new("syzygy-uncovered");
+    write_kqvk(&dir);
+    let mut tablebase = open(&dir);
+    let castling = "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1".parse().unwrap();
+    assert!(tablebase.probe(&castling).unwrap().is_none());
+    let crowded = "4k3/8/8/8/8/8/PP6/4K3 w - - 0 1".parse().unwrap();
+    assert!(tablebase.probe(&crowded).unwrap().is_none());
+    let missing = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1".parse().unwrap();
+    let error = tablebase.probe(&missing).unwrap_err();
+    assert!(error.contains("KRvK"), "{}", error);
+}
+
+#[test]
+fn rejects_broken_tables() {
+    let dir = TempDir::new("syzygy-broken");
+    fs::write(dir.0.join("KQvK.rtbw"), vec![0; 4]).unwrap();
+    // Blocks of 2^64 bytes, then symbols longer at their shortest than at their longest.
+    let huge_blocks: &[u8] = &[0, 64, 6, 0, 0, 0, 0, 0, 8, 2];
+    let bad_lengths: &[u8] = &[0, 6, 6, 0, 0, 0, 0, 0, 1, 2];
+    let tables = [
+        (
+            "KNvK.rtbw",
+            table(WDL_MAGIC, [6, 2, 14], &[huge_blocks, huge_blocks]),
+        ),
+        (
+            "KBvK.rtbw",
+            table(WDL_MAGIC, [6, 3, 14], &[bad_lengths, bad_lengths]),
+        ),
+    ];
+    for (name, bytes) in tables.iter() {
+        fs::write(dir.0.join(name), bytes).unwrap();
+    }
+    let mut tablebase = open(&dir);
+    for (fen, expected) in [
+        (
+            "4k3/8/8/8/8/8/8/4KQ2 w - - 0 1",
+            "KQvK.rtbw is not a Syzygy table",
+        ),
+        (
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "KNvK.rtbw has a block size or span of 2^64",
+        ),
+        (
+            "4k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+            "KBvK.rtbw has symbols of 2 to 1 bits",
+        ),
+    ]
+    .iter()
+    {
+        let error = tablebase.probe(&fen.parse().unwrap()).unwrap_err();
+        assert_eq!(error, *expected);
+    }
+}
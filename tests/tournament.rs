@@ -0,0 +1,276 @@
+//! Pairing round-robin and Swiss tournaments and ranking their players.
+
+use oskhen_chess_gui::tournament::{Format, Player, Tournament, MAX_SWISS_PLAYERS};
+
+fn tournament(count: usize, format: Format) -> Tournament {
+    let players = (0..count)
+        .map(|index| Player::human(&format!("Player {}", index + 1)))
+        .collect();
+    Tournament::new("Test", players, format, None).unwrap()
+}
+
+/// Every pair of players meeting in the rounds paired so far, smallest first.
+fn games(tournament: &Tournament) -> Vec<(usize, usize)> {
+    let mut games: Vec<(usize, usize)> = tournament
+        .rounds
+        .iter()
+        .flatten()
+        .filter_map(|pairing| {
+            let black = pairing.black?;
+            Some((pairing.white.min(black), pairing.white.max(black)))
+        })
+        .collect();
+    games.sort_unstable();
+    games
+}
+
+/// Checks that every player is in each round exactly once.
+fn assert_everyone_plays_once_a_round(tournament: &Tournament) {
+    for round in tournament.rounds.iter() {
+        let mut seated: Vec<usize> = round
+            .iter()
+            .flat_map(|pairing| Some(pairing.white).into_iter().chain(pairing.black))
+            .collect();
+        seated.sort_unstable();
+        assert_eq!(seated, (0..tournament.players.len()).collect::<Vec<_>>());
+    }
+}
+
+fn byes(tournament: &Tournament) -> Vec<usize> {
+    tournament
+        .rounds
+        .iter()
+        .flatten()
+        .filter(|pairing| pairing.black.is_none())
+        .map(|pairing| pairing.white)
+        .collect()
+}
+
+#[test]
+fn pairs_everyone_once_in_a_round_robin() {
+    for count in [4, 5] {
+        let tournament = tournament(count, Format::RoundRobin);
+        assert_eq!(tournament.rounds.len(), count + count % 2 - 1);
+        assert_everyone_plays_once_a_round(&tournament);
+        let expected: Vec<(usize, usize)> = (0..count)
+            .flat_map(|first| (first + 1..count).map(move |second| (first, second)))
+            .collect();
+        assert_eq!(games(&tournament), expected, "{} players", count);
+    }
+    // With five players each sits out once, on the last board.
+    let tournament = tournament(5, Format::RoundRobin);
+    let mut byes = byes(&tournament);
+    byes.sort_unstable();
+    assert_eq!(byes, [0, 1, 2, 3, 4]);
+    assert!(tournament
+        .rounds
+        .iter()
+        .all(|round| round.last().unwrap().black.is_none()));
+}
+
+#[test]
+fn alternates_colors_in_a_round_robin() {
+    let tournament = tournament(6, Format::RoundRobin);
+    for player in 0..6 {
+        let whites = tournament
+            .rounds
+            .iter()
+            .flatten()
+            .filter(|pairing| pairing.white == player)
+            .count();
+        assert!(
+            (2..=3).contains(&whites),
+            "player {} has white {} times",
+            player,
+            whites
+        );
+    }
+}
+
+/// Plays a Swiss tournament out, the lower numbered player winning every
+/// game, checking each round as it is paired.
+fn play_swiss(count: usize, rounds: usize) -> Tournament {
+    let mut tournament = tournament(count, Format::Swiss { rounds });
+    let mut paired = 0;
+    while let Some((round, board)) = tournament.next_game() {
+        if round == paired {
+            // White goes to the player who has had it less often so far.
+            let balance = |player: usize| -> i32 {
+                tournament.rounds[..round]
+                    .iter()
+                    .flatten()
+                    .filter(|pairing| pairing.black.is_some())
+                    .map(|pairing| match pairing.black {
+                        Some(black) if black == player => -1,
+                        _ if pairing.white == player => 1,
+                        _ => 0,
+                    })
+                    .sum()
+            };
+            for pairing in tournament.rounds[round].iter() {
+                if let Some(black) = pairing.black {
+                    assert!(balance(pairing.white) <= balance(black));
+                }
+            }
+            paired += 1;
+        }
+        let pairing = &tournament.rounds[round][board];
+        let result = if pairing.white < pairing.black.unwrap() {
+            "1-0"
+        } else {
+            "0-1"
+        };
+        tournament.set_result(round, board, result);
+    }
+    assert!(tournament.is_finished());
+    assert_eq!(tournament.rounds.len(), rounds);
+    tournament
+}
+
+#[test]
+fn never_pairs_a_rematch_in_a_swiss_tournament() {
+    // Five rounds between six players leave one way to avoid rematches.
+    let tournament = play_swiss(6, 5);
+    assert_everyone_plays_once_a_round(&tournament);
+    let games = games(&tournament);
+    let mut unique = games.clone();
+    unique.dedup();
+    assert_eq!(games, unique);
+    assert_eq!(games.len(), 15);
+}
+
+#[test]
+fn pairs_players_on_the_same_score() {
+    let tournament = play_swiss(8, 2);
+    // After the first round, the winners meet each other and so do the losers.
+    let winners: Vec<usize> = (0..8)
+        .filter(|player| {
+            tournament.rounds[0]
+                .iter()
+                .any(|pairing| pairing.white.min(pairing.black.unwrap()) == *player)
+        })
+        .collect();
+    for pairing in tournament.rounds[1].iter() {
+        let black = pairing.black.unwrap();
+        assert_eq!(
+            winners.contains(&pairing.white),
+            winners.contains(&black),
+            "{} - {}",
+            pairing.white,
+            black
+        );
+    }
+}
+
+#[test]
+fn gives_a_different_player_the_bye_each_swiss_round() {
+    let tournament = play_swiss(5, 3);
+    assert_everyone_plays_once_a_round(&tournament);
+    let byes = byes(&tournament);
+    assert_eq!(byes.len(), 3);
+    assert!(byes
+        .iter()
+        .all(|player| byes.iter().filter(|other| *other == player).count() == 1));
+    // A bye is a point in a Swiss tournament, though not towards the tiebreaks.
+    let player = byes[0];
+    let won = tournament
+        .rounds
+        .iter()
+        .flatten()
+        .filter(|pairing| pairing.black.is_some())
+        .filter(|pairing| pairing.white.min(pairing.black.unwrap()) == player)
+        .count();
+    assert_eq!(tournament.points(player), won as f64 + 1.0);
+    let standing = tournament
+        .standings()
+        .into_iter()
+        .find(|standing| standing.player == player)
+        .unwrap();
+    assert_eq!(standing.games, 2);
+}
+
+#[test]
+fn turns_down_swiss_tournaments_too_large_to_pair() {
+    let players = |count: usize| {
+        (0..count)
+            .map(|index| Player::human(&index.to_string()))
+            .collect()
+    };
+    let swiss = Format::Swiss { rounds: 3 };
+    assert!(Tournament::new("Large", players(MAX_SWISS_PLAYERS), swiss, None).is_ok());
+    assert!(Tournament::new("Large", players(MAX_SWISS_PLAYERS + 1), swiss, None).is_err());
+    assert!(Tournament::new(
+        "Large",
+        players(MAX_SWISS_PLAYERS + 1),
+        Format::RoundRobin,
+        None
+    )
+    .is_ok());
+    assert!(Tournament::new("Alone", players(1), Format::RoundRobin, None).is_err());
+    assert!(Tournament::new("None", players(4), Format::Swiss { rounds: 0 }, None).is_err());
+}
+
+#[test]
+fn pairs_the_largest_swiss_tournament_when_rematches_cannot_be_avoided() {
+    // Fifteen rounds between sixteen players run into rounds where the games
+    // left cannot all be paired, which takes trying every way of pairing them.
+    let tournament = play_swiss(MAX_SWISS_PLAYERS, MAX_SWISS_PLAYERS - 1);
+    assert_everyone_plays_once_a_round(&tournament);
+    assert_eq!(games(&tournament).len(), 8 * 15);
+}
+
+#[test]
+fn ranks_by_points_buchholz_and_sonneborn_berger() {
+    // Anna beats Bo and Cleo and draws Dan, Bo beats Cleo and draws Dan, and
+    // Cleo beats Dan.
+    //
+    //         Anna Bo  Cleo Dan  Points Buchholz S-B
+    // Anna     -   1   1    ½    2½     3½       3
+    // Bo       0   -   1    ½    1½     4½       1½
+    // Cleo     0   0   -    1    1      5        1
+    // Dan      ½   ½   0    -    1      5        2
+    let players = ["Anna", "Bo", "Cleo", "Dan"]
+        .iter()
+        .map(|name| Player::human(name))
+        .collect();
+    let mut tournament = Tournament::new("Crosstable", players, Format::RoundRobin, None).unwrap();
+    let score = |first: usize, second: usize| match (first.min(second), first.max(second)) {
+        (0, 3) | (1, 3) => 0.5,
+        _ => 1.0,
+    };
+    while let Some((round, board)) = tournament.next_game() {
+        let pairing = &tournament.rounds[round][board];
+        let (white, black) = (pairing.white, pairing.black.unwrap());
+        let result = match (score(white, black), white < black) {
+            (0.5, _) => "1/2-1/2",
+            (_, true) => "1-0",
+            (_, false) => "0-1",
+        };
+        tournament.set_result(round, board, result);
+    }
+    let standings: Vec<(usize, f64, f64, f64, usize)> = tournament
+        .standings()
+        .into_iter()
+        .map(|standing| {
+            (
+                standing.player,
+                standing.points,
+                standing.buchholz,
+                standing.sonneborn_berger,
+                standing.games,
+            )
+        })
+        .collect();
+    assert_eq!(
+        standings,
+        [
+            (0, 2.5, 3.5, 3.0, 3),
+            (1, 1.5, 4.5, 1.5, 3),
+            (3, 1.0, 5.0, 2.0, 3),
+            (2, 1.0, 5.0, 1.0, 3),
+        ]
+    );
+    let table = tournament.standings_table();
+    assert_eq!(table.len(), 5);
+    assert!(table[1].contains("Anna") && table[4].contains("Cleo"));
+}
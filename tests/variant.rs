@@ -0,0 +1,252 @@
+//! Win conditions and move rules of the variants other than standard chess.
+
+use chess::game::{Game, Rank, Team};
+use oskhen_chess_gui::fen::Fen;
+use oskhen_chess_gui::outcome::Outcome;
+use oskhen_chess_gui::position::{in_check, king_position, piece_at};
+use oskhen_chess_gui::record::{GameRecord, RecordedMove};
+use oskhen_chess_gui::variant::Variant;
+
+fn setup(fen: &str) -> Game {
+    fen.parse::<Fen>().unwrap().setup()
+}
+
+/// A game of `variant` read from `moves`, starting from `fen` if given.
+fn play(variant: Variant, fen: Option<&str>, moves: &str) -> (GameRecord, Game) {
+    let mut pgn = format!("[Variant \"{}\"]\n", variant.name());
+    if let Some(fen) = fen {
+        pgn += &format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen);
+    }
+    pgn += &format!("\n{} *\n", moves);
+    GameRecord::from_pgn(&pgn).unwrap()
+}
+
+fn extra_moves(variant: Variant, fen: &str) -> Vec<((isize, isize), (isize, isize))> {
+    variant.extra_moves(&setup(fen))
+}
+
+fn allows(variant: Variant, fen: &str, from: (isize, isize), to: (isize, isize)) -> bool {
+    let recorded = RecordedMove {
+        from,
+        to,
+        promotion: None,
+    };
+    variant.allows(&setup(fen), recorded)
+}
+
+#[test]
+fn reads_the_names_other_programs_use() {
+    for (name, variant) in [
+        ("King of the Hill", Variant::KingOfTheHill),
+        ("koth", Variant::KingOfTheHill),
+        ("3check", Variant::ThreeCheck),
+        ("Three-check", Variant::ThreeCheck),
+        ("horde", Variant::Horde),
+        ("Atomic", Variant::Atomic),
+        ("Fischerandom", Variant::Chess960),
+    ] {
+        assert_eq!(name.parse::<Variant>(), Ok(variant));
+    }
+    for variant in Variant::ALL.iter() {
+        assert_eq!(variant.name().parse::<Variant>(), Ok(*variant));
+    }
+    assert!("crazyhouse".parse::<Variant>().is_err());
+}
+
+#[test]
+fn a_king_on_the_hill_wins() {
+    let record = GameRecord::new();
+    let winner = |fen: &str| Variant::KingOfTheHill.winner(&record, &setup(fen));
+    assert!(winner("8/8/8/8/3K4/8/8/k7 b - - 0 1") == Some(Team::White));
+    assert!(winner("K7/8/8/4k3/8/8/8/8 w - - 0 1") == Some(Team::Black));
+    assert!(winner("8/8/8/8/8/3K4/8/k7 b - - 0 1").is_none());
+    // Standard chess has no hill.
+    assert!(Variant::Standard
+        .winner(&record, &setup("8/8/8/8/3K4/8/8/k7 b - - 0 1"))
+        .is_none());
+}
+
+#[test]
+fn walking_onto_the_hill_ends_the_game() {
+    let (record, mut game) = play(
+        Variant::KingOfTheHill,
+        Some("8/8/8/8/8/3K4/8/k7 w - - 0 1"),
+        "1. Kd4",
+    );
+    assert!(
+        Outcome::detect(&mut game, &record)
+            == Some(Outcome::Variant {
+                winner: Team::White,
+                variant: Variant::KingOfTheHill,
+            })
+    );
+}
+
+#[test]
+fn the_third_check_wins() {
+    let moves = "1. e4 d5 2. Bb5+ c6 3. Bxc6+ Nxc6 4. Qh5 a6";
+    let (record, mut game) = play(Variant::ThreeCheck, None, moves);
+    assert_eq!(record.checks_given(Team::White, record.ply()), 2);
+    assert!(Outcome::detect(&mut game, &record).is_none());
+
+    let (record, mut game) = play(Variant::ThreeCheck, None, &format!("{} 5. Qxf7+", moves));
+    assert_eq!(record.checks_given(Team::White, record.ply()), 3);
+    assert_eq!(record.checks_given(Team::Black, record.ply()), 0);
+    assert!(
+        Outcome::detect(&mut game, &record)
+            == Some(Outcome::Variant {
+                winner: Team::White,
+                variant: Variant::ThreeCheck,
+            })
+    );
+}
+
+#[test]
+fn the_horde_has_no_king() {
+    let game = Variant::Horde.start_position().unwrap().setup();
+    assert!(king_position(&game, Team::White).is_none());
+    assert!(king_position(&game, Team::Black).is_some());
+    assert!(!in_check(&game, Team::White));
+    assert!(!Variant::Horde.is_checkmate(&mut setup("4k3/8/8/8/8/8/8/P7 w - - 0 1")));
+}
+
+#[test]
+fn plays_the_horde_without_a_king() {
+    let (record, mut game) = play(Variant::Horde, None, "1. e5 d6 2. exd6 cxd6 3. b6");
+    assert_eq!(record.san, ["e5", "d6", "exd6", "cxd6", "b6"]);
+    assert!(Outcome::detect(&mut game, &record).is_none());
+}
+
+#[test]
+fn pawns_on_the_first_rank_step_two_squares_in_horde() {
+    let fen = "4k3/8/8/8/8/8/7P/P6P w - - 0 1";
+    assert_eq!(extra_moves(Variant::Horde, fen), [((0, 0), (0, 2))]);
+    assert!(extra_moves(Variant::Standard, fen).is_empty());
+    assert!(extra_moves(Variant::Horde, "4k3/8/8/8/8/p7/8/P7 w - - 0 1").is_empty());
+
+    let (record, game) = play(Variant::Horde, Some(fen), "1. a3");
+    assert_eq!(record.san, ["a3"]);
+    assert!(piece_at(&game, (0, 2)) == Some((Team::White, Rank::Pawn)));
+}
+
+#[test]
+fn taking_the_last_pawn_wins_horde() {
+    let record = GameRecord::new();
+    assert!(
+        Variant::Horde.winner(&record, &setup("4k3/8/8/8/8/8/8/8 w - - 0 1")) == Some(Team::Black)
+    );
+    assert!(Variant::Horde
+        .winner(&record, &setup("4k3/8/8/8/8/8/8/P7 w - - 0 1"))
+        .is_none());
+
+    let (record, mut game) = play(
+        Variant::Horde,
+        Some("4k3/8/8/8/8/8/r7/P7 b - - 0 1"),
+        "1... Rxa1",
+    );
+    assert!(
+        Outcome::detect(&mut game, &record)
+            == Some(Outcome::Variant {
+                winner: Team::Black,
+                variant: Variant::Horde,
+            })
+    );
+}
+
+#[test]
+fn a_blocked_horde_is_stalemated() {
+    let (record, mut game) = play(
+        Variant::Horde,
+        Some("4k3/8/8/8/8/p7/P7/8 b - - 0 1"),
+        "1... Ke7",
+    );
+    assert!(Outcome::detect(&mut game, &record) == Some(Outcome::Stalemate));
+}
+
+#[test]
+fn captures_explode() {
+    let (record, game) = play(Variant::Atomic, None, "1. e4 d5 2. exd5");
+    assert_eq!(record.san, ["e4", "d5", "exd5"]);
+    for square in [(3, 4), (4, 3)] {
+        assert!(piece_at(&game, square).is_none());
+    }
+    assert!(piece_at(&game, (3, 7)) == Some((Team::Black, Rank::Queen)));
+}
+
+#[test]
+fn blowing_up_the_king_wins() {
+    let (record, mut game) = play(Variant::Atomic, None, "1. d4 a6 2. Bg5 a5 3. Bxe7");
+    assert!(king_position(&game, Team::Black).is_none());
+    // Pawns next to the explosion stay, the other pieces go with the king.
+    assert!(piece_at(&game, (3, 6)) == Some((Team::Black, Rank::Pawn)));
+    assert!(piece_at(&game, (3, 7)).is_none());
+    assert!(piece_at(&game, (5, 7)).is_none());
+    assert!(
+        Outcome::detect(&mut game, &record)
+            == Some(Outcome::Variant {
+                winner: Team::White,
+                variant: Variant::Atomic,
+            })
+    );
+}
+
+#[test]
+fn blowing_up_the_king_beats_being_in_check() {
+    // The rook on e8 gives check, and the knight takes next to the black king.
+    let fen = "4r1k1/7p/8/6N1/8/8/8/4K3 w - - 0 1";
+    assert!(extra_moves(Variant::Atomic, fen).contains(&((6, 4), (7, 6))));
+    assert!(!allows(Variant::Atomic, fen, (6, 4), (5, 2)));
+
+    let (record, mut game) = play(Variant::Atomic, Some(fen), "1. Nxh7");
+    assert_eq!(record.san, ["Nxh7"]);
+    assert!(king_position(&game, Team::Black).is_none());
+    assert!(
+        Outcome::detect(&mut game, &record)
+            == Some(Outcome::Variant {
+                winner: Team::White,
+                variant: Variant::Atomic,
+            })
+    );
+}
+
+#[test]
+fn kings_may_touch_in_atomic() {
+    let fen = "8/8/4k3/8/4K3/8/8/8 w - - 0 1";
+    assert!(extra_moves(Variant::Atomic, fen).contains(&((4, 3), (4, 4))));
+    assert!(extra_moves(Variant::Standard, fen).is_empty());
+
+    let (record, game) = play(Variant::Atomic, Some(fen), "1. Ke5");
+    assert_eq!(record.san, ["Ke5"]);
+    // Touching kings give no check in Atomic, not even from a rook, as
+    // taking the king would blow up the own one.
+    let atomic = Variant::Atomic;
+    assert!(!atomic.in_check(&game, Team::Black));
+    let touching = setup("4r3/8/8/8/3kK3/8/8/8 w - - 0 1");
+    assert!(!atomic.in_check(&touching, Team::White));
+    assert!(in_check(&touching, Team::White));
+    assert!(Variant::Standard.in_check(&touching, Team::White));
+    assert!(atomic.in_check(&setup("4r3/8/8/2k5/4K3/8/8/8 w - - 0 1"), Team::White));
+}
+
+#[test]
+fn kings_cannot_capture_or_blow_themselves_up() {
+    let fen = "4k3/8/8/8/8/8/3r4/3QK3 w - - 0 1";
+    assert!(!allows(Variant::Atomic, fen, (4, 0), (3, 1)));
+    assert!(!allows(Variant::Atomic, fen, (3, 0), (3, 1)));
+    assert!(allows(Variant::Standard, fen, (3, 0), (3, 1)));
+    // A capture away from the king is fine.
+    assert!(allows(
+        Variant::Atomic,
+        "4k3/8/8/8/8/8/r7/Q3K3 w - - 0 1",
+        (0, 0),
+        (0, 1)
+    ));
+}
+
+#[test]
+fn explosions_may_not_uncover_the_own_king() {
+    // Taking on d6 blows up the bishop on e7 that shields the king from the rook.
+    let fen = "4r1k1/4B3/3p4/8/8/8/3Q4/4K3 w - - 0 1";
+    assert!(!allows(Variant::Atomic, fen, (3, 1), (3, 5)));
+    assert!(allows(Variant::Standard, fen, (3, 1), (3, 5)));
+}